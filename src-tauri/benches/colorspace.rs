@@ -0,0 +1,108 @@
+// benches/colorspace.rs
+
+//! Per-frame pixel work of the recorder at 1920x1080.
+//!
+//! * `*_per_pixel` is what the frame sources and writers used to do: a
+//!   `flat_map` or push per pixel, and floating point for the YUV matrix.
+//! * The other benchmarks are the `colorspace` functions that replaced them.
+//!
+//! Run with `cargo bench --bench colorspace`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use main_dashboard_spinup_lib::video_main::colorspace::{self, ColorSpace, PackedFormat, ScaleFilter, YuvPlanes};
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+
+fn test_frame(bpp: usize) -> Vec<u8> {
+    (0..WIDTH * HEIGHT * bpp).map(|i| (i * 7 + i / 4099) as u8).collect()
+}
+
+fn bgra_to_rgb(c: &mut Criterion) {
+    let bgra = test_frame(4);
+    c.bench_function("bgra_to_rgb_per_pixel", |b| {
+        b.iter(|| {
+            let mut rgb = Vec::with_capacity(WIDTH * HEIGHT * 3);
+            rgb.extend(black_box(&bgra).chunks_exact(4).flat_map(|p| [p[2], p[1], p[0]]));
+            rgb
+        })
+    });
+    let mut rgb = vec![0; WIDTH * HEIGHT * 3];
+    c.bench_function("bgra_to_rgb", |b| b.iter(|| colorspace::bgra_to_rgb(black_box(&bgra), &mut rgb)));
+}
+
+fn rgb_to_bgra(c: &mut Criterion) {
+    let rgb = test_frame(3);
+    c.bench_function("rgb_to_bgra_per_pixel", |b| {
+        b.iter(|| {
+            let mut bgra = Vec::with_capacity(WIDTH * HEIGHT * 4);
+            for px in black_box(&rgb).chunks_exact(3) {
+                bgra.push(px[2]);
+                bgra.push(px[1]);
+                bgra.push(px[0]);
+                bgra.push(255);
+            }
+            bgra
+        })
+    });
+    let mut bgra = vec![0; WIDTH * HEIGHT * 4];
+    c.bench_function("rgb_to_bgra", |b| b.iter(|| colorspace::rgb_to_bgra(black_box(&rgb), &mut bgra)));
+}
+
+fn rgb_to_i420(c: &mut Criterion) {
+    let rgb = test_frame(3);
+    let mut y = vec![0; WIDTH * HEIGHT];
+    let mut u = vec![0; WIDTH * HEIGHT / 4];
+    let mut v = vec![0; WIDTH * HEIGHT / 4];
+    c.bench_function("rgb_to_i420_per_pixel", |b| {
+        b.iter(|| {
+            let rgb = black_box(&rgb);
+            for row in 0..HEIGHT {
+                for col in 0..WIDTH {
+                    let i = (row * WIDTH + col) * 3;
+                    let (r, g, b) = (rgb[i] as f32, rgb[i + 1] as f32, rgb[i + 2] as f32);
+                    y[row * WIDTH + col] = (0.299 * r + 0.587 * g + 0.114 * b).round().clamp(0.0, 255.0) as u8;
+                    if row % 2 == 0 && col % 2 == 0 {
+                        let c = (row / 2) * (WIDTH / 2) + col / 2;
+                        u[c] = (-0.169 * r - 0.331 * g + 0.5 * b + 128.0).round().clamp(0.0, 255.0) as u8;
+                        v[c] = (0.5 * r - 0.419 * g - 0.081 * b + 128.0).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        })
+    });
+    c.bench_function("rgb_to_i420", |b| {
+        b.iter(|| {
+            let planes = YuvPlanes::I420 { y: &mut y, y_stride: WIDTH, u: &mut u, v: &mut v, uv_stride: WIDTH / 2 };
+            colorspace::to_yuv420(black_box(&rgb), PackedFormat::Rgb, WIDTH, HEIGHT, planes, ColorSpace::BT601_FULL)
+        })
+    });
+    let bgra = test_frame(4);
+    let mut uv = vec![0; WIDTH * HEIGHT / 2];
+    c.bench_function("bgra_to_nv12", |b| {
+        b.iter(|| {
+            let planes = YuvPlanes::Nv12 { y: &mut y, y_stride: WIDTH, uv: &mut uv, uv_stride: WIDTH };
+            colorspace::to_yuv420(black_box(&bgra), PackedFormat::Bgra, WIDTH, HEIGHT, planes, ColorSpace::BT709_LIMITED)
+        })
+    });
+}
+
+fn downscale(c: &mut Criterion) {
+    let rgb = test_frame(3);
+    let (w, h) = (WIDTH as u32, HEIGHT as u32);
+    c.bench_function("downscale_image_triangle", |b| {
+        b.iter(|| {
+            let img = image::RgbImage::from_raw(w, h, black_box(&rgb).clone()).unwrap();
+            image::imageops::resize(&img, 1280, 720, image::imageops::FilterType::Triangle)
+        })
+    });
+    c.bench_function("downscale_bilinear", |b| {
+        b.iter(|| colorspace::scale(black_box(&rgb), w, h, 3, 1280, 720, ScaleFilter::Bilinear))
+    });
+    c.bench_function("downscale_box_half", |b| {
+        b.iter(|| colorspace::scale(black_box(&rgb), w, h, 3, w / 2, h / 2, ScaleFilter::Box))
+    });
+}
+
+criterion_group!(benches, bgra_to_rgb, rgb_to_bgra, rgb_to_i420, downscale);
+criterion_main!(benches);
@@ -0,0 +1,109 @@
+// benches/input_callback.rs
+
+//! Time spent inside the input hook callback per key press.
+//!
+//! * `locked_bucket_update` is the callback as it used to be: check the
+//!   running flag, look up the active window through the platform backend,
+//!   stamp the last input time and update the minute bucket, all on the
+//!   input thread. It runs the service's own `InputAggregator` with window
+//!   lookups on every event, as the old callback did them.
+//! * `pipeline_push` is the callback as it is now: timestamp the event and
+//!   push a `TimedInput` onto the bounded queue.
+//!
+//! The old callback asks the real `activity_platform` window backend, so its
+//! numbers include a window-system round trip wherever one can be opened. The
+//! backend in use is printed first; without one the lookup is skipped and the
+//! gap shown is smaller than the real one.
+//!
+//! Run with `cargo bench --bench input_callback`.
+
+// Only the window provider and the aggregator are used. With
+// `harness = false` the modules' `#[test]` functions are compiled out,
+// leaving their imports unused.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/activity_platform/mod.rs"]
+mod activity_platform;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/input_aggregator.rs"]
+mod input_aggregator;
+
+use activity_platform::{ActiveWindowProvider, InputEvent, TimedInput};
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use input_aggregator::{InputAggregator, InputBuckets};
+use main_dashboard_spinup_lib::input_pipeline;
+use rdev::Key;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn key_press() -> InputEvent {
+    InputEvent::KeyPress {
+        key: Key::KeyA,
+        name: Some("a".into()),
+    }
+}
+
+/// The hook callback before events were queued: the aggregator's work,
+/// done inline under the service's locks.
+fn old_callback(window_provider: Option<Box<dyn ActiveWindowProvider>>) -> impl FnMut(InputEvent) {
+    let is_running = Arc::new(Mutex::new(true));
+    let input_buckets: Arc<Mutex<InputBuckets>> = Arc::default();
+    let last_input_at: Arc<Mutex<DateTime<Utc>>> = Arc::new(Mutex::new(Utc::now()));
+    let mut aggregator = InputAggregator::new(window_provider, Duration::ZERO);
+
+    move |event| {
+        if !*is_running.lock().unwrap() {
+            return;
+        }
+        let at = Utc::now();
+        if aggregator.add(&mut input_buckets.lock().unwrap(), TimedInput { at, event }) {
+            *last_input_at.lock().unwrap() = at;
+        }
+    }
+}
+
+fn locked_bucket_update(c: &mut Criterion) {
+    let window_provider = activity_platform::window_provider().ok();
+    eprintln!(
+        "active window backend: {}",
+        window_provider.as_ref().map_or("none", |p| p.name())
+    );
+    let mut callback = old_callback(window_provider);
+
+    c.bench_function("locked_bucket_update", |b| {
+        b.iter_batched(key_press, &mut callback, BatchSize::SmallInput)
+    });
+}
+
+fn pipeline_push(c: &mut Criterion) {
+    const CAPACITY: usize = 4096;
+    let (producer, consumer) = input_pipeline::bounded::<TimedInput>(CAPACITY);
+    let mut callback = move |event| {
+        producer.push(TimedInput { at: Utc::now(), event });
+    };
+
+    c.bench_function("pipeline_push", |b| {
+        b.iter_custom(|iters| {
+            // Only the callback is timed. The queue is emptied between chunks
+            // so every push takes the normal path rather than the cheaper
+            // "queue full" one.
+            let mut elapsed = Duration::ZERO;
+            let mut remaining = iters;
+            while remaining > 0 {
+                let chunk = remaining.min(CAPACITY as u64);
+                let events: Vec<InputEvent> = (0..chunk).map(|_| key_press()).collect();
+                let start = Instant::now();
+                events.into_iter().for_each(&mut callback);
+                elapsed += start.elapsed();
+                while consumer.try_recv().is_some() {}
+                remaining -= chunk;
+            }
+            elapsed
+        })
+    });
+
+    assert_eq!(consumer.dropped(), 0);
+}
+
+criterion_group!(benches, locked_bucket_update, pipeline_push);
+criterion_main!(benches);
@@ -0,0 +1,398 @@
+// src/activity_export.rs
+
+//! Exports recorded activity for a date range so employees and auditors can
+//! get a copy of what was captured about them.
+//!
+//! * CSV: one row per record with the payload flattened into columns.
+//! * JSON Lines: one `ActivityMeta` object per line. Media references, when
+//!   requested, follow as `{"media_reference": {...}}` lines.
+
+use crate::activity_service::{ActivityMeta, ActivityType};
+use crate::activity_store::ActivityStore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, State};
+
+/// Pending folders whose files are listed as media references.
+const MEDIA_DIRS: [(&str, &str); 2] = [
+    ("screenshot", "screenshots_pending"),
+    ("video", "video_recordings_pending"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportRequest {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub format: ExportFormat,
+    pub include_media: bool,
+    pub output: PathBuf,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportSummary {
+    pub path: PathBuf,
+    pub activities: usize,
+    pub media_references: usize,
+    /// Stored records that could not be read and are missing from the
+    /// export, e.g. rows written by an older schema version.
+    pub unreadable_records: usize,
+}
+
+/// A screenshot or video that was captured in the exported range and is
+/// still on this machine (i.e. not yet uploaded).
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaReference {
+    pub kind: &'static str,
+    pub path: PathBuf,
+    pub captured_at: DateTime<Utc>,
+}
+
+pub fn export_activity(
+    store: &ActivityStore,
+    app_data_dir: &Path,
+    req: &ExportRequest,
+) -> anyhow::Result<ExportSummary> {
+    if req.to <= req.from {
+        anyhow::bail!("export range is empty: {} .. {}", req.from, req.to);
+    }
+
+    let mut activities = Vec::new();
+    let mut unreadable_records = 0;
+    for payload in store.timeline_payloads(req.from, req.to)? {
+        match serde_json::from_str::<ActivityMeta>(&payload) {
+            Ok(activity) => activities.push(activity),
+            Err(e) => {
+                log::warn!("Record left out of the export, it cannot be read: {}", e);
+                unreadable_records += 1;
+            }
+        }
+    }
+    let media = if req.include_media {
+        collect_media(app_data_dir, req.from, req.to)
+    } else {
+        Vec::new()
+    };
+
+    if let Some(dir) = req.output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = File::create(&req.output)?;
+    match req.format {
+        ExportFormat::Csv => write_csv(file, &activities, &media)?,
+        ExportFormat::Jsonl => write_jsonl(file, &activities, &media)?,
+    }
+
+    Ok(ExportSummary {
+        path: req.output.clone(),
+        activities: activities.len(),
+        media_references: media.len(),
+        unreadable_records,
+    })
+}
+
+/// Flat CSV row. Columns that do not apply to a record's kind stay empty.
+#[derive(Serialize, Default)]
+struct CsvRow<'a> {
+    timestamp: String,
+    kind: &'a str,
+    app_name: Option<&'a str>,
+    window_title: Option<&'a str>,
+    started_at: Option<String>,
+    ended_at: Option<String>,
+    duration_ms: Option<i64>,
+    key_count: Option<u32>,
+    backspace_count: Option<u32>,
+    text: Option<&'a str>,
+    clicks_left: Option<u32>,
+    clicks_right: Option<u32>,
+    clicks_middle: Option<u32>,
+    clicks_other: Option<u32>,
+    scroll_distance: Option<u64>,
+    mouse_distance_px: Option<f64>,
+    reason: Option<&'a str>,
+    media_path: Option<String>,
+}
+
+impl<'a> From<&'a ActivityMeta> for CsvRow<'a> {
+    fn from(activity: &'a ActivityMeta) -> Self {
+        let mut row = CsvRow {
+            timestamp: activity.timestamp.to_rfc3339(),
+            kind: activity.activity_type.kind(),
+            app_name: activity.app_name.as_deref(),
+            window_title: activity.window_title.as_deref(),
+            ..Default::default()
+        };
+        match &activity.activity_type {
+            ActivityType::Input(stats) => {
+                row.started_at = Some(stats.minute_start.to_rfc3339());
+                row.key_count = Some(stats.key_count);
+                row.backspace_count = Some(stats.backspace_count);
+                row.text = Some(&stats.text);
+                row.clicks_left = Some(stats.clicks.left);
+                row.clicks_right = Some(stats.clicks.right);
+                row.clicks_middle = Some(stats.clicks.middle);
+                row.clicks_other = Some(stats.clicks.other);
+                row.scroll_distance = Some(stats.scroll_distance);
+                row.mouse_distance_px = Some(stats.mouse_distance_px);
+            }
+            ActivityType::WindowFocus(span) | ActivityType::BrowserActivity(span) | ActivityType::Idle(span) => {
+                row.started_at = Some(span.started_at.to_rfc3339());
+                row.ended_at = Some(span.ended_at.to_rfc3339());
+                row.duration_ms = Some(span.duration_ms);
+            }
+            ActivityType::Paused(pause) => {
+                row.reason = Some(&pause.reason);
+                row.started_at = Some(activity.timestamp.to_rfc3339());
+                row.ended_at = Some(pause.resume_at.to_rfc3339());
+            }
+            ActivityType::Resumed(pause) => {
+                row.reason = Some(&pause.reason);
+                row.started_at = Some(pause.started_at.to_rfc3339());
+                row.ended_at = Some(pause.ended_at.to_rfc3339());
+                row.duration_ms = Some(pause.duration_ms);
+            }
+        }
+        row
+    }
+}
+
+fn write_csv(file: File, activities: &[ActivityMeta], media: &[MediaReference]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+    for activity in activities {
+        writer.serialize(CsvRow::from(activity))?;
+    }
+    for m in media {
+        writer.serialize(CsvRow {
+            timestamp: m.captured_at.to_rfc3339(),
+            kind: m.kind,
+            media_path: Some(m.path.display().to_string()),
+            ..Default::default()
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_jsonl(file: File, activities: &[ActivityMeta], media: &[MediaReference]) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct MediaLine<'a> {
+        media_reference: &'a MediaReference,
+    }
+
+    let mut writer = BufWriter::new(file);
+    for activity in activities {
+        serde_json::to_writer(&mut writer, activity)?;
+        writer.write_all(b"\n")?;
+    }
+    for m in media {
+        serde_json::to_writer(&mut writer, &MediaLine { media_reference: m })?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Lists screenshots and videos under the pending folders whose modification
+/// time falls within `[from, to)`, oldest first.
+fn collect_media(app_data_dir: &Path, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<MediaReference> {
+    fn walk(dir: &Path, kind: &'static str, from: DateTime<Utc>, to: DateTime<Utc>, out: &mut Vec<MediaReference>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, kind, from, to, out);
+                continue;
+            }
+            let Some(captured_at) = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from)
+            else {
+                continue;
+            };
+            if captured_at >= from && captured_at < to {
+                out.push(MediaReference { kind, path, captured_at });
+            }
+        }
+    }
+
+    let mut media = Vec::new();
+    for (kind, dir) in MEDIA_DIRS {
+        walk(&app_data_dir.join(dir), kind, from, to, &mut media);
+    }
+    media.sort_by_key(|m| m.captured_at);
+    media
+}
+
+#[tauri::command]
+pub fn export_activity_log(
+    app: AppHandle,
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    format: ExportFormat,
+    include_media: bool,
+    path: String,
+) -> Result<ExportSummary, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let req = ExportRequest {
+        from,
+        to,
+        format,
+        include_media,
+        output: PathBuf::from(path),
+    };
+    export_activity(&store, &app_data_dir, &req).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity_service::ActivitySpan;
+    use crate::input_aggregator::{ClickCounts, InputStats};
+    use chrono::{DurationRound, TimeDelta};
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("activity_export_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A recent minute, so the store's retention keeps the records.
+    fn minute() -> DateTime<Utc> {
+        (Utc::now() - TimeDelta::hours(1))
+            .duration_trunc(TimeDelta::minutes(1))
+            .unwrap()
+    }
+
+    fn records(at: DateTime<Utc>) -> Vec<ActivityMeta> {
+        vec![
+            ActivityMeta {
+                timestamp: at + TimeDelta::minutes(1),
+                activity_type: ActivityType::Input(InputStats {
+                    minute_start: at,
+                    key_count: 12,
+                    backspace_count: 1,
+                    text: "say \"hi\", then\nleave".to_string(),
+                    clicks: ClickCounts {
+                        left: 2,
+                        ..ClickCounts::default()
+                    },
+                    scroll_distance: 0,
+                    mouse_distance_px: 0.0,
+                }),
+                window_title: Some("notes, draft".to_string()),
+                app_name: Some("editor".to_string()),
+            },
+            ActivityMeta {
+                timestamp: at + TimeDelta::minutes(2),
+                activity_type: ActivityType::Idle(ActivitySpan::new(at + TimeDelta::minutes(1), at + TimeDelta::minutes(2))),
+                window_title: None,
+                app_name: None,
+            },
+        ]
+    }
+
+    fn export(dir: &Path, store: &ActivityStore, from: DateTime<Utc>, format: ExportFormat) -> (ExportSummary, String) {
+        let req = ExportRequest {
+            from,
+            to: from + TimeDelta::hours(1),
+            format,
+            include_media: false,
+            output: dir.join("export").join("activity.out"),
+        };
+        let summary = export_activity(store, dir, &req).unwrap();
+        let written = fs::read_to_string(&summary.path).unwrap();
+        (summary, written)
+    }
+
+    #[test]
+    fn csv_has_a_header_and_escapes_fields() {
+        let dir = temp_dir("csv");
+        let path = dir.join("rows.csv");
+        write_csv(File::create(&path).unwrap(), &records(minute()), &[]).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+
+        let mut lines = written.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "timestamp,kind,app_name,window_title,started_at,ended_at,duration_ms,key_count,\
+                 backspace_count,text,clicks_left,clicks_right,clicks_middle,clicks_other,\
+                 scroll_distance,mouse_distance_px,reason,media_path"
+            )
+        );
+
+        // Commas, quotes and newlines survive a round trip through a reader.
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][1], "input");
+        assert_eq!(&rows[0][3], "notes, draft");
+        assert_eq!(&rows[0][9], "say \"hi\", then\nleave");
+        assert_eq!(&rows[0][10], "2");
+        assert_eq!(&rows[1][1], "idle");
+        assert_eq!(&rows[1][2], "");
+        assert_eq!(&rows[1][6], "60000");
+        assert_eq!(&rows[1][9], "");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn jsonl_has_one_record_per_line() {
+        let dir = temp_dir("jsonl");
+        let store = ActivityStore::open(&dir.join("activity.sqlite")).unwrap();
+        let at = minute();
+        store.insert(&records(at)).unwrap();
+
+        let (summary, written) = export(&dir, &store, at, ExportFormat::Jsonl);
+        assert_eq!((summary.activities, summary.unreadable_records), (2, 0));
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["activity_type"]["kind"], "input");
+        assert_eq!(lines[0]["activity_type"]["text"], "say \"hi\", then\nleave");
+        assert_eq!(lines[0]["app_name"], "editor");
+        assert_eq!(lines[1]["activity_type"]["kind"], "idle");
+        assert!(lines[1].get("app_name").is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreadable_records_are_counted() {
+        let dir = temp_dir("unreadable");
+        let db = dir.join("activity.sqlite");
+        let store = ActivityStore::open(&db).unwrap();
+        let at = minute();
+        store.insert(&records(at)).unwrap();
+        rusqlite::Connection::open(&db)
+            .unwrap()
+            .execute(
+                "INSERT INTO activity_events (kind, start_ms, end_ms, payload) VALUES ('input', ?1, ?1, '{\"old\": true}')",
+                [at.timestamp_millis()],
+            )
+            .unwrap();
+
+        let (summary, written) = export(&dir, &store, at, ExportFormat::Csv);
+        assert_eq!((summary.activities, summary.unreadable_records), (2, 1));
+        // Header plus the two readable records.
+        assert_eq!(csv::Reader::from_reader(written.as_bytes()).records().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,213 @@
+// src/activity_platform/mod.rs
+
+//! Platform backends used by the activity service: which window currently has
+//! focus, and a stream of global keyboard and mouse events.
+//!
+//! On Windows and macOS these wrap `active-win-pos-rs` and `rdev`. On Linux
+//! those crates only work against X11 and silently return nothing on Wayland,
+//! so dedicated X11 and (best-effort) Wayland backends are used instead. When
+//! no backend can be brought up the reason is reported through
+//! [`capabilities`] rather than failing silently.
+
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod x11;
+
+use chrono::{DateTime, Utc};
+use rdev::{Button, Key};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub app_name: String,
+    pub title: String,
+}
+
+/// A backend-neutral input event. Keys and buttons reuse the `rdev` types so
+/// all backends can be consumed the same way.
+#[derive(Debug, Clone)]
+pub enum InputEvent {
+    /// `name` is the text the key produces, when the backend can tell.
+    KeyPress { key: Key, name: Option<String> },
+    ButtonPress(Button),
+    Wheel { delta_x: i64, delta_y: i64 },
+    /// Pointer position in screen pixels. Backends that only see relative
+    /// motion report an accumulated virtual position instead.
+    MouseMove { x: f64, y: f64 },
+}
+
+/// What the input hook hands to the aggregator: the event and when it came in.
+#[derive(Debug, Clone)]
+pub struct TimedInput {
+    pub at: DateTime<Utc>,
+    pub event: InputEvent,
+}
+
+pub trait ActiveWindowProvider: Send {
+    fn name(&self) -> &'static str;
+
+    /// Returns `Ok(None)` when no window has focus, e.g. the desktop.
+    fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>>;
+}
+
+pub trait InputEventSource: Send {
+    fn name(&self) -> &'static str;
+
+    /// Blocks the calling thread, delivering events to `callback` until the
+    /// underlying source fails.
+    fn run(self: Box<Self>, callback: Box<dyn FnMut(InputEvent) + Send>) -> anyhow::Result<()>;
+}
+
+/// Why a capability could not be provided on this machine.
+#[derive(Debug, Clone)]
+pub struct Unavailable(pub String);
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "capability not available: {}", self.0)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Capability {
+    Available { backend: String },
+    Unavailable { reason: String },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlatformCapabilities {
+    pub active_window: Capability,
+    pub input_events: Capability,
+}
+
+/// Opens the best active-window backend for the current session.
+pub fn window_provider() -> Result<Box<dyn ActiveWindowProvider>, Unavailable> {
+    #[cfg(target_os = "linux")]
+    {
+        match SessionType::detect() {
+            SessionType::Wayland => wayland::window_provider(),
+            SessionType::X11 => Ok(Box::new(x11::X11WindowProvider::connect()?)),
+            SessionType::Headless => Err(Unavailable(
+                "no graphical session (neither WAYLAND_DISPLAY nor DISPLAY is set)".into(),
+            )),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok(Box::new(NativeWindowProvider))
+    }
+}
+
+/// Opens the best global input backend for the current session.
+pub fn input_source() -> Result<Box<dyn InputEventSource>, Unavailable> {
+    #[cfg(target_os = "linux")]
+    {
+        match SessionType::detect() {
+            SessionType::Wayland => Ok(Box::new(wayland::EvdevInputSource::open()?)),
+            SessionType::X11 => Ok(Box::new(x11::X11InputSource::connect()?)),
+            SessionType::Headless => Err(Unavailable(
+                "no graphical session (neither WAYLAND_DISPLAY nor DISPLAY is set)".into(),
+            )),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok(Box::new(RdevInputSource))
+    }
+}
+
+/// Probes both backends and reports which ones are usable.
+pub fn capabilities() -> PlatformCapabilities {
+    PlatformCapabilities {
+        active_window: match window_provider() {
+            Ok(p) => Capability::Available { backend: p.name().to_string() },
+            Err(e) => Capability::Unavailable { reason: e.0 },
+        },
+        input_events: match input_source() {
+            Ok(s) => Capability::Available { backend: s.name().to_string() },
+            Err(e) => Capability::Unavailable { reason: e.0 },
+        },
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionType {
+    Wayland,
+    X11,
+    Headless,
+}
+
+#[cfg(target_os = "linux")]
+impl SessionType {
+    fn detect() -> Self {
+        let env_set = |name: &str| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+        let session = std::env::var("XDG_SESSION_TYPE").unwrap_or_default();
+
+        if session == "wayland" || env_set("WAYLAND_DISPLAY") {
+            SessionType::Wayland
+        } else if env_set("DISPLAY") {
+            SessionType::X11
+        } else {
+            SessionType::Headless
+        }
+    }
+}
+
+// --- Windows / macOS: active-win-pos-rs and rdev ---
+
+#[cfg(not(target_os = "linux"))]
+struct NativeWindowProvider;
+
+#[cfg(not(target_os = "linux"))]
+impl ActiveWindowProvider for NativeWindowProvider {
+    fn name(&self) -> &'static str {
+        "active-win-pos-rs"
+    }
+
+    fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>> {
+        match active_win_pos_rs::get_active_window() {
+            Ok(w) => Ok(Some(WindowInfo {
+                app_name: w.app_name,
+                title: w.title,
+            })),
+            Err(()) => Ok(None),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct RdevInputSource;
+
+#[cfg(not(target_os = "linux"))]
+impl InputEventSource for RdevInputSource {
+    fn name(&self) -> &'static str {
+        "rdev"
+    }
+
+    fn run(self: Box<Self>, mut callback: Box<dyn FnMut(InputEvent) + Send>) -> anyhow::Result<()> {
+        use rdev::EventType;
+
+        rdev::listen(move |event| {
+            let input = match event.event_type {
+                EventType::KeyPress(key) => InputEvent::KeyPress {
+                    key,
+                    name: event.name,
+                },
+                EventType::ButtonPress(button) => InputEvent::ButtonPress(button),
+                EventType::Wheel { delta_x, delta_y } => InputEvent::Wheel { delta_x, delta_y },
+                EventType::MouseMove { x, y } => InputEvent::MouseMove { x, y },
+                _ => return,
+            };
+            callback(input);
+        })
+        .map_err(|e| anyhow::anyhow!("rdev listen failed: {:?}", e))
+    }
+}
@@ -0,0 +1,344 @@
+// src/activity_platform/wayland.rs
+
+//! Best-effort Wayland backends. The core protocol deliberately exposes
+//! neither the focused window nor global input, so:
+//!
+//! * the focused window is asked from compositors that offer an IPC for it
+//!   (sway, Hyprland), falling back to XWayland, which only sees X11 clients;
+//! * input is read from `/dev/input/event*`, which requires the user to be in
+//!   the `input` group. Key names are not available this way, only counts.
+
+use super::{ActiveWindowProvider, InputEvent, InputEventSource, Unavailable, WindowInfo};
+use main_dashboard_spinup_lib::input_pipeline::Throttle;
+use rdev::{Button, Key};
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shortest time between two compositor queries. Each one forks a process,
+/// so asking more often returns the previous answer.
+const IPC_MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn window_provider() -> Result<Box<dyn ActiveWindowProvider>, Unavailable> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        return Ok(Box::new(CompositorIpcProvider::new(Compositor::Sway)));
+    }
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return Ok(Box::new(CompositorIpcProvider::new(Compositor::Hyprland)));
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        if let Ok(x11) = super::x11::X11WindowProvider::connect() {
+            return Ok(Box::new(XWaylandProvider(x11)));
+        }
+    }
+    Err(Unavailable(
+        "this Wayland compositor does not expose the focused window".into(),
+    ))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Compositor {
+    Sway,
+    Hyprland,
+}
+
+struct CompositorIpcProvider {
+    compositor: Compositor,
+    throttle: Throttle,
+    /// Answer of the last query, returned until the next one is due.
+    last: Option<WindowInfo>,
+}
+
+impl CompositorIpcProvider {
+    fn new(compositor: Compositor) -> Self {
+        Self {
+            compositor,
+            throttle: Throttle::new(IPC_MIN_INTERVAL),
+            last: None,
+        }
+    }
+
+    fn query(program: &str, args: &[&str]) -> anyhow::Result<Value> {
+        let output = Command::new(program).args(args).output()?;
+        if !output.status.success() {
+            anyhow::bail!("{} exited with {}", program, output.status);
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn focused_window(&self) -> anyhow::Result<Option<WindowInfo>> {
+        match self.compositor {
+            Compositor::Sway => {
+                let tree = Self::query("swaymsg", &["-t", "get_tree", "-r"])?;
+                Ok(find_focused_sway_node(&tree).map(|node| WindowInfo {
+                    app_name: node["app_id"]
+                        .as_str()
+                        .or_else(|| node["window_properties"]["class"].as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    title: node["name"].as_str().unwrap_or_default().to_string(),
+                }))
+            }
+            Compositor::Hyprland => {
+                let window = Self::query("hyprctl", &["activewindow", "-j"])?;
+                let title = window["title"].as_str().unwrap_or_default();
+                let class = window["class"].as_str().unwrap_or_default();
+                if title.is_empty() && class.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(WindowInfo {
+                    app_name: class.to_string(),
+                    title: title.to_string(),
+                }))
+            }
+        }
+    }
+}
+
+impl ActiveWindowProvider for CompositorIpcProvider {
+    fn name(&self) -> &'static str {
+        match self.compositor {
+            Compositor::Sway => "wayland-sway-ipc",
+            Compositor::Hyprland => "wayland-hyprland-ipc",
+        }
+    }
+
+    fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>> {
+        if self.throttle.ready(Instant::now()) {
+            self.last = self.focused_window()?;
+        }
+        Ok(self.last.clone())
+    }
+}
+
+/// Depth-first search for the focused leaf in a `swaymsg -t get_tree` dump.
+fn find_focused_sway_node(node: &Value) -> Option<&Value> {
+    if node["focused"].as_bool() == Some(true) && node["type"] != "workspace" {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[*key].as_array())
+        .flatten()
+        .find_map(find_focused_sway_node)
+}
+
+struct XWaylandProvider(super::x11::X11WindowProvider);
+
+impl ActiveWindowProvider for XWaylandProvider {
+    fn name(&self) -> &'static str {
+        "wayland-xwayland-ewmh"
+    }
+
+    fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>> {
+        self.0.active_window()
+    }
+}
+
+// --- evdev input ---
+
+// Event types and codes from linux/input-event-codes.h.
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const KEY_BACKSPACE: u16 = 14;
+const KEY_TAB: u16 = 15;
+const KEY_ENTER: u16 = 28;
+const KEY_SPACE: u16 = 57;
+const KEY_KPENTER: u16 = 96;
+const BTN_MISC: u16 = 0x100;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_TASK: u16 = 0x117;
+
+/// `struct input_event`: a `timeval` followed by type, code and value.
+#[cfg(target_pointer_width = "64")]
+const INPUT_EVENT_SIZE: usize = 24;
+#[cfg(not(target_pointer_width = "64"))]
+const INPUT_EVENT_SIZE: usize = 16;
+
+pub struct EvdevInputSource {
+    devices: Vec<File>,
+}
+
+impl EvdevInputSource {
+    pub fn open() -> Result<Self, Unavailable> {
+        let entries = std::fs::read_dir("/dev/input")
+            .map_err(|e| Unavailable(format!("cannot read /dev/input: {}", e)))?;
+
+        let devices: Vec<File> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("event"))
+            })
+            .filter_map(|p| File::open(p).ok())
+            .collect();
+
+        if devices.is_empty() {
+            return Err(Unavailable(
+                "no readable /dev/input/event* devices; add the user to the `input` group".into(),
+            ));
+        }
+        Ok(Self { devices })
+    }
+}
+
+impl InputEventSource for EvdevInputSource {
+    fn name(&self) -> &'static str {
+        "wayland-evdev"
+    }
+
+    fn run(self: Box<Self>, mut callback: Box<dyn FnMut(InputEvent) + Send>) -> anyhow::Result<()> {
+        // One blocking reader per device, funnelled into a single channel.
+        let (tx, rx) = mpsc::channel::<(u16, u16, i32)>();
+        for mut device in self.devices {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; INPUT_EVENT_SIZE];
+                while device.read_exact(&mut buf).is_ok() {
+                    let at = INPUT_EVENT_SIZE - 8;
+                    let type_ = u16::from_ne_bytes([buf[at], buf[at + 1]]);
+                    let code = u16::from_ne_bytes([buf[at + 2], buf[at + 3]]);
+                    let value = i32::from_ne_bytes([buf[at + 4], buf[at + 5], buf[at + 6], buf[at + 7]]);
+                    if tx.send((type_, code, value)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // evdev mice only report relative motion; track a virtual position.
+        let mut pointer = (0.0, 0.0);
+        for (type_, code, value) in rx {
+            if let Some(input) = decode(type_, code, value, &mut pointer) {
+                callback(input);
+            }
+        }
+        anyhow::bail!("all input devices were closed")
+    }
+}
+
+/// Turns one evdev event into an `InputEvent`, adding relative motion to
+/// the virtual `pointer` position. `None` for events that are not counted.
+fn decode(type_: u16, code: u16, value: i32, pointer: &mut (f64, f64)) -> Option<InputEvent> {
+    Some(match (type_, code) {
+        // value: 1 = press, 2 = autorepeat, 0 = release
+        (EV_KEY, BTN_LEFT) if value == 1 => InputEvent::ButtonPress(Button::Left),
+        (EV_KEY, BTN_RIGHT) if value == 1 => InputEvent::ButtonPress(Button::Right),
+        (EV_KEY, BTN_MIDDLE) if value == 1 => InputEvent::ButtonPress(Button::Middle),
+        (EV_KEY, c @ BTN_LEFT..=BTN_TASK) if value == 1 => {
+            InputEvent::ButtonPress(Button::Unknown((c - BTN_LEFT + 1) as u8))
+        }
+        // Codes from BTN_MISC up are buttons of other devices (joysticks,
+        // tablets, touchscreens), not keys.
+        (EV_KEY, c) if value == 1 && c < BTN_MISC => InputEvent::KeyPress {
+            key: match c {
+                KEY_ENTER => Key::Return,
+                KEY_KPENTER => Key::KpReturn,
+                KEY_SPACE => Key::Space,
+                KEY_TAB => Key::Tab,
+                KEY_BACKSPACE => Key::Backspace,
+                other => Key::Unknown(other as u32),
+            },
+            name: None,
+        },
+        (EV_REL, REL_X) => {
+            pointer.0 += value as f64;
+            InputEvent::MouseMove { x: pointer.0, y: pointer.1 }
+        }
+        (EV_REL, REL_Y) => {
+            pointer.1 += value as f64;
+            InputEvent::MouseMove { x: pointer.0, y: pointer.1 }
+        }
+        (EV_REL, REL_WHEEL) => InputEvent::Wheel { delta_x: 0, delta_y: value as i64 },
+        (EV_REL, REL_HWHEEL) => InputEvent::Wheel { delta_x: value as i64, delta_y: 0 },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_one(type_: u16, code: u16, value: i32) -> Option<InputEvent> {
+        decode(type_, code, value, &mut (0.0, 0.0))
+    }
+
+    #[test]
+    fn key_presses_count_once() {
+        assert!(matches!(
+            decode_one(EV_KEY, KEY_ENTER, 1),
+            Some(InputEvent::KeyPress { key: Key::Return, name: None })
+        ));
+        assert!(matches!(
+            decode_one(EV_KEY, 30, 1),
+            Some(InputEvent::KeyPress { key: Key::Unknown(30), .. })
+        ));
+        // Release and autorepeat.
+        assert!(decode_one(EV_KEY, 30, 0).is_none());
+        assert!(decode_one(EV_KEY, 30, 2).is_none());
+    }
+
+    #[test]
+    fn misc_and_other_device_buttons_are_not_keys() {
+        // BTN_0..BTN_9 and the rest of the BTN_MISC range.
+        for code in BTN_MISC..BTN_LEFT {
+            assert!(decode_one(EV_KEY, code, 1).is_none(), "code {:#x}", code);
+        }
+        // BTN_JOYSTICK, BTN_GAMEPAD, BTN_TOUCH.
+        for code in [0x120, 0x130, 0x14a] {
+            assert!(decode_one(EV_KEY, code, 1).is_none(), "code {:#x}", code);
+        }
+    }
+
+    #[test]
+    fn mouse_buttons_and_wheel() {
+        assert!(matches!(decode_one(EV_KEY, BTN_LEFT, 1), Some(InputEvent::ButtonPress(Button::Left))));
+        assert!(matches!(decode_one(EV_KEY, BTN_RIGHT, 1), Some(InputEvent::ButtonPress(Button::Right))));
+        assert!(matches!(decode_one(EV_KEY, BTN_MIDDLE, 1), Some(InputEvent::ButtonPress(Button::Middle))));
+        assert!(matches!(decode_one(EV_KEY, BTN_TASK, 1), Some(InputEvent::ButtonPress(Button::Unknown(8)))));
+        assert!(matches!(
+            decode_one(EV_REL, REL_WHEEL, -1),
+            Some(InputEvent::Wheel { delta_x: 0, delta_y: -1 })
+        ));
+        assert!(matches!(
+            decode_one(EV_REL, REL_HWHEEL, 2),
+            Some(InputEvent::Wheel { delta_x: 2, delta_y: 0 })
+        ));
+    }
+
+    #[test]
+    fn relative_motion_accumulates() {
+        let mut pointer = (0.0, 0.0);
+        decode(EV_REL, REL_X, 5, &mut pointer);
+        decode(EV_REL, REL_Y, -3, &mut pointer);
+        let moved = decode(EV_REL, REL_X, 2, &mut pointer);
+        assert!(matches!(moved, Some(InputEvent::MouseMove { x, y }) if x == 7.0 && y == -3.0));
+    }
+
+    #[test]
+    fn focused_sway_node_skips_workspaces() {
+        let tree: Value = serde_json::from_str(
+            r#"{"type": "root", "nodes": [
+                {"type": "workspace", "focused": true, "nodes": []},
+                {"type": "workspace", "nodes": [], "floating_nodes": [
+                    {"type": "floating_con", "focused": true, "app_id": "foot", "name": "shell"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(find_focused_sway_node(&tree).unwrap()["app_id"], "foot");
+    }
+}
@@ -0,0 +1,444 @@
+// src/activity_platform/x11.rs
+
+//! X11 backends. The focused window comes from the EWMH `_NET_ACTIVE_WINDOW`
+//! property on the root window; input comes from XInput2 raw events, which
+//! are delivered for the whole screen without grabbing anything. Both only
+//! need a reachable `$DISPLAY`, so they also run under Xvfb.
+
+use super::{ActiveWindowProvider, InputEvent, InputEventSource, Unavailable, WindowInfo};
+use rdev::{Button, Key};
+use x11rb::connection::Connection;
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+// Keysyms we need to recognise (from X11/keysymdef.h).
+const XK_BACKSPACE: u32 = 0xff08;
+const XK_TAB: u32 = 0xff09;
+const XK_RETURN: u32 = 0xff0d;
+const XK_KP_ENTER: u32 = 0xff8d;
+const XK_SPACE: u32 = 0x0020;
+const XK_SHIFT_L: u32 = 0xffe1;
+const XK_SHIFT_R: u32 = 0xffe2;
+
+/// Connects to `display`, or `$DISPLAY` for `None`.
+fn connect(display: Option<&str>) -> Result<(RustConnection, Window), Unavailable> {
+    let (conn, screen_num) = x11rb::connect(display)
+        .map_err(|e| Unavailable(format!("cannot connect to X server: {}", e)))?;
+    let root = conn.setup().roots[screen_num].root;
+    Ok((conn, root))
+}
+
+fn intern(conn: &RustConnection, name: &str) -> Result<Atom, Unavailable> {
+    conn.intern_atom(false, name.as_bytes())
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map(|reply| reply.atom)
+        .ok_or_else(|| Unavailable(format!("cannot intern atom {}", name)))
+}
+
+pub struct X11WindowProvider {
+    conn: RustConnection,
+    /// Window holding `active_window`: the root window outside tests.
+    holder: Window,
+    /// Property naming the focused window: `_NET_ACTIVE_WINDOW` outside tests.
+    active_window: Atom,
+    net_wm_name: Atom,
+    net_wm_pid: Atom,
+    utf8_string: Atom,
+}
+
+impl X11WindowProvider {
+    pub fn connect() -> Result<Self, Unavailable> {
+        let (conn, root) = connect(None)?;
+        let net_active_window = intern(&conn, "_NET_ACTIVE_WINDOW")?;
+        Self::watching(conn, root, net_active_window)
+    }
+
+    /// Follows the window named by the `active_window` property of `holder`.
+    fn watching(conn: RustConnection, holder: Window, active_window: Atom) -> Result<Self, Unavailable> {
+        let provider = Self {
+            holder,
+            active_window,
+            net_wm_name: intern(&conn, "_NET_WM_NAME")?,
+            net_wm_pid: intern(&conn, "_NET_WM_PID")?,
+            utf8_string: intern(&conn, "UTF8_STRING")?,
+            conn,
+        };
+
+        // Without an EWMH-compliant window manager the root property is never
+        // set, and every lookup would quietly return "no window".
+        let supported = provider
+            .conn
+            .get_property(false, provider.holder, provider.active_window, AtomEnum::WINDOW, 0, 1)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some_and(|reply| reply.type_ != u32::from(AtomEnum::NONE));
+        if !supported {
+            return Err(Unavailable(
+                "the window manager does not publish _NET_ACTIVE_WINDOW".into(),
+            ));
+        }
+        Ok(provider)
+    }
+
+    fn property(&self, window: Window, property: Atom, type_: Atom) -> anyhow::Result<Vec<u8>> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, u32::MAX / 4)?
+            .reply()?;
+        Ok(reply.value)
+    }
+
+    fn window_title(&self, window: Window) -> anyhow::Result<String> {
+        let utf8 = self.property(window, self.net_wm_name, self.utf8_string)?;
+        if !utf8.is_empty() {
+            return Ok(String::from_utf8_lossy(&utf8).into_owned());
+        }
+        // Legacy WM_NAME is Latin-1.
+        let latin1 = self.property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?;
+        Ok(latin1.iter().map(|&b| b as char).collect())
+    }
+
+    fn app_name(&self, window: Window) -> anyhow::Result<String> {
+        // WM_CLASS holds two NUL-terminated strings: instance, then class.
+        let class = self.property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+        if let Some(name) = class
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .nth(1)
+        {
+            return Ok(String::from_utf8_lossy(name).into_owned());
+        }
+
+        let pid = self
+            .conn
+            .get_property(false, window, self.net_wm_pid, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut v| v.next());
+        Ok(pid
+            .and_then(|pid| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok())
+            .map(|comm| comm.trim().to_string())
+            .unwrap_or_default())
+    }
+}
+
+impl ActiveWindowProvider for X11WindowProvider {
+    fn name(&self) -> &'static str {
+        "x11-ewmh"
+    }
+
+    fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>> {
+        let window = self
+            .conn
+            .get_property(false, self.holder, self.active_window, AtomEnum::WINDOW, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut v| v.next())
+            .unwrap_or(x11rb::NONE);
+        if window == x11rb::NONE {
+            return Ok(None);
+        }
+
+        Ok(Some(WindowInfo {
+            app_name: self.app_name(window)?,
+            title: self.window_title(window)?,
+        }))
+    }
+}
+
+pub struct X11InputSource {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl X11InputSource {
+    pub fn connect() -> Result<Self, Unavailable> {
+        Self::connect_to(None)
+    }
+
+    fn connect_to(display: Option<&str>) -> Result<Self, Unavailable> {
+        let (conn, root) = connect(display)?;
+        // Raw events are delivered to the root window regardless of grabs
+        // from XInput 2.1 onwards.
+        let version = conn
+            .xinput_xi_query_version(2, 2)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .ok_or_else(|| Unavailable("the X server has no XInput extension".into()))?;
+        if (version.major_version, version.minor_version) < (2, 1) {
+            return Err(Unavailable(format!(
+                "XInput {}.{} is too old, 2.1 or newer is required",
+                version.major_version, version.minor_version
+            )));
+        }
+        Ok(Self { conn, root })
+    }
+}
+
+impl InputEventSource for X11InputSource {
+    fn name(&self) -> &'static str {
+        "x11-xinput2"
+    }
+
+    fn run(self: Box<Self>, mut callback: Box<dyn FnMut(InputEvent) + Send>) -> anyhow::Result<()> {
+        let conn = &self.conn;
+        conn.xinput_xi_select_events(
+            self.root,
+            &[xinput::EventMask {
+                deviceid: xinput::Device::ALL_MASTER.into(),
+                mask: vec![
+                    xinput::XIEventMask::RAW_KEY_PRESS
+                        | xinput::XIEventMask::RAW_KEY_RELEASE
+                        | xinput::XIEventMask::RAW_BUTTON_PRESS
+                        | xinput::XIEventMask::RAW_MOTION,
+                ],
+            }],
+        )?
+        .check()?;
+
+        let keymap = Keymap::load(conn)?;
+        let mut shift_down = false;
+
+        loop {
+            let input = match conn.wait_for_event()? {
+                Event::XinputRawKeyPress(e) => {
+                    let keycode = e.detail as u8;
+                    let keysym = keymap.keysym(keycode, shift_down);
+                    if keysym == XK_SHIFT_L || keysym == XK_SHIFT_R {
+                        shift_down = true;
+                    }
+                    InputEvent::KeyPress {
+                        key: key_from_keysym(keysym, keycode),
+                        name: keysym_to_char(keysym).map(String::from),
+                    }
+                }
+                Event::XinputRawKeyRelease(e) => {
+                    let keysym = keymap.keysym(e.detail as u8, false);
+                    if keysym == XK_SHIFT_L || keysym == XK_SHIFT_R {
+                        shift_down = false;
+                    }
+                    continue;
+                }
+                Event::XinputRawButtonPress(e) => match e.detail {
+                    1 => InputEvent::ButtonPress(Button::Left),
+                    2 => InputEvent::ButtonPress(Button::Middle),
+                    3 => InputEvent::ButtonPress(Button::Right),
+                    // Buttons 4-7 are the scroll wheel, matching rdev's signs.
+                    4 => InputEvent::Wheel { delta_x: 0, delta_y: 1 },
+                    5 => InputEvent::Wheel { delta_x: 0, delta_y: -1 },
+                    6 => InputEvent::Wheel { delta_x: -1, delta_y: 0 },
+                    7 => InputEvent::Wheel { delta_x: 1, delta_y: 0 },
+                    n => InputEvent::ButtonPress(Button::Unknown(n as u8)),
+                },
+                Event::XinputRawMotion(_) => {
+                    // Raw motion carries device deltas only; ask for the
+                    // resulting absolute position.
+                    let pointer = conn.query_pointer(self.root)?.reply()?;
+                    InputEvent::MouseMove {
+                        x: pointer.root_x as f64,
+                        y: pointer.root_y as f64,
+                    }
+                }
+                _ => continue,
+            };
+            callback(input);
+        }
+    }
+}
+
+/// Keycode to keysym table fetched once from the server.
+struct Keymap {
+    min_keycode: u8,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<u32>,
+}
+
+impl Keymap {
+    fn load(conn: &RustConnection) -> anyhow::Result<Self> {
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let reply = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+        Ok(Self {
+            min_keycode,
+            keysyms_per_keycode: reply.keysyms_per_keycode as usize,
+            keysyms: reply.keysyms,
+        })
+    }
+
+    fn keysym(&self, keycode: u8, shifted: bool) -> u32 {
+        if keycode < self.min_keycode || self.keysyms_per_keycode == 0 {
+            return 0;
+        }
+        let base = (keycode - self.min_keycode) as usize * self.keysyms_per_keycode;
+        let column = if shifted && self.keysyms_per_keycode > 1 { 1 } else { 0 };
+        match self.keysyms.get(base + column).copied() {
+            // No shifted symbol defined: fall back to the unshifted one.
+            Some(0) if column == 1 => self.keysyms.get(base).copied().unwrap_or(0),
+            Some(keysym) => keysym,
+            None => 0,
+        }
+    }
+}
+
+fn key_from_keysym(keysym: u32, keycode: u8) -> Key {
+    match keysym {
+        XK_RETURN => Key::Return,
+        XK_KP_ENTER => Key::KpReturn,
+        XK_SPACE => Key::Space,
+        XK_TAB => Key::Tab,
+        XK_BACKSPACE => Key::Backspace,
+        XK_SHIFT_L => Key::ShiftLeft,
+        XK_SHIFT_R => Key::ShiftRight,
+        _ => Key::Unknown(keycode as u32),
+    }
+}
+
+/// Printable character for a keysym. Latin-1 keysyms equal their code point;
+/// keysyms with the 0x0100_0000 bit set encode a Unicode code point directly.
+fn keysym_to_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x0021..=0x007e | 0x00a0..=0x00ff => char::from_u32(keysym),
+        0x0100_0100..=0x0110_ffff => char::from_u32(keysym - 0x0100_0000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    /// A private Xvfb server, so the tests never touch the desktop they run
+    /// on. Stopped on drop.
+    struct Xvfb {
+        process: Child,
+        display: String,
+    }
+
+    impl Xvfb {
+        /// `None` if Xvfb is not installed; the tests needing it are skipped.
+        fn start() -> Option<Self> {
+            let mut process = Command::new("Xvfb")
+                .args(["-displayfd", "1", "-nolisten", "tcp"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            // Xvfb picks a free display and prints its number once it is ready.
+            let mut number = String::new();
+            BufReader::new(process.stdout.take()?).read_line(&mut number).ok()?;
+            let display = format!(":{}", number.trim());
+            Some(Self { process, display })
+        }
+    }
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    fn create_window(conn: &RustConnection, root: Window) -> Window {
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            10,
+            10,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        window
+    }
+
+    #[test]
+    fn active_window_follows_the_active_window_property() {
+        let Some(xvfb) = Xvfb::start() else {
+            return;
+        };
+        let (conn, root) = connect(Some(&xvfb.display)).unwrap();
+        let atom = |name| intern(&conn, name).unwrap();
+
+        // Stand in for the window manager, which Xvfb does not have, with a
+        // window and property of our own rather than the root's.
+        let holder = create_window(&conn, root);
+        let active_window = atom("_TEST_ACTIVE_WINDOW");
+        let window = create_window(&conn, root);
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, b"notes\0Notes\0")
+            .unwrap();
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_NAME, AtomEnum::STRING, b"caf\xe9")
+            .unwrap();
+        conn.change_property32(PropMode::REPLACE, holder, active_window, AtomEnum::WINDOW, &[window])
+            .unwrap();
+        conn.get_input_focus().unwrap().reply().unwrap();
+
+        let (provider_conn, _) = connect(Some(&xvfb.display)).unwrap();
+        let mut provider = X11WindowProvider::watching(provider_conn, holder, active_window).unwrap();
+        // Legacy Latin-1 WM_NAME while there is no _NET_WM_NAME.
+        assert_eq!(
+            provider.active_window().unwrap(),
+            Some(WindowInfo {
+                app_name: "Notes".into(),
+                title: "café".into(),
+            })
+        );
+
+        conn.change_property8(PropMode::REPLACE, window, atom("_NET_WM_NAME"), atom("UTF8_STRING"), "naïve — ok".as_bytes())
+            .unwrap();
+        conn.get_input_focus().unwrap().reply().unwrap();
+        assert_eq!(provider.active_window().unwrap().unwrap().title, "naïve — ok");
+
+        conn.change_property32(PropMode::REPLACE, holder, active_window, AtomEnum::WINDOW, &[x11rb::NONE])
+            .unwrap();
+        conn.get_input_focus().unwrap().reply().unwrap();
+        assert_eq!(provider.active_window().unwrap(), None);
+    }
+
+    #[test]
+    fn window_provider_needs_the_active_window_property() {
+        let Some(xvfb) = Xvfb::start() else {
+            return;
+        };
+        let (conn, root) = connect(Some(&xvfb.display)).unwrap();
+        let unset = intern(&conn, "_TEST_ACTIVE_WINDOW").unwrap();
+        assert!(X11WindowProvider::watching(conn, root, unset).is_err());
+    }
+
+    #[test]
+    fn input_source_needs_xinput_2_1() {
+        let Some(xvfb) = Xvfb::start() else {
+            return;
+        };
+        let source = X11InputSource::connect_to(Some(&xvfb.display)).unwrap();
+        assert_eq!(source.name(), "x11-xinput2");
+    }
+
+    #[test]
+    fn keysyms_map_to_keys_and_text() {
+        assert_eq!(key_from_keysym(XK_RETURN, 36), Key::Return);
+        assert_eq!(key_from_keysym(XK_SHIFT_L, 50), Key::ShiftLeft);
+        assert_eq!(key_from_keysym(0x0061, 38), Key::Unknown(38));
+
+        assert_eq!(keysym_to_char(0x0061), Some('a'));
+        assert_eq!(keysym_to_char(0x00e9), Some('é'));
+        assert_eq!(keysym_to_char(0x0100_20ac), Some('€'));
+        // Space and control keys produce no text of their own.
+        assert_eq!(keysym_to_char(XK_SPACE), None);
+        assert_eq!(keysym_to_char(XK_RETURN), None);
+    }
+}
@@ -1,57 +1,168 @@
 // src/activity_service.rs
 
-use chrono::{Datelike, Utc};
+use chrono::{DateTime, Datelike, Utc};
+use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
     fs,
     io::{Read, Write},
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
     thread,
     time::Duration,
 };
 use tauri::AppHandle;
 use tauri::Manager;
-use rdev::{listen, EventType, Key};
-use active_win_pos_rs::get_active_window;
 use uuid::Uuid;
 // NEW: Import reqwest for making API calls
 use reqwest::blocking::Client;
-use super::MainAppState;
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use super::{capture_blocked_reason, MainAppState};
+use super::activity_platform::{self, PlatformCapabilities, TimedInput};
+use super::input_aggregator::{self, InputAggregator, InputBuckets, InputStats};
+use super::activity_store::ActivityStore;
+use super::remote_policy;
+use super::service_events::{self, UploadQueue};
+use super::service_supervisor::{Health, MonitoringService, ServiceHandle, ServiceSupervisor};
+use main_dashboard_spinup_lib::input_pipeline::{self, InputConsumer};
+
+/// Version of the `LogData` document layout. Bump this whenever `ActivityMeta`
+/// or `ActivityType` change shape so the server can pick the matching schema.
+/// Version 1 was the untyped `details: String` format, version 2 had separate
+/// unattributed click and scroll counters, version 3 had no pause records.
+pub const ACTIVITY_SCHEMA_VERSION: u32 = 4;
+
+/// Typed payload of a single activity record. Serialized with an internal
+/// `kind` tag next to the variant's fields, e.g.
+/// `{"kind": "idle", "started_at": "2026-01-05T09:12:00Z", "ended_at": "2026-01-05T09:15:30Z", "duration_ms": 210000}`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum ActivityType {
-    KeyboardInput,
-    MouseClick,
-    MouseScroll,
-    WindowFocus,
-    BrowserActivity,
+    Input(InputStats),
+    WindowFocus(ActivitySpan),
+    BrowserActivity(ActivitySpan),
+    Idle(ActivitySpan),
+    Paused(PauseStarted),
+    Resumed(PauseEnded),
+}
+
+impl ActivityType {
+    /// The serialized `kind` tag of this variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ActivityType::Input(_) => "input",
+            ActivityType::WindowFocus(_) => "window_focus",
+            ActivityType::BrowserActivity(_) => "browser_activity",
+            ActivityType::Idle(_) => "idle",
+            ActivityType::Paused(_) => "paused",
+            ActivityType::Resumed(_) => "resumed",
+        }
+    }
+}
+
+/// A closed time interval, used for focus and idle periods.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ActivitySpan {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_ms: i64,
+}
+
+impl ActivitySpan {
+    pub fn new(started_at: DateTime<Utc>, ended_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            ended_at,
+            duration_ms: (ended_at - started_at).num_milliseconds().max(0),
+        }
+    }
+}
+
+/// The user paused monitoring for a private break.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PauseStarted {
+    pub reason: String,
+    /// When monitoring resumes unless resumed earlier by hand.
+    pub resume_at: DateTime<Utc>,
+}
+
+/// Monitoring resumed after a pause.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PauseEnded {
+    pub reason: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_ms: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct ActivityMeta {
-    pub timestamp: String,
+    /// When the record was produced (RFC3339, UTC).
+    pub timestamp: DateTime<Utc>,
     pub activity_type: ActivityType,
-    pub details: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub window_title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub app_name: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct LogData {
+    pub schema_version: u32,
     pub activities: Vec<ActivityMeta>,
 }
 
+impl LogData {
+    pub fn new(activities: Vec<ActivityMeta>) -> Self {
+        Self {
+            schema_version: ACTIVITY_SCHEMA_VERSION,
+            activities,
+        }
+    }
+}
+
+impl Default for LogData {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// JSON Schema (draft 7) describing the uploaded `LogData` documents.
+pub fn activity_log_schema() -> serde_json::Value {
+    serde_json::to_value(schema_for!(LogData)).unwrap_or_default()
+}
+
 #[derive(Clone)]
 pub struct ActivityLoggerState {
     pub is_activity_logging_running: Arc<Mutex<bool>>,
     pub meta_lock: Arc<Mutex<()>>,
-    pub keystroke_buffer: Arc<Mutex<HashMap<String, String>>>,
-    pub mouse_click_count: Arc<Mutex<u32>>,
-    pub mouse_scroll_count: Arc<Mutex<u32>>,
+    pub input_buckets: Arc<Mutex<InputBuckets>>,
+    pub last_input_at: Arc<Mutex<DateTime<Utc>>>,
+    /// Notified together with `is_activity_logging_running` on start and stop
+    /// so the service threads wake up immediately instead of finishing a sleep.
+    pub wake: Arc<Condvar>,
+    /// Bumped on every start. Threads from an earlier start exit once it moves on,
+    /// so a quick stop/start never leaves two monitors running.
+    pub generation: Arc<AtomicU64>,
+    /// The OS input hook cannot be removed once installed, so it is started
+    /// once and simply ignores events while the service is stopped.
+    pub input_listener_started: Arc<AtomicBool>,
+}
+
+impl ActivityLoggerState {
+    /// Waits up to `timeout`. Returns `false` early once the service has been
+    /// stopped or restarted under a newer generation.
+    fn wait_while_running(&self, generation: u64, timeout: Duration) -> bool {
+        let current = || self.generation.load(Ordering::SeqCst) == generation;
+        let running = self.is_activity_logging_running.lock().unwrap();
+        let (running, _) = self
+            .wake
+            .wait_timeout_while(running, timeout, |running| *running && current())
+            .unwrap();
+        *running && current()
+    }
 }
 
 // MODIFIED: This function now points to the "pending" directory.
@@ -88,7 +199,7 @@ fn try_upload_activity_file(client: &Client, filepath: &PathBuf) -> Result<(), B
     let response = client.post(url).multipart(form).timeout(Duration::from_secs(15)).send()?;
     
     if response.status().is_success() {
-        println!("[API SUCCESS] Uploaded activity log: {}", filename);
+        log::info!("[API SUCCESS] Uploaded activity log: {}", filename);
         Ok(())
     } else {
         Err(format!("API Error for {}: {} - {}", filename, response.status(), response.text().unwrap_or_default()).into())
@@ -98,6 +209,7 @@ fn try_upload_activity_file(client: &Client, filepath: &PathBuf) -> Result<(), B
 // NEW: Logic to save activities to a file, then immediately try to upload it.
 /// Saves activities to a file and then attempts to upload it, deleting on success.
 fn save_and_try_upload(
+    app: &AppHandle,
     client: &Client,
     pending_dir: &PathBuf,
     activities: Vec<ActivityMeta>,
@@ -113,37 +225,40 @@ fn save_and_try_upload(
     let filename = format!("activity_{}_{}.json", timestamp, unique_id);
     let filepath = today_dir.join(&filename);
 
-    let log_data = LogData { activities };
+    let log_data = LogData::new(activities);
     let json = serde_json::to_vec_pretty(&log_data)?;
     
     let mut f = fs::File::create(&filepath)?;
     f.write_all(&json)?;
     f.sync_all()?;
-    println!("[SAVE] Saved pending activity log: {}", filepath.display());
+    log::info!("[SAVE] Saved pending activity log: {}", filepath.display());
     
     // Drop the file lock before making the network request
     drop(_guard);
 
-    match try_upload_activity_file(client, &filepath) {
+    let result = try_upload_activity_file(client, &filepath);
+    service_events::report_upload_result(app, UploadQueue::ActivityLogs, &filepath, &result);
+    match result {
         Ok(_) => {
             if let Err(e) = fs::remove_file(&filepath) {
-                eprintln!("[DELETE FAILED] Could not delete successfully uploaded log {}: {}", filepath.display(), e);
+                log::error!("[DELETE FAILED] Could not delete successfully uploaded log {}: {}", filepath.display(), e);
             } else {
-                println!("[DELETE SUCCESS] Deleted uploaded log: {}", filename);
+                log::info!("[DELETE SUCCESS] Deleted uploaded log: {}", filename);
             }
         }
         Err(e) => {
-            println!("[UPLOAD FAILED] Kept log on disk: {} - {}", filename, e);
+            log::warn!("[UPLOAD FAILED] Kept log on disk: {} - {}", filename, e);
         }
     }
+    service_events::update_queue_depth(app, UploadQueue::ActivityLogs);
 
     Ok(())
 }
 
 // NEW: The retry logic, adapted from the screenshot service.
 /// Scans the pending directory and tries to re-upload any found log files.
-pub fn retry_all_pending_activities(client: &Client, base_dir: &PathBuf) {
-    println!("\n[RETRY] ===== ACTIVITY RETRY CYCLE STARTED =====");
+pub fn retry_all_pending_activities(app: &AppHandle, client: &Client, base_dir: &PathBuf) {
+    log::info!("[RETRY] ===== ACTIVITY RETRY CYCLE STARTED =====");
     let date_dirs = match fs::read_dir(base_dir) {
         Ok(dirs) => dirs,
         Err(_) => { /* Folder might not exist yet, which is fine */ return; }
@@ -162,225 +277,369 @@ pub fn retry_all_pending_activities(client: &Client, base_dir: &PathBuf) {
             let file_path = file_entry.path();
             if file_path.extension().and_then(|s| s.to_str()) != Some("json") { continue; }
 
-            match try_upload_activity_file(client, &file_path) {
+            let result = try_upload_activity_file(client, &file_path);
+            service_events::report_upload_result(app, UploadQueue::ActivityLogs, &file_path, &result);
+            match result {
                 Ok(_) => {
                     if let Err(e) = fs::remove_file(&file_path) {
-                        eprintln!("[RETRY DELETE FAILED] Could not delete {}: {}", file_path.display(), e);
+                        log::error!("[RETRY DELETE FAILED] Could not delete {}: {}", file_path.display(), e);
                     } else {
-                        println!("[RETRY DELETE SUCCESS] Deleted: {}", file_path.display());
+                        log::info!("[RETRY DELETE SUCCESS] Deleted: {}", file_path.display());
                     }
                 }
                 Err(e) => {
-                    eprintln!("[RETRY UPLOAD FAILED] for {}: {}", file_path.display(), e);
+                    log::error!("[RETRY UPLOAD FAILED] for {}: {}", file_path.display(), e);
                 }
             }
         }
     }
-    println!("[RETRY] ===== ACTIVITY RETRY CYCLE ENDED =====\n");
+    service_events::update_queue_depth(app, UploadQueue::ActivityLogs);
+    log::info!("[RETRY] ===== ACTIVITY RETRY CYCLE ENDED =====");
 }
 
 
-/// Keyboard and mouse monitoring thread (No changes needed)
+/// Input events buffered between the hook and the aggregator. At a few
+/// hundred events per second this covers several seconds of a stalled
+/// aggregator before anything is dropped.
+const INPUT_QUEUE_CAPACITY: usize = 4096;
+
+/// The active window is looked up at most this often. Input in between is
+/// attributed to the last window seen.
+const WINDOW_RESOLVE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs the input hook. The hook callback only timestamps each event and
+/// queues it; window lookups and bucket updates happen on a separate
+/// aggregator thread so the OS input thread is never held up.
 fn run_input_monitor(state: ActivityLoggerState) {
-    // ... This function remains exactly the same ...
-    let is_running = state.is_activity_logging_running.clone();
-    let keystroke_buffer = state.keystroke_buffer.clone();
-    let mouse_click_count = state.mouse_click_count.clone();
-    let mouse_scroll_count = state.mouse_scroll_count.clone();
-
-    if let Err(error) = listen(move |event| {
-        if !*is_running.lock().unwrap() {
+    let source = match activity_platform::input_source() {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("Input monitor disabled: {}", e);
             return;
         }
-        
-        match event.event_type {
-            EventType::KeyPress(key) => {
-                if let Ok(active_window) = get_active_window() {
-                    let mut buffer = keystroke_buffer.lock().unwrap();
-                    let entry = buffer.entry(active_window.title).or_insert_with(String::new);
-
-                    match key {
-                        Key::Return | Key::KpReturn => *entry += "\n",
-                        Key::Space => *entry += " ",
-                        Key::Tab => *entry += "\t",
-                        Key::Backspace => { entry.pop(); }
-                        _ => {
-                            if let Some(name) = event.name {
-                                 *entry += &name;
-                            }
-                        }
-                    }
-                }
-            },
-            EventType::ButtonPress(_button) => {
-                let mut count = mouse_click_count.lock().unwrap();
-                *count += 1;
-            },
-            EventType::Wheel { delta_x: _, delta_y } => {
-                if delta_y != 0 {
-                    let mut count = mouse_scroll_count.lock().unwrap();
-                    *count += 1;
-                }
-            },
-            _ => {}
+    };
+    log::info!("Input monitor using backend: {}", source.name());
+
+    let (producer, consumer) = input_pipeline::bounded::<TimedInput>(INPUT_QUEUE_CAPACITY);
+    let aggregator = thread::spawn(move || run_input_aggregator(state, consumer));
+
+    if let Err(error) = source.run(Box::new(move |event| {
+        producer.push(TimedInput { at: Utc::now(), event });
+    })) {
+        log::error!("Input monitor failed: {:?}", error);
+    }
+    // The callback, and with it the producer, is gone once `run` returns,
+    // which ends the aggregator after it has drained the queue.
+    let _ = aggregator.join();
+}
+
+/// Folds queued input events into the per-minute buckets. Every event is
+/// attributed to the window that was active when it happened, resolved at
+/// most every `WINDOW_RESOLVE_INTERVAL`, and added to that window's bucket
+/// for its minute.
+fn run_input_aggregator(state: ActivityLoggerState, events: InputConsumer<TimedInput>) {
+    let mut aggregator = InputAggregator::new(activity_platform::window_provider().ok(), WINDOW_RESOLVE_INTERVAL);
+
+    while let Some(input) = events.recv() {
+        if !*state.is_activity_logging_running.lock().unwrap() {
+            continue;
+        }
+        let at = input.at;
+        if aggregator.add(&mut state.input_buckets.lock().unwrap(), input) {
+            *state.last_input_at.lock().unwrap() = at;
         }
-    }) {
-        eprintln!("[ERROR] Input monitor failed: {:?}", error);
+    }
+
+    if events.dropped() > 0 {
+        log::warn!(
+            "Input queue overflowed; {} events were dropped.",
+            events.dropped()
+        );
     }
 }
 
+/// Removes the input buckets whose minute has ended (or all of them when
+/// `include_current` is set) and turns them into activity records, oldest
+/// minute first.
+fn drain_input_buckets(input_buckets: &Mutex<InputBuckets>, include_current: bool) -> Vec<ActivityMeta> {
+    let now = Utc::now();
+    let drained = input_aggregator::drain_buckets(&mut input_buckets.lock().unwrap(), now, include_current);
+    drained
+        .into_iter()
+        .map(|(key, stats)| ActivityMeta {
+            timestamp: now,
+            activity_type: ActivityType::Input(stats),
+            window_title: Some(key.window_title).filter(|t| !t.is_empty()),
+            app_name: Some(key.app_name).filter(|a| !a.is_empty()),
+        })
+        .collect()
+}
+
 
 /// Main monitoring thread for window focus, browser activity, and periodic logging.
-fn run_main_monitor(app: AppHandle, state: ActivityLoggerState) {
+/// Flushes every `activity_flush_secs` of the agent policy, and once more right away when the service
+/// is stopped so nothing buffered is lost.
+fn run_main_monitor(app: AppHandle, state: ActivityLoggerState, generation: u64, handle: &ServiceHandle) {
     let client = Client::new();
     let pending_dir = get_pending_dir(&app);
     let lock = state.meta_lock.clone();
-    let is_running = state.is_activity_logging_running.clone();
-    let keystroke_buffer = state.keystroke_buffer.clone();
-    let mouse_click_count = state.mouse_click_count.clone();
-    let mouse_scroll_count = state.mouse_scroll_count.clone();
+    let input_buckets = state.input_buckets.clone();
+    let last_input_at = state.last_input_at.clone();
 
+    // The window currently in focus and when it gained focus. A focus record is
+    // only written once the span is closed by switching to another window.
     let mut last_window_title = String::new();
+    let mut last_app_name = String::new();
+    let mut focus_started_at = Utc::now();
+    // Start of the current idle period, if the user is idle.
+    let mut idle_since: Option<DateTime<Utc>> = None;
 
-    println!("Starting main monitor...");
-    loop {
-        if !*is_running.lock().unwrap() {
-            println!("Stopping main monitor thread.");
-            break;
+    let mut window_provider = match activity_platform::window_provider() {
+        Ok(provider) => Some(provider),
+        Err(e) => {
+            log::warn!("Window focus tracking disabled: {}", e);
+            None
         }
-        
-        // MODIFIED: Interval changed to 30 seconds
-        thread::sleep(Duration::from_secs(30));
+    };
+
+    log::info!("Starting main monitor...");
+    loop {
+        let policy = remote_policy::current(&app);
+        let stopping = !state.wait_while_running(generation, policy.activity_flush_interval());
 
         let mut activities_to_log: Vec<ActivityMeta> = Vec::new();
-        let current_timestamp = Utc::now().to_rfc3339();
+        let current_timestamp = Utc::now();
 
-        if let Ok(active_window) = get_active_window() {
+        if let Some(Ok(Some(active_window))) = window_provider.as_mut().map(|p| p.active_window()) {
             if active_window.title != last_window_title && !active_window.title.is_empty() {
-                let app_name = active_window.app_name.to_lowercase();
-                
-                let activity_type = if ["chrome", "firefox", "edge", "safari", "brave", "msedge"].iter().any(|&name| app_name.contains(name)) {
-                     ActivityType::BrowserActivity
-                } else {
-                    ActivityType::WindowFocus
-                };
+                if !last_window_title.is_empty() {
+                    activities_to_log.push(focus_activity(
+                        current_timestamp,
+                        ActivitySpan::new(focus_started_at, current_timestamp),
+                        &last_window_title,
+                        &last_app_name,
+                    ));
+                }
+                last_window_title = active_window.title;
+                last_app_name = active_window.app_name;
+                focus_started_at = current_timestamp;
+            }
+        }
 
+        // Idle detection: open a span once input has been quiet for longer than
+        // the threshold, close it on the first input after that.
+        let last_input = *last_input_at.lock().unwrap();
+        match idle_since {
+            None => {
+                // No keyboard or mouse input for this long marks the user as idle.
+                if (current_timestamp - last_input).to_std().unwrap_or_default() >= policy.idle_threshold() {
+                    idle_since = Some(last_input);
+                }
+            }
+            Some(started_at) if last_input > started_at => {
                 activities_to_log.push(ActivityMeta {
-                    timestamp: current_timestamp.clone(),
-                    activity_type,
-                    details: format!("Focus on: '{}'", active_window.title),
-                    window_title: Some(active_window.title.clone()),
-                    app_name: Some(active_window.app_name),
+                    timestamp: current_timestamp,
+                    activity_type: ActivityType::Idle(ActivitySpan::new(started_at, last_input)),
+                    window_title: None,
+                    app_name: None,
                 });
-                last_window_title = active_window.title.clone();
+                idle_since = None;
             }
+            Some(_) => {}
         }
-        
-        // Log keyboard input
-        let mut buffer = keystroke_buffer.lock().unwrap();
-        if !buffer.is_empty() {
-            for (window_title, text) in buffer.iter() {
-                if !text.is_empty() {
-                    activities_to_log.push(ActivityMeta {
-                        timestamp: current_timestamp.clone(),
-                        activity_type: ActivityType::KeyboardInput,
-                        details: text.clone(),
-                        window_title: Some(window_title.clone()),
-                        app_name: None, 
-                    });
-                }
+
+        // Final flush: close the open focus and idle spans as of now.
+        if stopping {
+            if !last_window_title.is_empty() {
+                activities_to_log.push(focus_activity(
+                    current_timestamp,
+                    ActivitySpan::new(focus_started_at, current_timestamp),
+                    &last_window_title,
+                    &last_app_name,
+                ));
+            }
+            if let Some(started_at) = idle_since {
+                activities_to_log.push(ActivityMeta {
+                    timestamp: current_timestamp,
+                    activity_type: ActivityType::Idle(ActivitySpan::new(started_at, current_timestamp)),
+                    window_title: None,
+                    app_name: None,
+                });
             }
-            buffer.clear();
         }
         
-        // Log mouse clicks
-        let mut clicks = mouse_click_count.lock().unwrap();
-        if *clicks > 0 {
-            activities_to_log.push(ActivityMeta {
-                timestamp: current_timestamp.clone(),
-                activity_type: ActivityType::MouseClick,
-                details: format!("{} mouse clicks", *clicks),
-                window_title: None,
-                app_name: None,
-            });
-            *clicks = 0;
+        // Log keyboard and mouse input for every minute that has ended, plus
+        // the current partial minute on the final flush.
+        activities_to_log.extend(drain_input_buckets(&input_buckets, stopping));
+
+        // MODIFIED: Use the new save-and-upload logic
+        if !activities_to_log.is_empty() {
+            handle.count("flushes");
+            handle.add("records", activities_to_log.len() as u64);
+            persist_activities(&app, &client, &pending_dir, &lock, activities_to_log);
         }
 
-        // Log mouse scrolls
-        let mut scrolls = mouse_scroll_count.lock().unwrap();
-        if *scrolls > 0 {
-            activities_to_log.push(ActivityMeta {
-                timestamp: current_timestamp.clone(),
-                activity_type: ActivityType::MouseScroll,
-                details: format!("{} scroll events", *scrolls),
-                window_title: None,
-                app_name: None,
-            });
-            *scrolls = 0;
+        if stopping {
+            log::info!("Stopping main monitor thread after final flush.");
+            break;
         }
+    }
+}
 
-        // MODIFIED: Use the new save-and-upload logic
-        if !activities_to_log.is_empty() {
-            if let Err(e) = save_and_try_upload(&client, &pending_dir, activities_to_log, &lock) {
-                eprintln!("[ERROR] CRITICAL: Failed to save or upload activity log: {}", e);
-            }
+/// Writes activities to the local store and to a pending log, then tries
+/// to upload the log.
+fn persist_activities(
+    app: &AppHandle,
+    client: &Client,
+    pending_dir: &PathBuf,
+    meta_lock: &Arc<Mutex<()>>,
+    activities: Vec<ActivityMeta>,
+) {
+    if let Some(store) = app.try_state::<ActivityStore>() {
+        if let Err(e) = store.insert(&activities) {
+            log::error!("Failed to write activity to local store: {}", e);
         }
     }
+
+    if let Err(e) = save_and_try_upload(app, client, pending_dir, activities, meta_lock) {
+        log::error!("CRITICAL: Failed to save or upload activity log: {}", e);
+    }
 }
 
+/// Records a single activity produced outside the monitor threads, such as
+/// a pause. The upload happens in the background.
+pub fn record_activity(app: &AppHandle, activity: ActivityMeta) {
+    let app = app.clone();
+    thread::spawn(move || {
+        let meta_lock = app.state::<MainAppState>().activity_logger_state.meta_lock.clone();
+        let pending_dir = get_pending_dir(&app);
+        persist_activities(&app, &Client::new(), &pending_dir, &meta_lock, vec![activity]);
+    });
+}
 
-// MODIFIED: The start command now launches the retry thread as well.
-#[tauri::command]
-pub fn start_activity_logging_service(app: AppHandle, state: tauri::State<'_, MainAppState>) {
-    let activity_state = state.activity_logger_state.clone();
-    let is_running = activity_state.is_activity_logging_running.clone();
-
-    {
-        let mut running_flag = is_running.lock().unwrap();
-        if *running_flag {
-            println!("⚠️ Activity logging service already running.");
-            return;
+/// Builds the focus record for a window that has just lost focus, stamped
+/// with `timestamp`, the time of the monitor cycle that noticed it.
+fn focus_activity(
+    timestamp: DateTime<Utc>,
+    span: ActivitySpan,
+    window_title: &str,
+    app_name: &str,
+) -> ActivityMeta {
+    let app_lower = app_name.to_lowercase();
+    let activity_type = if ["chrome", "firefox", "edge", "safari", "brave", "msedge"].iter().any(|&name| app_lower.contains(name)) {
+        ActivityType::BrowserActivity(span)
+    } else {
+        ActivityType::WindowFocus(span)
+    };
+
+    ActivityMeta {
+        timestamp,
+        activity_type,
+        window_title: Some(window_title.to_string()),
+        app_name: Some(app_name.to_string()),
+    }
+}
+
+
+/// Name of this service in the supervisor.
+pub const SERVICE_NAME: &str = "activity_logging";
+
+pub struct ActivityService;
+
+impl MonitoringService for ActivityService {
+    fn name(&self) -> &'static str {
+        SERVICE_NAME
+    }
+
+    /// Runs the main monitor on the supervisor's thread; the input monitor
+    /// and the retry loop run alongside it on their own threads.
+    fn run(&self, app: &AppHandle, handle: &ServiceHandle) -> anyhow::Result<()> {
+        let activity_state = app.state::<MainAppState>().activity_logger_state.clone();
+
+        let generation = {
+            let mut running_flag = activity_state.is_activity_logging_running.lock().unwrap();
+            *running_flag = true;
+            activity_state.generation.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        // Let threads left over from a previous run see the new generation and exit.
+        activity_state.wake.notify_all();
+
+        log::info!("Starting activity logging service...");
+
+        // 1. Start the Input Monitor Thread (Keyboard/Mouse), unless the hook from
+        //    an earlier start is still installed.
+        if !activity_state.input_listener_started.swap(true, Ordering::SeqCst) {
+            let input_state = activity_state.clone();
+            thread::spawn(move || {
+                run_input_monitor(input_state.clone());
+                input_state.input_listener_started.store(false, Ordering::SeqCst);
+            });
         }
-        *running_flag = true;
+
+        // 2. Start the Retry Thread (Runs every 5 minutes)
+        let retry_app = app.clone();
+        let retry_state = activity_state.clone();
+        thread::spawn(move || {
+            let client = Client::new();
+            let pending_dir = get_pending_dir(&retry_app);
+
+            // We wait first so we do not retry immediately on startup.
+            while retry_state.wait_while_running(generation, remote_policy::current(&retry_app).upload_retry_interval()) {
+                retry_all_pending_activities(&retry_app, &client, &pending_dir);
+            }
+            log::info!("Stopping activity retry thread.");
+        });
+
+        log::info!("Activity logging services started successfully.");
+
+        // 3. Main Monitor (Collects & Tries Initial Upload) until stopped.
+        run_main_monitor(app.clone(), activity_state, generation, handle);
+        Ok(())
     }
 
-    println!("Starting activity logging service...");
-    
-    // 1. Start the Input Monitor Thread (Keyboard/Mouse)
-    let input_state = activity_state.clone();
-    thread::spawn(move || run_input_monitor(input_state));
-    
-    // 2. Start the Main Monitor Thread (Collects & Tries Initial Upload)
-    let main_monitor_app = app.clone();
-    let main_monitor_state = activity_state.clone();
-    thread::spawn(move || run_main_monitor(main_monitor_app, main_monitor_state));
-
-    // 3. NEW: Start the Retry Thread (Runs every 5 minutes)
-    let retry_app = app.clone();
-    let retry_is_running = is_running.clone();
-    thread::spawn(move || {
-        let client = Client::new();
-        let pending_dir = get_pending_dir(&retry_app);
-        
-        loop {
-            if !*retry_is_running.lock().unwrap() {
-                println!("🛑 Stopping activity retry thread.");
-                break;
+    fn stop(&self, app: &AppHandle) {
+        let activity_state = &app.state::<MainAppState>().activity_logger_state;
+        *activity_state.is_activity_logging_running.lock().unwrap() = false;
+        activity_state.wake.notify_all();
+    }
+
+    fn health(&self, app: &AppHandle) -> Health {
+        let activity_state = &app.state::<MainAppState>().activity_logger_state;
+        if activity_state.input_listener_started.load(Ordering::SeqCst) {
+            Health::Healthy
+        } else {
+            Health::Degraded {
+                reason: "input monitor is not running".to_string(),
             }
-            
-            // Wait for 5 minutes before the next retry cycle.
-            // We sleep at the start to not retry immediately on startup.
-            thread::sleep(Duration::from_secs(300));
-            retry_all_pending_activities(&client, &pending_dir);
         }
-    });
+    }
+}
 
-    println!("Activity logging services started successfully.");
+#[tauri::command]
+pub fn start_activity_logging_service(app: AppHandle, supervisor: tauri::State<'_, ServiceSupervisor>) {
+    if let Some(reason) = capture_blocked_reason(&app) {
+        log::info!("Activity logging not started: {}.", reason);
+        return;
+    }
+    if !supervisor.start(&app, SERVICE_NAME) {
+        log::warn!("Activity logging service already running.");
+    }
 }
 
 #[tauri::command]
-pub fn stop_activity_logging_service(state: tauri::State<'_, MainAppState>) {
-    let mut is_running = state.activity_logger_state.is_activity_logging_running.lock().unwrap();
-    *is_running = false;
-    println!("🛑 Activity logging service manually stopped. It may take a moment for threads to terminate.");
-}
\ No newline at end of file
+pub fn stop_activity_logging_service(app: AppHandle, supervisor: tauri::State<'_, ServiceSupervisor>) {
+    supervisor.stop(&app, SERVICE_NAME);
+    log::info!("Activity logging service manually stopped. Flushing buffered activity now.");
+}
+
+/// Returns the JSON Schema for uploaded activity logs so the server can validate them.
+#[tauri::command]
+pub fn get_activity_log_schema() -> serde_json::Value {
+    activity_log_schema()
+}
+
+/// Reports which window and input backends work on this machine, or why not.
+#[tauri::command]
+pub fn get_activity_capabilities() -> PlatformCapabilities {
+    activity_platform::capabilities()
+}
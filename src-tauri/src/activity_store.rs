@@ -0,0 +1,623 @@
+// src/activity_store.rs
+
+//! Local SQLite copy of recorded activity. Uploaded log files are deleted, so
+//! this is what the employee-facing dashboard queries for "your day so far".
+//! Rows older than the configured retention period are pruned on every write.
+
+use crate::activity_service::{ActivityMeta, ActivityType};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::State;
+
+const DEFAULT_RETENTION_DAYS: u32 = 30;
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+
+pub struct ActivityStore {
+    conn: Mutex<Connection>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AppUsageTotal {
+    pub app_name: String,
+    /// Time the app's windows had focus, clipped to the queried range.
+    pub focus_ms: i64,
+    pub key_count: i64,
+    pub click_count: i64,
+    /// Minutes with any keyboard or mouse input in this app.
+    pub active_minutes: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IdleSummary {
+    pub idle_ms: i64,
+    pub idle_periods: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HourlyActivity {
+    pub hour_start: DateTime<Utc>,
+    pub key_count: i64,
+    pub click_count: i64,
+    pub scroll_distance: i64,
+    pub mouse_distance_px: f64,
+    pub active_minutes: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DailySummary {
+    /// UTC calendar day.
+    pub date: NaiveDate,
+    pub focus_ms: i64,
+    pub idle_ms: i64,
+    pub key_count: i64,
+    pub click_count: i64,
+    pub active_minutes: i64,
+    pub top_app: Option<String>,
+}
+
+impl ActivityStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS activity_events (
+                 id INTEGER PRIMARY KEY,
+                 kind TEXT NOT NULL,
+                 start_ms INTEGER NOT NULL,
+                 end_ms INTEGER NOT NULL,
+                 app_name TEXT,
+                 window_title TEXT,
+                 key_count INTEGER NOT NULL DEFAULT 0,
+                 click_count INTEGER NOT NULL DEFAULT 0,
+                 scroll_distance INTEGER NOT NULL DEFAULT 0,
+                 mouse_distance_px REAL NOT NULL DEFAULT 0,
+                 payload TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_activity_events_start ON activity_events(start_ms);
+             CREATE INDEX IF NOT EXISTS idx_activity_events_end ON activity_events(end_ms);
+             CREATE TABLE IF NOT EXISTS settings (
+                 key TEXT PRIMARY KEY,
+                 value TEXT NOT NULL
+             );",
+        )?;
+        let store = Self {
+            conn: Mutex::new(conn),
+        };
+        store.prune()?;
+        Ok(store)
+    }
+
+    pub fn retention_days(&self) -> rusqlite::Result<u32> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'retention_days'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS))
+    }
+
+    pub fn set_retention_days(&self, days: u32) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO settings (key, value) VALUES ('retention_days', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![days.max(1).to_string()],
+        )?;
+        self.prune()
+    }
+
+    /// Deletes everything that ended before the retention cutoff.
+    pub fn prune(&self) -> rusqlite::Result<()> {
+        let days = self.retention_days()?;
+        let cutoff = (Utc::now() - TimeDelta::days(days as i64)).timestamp_millis();
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM activity_events WHERE end_ms < ?1", params![cutoff])?;
+        Ok(())
+    }
+
+    pub fn insert(&self, activities: &[ActivityMeta]) -> rusqlite::Result<()> {
+        {
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT INTO activity_events (kind, start_ms, end_ms, app_name, window_title,
+                         key_count, click_count, scroll_distance, mouse_distance_px, payload)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                )?;
+                for activity in activities {
+                    let row = EventRow::from(activity);
+                    let payload = serde_json::to_string(activity)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                    stmt.execute(params![
+                        row.kind,
+                        row.start_ms,
+                        row.end_ms,
+                        activity.app_name,
+                        activity.window_title,
+                        row.key_count,
+                        row.click_count,
+                        row.scroll_distance,
+                        row.mouse_distance_px,
+                        payload,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+        }
+        self.prune()
+    }
+
+    /// Every stored record overlapping `[from, to)`, oldest first.
+    pub fn timeline(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<ActivityMeta>> {
+        Ok(self
+            .timeline_payloads(from, to)?
+            .iter()
+            // Rows written by an older schema version are skipped, not fatal.
+            .filter_map(|payload| serde_json::from_str(payload).ok())
+            .collect())
+    }
+
+    /// The stored JSON of every record overlapping `[from, to)`, oldest
+    /// first, for callers that must account for rows they cannot parse.
+    pub fn timeline_payloads(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT payload FROM activity_events
+             WHERE end_ms >= ?1 AND start_ms < ?2
+             ORDER BY start_ms, id",
+        )?;
+        let rows = stmt.query_map(params![from.timestamp_millis(), to.timestamp_millis()], |row| {
+            row.get::<_, String>(0)
+        })?;
+        rows.collect()
+    }
+
+    pub fn app_totals(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<AppUsageTotal>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT COALESCE(app_name, ''),
+                    SUM(CASE WHEN kind IN ('window_focus', 'browser_activity')
+                             THEN MIN(end_ms, ?2) - MAX(start_ms, ?1) ELSE 0 END),
+                    SUM(key_count),
+                    SUM(click_count),
+                    SUM(CASE WHEN kind = 'input' THEN 1 ELSE 0 END)
+             FROM activity_events
+             WHERE end_ms >= ?1 AND start_ms < ?2 AND kind NOT IN ('idle', 'paused', 'resumed')
+             GROUP BY 1
+             ORDER BY 2 DESC, 3 DESC",
+        )?;
+        let rows = stmt.query_map(params![from.timestamp_millis(), to.timestamp_millis()], |row| {
+            Ok(AppUsageTotal {
+                app_name: row.get(0)?,
+                focus_ms: row.get(1)?,
+                key_count: row.get(2)?,
+                click_count: row.get(3)?,
+                active_minutes: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn idle_summary(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<IdleSummary> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COALESCE(SUM(MIN(end_ms, ?2) - MAX(start_ms, ?1)), 0), COUNT(*)
+             FROM activity_events
+             WHERE kind = 'idle' AND end_ms > ?1 AND start_ms < ?2",
+            params![from.timestamp_millis(), to.timestamp_millis()],
+            |row| {
+                Ok(IdleSummary {
+                    idle_ms: row.get(0)?,
+                    idle_periods: row.get(1)?,
+                })
+            },
+        )
+    }
+
+    /// Input intensity per UTC hour. Hours without input are omitted.
+    pub fn hourly_activity(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<HourlyActivity>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT (start_ms / ?3) * ?3 AS hour_ms,
+                    SUM(key_count), SUM(click_count), SUM(scroll_distance),
+                    SUM(mouse_distance_px), COUNT(DISTINCT start_ms)
+             FROM activity_events
+             WHERE kind = 'input' AND start_ms >= ?1 AND start_ms < ?2
+             GROUP BY hour_ms
+             ORDER BY hour_ms",
+        )?;
+        let rows = stmt.query_map(
+            params![from.timestamp_millis(), to.timestamp_millis(), HOUR_MS],
+            |row| {
+                Ok(HourlyActivity {
+                    hour_start: DateTime::from_timestamp_millis(row.get(0)?).unwrap_or_default(),
+                    key_count: row.get(1)?,
+                    click_count: row.get(2)?,
+                    scroll_distance: row.get(3)?,
+                    mouse_distance_px: row.get(4)?,
+                    active_minutes: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// One summary per UTC day in `[from, to)` that has any recorded activity.
+    /// Focus and idle spans that cross midnight are split between the days
+    /// they cover, and everything is clipped to `[from, to)`. Input minutes
+    /// count towards the day they start in.
+    pub fn daily_summaries(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<DailySummary>> {
+        let conn = self.conn.lock().unwrap();
+        // `parts` holds every record once per day it overlaps, with the span
+        // clipped to that day; `lo` and `hi` are the day clipped to the range.
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE days(day_ms) AS (
+                 SELECT (?1 / ?3) * ?3 WHERE ?1 < ?2
+                 UNION ALL
+                 SELECT day_ms + ?3 FROM days WHERE day_ms + ?3 < ?2
+             ),
+             bounds AS (
+                 SELECT day_ms, MAX(day_ms, ?1) AS lo, MIN(day_ms + ?3, ?2) AS hi FROM days
+             ),
+             parts AS (
+                 SELECT b.day_ms, e.kind, e.key_count, e.click_count,
+                        MIN(e.end_ms, b.hi) - MAX(e.start_ms, b.lo) AS span_ms,
+                        e.start_ms >= b.lo AS starts_here
+                 FROM bounds b
+                 JOIN activity_events e
+                   ON e.start_ms < b.hi AND (e.end_ms > b.lo OR e.start_ms >= b.lo)
+             )
+             SELECT day_ms, MAX(day_ms, ?1), MIN(day_ms + ?3, ?2),
+                    SUM(CASE WHEN kind IN ('window_focus', 'browser_activity') THEN span_ms ELSE 0 END),
+                    SUM(CASE WHEN kind = 'idle' THEN span_ms ELSE 0 END),
+                    SUM(CASE WHEN starts_here THEN key_count ELSE 0 END),
+                    SUM(CASE WHEN starts_here THEN click_count ELSE 0 END),
+                    SUM(CASE WHEN kind = 'input' AND starts_here THEN 1 ELSE 0 END)
+             FROM parts
+             GROUP BY day_ms
+             ORDER BY day_ms",
+        )?;
+        let mut top_app = conn.prepare(
+            "SELECT app_name FROM activity_events
+             WHERE kind IN ('window_focus', 'browser_activity') AND app_name IS NOT NULL
+               AND end_ms > ?1 AND start_ms < ?2
+             GROUP BY app_name
+             ORDER BY SUM(MIN(end_ms, ?2) - MAX(start_ms, ?1)) DESC, app_name
+             LIMIT 1",
+        )?;
+
+        let rows = stmt.query_map(
+            params![from.timestamp_millis(), to.timestamp_millis(), DAY_MS],
+            |row| {
+                Ok((
+                    (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?),
+                    DailySummary {
+                        date: DateTime::from_timestamp_millis(row.get(0)?)
+                            .unwrap_or_default()
+                            .date_naive(),
+                        focus_ms: row.get(3)?,
+                        idle_ms: row.get(4)?,
+                        key_count: row.get(5)?,
+                        click_count: row.get(6)?,
+                        active_minutes: row.get(7)?,
+                        top_app: None,
+                    },
+                ))
+            },
+        )?;
+
+        let mut summaries = Vec::new();
+        for row in rows {
+            let ((lo, hi), mut summary) = row?;
+            summary.top_app = top_app.query_row(params![lo, hi], |r| r.get(0)).optional()?;
+            summaries.push(summary);
+        }
+        Ok(summaries)
+    }
+}
+
+/// The indexed columns derived from one activity record.
+struct EventRow {
+    kind: &'static str,
+    start_ms: i64,
+    end_ms: i64,
+    key_count: i64,
+    click_count: i64,
+    scroll_distance: i64,
+    mouse_distance_px: f64,
+}
+
+impl From<&ActivityMeta> for EventRow {
+    fn from(activity: &ActivityMeta) -> Self {
+        let at = activity.timestamp.timestamp_millis();
+        let mut row = EventRow {
+            kind: activity.activity_type.kind(),
+            start_ms: at,
+            end_ms: at,
+            key_count: 0,
+            click_count: 0,
+            scroll_distance: 0,
+            mouse_distance_px: 0.0,
+        };
+        match &activity.activity_type {
+            ActivityType::Input(stats) => {
+                row.start_ms = stats.minute_start.timestamp_millis();
+                row.end_ms = row.start_ms + 60_000;
+                row.key_count = stats.key_count as i64;
+                row.click_count = (stats.clicks.left
+                    + stats.clicks.right
+                    + stats.clicks.middle
+                    + stats.clicks.other) as i64;
+                row.scroll_distance = stats.scroll_distance as i64;
+                row.mouse_distance_px = stats.mouse_distance_px;
+            }
+            ActivityType::WindowFocus(span) | ActivityType::BrowserActivity(span) | ActivityType::Idle(span) => {
+                row.start_ms = span.started_at.timestamp_millis();
+                row.end_ms = span.ended_at.timestamp_millis();
+            }
+            ActivityType::Paused(_) => {}
+            ActivityType::Resumed(pause) => {
+                row.start_ms = pause.started_at.timestamp_millis();
+                row.end_ms = pause.ended_at.timestamp_millis();
+            }
+        }
+        row
+    }
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_activity_timeline(
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<ActivityMeta>, String> {
+    store.timeline(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_app_usage_totals(
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<AppUsageTotal>, String> {
+    store.app_totals(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_idle_summary(
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<IdleSummary, String> {
+    store.idle_summary(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_hourly_activity(
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<HourlyActivity>, String> {
+    store.hourly_activity(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_daily_summaries(
+    store: State<'_, ActivityStore>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<DailySummary>, String> {
+    store.daily_summaries(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_activity_retention_days(store: State<'_, ActivityStore>) -> Result<u32, String> {
+    store.retention_days().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_activity_retention_days(store: State<'_, ActivityStore>, days: u32) -> Result<(), String> {
+    store.set_retention_days(days).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity_service::ActivitySpan;
+    use crate::input_aggregator::{ClickCounts, InputStats};
+    use chrono::DurationRound;
+
+    fn store() -> ActivityStore {
+        ActivityStore::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    /// Midnight UTC two days ago, recent enough to survive pruning.
+    fn day_start() -> DateTime<Utc> {
+        (Utc::now() - TimeDelta::days(2))
+            .duration_trunc(TimeDelta::days(1))
+            .unwrap()
+    }
+
+    fn span(
+        activity_type: fn(ActivitySpan) -> ActivityType,
+        app: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> ActivityMeta {
+        ActivityMeta {
+            timestamp: to,
+            activity_type: activity_type(ActivitySpan::new(from, to)),
+            window_title: Some(format!("{} window", app)),
+            app_name: Some(app.to_string()),
+        }
+    }
+
+    fn input(app: &str, minute_start: DateTime<Utc>, key_count: u32, left_clicks: u32) -> ActivityMeta {
+        ActivityMeta {
+            timestamp: minute_start + TimeDelta::minutes(1),
+            activity_type: ActivityType::Input(InputStats {
+                minute_start,
+                key_count,
+                backspace_count: 0,
+                text: String::new(),
+                clicks: ClickCounts {
+                    left: left_clicks,
+                    ..ClickCounts::default()
+                },
+                scroll_distance: 0,
+                mouse_distance_px: 0.0,
+            }),
+            window_title: None,
+            app_name: Some(app.to_string()),
+        }
+    }
+
+    #[test]
+    fn spans_crossing_midnight_are_split_between_days() {
+        let store = store();
+        let day = day_start();
+        let midnight = day + TimeDelta::days(1);
+        store
+            .insert(&[
+                span(ActivityType::WindowFocus, "editor", day + TimeDelta::hours(22), day + TimeDelta::hours(23)),
+                // 30 minutes before midnight, 90 after.
+                span(ActivityType::WindowFocus, "terminal", midnight - TimeDelta::minutes(30), midnight + TimeDelta::minutes(90)),
+                span(ActivityType::Idle, "", midnight - TimeDelta::minutes(10), midnight + TimeDelta::minutes(20)),
+                input("editor", day + TimeDelta::hours(22), 40, 2),
+                input("terminal", midnight - TimeDelta::minutes(1), 5, 0),
+                input("terminal", midnight, 7, 1),
+            ])
+            .unwrap();
+
+        let summaries = store.daily_summaries(day, day + TimeDelta::days(2)).unwrap();
+        assert_eq!(summaries.len(), 2);
+        let (first, second) = (&summaries[0], &summaries[1]);
+
+        assert_eq!(first.date, day.date_naive());
+        assert_eq!(first.focus_ms, TimeDelta::minutes(90).num_milliseconds());
+        assert_eq!(first.idle_ms, TimeDelta::minutes(10).num_milliseconds());
+        assert_eq!((first.key_count, first.click_count, first.active_minutes), (45, 2, 2));
+        assert_eq!(first.top_app.as_deref(), Some("editor"));
+
+        assert_eq!(second.date, midnight.date_naive());
+        assert_eq!(second.focus_ms, TimeDelta::minutes(90).num_milliseconds());
+        assert_eq!(second.idle_ms, TimeDelta::minutes(20).num_milliseconds());
+        assert_eq!((second.key_count, second.click_count, second.active_minutes), (7, 1, 1));
+        assert_eq!(second.top_app.as_deref(), Some("terminal"));
+    }
+
+    #[test]
+    fn a_span_covering_a_whole_day_fills_it() {
+        let store = store();
+        let day = day_start();
+        store
+            .insert(&[span(ActivityType::Idle, "", day - TimeDelta::hours(1), day + TimeDelta::hours(25))])
+            .unwrap();
+
+        let summaries = store.daily_summaries(day, day + TimeDelta::days(2)).unwrap();
+        let idle: Vec<i64> = summaries.iter().map(|s| s.idle_ms).collect();
+        assert_eq!(idle, [DAY_MS, HOUR_MS]);
+        assert_eq!(summaries[0].top_app, None);
+    }
+
+    #[test]
+    fn summaries_are_clipped_to_the_range() {
+        let store = store();
+        let day = day_start();
+        store
+            .insert(&[
+                span(ActivityType::WindowFocus, "editor", day + TimeDelta::hours(8), day + TimeDelta::hours(12)),
+                span(ActivityType::BrowserActivity, "browser", day + TimeDelta::hours(12), day + TimeDelta::hours(13)),
+                input("editor", day + TimeDelta::hours(9), 10, 0),
+                input("editor", day + TimeDelta::hours(11), 20, 0),
+            ])
+            .unwrap();
+
+        let from = day + TimeDelta::hours(10);
+        let summaries = store.daily_summaries(from, from + TimeDelta::hours(2) + TimeDelta::minutes(30)).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].focus_ms, TimeDelta::minutes(150).num_milliseconds());
+        assert_eq!(summaries[0].key_count, 20);
+        assert_eq!(summaries[0].top_app.as_deref(), Some("editor"));
+
+        assert!(store.daily_summaries(from, from).unwrap().is_empty());
+        // Days without any record are left out.
+        assert!(store
+            .daily_summaries(day + TimeDelta::days(1), day + TimeDelta::days(2))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn app_totals_and_idle_summary_clip_to_the_range() {
+        let store = store();
+        let day = day_start();
+        store
+            .insert(&[
+                span(ActivityType::WindowFocus, "editor", day, day + TimeDelta::hours(2)),
+                span(ActivityType::Idle, "", day + TimeDelta::hours(2), day + TimeDelta::hours(3)),
+                input("editor", day + TimeDelta::hours(1), 12, 3),
+            ])
+            .unwrap();
+
+        let from = day + TimeDelta::hours(1);
+        let to = day + TimeDelta::minutes(150);
+        let totals = store.app_totals(from, to).unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].app_name, "editor");
+        assert_eq!(totals[0].focus_ms, HOUR_MS);
+        assert_eq!((totals[0].key_count, totals[0].click_count, totals[0].active_minutes), (12, 3, 1));
+
+        let idle = store.idle_summary(from, to).unwrap();
+        assert_eq!((idle.idle_ms, idle.idle_periods), (HOUR_MS / 2, 1));
+    }
+
+    #[test]
+    fn idle_periods_only_touching_the_range_are_not_counted() {
+        let store = store();
+        let day = day_start();
+        let from = day + TimeDelta::hours(1);
+        let to = day + TimeDelta::hours(2);
+        store
+            .insert(&[
+                // Ends exactly where the range starts.
+                span(ActivityType::Idle, "", day, from),
+                // Starts exactly where the range ends.
+                span(ActivityType::Idle, "", to, to + TimeDelta::minutes(5)),
+            ])
+            .unwrap();
+
+        let idle = store.idle_summary(from, to).unwrap();
+        assert_eq!((idle.idle_ms, idle.idle_periods), (0, 0));
+
+        store
+            .insert(&[span(
+                ActivityType::Idle,
+                "",
+                from - TimeDelta::minutes(1),
+                from + TimeDelta::minutes(1),
+            )])
+            .unwrap();
+        let idle = store.idle_summary(from, to).unwrap();
+        assert_eq!((idle.idle_ms, idle.idle_periods), (60_000, 1));
+    }
+}
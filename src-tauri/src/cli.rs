@@ -0,0 +1,159 @@
+// src/cli.rs
+
+//! Command-line subcommands. Running the binary without a subcommand starts
+//! the Tauri app as usual, whatever other arguments it gets; with one, it
+//! does the job and exits without opening a window.
+
+use crate::activity_export::{export_activity, ExportFormat, ExportRequest};
+use crate::activity_store::ActivityStore;
+use crate::diagnostics;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Must match `identifier` in tauri.conf.json; Tauri's `app_data_dir()` is
+/// the platform data directory joined with it.
+const APP_IDENTIFIER: &str = "main-dashboard";
+
+#[derive(Parser, Debug)]
+#[command(name = "main-dashboard", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Export recorded activity for a range of UTC days to CSV or JSON Lines.
+    ExportActivity {
+        /// First day to include (YYYY-MM-DD).
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last day to include (YYYY-MM-DD).
+        #[arg(long)]
+        to: NaiveDate,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Also list screenshots and videos still stored on this machine.
+        #[arg(long)]
+        include_media: bool,
+        /// File to write.
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Zip logs, redacted config, queue stats, monitors and audio devices for support.
+    CreateDiagnosticBundle {
+        /// File to write. Defaults to the `diagnostics` folder in the app data folder.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// The subcommand the command line asks for, if any. Everything else,
+/// including arguments this binary does not know, starts the app: a release
+/// build has no console to show a parse error on, and an extra argument from
+/// a launcher must not keep the window from opening.
+pub fn command_from_args() -> Option<CliCommand> {
+    let first = std::env::args_os().nth(1)?;
+    let first = first.to_str()?;
+    let wants_cli = matches!(first, "help" | "-h" | "--help" | "-V" | "--version")
+        || Cli::command().get_subcommands().any(|command| command.get_name() == first);
+    if !wants_cli {
+        return None;
+    }
+    attach_parent_console();
+    // From here on errors and `--help` are printed and exit, as usual.
+    Cli::parse().command
+}
+
+/// Lets the windowed release build print to the console it was started
+/// from. Without one, e.g. when started from Explorer, output is lost.
+#[cfg(windows)]
+fn attach_parent_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
+/// Same location Tauri resolves with `app.path().app_data_dir()`.
+pub fn app_data_dir() -> anyhow::Result<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join(APP_IDENTIFIER))
+        .ok_or_else(|| anyhow::anyhow!("cannot determine the platform data directory"))
+}
+
+fn day_start(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+/// Runs a subcommand and returns the process exit code.
+pub fn run(command: CliCommand) -> i32 {
+    let result = match command {
+        CliCommand::ExportActivity {
+            from,
+            to,
+            format,
+            include_media,
+            output,
+        } => run_export_activity(from, to, format, include_media, output),
+        CliCommand::CreateDiagnosticBundle { output } => run_create_diagnostic_bundle(output),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("❌ {:#}", e);
+            1
+        }
+    }
+}
+
+fn run_export_activity(
+    from: NaiveDate,
+    to: NaiveDate,
+    format: ExportFormat,
+    include_media: bool,
+    output: PathBuf,
+) -> anyhow::Result<()> {
+    let app_data_dir = app_data_dir()?;
+    let store = ActivityStore::open(&app_data_dir.join("activity.sqlite"))?;
+    let summary = export_activity(
+        &store,
+        &app_data_dir,
+        &ExportRequest {
+            from: day_start(from),
+            to: day_start(to) + TimeDelta::days(1),
+            format,
+            include_media,
+            output,
+        },
+    )?;
+    println!(
+        "Exported {} activities and {} media references to {}",
+        summary.activities,
+        summary.media_references,
+        summary.path.display()
+    );
+    if summary.unreadable_records > 0 {
+        eprintln!(
+            "⚠️ {} stored records could not be read and are missing from the export",
+            summary.unreadable_records
+        );
+    }
+    Ok(())
+}
+
+fn run_create_diagnostic_bundle(output: Option<PathBuf>) -> anyhow::Result<()> {
+    let app_data_dir = app_data_dir()?;
+    let output = output.unwrap_or_else(|| diagnostics::default_bundle_path(&app_data_dir));
+    let summary = diagnostics::create_bundle(&app_data_dir, &output, Vec::new())?;
+    println!(
+        "Wrote diagnostic bundle with {} files ({} bytes) to {}",
+        summary.files,
+        summary.bytes,
+        summary.path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,381 @@
+// src/diagnostics.rs
+
+//! Diagnostic bundles for support: a zip with recent logs, the current
+//! configuration with secrets redacted, upload queue stats, monitors, audio
+//! devices and the app version. Built by `create_diagnostic_bundle` or the
+//! `create-diagnostic-bundle` CLI subcommand.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, State};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use super::logging;
+use super::service_events::UploadQueue;
+use super::service_supervisor::ServiceSupervisor;
+
+/// Replaces the value of any config key containing one of these
+/// (case-insensitive), or ending in "key" like `signing_key` or `apiKey`.
+const SECRET_KEY_PARTS: &[&str] = &[
+    "secret",
+    "token",
+    "password",
+    "passwd",
+    "authorization",
+    "api_key",
+    "apikey",
+    "private_key",
+    "credential",
+];
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Serialize, Debug, Clone)]
+struct Manifest {
+    app_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueStats {
+    pub queue: UploadQueue,
+    pub files: u64,
+    pub bytes: u64,
+    /// Modification time of the oldest file waiting, i.e. how far uploads are behind.
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MonitorInfo {
+    name: String,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    scale_factor: f32,
+    is_primary: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct AudioDevices {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    default_input: Option<String>,
+    default_output: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BundleSummary {
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Replaces secret-looking values anywhere in `value`. URLs keep only
+/// their scheme and host, as credentials, paths and queries can carry
+/// tokens.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if key.ends_with("key") || SECRET_KEY_PARTS.iter().any(|part| key.contains(part)) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        Value::String(text) => {
+            if let Some(url) = redact_url(text) {
+                *text = url;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `scheme://host[:port]` of `text` if it is a URL with more than that.
+fn redact_url(text: &str) -> Option<String> {
+    let (scheme, rest) = text.split_once("://")?;
+    if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+        return None;
+    }
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if host.len() == authority.len() && rest[authority_end..].trim_start_matches('/').is_empty() {
+        return None;
+    }
+    Some(format!("{}://{}/{}", scheme, host, REDACTED))
+}
+
+/// JSON config files in the app data folder and the log level settings.
+fn config_files(app_data_dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(app_data_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.push(app_data_dir.join("logs").join("log_levels.json"));
+    files.retain(|path| path.exists());
+    files.sort();
+    files
+}
+
+/// Files and bytes waiting in each upload queue.
+pub fn queue_stats(app_data_dir: &Path) -> Vec<QueueStats> {
+    fn walk(dir: &Path, stats: &mut QueueStats) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, stats);
+            } else if let Ok(meta) = entry.metadata() {
+                stats.files += 1;
+                stats.bytes += meta.len();
+                if let Ok(modified) = meta.modified() {
+                    let modified = DateTime::<Utc>::from(modified);
+                    stats.oldest = Some(stats.oldest.map_or(modified, |oldest| oldest.min(modified)));
+                }
+            }
+        }
+    }
+
+    UploadQueue::ALL
+        .into_iter()
+        .map(|queue| {
+            let mut stats = QueueStats {
+                queue,
+                files: 0,
+                bytes: 0,
+                oldest: None,
+            };
+            walk(&app_data_dir.join(queue.dir_name()), &mut stats);
+            stats
+        })
+        .collect()
+}
+
+fn monitors() -> Result<Vec<MonitorInfo>, String> {
+    let monitors = xcap::Monitor::all().map_err(|e| e.to_string())?;
+    Ok(monitors
+        .iter()
+        .map(|monitor| MonitorInfo {
+            name: monitor.name().to_string(),
+            x: monitor.x(),
+            y: monitor.y(),
+            width: monitor.width(),
+            height: monitor.height(),
+            scale_factor: monitor.scale_factor(),
+            is_primary: monitor.is_primary(),
+        })
+        .collect())
+}
+
+#[cfg(feature = "audio_capture")]
+fn audio_devices() -> Result<AudioDevices, String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    fn names(devices: Result<impl Iterator<Item = cpal::Device>, cpal::DevicesError>) -> Vec<String> {
+        devices
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    let host = cpal::default_host();
+    Ok(AudioDevices {
+        inputs: names(host.input_devices()),
+        outputs: names(host.output_devices()),
+        default_input: host.default_input_device().and_then(|d| d.name().ok()),
+        default_output: host.default_output_device().and_then(|d| d.name().ok()),
+    })
+}
+
+#[cfg(not(feature = "audio_capture"))]
+fn audio_devices() -> Result<AudioDevices, String> {
+    Err("built without the audio_capture feature".to_string())
+}
+
+/// Serializes `result`, or the error message if collecting it failed.
+fn to_json<T: Serialize>(result: Result<T, String>) -> Value {
+    match result {
+        Ok(value) => serde_json::to_value(value).unwrap_or(Value::Null),
+        Err(error) => serde_json::json!({ "error": error }),
+    }
+}
+
+/// Default location for a new bundle.
+pub fn default_bundle_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("diagnostics").join(format!(
+        "diagnostic_{}.zip",
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ))
+}
+
+/// Writes a bundle to `output`. `extra` adds files only the running app
+/// can provide, such as the service status.
+pub fn create_bundle(
+    app_data_dir: &Path,
+    output: &Path,
+    extra: Vec<(&str, Value)>,
+) -> anyhow::Result<BundleSummary> {
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut zip = ZipWriter::new(File::create(output)?);
+    let options = SimpleFileOptions::default();
+    let mut files = 0;
+
+    let mut add_json = |zip: &mut ZipWriter<File>, name: &str, value: &Value| -> anyhow::Result<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(&serde_json::to_vec_pretty(value)?)?;
+        files += 1;
+        Ok(())
+    };
+
+    let manifest = Manifest {
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        created_at: Utc::now(),
+    };
+    add_json(&mut zip, "manifest.json", &serde_json::to_value(&manifest)?)?;
+    add_json(&mut zip, "queues.json", &serde_json::to_value(queue_stats(app_data_dir))?)?;
+    add_json(&mut zip, "monitors.json", &to_json(monitors()))?;
+    add_json(&mut zip, "audio_devices.json", &to_json(audio_devices()))?;
+
+    for path in config_files(app_data_dir) {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("config.json");
+        let mut value = match fs::read_to_string(&path).map(|json| serde_json::from_str::<Value>(&json)) {
+            Ok(Ok(value)) => value,
+            // Not included verbatim, as it might hold secrets we cannot redact.
+            Ok(Err(e)) => serde_json::json!({ "error": format!("invalid JSON: {}", e) }),
+            Err(e) => serde_json::json!({ "error": e.to_string() }),
+        };
+        redact(&mut value);
+        add_json(&mut zip, &format!("config/{}", name), &value)?;
+    }
+
+    for (name, value) in &extra {
+        add_json(&mut zip, name, value)?;
+    }
+
+    for path in logging::log_files(&app_data_dir.join("logs")) {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("app.log");
+        let Ok(mut log_file) = File::open(&path) else {
+            continue;
+        };
+        zip.start_file(format!("logs/{}", name), options)?;
+        io::copy(&mut log_file, &mut zip)?;
+        files += 1;
+    }
+
+    zip.finish()?;
+    let bytes = fs::metadata(output)?.len();
+    log::info!("Diagnostic bundle written to {} ({} files)", output.display(), files);
+    Ok(BundleSummary {
+        path: output.to_path_buf(),
+        files,
+        bytes,
+    })
+}
+
+/// Builds a bundle including the live service status. Without `path` it is
+/// written to the `diagnostics` folder in the app data folder.
+#[tauri::command]
+pub fn create_diagnostic_bundle(
+    app: AppHandle,
+    supervisor: State<'_, ServiceSupervisor>,
+    path: Option<String>,
+) -> Result<BundleSummary, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let output = path.map(PathBuf::from).unwrap_or_else(|| default_bundle_path(&app_data_dir));
+    let services = serde_json::to_value(supervisor.status(&app)).map_err(|e| e.to_string())?;
+    create_bundle(&app_data_dir, &output, vec![("services.json", services)]).map_err(|e| format!("{:#}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_redacted_at_any_depth() {
+        let mut config = json!({
+            "auth_token": "abc",
+            "upload": {
+                "Password": "hunter2",
+                "servers": [
+                    {"name": "primary", "apiKey": "k-1", "signing_key": "k-2"},
+                    {"name": "backup", "credentials": {"user": "me", "pass": "x"}}
+                ]
+            },
+            "headers": [{"Authorization": "Bearer abc"}],
+            "client_secret": 42
+        });
+        redact(&mut config);
+
+        assert_eq!(
+            config,
+            json!({
+                "auth_token": REDACTED,
+                "upload": {
+                    "Password": REDACTED,
+                    "servers": [
+                        {"name": "primary", "apiKey": REDACTED, "signing_key": REDACTED},
+                        {"name": "backup", "credentials": REDACTED}
+                    ]
+                },
+                "headers": [{"Authorization": REDACTED}],
+                "client_secret": REDACTED
+            })
+        );
+    }
+
+    #[test]
+    fn urls_keep_only_scheme_and_host() {
+        let mut config = json!({
+            "endpoint": "https://user:pw@api.example.com:8443/v1/upload?token=abc",
+            "mirrors": ["http://10.0.0.2/files#x", {"url": "wss://stream.example.com?sig=1"}],
+            "home": "https://example.com/"
+        });
+        redact(&mut config);
+
+        let redacted = |base: &str| format!("{}/{}", base, REDACTED);
+        assert_eq!(config["endpoint"], redacted("https://api.example.com:8443"));
+        assert_eq!(config["mirrors"][0], redacted("http://10.0.0.2"));
+        assert_eq!(config["mirrors"][1]["url"], redacted("wss://stream.example.com"));
+        // Nothing beyond the host to hide.
+        assert_eq!(config["home"], "https://example.com/");
+    }
+
+    #[test]
+    fn other_values_are_left_alone() {
+        let original = json!({
+            "screenshot_interval_secs": 10,
+            "video": {"enabled": true, "fps": 5, "container": "mp4"},
+            "work_hours": [{"days": ["mon", "tue"], "start": "09:00"}],
+            "note": "see docs: not a url",
+            "monkeys": null,
+            "ratio": 0.5
+        });
+        let mut config = original.clone();
+        redact(&mut config);
+        assert_eq!(config, original);
+    }
+}
@@ -0,0 +1,377 @@
+// src/heartbeat.rs
+
+//! Heartbeats tell the server the agent is alive even when no files arrive.
+//! Every minute a status snapshot is written to `heartbeats_pending` and
+//! posted to `HEARTBEAT_URL`. Heartbeats that could not be sent stay queued
+//! and go out, oldest first, after the current one once the server is
+//! reachable again, so gaps in the timeline can be reconstructed.
+
+use chrono::{DateTime, Datelike, Utc};
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::{System, SystemExt};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::diagnostics::{self, QueueStats};
+use super::logging::{self, LogLine};
+use super::service_events::{self, UploadHistory, UploadOutcomes, UploadQueue};
+use super::service_supervisor::{Health, ServiceState, ServiceSupervisor};
+use super::{capture_blocked_reason, MainAppState, VIDEO_SERVICE_NAME};
+
+const HEARTBEAT_URL: &str = "http://192.168.1.26:3000/api/v1/heartbeat";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Queued heartbeats sent per tick, so a long backlog does not hold up the
+/// current one for long.
+const BACKLOG_PER_TICK: usize = 30;
+
+/// Error log lines included in each heartbeat.
+const RECENT_ERRORS: usize = 5;
+
+#[derive(Serialize, Debug, Clone)]
+struct ServiceSummary {
+    name: &'static str,
+    state: ServiceState,
+    health: Health,
+    last_error: Option<String>,
+    restarts: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct QueueSummary {
+    #[serde(flatten)]
+    stats: QueueStats,
+    #[serde(flatten)]
+    uploads: UploadOutcomes,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct VideoSummary {
+    target_fps: u32,
+    /// Frames written per second since the previous heartbeat.
+    achieved_fps: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Heartbeat {
+    agent_id: String,
+    hostname: Option<String>,
+    created_at: DateTime<Utc>,
+    app_version: &'static str,
+    os: &'static str,
+    os_version: Option<String>,
+    /// Why capture is currently not allowed, if it is not.
+    capture_blocked: Option<&'static str>,
+    services: Vec<ServiceSummary>,
+    queues: Vec<QueueSummary>,
+    video: Option<VideoSummary>,
+    recent_errors: Vec<LogLine>,
+}
+
+/// Frame count at the previous heartbeat, for the achieved frame rate.
+struct FrameSample {
+    frames: u64,
+    at: Instant,
+}
+
+/// A random id stored in the app data folder, stable across restarts.
+fn agent_id(app_data_dir: &Path) -> String {
+    let path = app_data_dir.join("agent_id");
+    if let Ok(id) = fs::read_to_string(&path) {
+        if !id.trim().is_empty() {
+            return id.trim().to_string();
+        }
+    }
+    let id = Uuid::new_v4().to_string();
+    if let Err(e) = fs::write(&path, &id) {
+        log::warn!("Failed to store agent id: {}", e);
+    }
+    id
+}
+
+fn video_summary(app: &AppHandle, last: &mut FrameSample) -> Option<VideoSummary> {
+    let video_state = &app.state::<MainAppState>().video_state;
+    let now = FrameSample {
+        frames: video_state.frames_written.load(Ordering::Relaxed),
+        at: Instant::now(),
+    };
+    let elapsed = now.at.duration_since(last.at).as_secs_f64();
+    let achieved_fps = if elapsed > 0.0 {
+        now.frames.saturating_sub(last.frames) as f64 / elapsed
+    } else {
+        0.0
+    };
+    *last = now;
+
+    if !app.state::<ServiceSupervisor>().is_active(VIDEO_SERVICE_NAME) {
+        return None;
+    }
+    let target_fps = video_state.settings.lock().unwrap().as_ref()?.fps;
+    Some(VideoSummary {
+        target_fps,
+        achieved_fps: (achieved_fps * 10.0).round() / 10.0,
+    })
+}
+
+fn collect(
+    app: &AppHandle,
+    app_data_dir: &Path,
+    agent_id: &str,
+    system: &System,
+    frames: &mut FrameSample,
+) -> Heartbeat {
+    let services = app
+        .state::<ServiceSupervisor>()
+        .status(app)
+        .into_iter()
+        .map(|status| ServiceSummary {
+            name: status.name,
+            state: status.state,
+            health: status.health,
+            last_error: status.last_error,
+            restarts: status.restarts,
+        })
+        .collect();
+
+    let history = app.try_state::<UploadHistory>();
+    let queues = diagnostics::queue_stats(app_data_dir)
+        .into_iter()
+        .map(|stats| QueueSummary {
+            uploads: history
+                .as_ref()
+                .map(|history| history.get(stats.queue))
+                .unwrap_or_default(),
+            stats,
+        })
+        .collect();
+
+    let recent_errors = logging::log_dir()
+        .map(|dir| logging::recent_lines(dir, RECENT_ERRORS, log::LevelFilter::Error))
+        .unwrap_or_default();
+
+    Heartbeat {
+        agent_id: agent_id.to_string(),
+        hostname: system.host_name(),
+        created_at: Utc::now(),
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        os_version: system.long_os_version(),
+        capture_blocked: capture_blocked_reason(app),
+        services,
+        queues,
+        video: video_summary(app, frames),
+        recent_errors,
+    }
+}
+
+fn save(pending_dir: &Path, heartbeat: &Heartbeat) -> anyhow::Result<PathBuf> {
+    let at = heartbeat.created_at;
+    let dir = pending_dir.join(format!("{:04}-{:02}-{:02}", at.year(), at.month(), at.day()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("heartbeat_{}.json", at.format("%Y%m%d_%H%M%S_%3f")));
+    fs::write(&path, serde_json::to_vec(heartbeat)?)?;
+    Ok(path)
+}
+
+fn try_upload_heartbeat(client: &Client, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let body = fs::read(path)?;
+    let response = client
+        .post(HEARTBEAT_URL)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(Duration::from_secs(15))
+        .send()?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Heartbeat upload failed: {}", response.status()).into())
+    }
+}
+
+/// Queued heartbeats, oldest first. File names sort by time.
+fn pending_heartbeats(pending_dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(pending_dir)
+        .map(|days| {
+            days.flatten()
+                .filter_map(|day| fs::read_dir(day.path()).ok())
+                .flat_map(|files| files.flatten().map(|file| file.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    files
+}
+
+/// Sends `current` first, then up to `BACKLOG_PER_TICK` queued heartbeats
+/// oldest first, stopping at the first failure so the backlog arrives in
+/// order. Sent files are deleted.
+fn send_queued(
+    pending_dir: &Path,
+    current: Option<&Path>,
+    mut upload: impl FnMut(&Path) -> Result<(), Box<dyn std::error::Error>>,
+) {
+    let backlog = pending_heartbeats(pending_dir)
+        .into_iter()
+        .filter(|path| Some(path.as_path()) != current)
+        .take(BACKLOG_PER_TICK);
+    for path in current.map(Path::to_path_buf).into_iter().chain(backlog) {
+        match upload(&path) {
+            Ok(()) => {
+                if let Err(e) = fs::remove_file(&path) {
+                    log::warn!("Failed to delete sent heartbeat {}: {}", path.display(), e);
+                }
+                if let Some(day) = path.parent() {
+                    // Only succeeds once the folder is empty.
+                    let _ = fs::remove_dir(day);
+                }
+            }
+            Err(e) => {
+                log::debug!("Heartbeat not sent, kept for later: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn send_pending(app: &AppHandle, client: &Client, pending_dir: &Path, current: Option<&Path>) {
+    send_queued(pending_dir, current, |path| {
+        let result = try_upload_heartbeat(client, path);
+        service_events::report_upload_result(app, UploadQueue::Heartbeats, path, &result);
+        result
+    });
+    service_events::update_queue_depth(app, UploadQueue::Heartbeats);
+}
+
+/// Heartbeat thread.
+pub fn run_heartbeat(app: AppHandle) {
+    let app_data_dir = match app.path().app_data_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            log::error!("Heartbeat disabled: {}", e);
+            return;
+        }
+    };
+    let pending_dir = app_data_dir.join(UploadQueue::Heartbeats.dir_name());
+    let agent_id = agent_id(&app_data_dir);
+    let client = Client::new();
+    let mut system = System::new();
+    let mut frames = FrameSample {
+        frames: app.state::<MainAppState>().video_state.frames_written.load(Ordering::Relaxed),
+        at: Instant::now(),
+    };
+
+    loop {
+        thread::sleep(HEARTBEAT_INTERVAL);
+
+        system.refresh_system();
+        let heartbeat = collect(&app, &app_data_dir, &agent_id, &system, &mut frames);
+        let current = save(&pending_dir, &heartbeat)
+            .map_err(|e| log::error!("Failed to queue heartbeat: {:#}", e))
+            .ok();
+        send_pending(&app, &client, &pending_dir, current.as_deref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("heartbeat_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn queue(pending_dir: &Path, day: &str, name: &str) -> PathBuf {
+        let dir = pending_dir.join(day);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, b"{}").unwrap();
+        path
+    }
+
+    #[test]
+    fn current_goes_out_before_the_backlog() {
+        let dir = pending_dir("order");
+        let old = queue(&dir, "2024-01-01", "heartbeat_20240101_235900_000.json");
+        let older = queue(&dir, "2024-01-01", "heartbeat_20240101_120000_000.json");
+        let current = queue(&dir, "2024-01-02", "heartbeat_20240102_000100_000.json");
+
+        let mut sent = Vec::new();
+        send_queued(&dir, Some(&current), |path| {
+            sent.push(path.to_path_buf());
+            Ok(())
+        });
+
+        assert_eq!(sent, vec![current, older, old]);
+        assert!(pending_heartbeats(&dir).is_empty());
+        assert!(!dir.join("2024-01-01").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failure_keeps_the_rest_queued() {
+        let dir = pending_dir("failure");
+        let first = queue(&dir, "2024-01-01", "heartbeat_20240101_120000_000.json");
+        let second = queue(&dir, "2024-01-01", "heartbeat_20240101_120100_000.json");
+        let current = queue(&dir, "2024-01-01", "heartbeat_20240101_120200_000.json");
+
+        let mut attempts = 0;
+        send_queued(&dir, Some(&current), |path| {
+            attempts += 1;
+            if path == second {
+                Err("unreachable".into())
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(attempts, 3);
+        assert_eq!(pending_heartbeats(&dir), vec![second]);
+        assert!(!first.exists() && !current.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_current_skips_the_backlog() {
+        let dir = pending_dir("offline");
+        let old = queue(&dir, "2024-01-01", "heartbeat_20240101_120000_000.json");
+        let current = queue(&dir, "2024-01-01", "heartbeat_20240101_120100_000.json");
+
+        let mut sent = Vec::new();
+        send_queued(&dir, Some(&current), |path| {
+            sent.push(path.to_path_buf());
+            Err("unreachable".into())
+        });
+
+        assert_eq!(sent, vec![current.clone()]);
+        assert_eq!(pending_heartbeats(&dir), vec![old, current]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backlog_is_capped_per_tick() {
+        let dir = pending_dir("cap");
+        for i in 0..BACKLOG_PER_TICK + 5 {
+            queue(&dir, "2024-01-01", &format!("heartbeat_20240101_{:06}_000.json", i));
+        }
+
+        let mut sent = 0;
+        send_queued(&dir, None, |_| {
+            sent += 1;
+            Ok(())
+        });
+
+        assert_eq!(sent, BACKLOG_PER_TICK);
+        assert_eq!(pending_heartbeats(&dir).len(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
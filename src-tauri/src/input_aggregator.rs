@@ -0,0 +1,325 @@
+// src/input_aggregator.rs
+
+//! Folds timestamped input events into per-application, per-window,
+//! per-minute buckets. The activity service runs this on its aggregator
+//! thread and drains the buckets of minutes that have ended.
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use main_dashboard_spinup_lib::input_pipeline::Throttle;
+use rdev::{Button, Key};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::activity_platform::{ActiveWindowProvider, InputEvent, TimedInput};
+
+/// Keyboard and mouse input for one application window during one minute.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct InputStats {
+    /// Start of the minute this bucket covers.
+    pub minute_start: DateTime<Utc>,
+    /// Every key press, including editing keys.
+    pub key_count: u32,
+    pub backspace_count: u32,
+    /// The text as typed, with backspaces applied.
+    pub text: String,
+    pub clicks: ClickCounts,
+    /// Sum of absolute wheel deltas on both axes, in wheel steps.
+    pub scroll_distance: u64,
+    /// Distance the pointer travelled, in screen pixels.
+    pub mouse_distance_px: f64,
+}
+
+impl InputStats {
+    fn new(minute_start: DateTime<Utc>) -> Self {
+        Self {
+            minute_start,
+            key_count: 0,
+            backspace_count: 0,
+            text: String::new(),
+            clicks: ClickCounts::default(),
+            scroll_distance: 0,
+            mouse_distance_px: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default)]
+pub struct ClickCounts {
+    pub left: u32,
+    pub right: u32,
+    pub middle: u32,
+    pub other: u32,
+}
+
+/// Input is aggregated per application, window and wall-clock minute.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputBucketKey {
+    pub app_name: String,
+    pub window_title: String,
+    pub minute_start: DateTime<Utc>,
+}
+
+pub type InputBuckets = HashMap<InputBucketKey, InputStats>;
+
+fn minute_start(t: DateTime<Utc>) -> DateTime<Utc> {
+    t.duration_trunc(TimeDelta::minutes(1)).unwrap_or(t)
+}
+
+/// Attributes events to the active window and adds them to its bucket.
+pub struct InputAggregator {
+    window_provider: Option<Box<dyn ActiveWindowProvider>>,
+    resolve: Throttle,
+    /// (app name, window title) of the last resolved active window.
+    window: (String, String),
+    last_pointer: Option<(f64, f64)>,
+}
+
+impl InputAggregator {
+    /// The active window is looked up at most every `resolve_interval`;
+    /// input in between is attributed to the last window seen.
+    pub fn new(window_provider: Option<Box<dyn ActiveWindowProvider>>, resolve_interval: Duration) -> Self {
+        Self {
+            window_provider,
+            resolve: Throttle::new(resolve_interval),
+            window: (String::new(), String::new()),
+            last_pointer: None,
+        }
+    }
+
+    /// Adds `input` to the bucket of its window and minute. Returns `false`
+    /// if it was not user input, i.e. a pointer move without travel.
+    pub fn add(&mut self, buckets: &mut InputBuckets, TimedInput { at, event }: TimedInput) -> bool {
+        // Pointer movement alone never triggers a lookup.
+        if !matches!(event, InputEvent::MouseMove { .. }) && self.resolve.ready(Instant::now()) {
+            if let Some(Ok(Some(active_window))) = self.window_provider.as_mut().map(|p| p.active_window()) {
+                self.window = (active_window.app_name, active_window.title);
+            }
+        }
+
+        let pointer_travel = match event {
+            InputEvent::MouseMove { x, y } => {
+                let travel = self.last_pointer.map(|(px, py)| (x - px).hypot(y - py));
+                self.last_pointer = Some((x, y));
+                match travel {
+                    Some(d) if d > 0.0 => d,
+                    _ => return false,
+                }
+            }
+            _ => 0.0,
+        };
+
+        let bucket = InputBucketKey {
+            app_name: self.window.0.clone(),
+            window_title: self.window.1.clone(),
+            minute_start: minute_start(at),
+        };
+        let stats = buckets
+            .entry(bucket)
+            .or_insert_with(|| InputStats::new(minute_start(at)));
+
+        match event {
+            InputEvent::KeyPress { key, name } => {
+                stats.key_count += 1;
+                match key {
+                    Key::Return | Key::KpReturn => stats.text += "\n",
+                    Key::Space => stats.text += " ",
+                    Key::Tab => stats.text += "\t",
+                    Key::Backspace => {
+                        stats.backspace_count += 1;
+                        stats.text.pop();
+                    }
+                    _ => {
+                        if let Some(name) = name {
+                            stats.text += &name;
+                        }
+                    }
+                }
+            }
+            InputEvent::ButtonPress(button) => match button {
+                Button::Left => stats.clicks.left += 1,
+                Button::Right => stats.clicks.right += 1,
+                Button::Middle => stats.clicks.middle += 1,
+                Button::Unknown(_) => stats.clicks.other += 1,
+            },
+            InputEvent::Wheel { delta_x, delta_y } => {
+                stats.scroll_distance += delta_x.unsigned_abs() + delta_y.unsigned_abs();
+            }
+            InputEvent::MouseMove { .. } => stats.mouse_distance_px += pointer_travel,
+        }
+        true
+    }
+}
+
+/// Removes the buckets whose minute has ended by `now` (or all of them when
+/// `include_current` is set), oldest minute first.
+pub fn drain_buckets(
+    buckets: &mut InputBuckets,
+    now: DateTime<Utc>,
+    include_current: bool,
+) -> Vec<(InputBucketKey, InputStats)> {
+    let current_minute = minute_start(now);
+    let closed: Vec<InputBucketKey> = buckets
+        .keys()
+        .filter(|k| include_current || k.minute_start < current_minute)
+        .cloned()
+        .collect();
+
+    let mut drained: Vec<(InputBucketKey, InputStats)> = closed
+        .into_iter()
+        .filter_map(|key| buckets.remove_entry(&key))
+        .collect();
+    drained.sort_by(|(a, _), (b, _)| {
+        (a.minute_start, &a.app_name, &a.window_title).cmp(&(b.minute_start, &b.app_name, &b.window_title))
+    });
+    drained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity_platform::WindowInfo;
+    use std::sync::{Arc, Mutex};
+
+    /// (app name, window title) in focus, set by the test.
+    type Focus = Arc<Mutex<Option<(&'static str, &'static str)>>>;
+
+    /// What the tests compare of a drained bucket: app, title, minute,
+    /// keys, text and clicks.
+    type BucketSummary<'a> = (&'a str, &'a str, DateTime<Utc>, u32, &'a str, u32);
+
+    /// Reports whatever window the test put in focus.
+    struct FocusedWindow(Focus);
+
+    impl ActiveWindowProvider for FocusedWindow {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn active_window(&mut self) -> anyhow::Result<Option<WindowInfo>> {
+            Ok(self.0.lock().unwrap().map(|(app_name, title)| WindowInfo {
+                app_name: app_name.to_string(),
+                title: title.to_string(),
+            }))
+        }
+    }
+
+    fn aggregator() -> (InputAggregator, Focus) {
+        let focus = Arc::new(Mutex::new(Some(("editor", "notes.txt"))));
+        let provider = Box::new(FocusedWindow(focus.clone()));
+        (InputAggregator::new(Some(provider), Duration::ZERO), focus)
+    }
+
+    fn minute() -> DateTime<Utc> {
+        "2026-01-05T09:12:00Z".parse().unwrap()
+    }
+
+    fn key(at: DateTime<Utc>, key: Key, name: &str) -> TimedInput {
+        TimedInput {
+            at,
+            event: InputEvent::KeyPress {
+                key,
+                name: Some(name.to_string()),
+            },
+        }
+    }
+
+    fn click(at: DateTime<Utc>, button: Button) -> TimedInput {
+        TimedInput {
+            at,
+            event: InputEvent::ButtonPress(button),
+        }
+    }
+
+    fn summary(drained: &[(InputBucketKey, InputStats)]) -> Vec<BucketSummary<'_>> {
+        drained
+            .iter()
+            .map(|(key, stats)| {
+                (
+                    key.app_name.as_str(),
+                    key.window_title.as_str(),
+                    stats.minute_start,
+                    stats.key_count,
+                    stats.text.as_str(),
+                    stats.clicks.left + stats.clicks.right,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn input_is_split_at_minute_boundaries() {
+        let (mut aggregator, _) = aggregator();
+        let mut buckets = InputBuckets::new();
+        let start = minute();
+        let next = start + TimeDelta::minutes(1);
+
+        for input in [
+            key(start + TimeDelta::seconds(58), Key::KeyH, "h"),
+            key(start + TimeDelta::seconds(59), Key::KeyI, "i"),
+            click(start + TimeDelta::milliseconds(59_999), Button::Left),
+            key(next, Key::Space, " "),
+            key(next + TimeDelta::seconds(1), Key::KeyX, "x"),
+            key(next + TimeDelta::seconds(2), Key::Backspace, ""),
+        ] {
+            assert!(aggregator.add(&mut buckets, input));
+        }
+
+        // Only the minute that has ended is drained.
+        let drained = drain_buckets(&mut buckets, next + TimeDelta::seconds(30), false);
+        assert_eq!(summary(&drained), [("editor", "notes.txt", start, 2, "hi", 1)]);
+
+        let drained = drain_buckets(&mut buckets, next + TimeDelta::seconds(30), true);
+        assert_eq!(summary(&drained), [("editor", "notes.txt", next, 3, " ", 0)]);
+        assert_eq!(drained[0].1.backspace_count, 1);
+        assert!(buckets.is_empty());
+    }
+
+    #[test]
+    fn input_follows_the_focused_window() {
+        let (mut aggregator, focus) = aggregator();
+        let mut buckets = InputBuckets::new();
+        let start = minute();
+
+        aggregator.add(&mut buckets, key(start, Key::KeyA, "a"));
+        *focus.lock().unwrap() = Some(("browser", "Docs"));
+        aggregator.add(&mut buckets, key(start + TimeDelta::seconds(1), Key::KeyB, "b"));
+        aggregator.add(&mut buckets, click(start + TimeDelta::seconds(2), Button::Right));
+        // No window in focus: input stays with the last window seen.
+        *focus.lock().unwrap() = None;
+        aggregator.add(&mut buckets, key(start + TimeDelta::seconds(3), Key::KeyC, "c"));
+        *focus.lock().unwrap() = Some(("editor", "notes.txt"));
+        aggregator.add(&mut buckets, key(start + TimeDelta::seconds(4), Key::KeyD, "d"));
+
+        let drained = drain_buckets(&mut buckets, start + TimeDelta::minutes(1), false);
+        assert_eq!(
+            summary(&drained),
+            [("browser", "Docs", start, 2, "bc", 1), ("editor", "notes.txt", start, 2, "ad", 0)]
+        );
+    }
+
+    #[test]
+    fn pointer_moves_count_travel_and_never_resolve_the_window() {
+        let (mut aggregator, focus) = aggregator();
+        let mut buckets = InputBuckets::new();
+        let start = minute();
+        let mouse_move = |at, x, y| TimedInput {
+            at,
+            event: InputEvent::MouseMove { x, y },
+        };
+
+        aggregator.add(&mut buckets, key(start, Key::KeyA, "a"));
+        *focus.lock().unwrap() = Some(("browser", "Docs"));
+        // The first position has nothing to measure from.
+        assert!(!aggregator.add(&mut buckets, mouse_move(start, 0.0, 0.0)));
+        assert!(aggregator.add(&mut buckets, mouse_move(start, 3.0, 4.0)));
+        assert!(!aggregator.add(&mut buckets, mouse_move(start, 3.0, 4.0)));
+        assert!(aggregator.add(&mut buckets, mouse_move(start, 3.0, 14.0)));
+
+        let drained = drain_buckets(&mut buckets, start, true);
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].0.app_name, "editor");
+        assert_eq!(drained[0].1.mouse_distance_px, 15.0);
+    }
+}
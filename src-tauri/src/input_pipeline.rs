@@ -0,0 +1,96 @@
+// src/input_pipeline.rs
+
+//! Hand-off from an OS input hook to the thread that aggregates the events.
+//!
+//! Input hooks run on the thread that delivers input to the rest of the
+//! desktop, so anything slow in the callback is felt as input lag. The
+//! producer side therefore only does a non-blocking push onto a bounded
+//! lock-free queue; when the consumer falls behind, events are dropped and
+//! counted rather than stalling the hook.
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Creates a pipeline that buffers up to `capacity` events.
+pub fn bounded<T>(capacity: usize) -> (InputProducer<T>, InputConsumer<T>) {
+    let (tx, rx) = crossbeam_channel::bounded(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    (
+        InputProducer {
+            tx,
+            dropped: dropped.clone(),
+        },
+        InputConsumer { rx, dropped },
+    )
+}
+
+/// Sending half, owned by the input hook callback.
+pub struct InputProducer<T> {
+    tx: Sender<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> InputProducer<T> {
+    /// Queues an event without blocking. Returns `false` if it was dropped
+    /// because the queue is full or the consumer has gone away.
+    #[inline]
+    pub fn push(&self, event: T) -> bool {
+        match self.tx.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+/// Receiving half, owned by the aggregator thread.
+pub struct InputConsumer<T> {
+    rx: Receiver<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> InputConsumer<T> {
+    /// Blocks until the next event, or returns `None` once every producer
+    /// has been dropped and the queue is empty.
+    pub fn recv(&self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+
+    /// Takes the next event if one is already queued.
+    pub fn try_recv(&self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Total number of events dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Lets an action run at most once per `interval`.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+
+    /// Returns `true`, and restarts the interval, if the action is due.
+    pub fn ready(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some(last) if now.duration_since(last) < self.interval => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
+        }
+    }
+}
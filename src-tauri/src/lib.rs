@@ -1,4 +1,5 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+pub mod input_pipeline;
 pub mod video_main;
 
 #[tauri::command]
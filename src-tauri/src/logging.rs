@@ -0,0 +1,390 @@
+// src/logging.rs
+
+//! Backend for the `log` crate. Records go to `logs/app.log` in the app data
+//! folder as one JSON object per line, and in debug builds to stderr too. The
+//! file is rotated by size, keeping `KEPT_FILES` files in total.
+//!
+//! Levels are set per module and can be changed at runtime with
+//! `set_log_level`; they are saved in `logs/log_levels.json` so a level
+//! raised for a support case survives a restart.
+
+use chrono::{DateTime, Utc};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+const LOG_FILE: &str = "app.log";
+const LEVELS_FILE: &str = "log_levels.json";
+
+/// Size at which `app.log` is rotated to `app.1.log`.
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// `app.log` plus `app.1.log` … `app.4.log`.
+const KEPT_FILES: usize = 5;
+
+/// Most lines `get_recent_logs` returns at once.
+const MAX_RECENT_LINES: usize = 5000;
+
+static LOGGER: OnceLock<JsonLogger> = OnceLock::new();
+
+/// One line of the log file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogLine {
+    pub ts: DateTime<Utc>,
+    pub level: String,
+    /// Module that logged the record, e.g. `main_dashboard_spinup::screenshot_service`.
+    pub target: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+}
+
+/// Level used for modules without their own entry, plus per-module overrides.
+///
+/// A module key matches a target equal to it or below it (`key::…`), with
+/// or without the crate name, so `screenshot_service` and
+/// `main_dashboard_spinup::screenshot_service` both work. The longest
+/// matching key wins.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogLevels {
+    pub default: LevelFilter,
+    #[serde(default)]
+    pub modules: BTreeMap<String, LevelFilter>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+impl LogLevels {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let without_crate = target.split_once("::").map(|(_, rest)| rest);
+        let matches = |key: &str, path: &str| {
+            path == key || path.strip_prefix(key).is_some_and(|rest| rest.starts_with("::"))
+        };
+        self.modules
+            .iter()
+            .filter(|(key, _)| matches(key, target) || without_crate.is_some_and(|path| matches(key, path)))
+            .max_by_key(|(key, _)| key.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.values().copied().fold(self.default, Ord::max)
+    }
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+struct JsonLogger {
+    dir: PathBuf,
+    levels: RwLock<LogLevels>,
+    /// `None` if the log file could not be opened; records are then lost
+    /// unless `console` is set.
+    file: Mutex<Option<LogFile>>,
+    /// Size at which the file is rotated, `MAX_FILE_BYTES` outside tests.
+    max_file_bytes: u64,
+    /// Whether records are echoed to stderr.
+    console: bool,
+}
+
+impl JsonLogger {
+    fn open_file(&self) -> Option<LogFile> {
+        let path = self.dir.join(LOG_FILE);
+        let file = OpenOptions::new().create(true).append(true).open(&path).ok()?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Some(LogFile { file, size })
+    }
+
+    /// Shifts `app.N.log` to `app.N+1.log`, dropping the oldest, and starts
+    /// a fresh `app.log`.
+    fn rotate(&self, current: &mut Option<LogFile>) {
+        *current = None;
+        let _ = fs::remove_file(self.dir.join(rotated_name(KEPT_FILES - 1)));
+        for n in (1..KEPT_FILES - 1).rev() {
+            let _ = fs::rename(self.dir.join(rotated_name(n)), self.dir.join(rotated_name(n + 1)));
+        }
+        let _ = fs::rename(self.dir.join(LOG_FILE), self.dir.join(rotated_name(1)));
+        *current = self.open_file();
+    }
+
+    fn write_line(&self, line: &[u8]) {
+        let mut current = self.file.lock().unwrap();
+        if current
+            .as_ref()
+            .is_some_and(|f| f.size > 0 && f.size + line.len() as u64 > self.max_file_bytes)
+        {
+            self.rotate(&mut current);
+        }
+        if let Some(log_file) = current.as_mut() {
+            if log_file.file.write_all(line).is_ok() {
+                log_file.size += line.len() as u64;
+            }
+        }
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.read().unwrap().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = LogLine {
+            ts: Utc::now(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            msg: record.args().to_string(),
+            thread: std::thread::current().name().map(str::to_string),
+        };
+        if self.console {
+            eprintln!("[{} {}] {}", line.level, line.target, line.msg);
+        }
+
+        if let Ok(mut json) = serde_json::to_vec(&line) {
+            json.push(b'\n');
+            self.write_line(&json);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(log_file) = self.file.lock().unwrap().as_mut() {
+            let _ = log_file.file.flush();
+        }
+    }
+}
+
+fn rotated_name(n: usize) -> String {
+    format!("app.{}.log", n)
+}
+
+/// Installs the logger, writing into `dir`. Also logs panics, which would
+/// otherwise only reach stderr.
+pub fn init(dir: PathBuf) -> anyhow::Result<()> {
+    fs::create_dir_all(&dir)?;
+    let levels = fs::read_to_string(dir.join(LEVELS_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str::<LogLevels>(&json).ok())
+        .unwrap_or_default();
+    let max_level = levels.max_level();
+
+    let logger = LOGGER.get_or_init(|| JsonLogger {
+        dir,
+        levels: RwLock::new(levels),
+        file: Mutex::new(None),
+        max_file_bytes: MAX_FILE_BYTES,
+        console: cfg!(debug_assertions),
+    });
+    *logger.file.lock().unwrap() = logger.open_file();
+    log::set_logger(logger).map_err(|e| anyhow::anyhow!("logger already installed: {}", e))?;
+    log::set_max_level(max_level);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        log::error!(target: "panic", "{}", info);
+        default_hook(info);
+    }));
+    Ok(())
+}
+
+/// Folder holding the log files, once the logger is installed.
+pub fn log_dir() -> Option<&'static Path> {
+    LOGGER.get().map(|logger| logger.dir.as_path())
+}
+
+/// Log files from newest to oldest.
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    std::iter::once(LOG_FILE.to_string())
+        .chain((1..KEPT_FILES).map(rotated_name))
+        .map(|name| dir.join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// The last `limit` lines at `min_level` or more severe, oldest first.
+pub fn recent_lines(dir: &Path, limit: usize, min_level: LevelFilter) -> Vec<LogLine> {
+    let mut lines: Vec<LogLine> = Vec::new();
+    for path in log_files(dir) {
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        let mut from_file: Vec<LogLine> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<LogLine>(&line).ok())
+            .filter(|line| log::Level::from_str(&line.level).is_ok_and(|level| level <= min_level))
+            .collect();
+        // Older file, so its lines go in front.
+        from_file.append(&mut lines);
+        lines = from_file;
+        if lines.len() >= limit {
+            break;
+        }
+    }
+    let skip = lines.len().saturating_sub(limit);
+    lines.split_off(skip)
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| format!("Unknown log level: {}", level))
+}
+
+/// Returns recent log lines, e.g. to attach to a support ticket.
+#[tauri::command]
+pub fn get_recent_logs(limit: Option<usize>, min_level: Option<String>) -> Result<Vec<LogLine>, String> {
+    let dir = log_dir().ok_or("Logging is not initialized.")?;
+    let min_level = min_level.as_deref().map(parse_level).transpose()?.unwrap_or(LevelFilter::Trace);
+    Ok(recent_lines(dir, limit.unwrap_or(200).min(MAX_RECENT_LINES), min_level))
+}
+
+#[tauri::command]
+pub fn get_log_levels() -> Result<LogLevels, String> {
+    let logger = LOGGER.get().ok_or("Logging is not initialized.")?;
+    Ok(logger.levels.read().unwrap().clone())
+}
+
+/// Sets the level of `module`, or the default level without one. A `null`
+/// level removes the module's override.
+#[tauri::command]
+pub fn set_log_level(module: Option<String>, level: Option<String>) -> Result<LogLevels, String> {
+    let logger = LOGGER.get().ok_or("Logging is not initialized.")?;
+    let level = level.as_deref().map(parse_level).transpose()?;
+
+    let levels = {
+        let mut levels = logger.levels.write().unwrap();
+        match (module, level) {
+            (None, Some(level)) => levels.default = level,
+            (None, None) => return Err("The default level cannot be removed.".into()),
+            (Some(module), Some(level)) => {
+                levels.modules.insert(module, level);
+            }
+            (Some(module), None) => {
+                levels.modules.remove(&module);
+            }
+        }
+        levels.clone()
+    };
+    log::set_max_level(levels.max_level());
+
+    let json = serde_json::to_string_pretty(&levels).map_err(|e| e.to_string())?;
+    fs::write(logger.dir.join(LEVELS_FILE), json).map_err(|e| e.to_string())?;
+    log::info!("Log levels changed: {:?}", levels);
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logging_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn logger(dir: &Path, max_file_bytes: u64) -> JsonLogger {
+        let logger = JsonLogger {
+            dir: dir.to_path_buf(),
+            levels: RwLock::new(LogLevels::default()),
+            file: Mutex::new(None),
+            max_file_bytes,
+            console: false,
+        };
+        *logger.file.lock().unwrap() = logger.open_file();
+        logger
+    }
+
+    fn line(level: &str, msg: &str) -> String {
+        serde_json::to_string(&LogLine {
+            ts: Utc::now(),
+            level: level.to_string(),
+            target: "main_dashboard_spinup::test".to_string(),
+            msg: msg.to_string(),
+            thread: None,
+        })
+        .unwrap()
+            + "\n"
+    }
+
+    #[test]
+    fn module_levels_match_whole_path_segments() {
+        let levels = LogLevels {
+            default: LevelFilter::Info,
+            modules: BTreeMap::from([
+                ("screenshot_service".to_string(), LevelFilter::Debug),
+                ("video_main".to_string(), LevelFilter::Warn),
+                ("video_main::recorder".to_string(), LevelFilter::Trace),
+            ]),
+        };
+
+        assert_eq!(levels.level_for("screenshot_service"), LevelFilter::Debug);
+        assert_eq!(levels.level_for("main_dashboard_spinup::screenshot_service"), LevelFilter::Debug);
+        assert_eq!(levels.level_for("main_dashboard_spinup::screenshot_service::upload"), LevelFilter::Debug);
+        // A key is a whole module, not a string prefix.
+        assert_eq!(levels.level_for("main_dashboard_spinup::screenshot_service_v2"), LevelFilter::Info);
+        // The longest matching key wins.
+        assert_eq!(levels.level_for("main_dashboard_spinup_lib::video_main::encoder"), LevelFilter::Warn);
+        assert_eq!(levels.level_for("main_dashboard_spinup_lib::video_main::recorder"), LevelFilter::Trace);
+        assert_eq!(levels.level_for("panic"), LevelFilter::Info);
+        assert_eq!(levels.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn file_rotates_at_the_size_limit() {
+        let dir = temp_dir("rotate");
+        let entry = line("INFO", "x");
+        let logger = logger(&dir, 2 * entry.len() as u64);
+
+        logger.write_line(entry.as_bytes());
+        logger.write_line(entry.as_bytes());
+        assert_eq!(log_files(&dir).len(), 1);
+        // The third line would go over the limit, so it starts a new file.
+        logger.write_line(entry.as_bytes());
+        assert_eq!(log_files(&dir), [dir.join(LOG_FILE), dir.join("app.1.log")]);
+        assert_eq!(fs::read_to_string(dir.join(LOG_FILE)).unwrap(), entry);
+        assert_eq!(fs::read_to_string(dir.join("app.1.log")).unwrap(), entry.repeat(2));
+
+        for _ in 0..4 * KEPT_FILES {
+            logger.write_line(entry.as_bytes());
+        }
+        assert_eq!(log_files(&dir).len(), KEPT_FILES);
+        assert!(!dir.join(rotated_name(KEPT_FILES)).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn recent_lines_filter_by_level_across_files() {
+        let dir = temp_dir("recent");
+        let older = [line("ERROR", "first"), line("INFO", "chatter"), line("WARN", "second")].concat();
+        fs::write(dir.join("app.1.log"), older).unwrap();
+        let newer = [line("DEBUG", "detail"), "not json\n".to_string(), line("ERROR", "third")].concat();
+        fs::write(dir.join(LOG_FILE), newer).unwrap();
+
+        let messages = |limit, level| -> Vec<String> {
+            recent_lines(&dir, limit, level).into_iter().map(|l| l.msg).collect()
+        };
+        assert_eq!(messages(10, LevelFilter::Warn), ["first", "second", "third"]);
+        assert_eq!(messages(2, LevelFilter::Warn), ["second", "third"]);
+        assert_eq!(messages(10, LevelFilter::Error), ["first", "third"]);
+        assert_eq!(messages(10, LevelFilter::Trace), ["first", "chatter", "second", "detail", "third"]);
+        assert!(messages(10, LevelFilter::Off).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
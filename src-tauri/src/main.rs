@@ -3,15 +3,42 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 // --- Module declarations for your services ---
+mod activity_export;
+mod activity_platform;
 mod activity_service;
+mod activity_store;
+mod cli;
+mod diagnostics;
+mod heartbeat;
+mod input_aggregator;
+mod logging;
+mod monitoring_pause;
+mod remote_policy;
 mod screenshot_service;
+mod service_events;
+mod service_supervisor;
+mod work_schedule;
 
 // --- Imports from other services ---
 use activity_service::{
-    retry_all_pending_activities, start_activity_logging_service, stop_activity_logging_service,
-    ActivityLoggerState,
+    get_activity_capabilities, get_activity_log_schema, retry_all_pending_activities,
+    start_activity_logging_service, stop_activity_logging_service, ActivityLoggerState,
+    ActivityService,
 };
-use screenshot_service::{start_screenshot_service, stop_screenshot_service};
+use activity_export::export_activity_log;
+use activity_store::{
+    get_activity_retention_days, get_activity_timeline, get_app_usage_totals,
+    get_daily_summaries, get_hourly_activity, get_idle_summary, set_activity_retention_days,
+    ActivityStore,
+};
+use monitoring_pause::{get_pause_status, pause_monitoring, resume_monitoring, MonitoringPause};
+use remote_policy::{get_agent_policy, refresh_agent_policy, RemotePolicy};
+use screenshot_service::{start_screenshot_service, stop_screenshot_service, ScreenshotService};
+use diagnostics::create_diagnostic_bundle;
+use logging::{get_log_levels, get_recent_logs, set_log_level};
+use service_events::{QueueDepths, SegmentFinalized, UploadHistory, UploadQueue};
+use service_supervisor::{get_services_status, MonitoringService, ServiceHandle, ServiceSupervisor};
+use work_schedule::{get_work_policy, get_work_schedule_status, set_work_policy, WorkSchedule};
 
 // --- Standard, Tauri, and external crate imports ---
 use chrono::{Datelike, Utc};
@@ -21,25 +48,56 @@ use std::{
     fs,
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
     },
     thread,
     time::Duration,
 };
+use serde::{Deserialize, Serialize};
 use tauri::{command, AppHandle, Manager, State};
 
 // Use the correct library name for your video recorder
-use main_dashboard_spinup_lib::video_main::{AudioSource, Container, Recorder, RecorderConfig};
+use main_dashboard_spinup_lib::video_main::{
+    recovery, AudioSource, Container, Recorder, RecorderConfig, StopHandle,
+};
 
 // --- State Management Structs ---
 pub struct VideoState {
     pub is_running: Arc<Mutex<bool>>,
-    pub stop_handle: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    pub stop_handle: Arc<Mutex<Option<StopHandle>>>,
+    /// Settings of the current recording, so it can be resumed after a pause.
+    pub settings: Arc<Mutex<Option<VideoSettings>>>,
+    /// Set when `settings` changed during a recording; the recorder picks
+    /// them up at the next segment boundary.
+    pub reconfigure: Arc<AtomicBool>,
+    /// Frames written across all recordings, for the achieved frame rate.
+    pub frames_written: Arc<AtomicU64>,
+}
+
+/// The arguments of `start_video_recording`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VideoSettings {
+    pub fps: u32,
+    pub container: String,
+    pub segment_duration: u64,
+    pub audio: bool,
+    pub audio_source: String,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            fps: 15,
+            container: "Mp4".to_string(),
+            segment_duration: 300,
+            audio: false,
+            audio_source: "Both".to_string(),
+        }
+    }
 }
 
 pub struct MainAppState {
-    pub screenshot_is_running: Arc<Mutex<bool>>,
     pub activity_logger_state: ActivityLoggerState,
     pub video_state: VideoState,
 }
@@ -73,6 +131,17 @@ fn get_dated_folder(base_dir: &PathBuf) -> PathBuf {
     ))
 }
 
+/// Why the monitoring services may not be started right now, if they may not.
+pub fn capture_blocked_reason(app: &AppHandle) -> Option<&'static str> {
+    if monitoring_pause::is_paused(app) {
+        Some("monitoring is paused")
+    } else if !work_schedule::capture_allowed(app) {
+        Some("outside work hours")
+    } else {
+        None
+    }
+}
+
 // --- Tauri Commands ---
 #[command]
 fn start_video_recording(
@@ -84,99 +153,160 @@ fn start_video_recording(
     audio: bool,
     audio_source: String,
 ) -> Result<(), String> {
-    let video_state = &state.video_state;
-
-    // VVVV --- FIX 1: Adopt the scoped lock pattern from screenshot_service.rs --- VVVV
-    {
-        let mut is_running = video_state.is_running.lock().unwrap();
-        if *is_running {
-            println!("⚠️ Video recording is already running.");
-            // We return Ok because it's not a failure, the state is just already active.
-            // The frontend can handle this gracefully.
-            return Ok(());
-        }
-        *is_running = true;
-    } // The lock on `is_running` is released here.
-      // ^^^^ --- FIX 1 --- ^^^^
-
-    println!("▶️ Starting video recording...");
-
-    let base_pending_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?
-        .join("video_recordings_pending");
-
-    let output_dir = get_dated_folder(&base_pending_dir);
-
-    let recorder_cfg = RecorderConfig {
-        output_dir,
-        base_name: "recording".to_string(),
-        segment_duration: Duration::from_secs(segment_duration),
-        fps: 15,
-        container: container_from_str(&container),
-        display_index: 0,
-        record_all: false,
-        combine_all: false,
-        flip_vertical: true,
-        flip_horizontal: false,
-        video_bitrate_kbps: 4000,
-        scale_max_width: None,
-        include_audio: audio,
-        audio_bitrate_kbps: 128,
-        audio_source: audio_source_from_str(&audio_source),
+    let settings = VideoSettings {
+        fps,
+        container,
+        segment_duration,
+        audio,
+        audio_source,
     };
+    start_video(app, state, settings)
+}
 
-    let recorder = Recorder::new(recorder_cfg);
-    let stop_flag = recorder.stop_flag();
-    *video_state.stop_handle.lock().unwrap() = Some(stop_flag);
-
-    // This thread will run the recorder. We clone the state we need.
-    let is_running_clone = video_state.is_running.clone();
-    let stop_handle_clone = video_state.stop_handle.clone();
-    thread::spawn(move || {
-        println!("📹 Recorder thread started.");
-        if let Err(e) = recorder.run_blocking() {
-            eprintln!("❌ Recorder thread exited with error: {}", e);
-        } else {
-            println!("📹 Recorder thread finished gracefully.");
-        }
+/// Starts video recording with `settings`. Shared by the command, the
+/// work-hours scheduler and resuming after a pause.
+pub fn start_video(
+    app: AppHandle,
+    state: State<'_, MainAppState>,
+    settings: VideoSettings,
+) -> Result<(), String> {
+    if let Some(reason) = capture_blocked_reason(&app) {
+        log::info!("Video recording not started: {}.", reason);
+        return Err(format!("Video recording not started: {}.", reason));
+    }
 
-        // When the thread finishes (either by stop signal or error),
-        // ensure the state is cleaned up.
-        *is_running_clone.lock().unwrap() = false;
-        *stop_handle_clone.lock().unwrap() = None;
-    });
+    let supervisor = app.state::<ServiceSupervisor>();
+    if supervisor.is_active(VIDEO_SERVICE_NAME) {
+        log::warn!("Video recording is already running.");
+        // We return Ok because it's not a failure, the state is just already active.
+        // The frontend can handle this gracefully.
+        return Ok(());
+    }
 
-    println!("✅ Video recording started successfully.");
+    log::info!("Starting video recording...");
+    // A server policy overrides whatever the caller asked for.
+    let settings = remote_policy::video_settings(&app).unwrap_or(settings);
+    *state.video_state.settings.lock().unwrap() = Some(settings);
+    supervisor.start(&app, VIDEO_SERVICE_NAME);
+    log::info!("Video recording started successfully.");
     Ok(())
 }
 
-#[command]
-fn stop_video_recording(state: State<'_, MainAppState>) -> Result<(), String> {
-    let video_state = &state.video_state;
+/// Recorder configuration for `settings`, writing into today's pending folder.
+fn recorder_config(app: &AppHandle, settings: &VideoSettings) -> anyhow::Result<RecorderConfig> {
+    let base_pending_dir = app.path().app_data_dir()?.join("video_recordings_pending");
+    Ok(RecorderConfig {
+        segment_duration: Duration::from_secs(settings.segment_duration),
+        fps: settings.fps,
+        container: container_from_str(&settings.container).supported(),
+        include_audio: settings.audio,
+        audio_source: audio_source_from_str(&settings.audio_source),
+        ..RecorderConfig::new(get_dated_folder(&base_pending_dir))
+    })
+}
 
-    // VVVV --- FIX 2: Adopt the scoped lock pattern for stopping as well --- VVVV
-    // Check if it's running first, without holding the lock for too long.
-    if !*video_state.is_running.lock().unwrap() {
-        println!("⚠️ Video recording is not running, nothing to stop.");
-        return Ok(());
+/// Name of the video service in the supervisor.
+pub const VIDEO_SERVICE_NAME: &str = "video";
+
+/// Runs the recorder with the settings in `VideoState::settings`.
+pub struct VideoService;
+
+/// Clears `VideoState::is_running` when the recorder returns or panics.
+struct RunningFlag(Arc<Mutex<bool>>);
+
+impl Drop for RunningFlag {
+    fn drop(&mut self) {
+        *self.0.lock().unwrap() = false;
+    }
+}
+
+impl MonitoringService for VideoService {
+    fn name(&self) -> &'static str {
+        VIDEO_SERVICE_NAME
     }
 
-    println!("⏹️ Stopping video recording...");
+    /// Records until stopped. When the settings change while recording, the
+    /// recorder stops at the end of its segment and is started again here
+    /// with the new settings.
+    fn run(&self, app: &AppHandle, handle: &ServiceHandle) -> anyhow::Result<()> {
+        let state = app.state::<MainAppState>();
+        let video_state = &state.video_state;
+        *video_state.is_running.lock().unwrap() = true;
+        let _running = RunningFlag(video_state.is_running.clone());
+
+        loop {
+            if handle.is_stopped() {
+                return Ok(());
+            }
+            let settings = video_state
+                .settings
+                .lock()
+                .unwrap()
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("no video settings"))?;
+
+            let segment_app = app.clone();
+            let recorder = Recorder::new(recorder_config(app, &settings)?).on_segment_finalized(move |segment| {
+                service_events::emit(
+                    &segment_app,
+                    SegmentFinalized {
+                        path: segment.path.clone(),
+                        frames: segment.frames,
+                        dropped_frames: segment.dropped_frames,
+                        unchanged_frames: segment.unchanged_frames,
+                        achieved_fps: segment.achieved_fps,
+                    },
+                );
+                service_events::update_queue_depth(&segment_app, UploadQueue::Videos);
+            })
+            .count_frames_into(video_state.frames_written.clone());
+            let stop_handle = recorder.stop_handle();
+            *video_state.stop_handle.lock().unwrap() = Some(stop_handle.clone());
+            // A stop that came in while the recorder was being set up found
+            // no handle to stop.
+            if handle.is_stopped() {
+                stop_handle.stop();
+            }
+            handle.count("recordings");
 
-    // Now get the stop handle and send the signal.
-    if let Some(stop_handle) = video_state.stop_handle.lock().unwrap().take() {
-        stop_handle.store(true, Ordering::Relaxed);
-        // The `is_running` flag will be set to false by the thread itself when it exits.
-    } else {
-        // This is a fallback in case the handle is missing but state is running
-        *video_state.is_running.lock().unwrap() = false;
-        return Err("Error: Could not find stop handle, but forced state to 'stopped'.".into());
+            log::info!("Recorder thread started.");
+            recorder.run_blocking()?;
+            log::info!("Recorder thread finished gracefully.");
+
+            if handle.is_stopped()
+                || stop_handle.is_stopped()
+                || !video_state.reconfigure.swap(false, Ordering::SeqCst)
+            {
+                return Ok(());
+            }
+            log::info!("Restarting recorder with new settings.");
+        }
+    }
+
+    fn stop(&self, app: &AppHandle) {
+        let state = app.state::<MainAppState>();
+        let video_state = &state.video_state;
+        video_state.reconfigure.store(false, Ordering::SeqCst);
+        // Taken by `stop_services` already when pausing, which keeps the segment.
+        let stop_handle = video_state.stop_handle.lock().unwrap().take();
+        if let Some(stop_handle) = stop_handle {
+            stop_handle.stop();
+        }
+    }
+}
+
+#[command]
+fn stop_video_recording(app: AppHandle, state: State<'_, MainAppState>) -> Result<(), String> {
+    let supervisor = app.state::<ServiceSupervisor>();
+    if !supervisor.is_active(VIDEO_SERVICE_NAME) {
+        log::warn!("Video recording is not running, nothing to stop.");
+        return Ok(());
     }
-    // ^^^^ --- FIX 2 --- ^^^^
 
-    println!("✅ Stop signal sent to video recorder.");
+    log::info!("Stopping video recording...");
+    supervisor.stop(&app, VIDEO_SERVICE_NAME);
+    *state.video_state.settings.lock().unwrap() = None;
+    log::info!("Stop signal sent to video recorder.");
     Ok(())
 }
 // --- Pending File Upload Logic ---
@@ -209,15 +339,15 @@ fn try_upload_video_file(
         .timeout(Duration::from_secs(60))
         .send()?;
     if response.status().is_success() {
-        println!("✅ Video upload success: {}", filename);
+        log::info!("Video upload success: {}", filename);
         Ok(())
     } else {
         Err(format!("Video upload failed: {} - {}", filename, response.status()).into())
     }
 }
 
-pub fn retry_all_pending_videos(client: &Client, pending_dir: &PathBuf) {
-    println!("🔄 Checking for pending videos in {:?}...", pending_dir);
+pub fn retry_all_pending_videos(app: &AppHandle, client: &Client, pending_dir: &PathBuf) {
+    log::info!("Checking for pending videos in {:?}...", pending_dir);
     let date_dirs = match fs::read_dir(pending_dir) {
         Ok(dirs) => dirs,
         Err(_) => return,
@@ -243,70 +373,120 @@ pub fn retry_all_pending_videos(client: &Client, pending_dir: &PathBuf) {
             continue;
         }
 
-        println!(
-            "📂 Found {} pending videos in {}",
+        log::info!(
+            "Found {} pending videos in {}",
             video_files.len(),
             dir_path.display()
         );
         for file in video_files {
-            match try_upload_video_file(client, &file) {
+            let result = try_upload_video_file(client, &file);
+            service_events::report_upload_result(app, UploadQueue::Videos, &file, &result);
+            match result {
                 Ok(_) => {
                     if let Err(e) = fs::remove_file(&file) {
-                        eprintln!("⚠️ Failed to delete video {}: {}", file.display(), e);
+                        log::warn!("Failed to delete video {}: {}", file.display(), e);
                     }
                 }
-                Err(e) => eprintln!("❌ Retry failed for video {}: {}", file.display(), e),
+                Err(e) => log::error!("Retry failed for video {}: {}", file.display(), e),
             }
         }
     }
+    service_events::update_queue_depth(app, UploadQueue::Videos);
 }
 
 // --- Main Application Setup ---
 fn main() {
+    if let Some(command) = cli::command_from_args() {
+        std::process::exit(cli::run(command));
+    }
+
+    match cli::app_data_dir() {
+        Ok(app_data_dir) => {
+            if let Err(e) = logging::init(app_data_dir.join("logs")) {
+                eprintln!("❌ Failed to set up logging: {:#}", e);
+            }
+        }
+        Err(e) => eprintln!("❌ Failed to set up logging: {:#}", e),
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .manage(MainAppState {
-            screenshot_is_running: Arc::new(Mutex::new(false)),
             activity_logger_state: ActivityLoggerState {
                 is_activity_logging_running: Arc::new(Mutex::new(false)),
                 meta_lock: Arc::new(Mutex::new(())),
-                keystroke_buffer: Arc::new(Mutex::new(HashMap::new())),
-                mouse_click_count: Arc::new(Mutex::new(0)),
-                mouse_scroll_count: Arc::new(Mutex::new(0)),
+                input_buckets: Arc::new(Mutex::new(HashMap::new())),
+                last_input_at: Arc::new(Mutex::new(Utc::now())),
+                wake: Arc::new(Condvar::new()),
+                generation: Arc::new(AtomicU64::new(0)),
+                input_listener_started: Arc::new(AtomicBool::new(false)),
             },
             video_state: VideoState {
                 is_running: Arc::new(Mutex::new(false)),
                 stop_handle: Arc::new(Mutex::new(None)),
+                settings: Arc::new(Mutex::new(None)),
+                reconfigure: Arc::new(AtomicBool::new(false)),
+                frames_written: Arc::new(AtomicU64::new(0)),
             },
         })
+        .manage(MonitoringPause::default())
+        .manage(QueueDepths::default())
+        .manage(UploadHistory::default())
+        .manage(ServiceSupervisor::new(vec![
+            Arc::new(ScreenshotService::default()),
+            Arc::new(ActivityService),
+            Arc::new(VideoService),
+        ]))
         .setup(|app| {
             let app_data_dir = app.path().app_data_dir().unwrap();
             let client = Client::new();
 
+            match ActivityStore::open(&app_data_dir.join("activity.sqlite")) {
+                Ok(store) => {
+                    app.manage(store);
+                }
+                Err(e) => log::error!("Failed to open local activity store: {}", e),
+            }
+
+            // Before anything can start recording into the folder again.
+            recovery::recover_segments(&app_data_dir.join("video_recordings_pending"));
+
+            app.manage(WorkSchedule::load(app_data_dir.join("work_policy.json")));
+            app.manage(RemotePolicy::load(app_data_dir.join("agent_policy.json")));
+            let policy_app = app.handle().clone();
+            thread::spawn(move || remote_policy::run_policy_sync(policy_app));
+            let heartbeat_app = app.handle().clone();
+            thread::spawn(move || heartbeat::run_heartbeat(heartbeat_app));
+            let scheduler_app = app.handle().clone();
+            thread::spawn(move || work_schedule::run_scheduler(scheduler_app));
+
             let screenshot_pending_dir = app_data_dir.join("screenshots_pending");
             if screenshot_pending_dir.exists() {
                 let s_client = client.clone();
+                let s_app = app.handle().clone();
                 thread::spawn(move || {
                     thread::sleep(Duration::from_secs(2));
-                    screenshot_service::retry_all_pending(&s_client, &screenshot_pending_dir);
+                    screenshot_service::retry_all_pending(&s_app, &s_client, &screenshot_pending_dir);
                 });
             }
 
             let activity_pending_dir = app_data_dir.join("activity_logs_pending");
             if activity_pending_dir.exists() {
                 let a_client = client.clone();
+                let a_app = app.handle().clone();
                 thread::spawn(move || {
                     thread::sleep(Duration::from_secs(4));
-                    retry_all_pending_activities(&a_client, &activity_pending_dir);
+                    retry_all_pending_activities(&a_app, &a_client, &activity_pending_dir);
                 });
             }
 
             let video_pending_dir = app_data_dir.join("video_recordings_pending");
             if video_pending_dir.exists() {
                 let v_client = client.clone();
+                let v_app = app.handle().clone();
                 thread::spawn(move || {
                     thread::sleep(Duration::from_secs(6));
-                    retry_all_pending_videos(&v_client, &video_pending_dir);
+                    retry_all_pending_videos(&v_app, &v_client, &video_pending_dir);
                 });
             }
             Ok(())
@@ -316,9 +496,38 @@ fn main() {
             stop_screenshot_service,
             start_activity_logging_service,
             stop_activity_logging_service,
+            get_activity_log_schema,
+            get_activity_capabilities,
+            get_activity_timeline,
+            get_app_usage_totals,
+            get_idle_summary,
+            get_hourly_activity,
+            get_daily_summaries,
+            get_activity_retention_days,
+            set_activity_retention_days,
+            export_activity_log,
+            get_work_policy,
+            set_work_policy,
+            get_work_schedule_status,
+            pause_monitoring,
+            resume_monitoring,
+            get_pause_status,
             start_video_recording,
             stop_video_recording,
+            get_services_status,
+            get_recent_logs,
+            get_log_levels,
+            set_log_level,
+            create_diagnostic_bundle,
+            get_agent_policy,
+            refresh_agent_policy,
         ])
-        .run(tauri::generate_context!())
-        .expect("❌ Error while running Tauri app");
+        .build(tauri::generate_context!())
+        .expect("❌ Error while building Tauri app")
+        .run(|app, event| {
+            // Give the services a chance to flush before the process exits.
+            if let tauri::RunEvent::Exit = event {
+                app.state::<ServiceSupervisor>().shutdown(app, Duration::from_secs(5));
+            }
+        });
 }
@@ -0,0 +1,458 @@
+// src/monitoring_pause.rs
+
+//! Private-break pauses. `pause_monitoring` stops screenshots, activity
+//! logging and video together, records why, and brings back whatever was
+//! running once the timer runs out or `resume_monitoring` is called.
+//!
+//! While paused, the `start_*` commands refuse to start anything, so neither
+//! the UI nor the work-hours scheduler can end a pause early by accident.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use tauri::{AppHandle, Manager, State};
+
+use super::activity_service::{
+    self, record_activity, start_activity_logging_service, stop_activity_logging_service, ActivityMeta,
+    ActivityType, PauseEnded, PauseStarted,
+};
+use super::screenshot_service::{self, start_screenshot_service, stop_screenshot_service};
+use super::service_supervisor::ServiceSupervisor;
+use super::{start_video, MainAppState, VideoSettings, VIDEO_SERVICE_NAME};
+
+/// Longest pause a user can take in one go.
+const MAX_PAUSE_MINUTES: u32 = 240;
+
+/// Services a pause stops, in the order they are stopped and restarted.
+const PAUSABLE_SERVICES: [&str; 3] = [
+    screenshot_service::SERVICE_NAME,
+    activity_service::SERVICE_NAME,
+    VIDEO_SERVICE_NAME,
+];
+
+/// What a pause acts on: the app's services, or a fake in tests.
+trait PauseTarget {
+    fn is_active(&self, service: &'static str) -> bool;
+
+    /// Settings the running recorder was started with.
+    fn video_settings(&self) -> Option<VideoSettings>;
+
+    /// Stops `service`. The current video segment is finalized and kept,
+    /// not discarded.
+    fn stop(&self, service: &'static str);
+
+    /// Starts `service` again. Goes through the usual start checks, so
+    /// nothing restarts outside work hours.
+    fn start(&self, service: &'static str, video: Option<&VideoSettings>);
+
+    fn record(&self, activity: ActivityMeta);
+}
+
+impl PauseTarget for AppHandle {
+    fn is_active(&self, service: &'static str) -> bool {
+        self.state::<ServiceSupervisor>().is_active(service)
+    }
+
+    fn video_settings(&self) -> Option<VideoSettings> {
+        self.state::<MainAppState>().video_state.settings.lock().unwrap().clone()
+    }
+
+    fn stop(&self, service: &'static str) {
+        match service {
+            screenshot_service::SERVICE_NAME => stop_screenshot_service(self.clone(), self.state()),
+            activity_service::SERVICE_NAME => stop_activity_logging_service(self.clone(), self.state()),
+            // VIDEO_SERVICE_NAME
+            _ => {
+                let video_state = &self.state::<MainAppState>().video_state;
+                if let Some(stop_handle) = video_state.stop_handle.lock().unwrap().take() {
+                    stop_handle.stop_and_keep_segment();
+                }
+                self.state::<ServiceSupervisor>().stop(self, service);
+            }
+        }
+    }
+
+    fn start(&self, service: &'static str, video: Option<&VideoSettings>) {
+        match service {
+            screenshot_service::SERVICE_NAME => start_screenshot_service(self.clone(), self.state()),
+            activity_service::SERVICE_NAME => start_activity_logging_service(self.clone(), self.state()),
+            // VIDEO_SERVICE_NAME
+            _ => {
+                let Some(settings) = video else {
+                    log::warn!("Video recording not resumed: its settings are unknown");
+                    return;
+                };
+                // If the previous recorder is still finalizing its segment, the
+                // supervisor starts the new one as soon as it is done.
+                if let Err(e) = start_video(self.clone(), self.state(), settings.clone()) {
+                    log::error!("Failed to resume video recording: {}", e);
+                }
+            }
+        }
+    }
+
+    fn record(&self, activity: ActivityMeta) {
+        record_activity(self, activity);
+    }
+}
+
+/// What was running when the pause began and is restarted on resume.
+#[derive(Debug, Clone)]
+struct PausedServices {
+    services: Vec<&'static str>,
+    video: Option<VideoSettings>,
+}
+
+impl PausedServices {
+    /// Stops every running service and returns what has to be restarted
+    /// later. Services that were not running stay stopped after the pause.
+    fn stop(target: &impl PauseTarget) -> Self {
+        let mut paused = Self {
+            services: Vec::new(),
+            video: None,
+        };
+        for service in PAUSABLE_SERVICES {
+            if !target.is_active(service) {
+                continue;
+            }
+            if service == VIDEO_SERVICE_NAME {
+                paused.video = target.video_settings();
+            }
+            target.stop(service);
+            paused.services.push(service);
+        }
+        paused
+    }
+
+    fn start(self, target: &impl PauseTarget) {
+        for service in self.services {
+            target.start(service, self.video.as_ref());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ActivePause {
+    /// Distinguishes this pause from later ones, so a stale timer never
+    /// resumes a pause it did not start.
+    id: u64,
+    reason: String,
+    started_at: DateTime<Utc>,
+    resume_at: DateTime<Utc>,
+    services: PausedServices,
+}
+
+/// Managed state holding the current pause, if any.
+#[derive(Default)]
+pub struct MonitoringPause {
+    current: Mutex<Option<ActivePause>>,
+    changed: Condvar,
+    next_id: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PauseStatus {
+    pub reason: String,
+    pub started_at: DateTime<Utc>,
+    pub resume_at: DateTime<Utc>,
+}
+
+impl From<&ActivePause> for PauseStatus {
+    fn from(pause: &ActivePause) -> Self {
+        Self {
+            reason: pause.reason.clone(),
+            started_at: pause.started_at,
+            resume_at: pause.resume_at,
+        }
+    }
+}
+
+impl MonitoringPause {
+    /// Stops the running services of `target` and starts pause number
+    /// `id`, returned together with its status.
+    fn pause(&self, target: &impl PauseTarget, reason: &str, minutes: u32) -> Result<(u64, PauseStatus), String> {
+        if minutes == 0 || minutes > MAX_PAUSE_MINUTES {
+            return Err(format!("A pause must last 1 to {} minutes.", MAX_PAUSE_MINUTES));
+        }
+
+        // Held until every service is stopped, so start commands arriving in the
+        // meantime wait and then see the pause.
+        let mut current = self.current.lock().unwrap();
+        if current.is_some() {
+            return Err("Monitoring is already paused.".into());
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let started_at = Utc::now();
+        let pause = ActivePause {
+            id,
+            reason: reason.trim().to_string(),
+            started_at,
+            resume_at: started_at + TimeDelta::minutes(minutes as i64),
+            services: PausedServices::stop(target),
+        };
+        let status = PauseStatus::from(&pause);
+
+        target.record(ActivityMeta {
+            timestamp: started_at,
+            activity_type: ActivityType::Paused(PauseStarted {
+                reason: pause.reason.clone(),
+                resume_at: pause.resume_at,
+            }),
+            window_title: None,
+            app_name: None,
+        });
+        log::info!("Monitoring paused for {} minutes: {}", minutes, pause.reason);
+
+        *current = Some(pause);
+        Ok((id, status))
+    }
+
+    /// Ends the pause `id` (or whichever pause is active, for `None`) and
+    /// restarts the services it stopped. Returns `false` if there was nothing to end.
+    fn resume(&self, target: &impl PauseTarget, id: Option<u64>) -> bool {
+        let pause = {
+            let mut current = self.current.lock().unwrap();
+            match current.as_ref() {
+                Some(pause) if id.is_none_or(|id| id == pause.id) => current.take(),
+                _ => None,
+            }
+        };
+        // Wake the timer of a pause that was ended by hand.
+        self.changed.notify_all();
+
+        let Some(pause) = pause else {
+            return false;
+        };
+
+        let now = Utc::now();
+        log::info!("Monitoring resumed after pause: {}", pause.reason);
+        target.record(ActivityMeta {
+            timestamp: now,
+            activity_type: ActivityType::Resumed(PauseEnded {
+                reason: pause.reason,
+                started_at: pause.started_at,
+                ended_at: now,
+                duration_ms: (now - pause.started_at).num_milliseconds().max(0),
+            }),
+            window_title: None,
+            app_name: None,
+        });
+        pause.services.start(target);
+        true
+    }
+
+    /// Waits until `resume_at`. Returns whether pause `id` is still the
+    /// current one by then, i.e. was not ended in the meantime.
+    fn wait_until(&self, id: u64, resume_at: DateTime<Utc>) -> bool {
+        let mut current = self.current.lock().unwrap();
+        loop {
+            if current.as_ref().is_none_or(|pause| pause.id != id) {
+                return false;
+            }
+            let remaining = (resume_at - Utc::now()).to_std().unwrap_or_default();
+            if remaining.is_zero() {
+                return true;
+            }
+            current = self.changed.wait_timeout(current, remaining).unwrap().0;
+        }
+    }
+
+    fn status(&self) -> Option<PauseStatus> {
+        self.current.lock().unwrap().as_ref().map(PauseStatus::from)
+    }
+}
+
+/// Whether monitoring is currently paused.
+pub fn is_paused(app: &AppHandle) -> bool {
+    app.try_state::<MonitoringPause>()
+        .is_some_and(|pause| pause.current.lock().unwrap().is_some())
+}
+
+/// Waits until `resume_at`, then resumes the pause unless it already ended.
+fn run_resume_timer(app: AppHandle, id: u64, resume_at: DateTime<Utc>) {
+    let pause_state = app.state::<MonitoringPause>();
+    if pause_state.wait_until(id, resume_at) {
+        pause_state.resume(&app, Some(id));
+    }
+}
+
+/// Pauses all monitoring for `minutes`, recording `reason`.
+#[tauri::command]
+pub fn pause_monitoring(
+    app: AppHandle,
+    pause_state: State<'_, MonitoringPause>,
+    reason: String,
+    minutes: u32,
+) -> Result<PauseStatus, String> {
+    let (id, status) = pause_state.pause(&app, &reason, minutes)?;
+    let resume_at = status.resume_at;
+    thread::spawn(move || run_resume_timer(app, id, resume_at));
+    Ok(status)
+}
+
+/// Ends the current pause early.
+#[tauri::command]
+pub fn resume_monitoring(app: AppHandle, pause_state: State<'_, MonitoringPause>) -> Result<(), String> {
+    if pause_state.resume(&app, None) {
+        Ok(())
+    } else {
+        Err("Monitoring is not paused.".into())
+    }
+}
+
+#[tauri::command]
+pub fn get_pause_status(pause_state: State<'_, MonitoringPause>) -> Option<PauseStatus> {
+    pause_state.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// Services that start and stop by name, with everything done to them
+    /// kept in `calls`.
+    #[derive(Default)]
+    struct FakeServices {
+        active: Mutex<Vec<&'static str>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl FakeServices {
+        fn running(services: &[&'static str]) -> Self {
+            Self {
+                active: Mutex::new(services.to_vec()),
+                ..Self::default()
+            }
+        }
+
+        fn take_calls(&self) -> Vec<String> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+    }
+
+    impl PauseTarget for FakeServices {
+        fn is_active(&self, service: &'static str) -> bool {
+            self.active.lock().unwrap().contains(&service)
+        }
+
+        fn video_settings(&self) -> Option<VideoSettings> {
+            Some(VideoSettings::default())
+        }
+
+        fn stop(&self, service: &'static str) {
+            self.active.lock().unwrap().retain(|s| *s != service);
+            self.calls.lock().unwrap().push(format!("stop {}", service));
+        }
+
+        fn start(&self, service: &'static str, video: Option<&VideoSettings>) {
+            self.active.lock().unwrap().push(service);
+            let with_settings = if video.is_some() { " with settings" } else { "" };
+            self.calls.lock().unwrap().push(format!("start {}{}", service, with_settings));
+        }
+
+        fn record(&self, activity: ActivityMeta) {
+            self.calls.lock().unwrap().push(format!("record {}", activity.activity_type.kind()));
+        }
+    }
+
+    #[test]
+    fn pause_stops_and_resume_restarts_what_was_running() {
+        let services = FakeServices::running(&[screenshot_service::SERVICE_NAME, VIDEO_SERVICE_NAME]);
+        let pause = MonitoringPause::default();
+
+        let (_, status) = pause.pause(&services, "  lunch ", 30).unwrap();
+        assert_eq!(status.reason, "lunch");
+        assert_eq!(status.resume_at - status.started_at, TimeDelta::minutes(30));
+        assert_eq!(
+            services.take_calls(),
+            [
+                format!("stop {}", screenshot_service::SERVICE_NAME),
+                format!("stop {}", VIDEO_SERVICE_NAME),
+                "record paused".to_string(),
+            ]
+        );
+        assert!(pause.status().is_some());
+
+        assert!(pause.resume(&services, None));
+        // Activity logging was not running before the pause, so it stays off.
+        assert_eq!(
+            services.take_calls(),
+            [
+                "record resumed".to_string(),
+                format!("start {} with settings", screenshot_service::SERVICE_NAME),
+                format!("start {} with settings", VIDEO_SERVICE_NAME),
+            ]
+        );
+        assert!(pause.status().is_none());
+        assert!(!pause.resume(&services, None));
+        assert!(services.take_calls().is_empty());
+    }
+
+    #[test]
+    fn invalid_or_second_pauses_are_refused() {
+        let services = FakeServices::running(&[activity_service::SERVICE_NAME]);
+        let pause = MonitoringPause::default();
+
+        assert!(pause.pause(&services, "break", 0).is_err());
+        assert!(pause.pause(&services, "break", MAX_PAUSE_MINUTES + 1).is_err());
+        assert!(services.take_calls().is_empty());
+
+        pause.pause(&services, "break", MAX_PAUSE_MINUTES).unwrap();
+        services.take_calls();
+        assert!(pause.pause(&services, "again", 5).is_err());
+        assert!(services.take_calls().is_empty());
+    }
+
+    #[test]
+    fn pauses_get_new_ids() {
+        let services = FakeServices::default();
+        let pause = MonitoringPause::default();
+
+        let (first, _) = pause.pause(&services, "one", 5).unwrap();
+        assert!(!pause.resume(&services, Some(first + 1)));
+        assert!(pause.resume(&services, Some(first)));
+        let (second, _) = pause.pause(&services, "two", 5).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn stale_timer_does_not_end_a_later_pause() {
+        let services = FakeServices::running(&[screenshot_service::SERVICE_NAME]);
+        let pause = MonitoringPause::default();
+
+        let (first, status) = pause.pause(&services, "one", 5).unwrap();
+        assert!(pause.resume(&services, None));
+        let (second, _) = pause.pause(&services, "two", 5).unwrap();
+        services.take_calls();
+
+        // The first pause's timer runs out while the second one is on.
+        assert!(!pause.wait_until(first, status.started_at));
+        assert!(!pause.resume(&services, Some(first)));
+        assert!(services.take_calls().is_empty());
+        assert_eq!(pause.status().unwrap().reason, "two");
+
+        assert!(pause.wait_until(second, Utc::now()));
+    }
+
+    #[test]
+    fn manual_resume_wakes_the_timer() {
+        let services = FakeServices::default();
+        let pause = Arc::new(MonitoringPause::default());
+        let (id, status) = pause.pause(&services, "long", MAX_PAUSE_MINUTES).unwrap();
+
+        let timer = {
+            let pause = pause.clone();
+            thread::spawn(move || pause.wait_until(id, status.resume_at))
+        };
+        thread::sleep(Duration::from_millis(20));
+        let started = Instant::now();
+        assert!(pause.resume(&services, None));
+        assert!(!timer.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,578 @@
+// src/remote_policy.rs
+
+//! Agent policy pushed from the server: capture intervals, video settings
+//! and optionally the work hours.
+//!
+//! The policy is polled from `POLICY_URL` with `If-None-Match`, so an
+//! unchanged policy costs a 304. The server signs the policy document with
+//! ed25519; the key is baked in at build time through the
+//! `POLICY_PUBLIC_KEY` environment variable (base64), and builds without it
+//! keep the defaults. Every verified policy is saved as last-known-good and
+//! used after a restart while the server is unreachable.
+//!
+//! Changes are applied to running services without a restart: intervals are
+//! read on every cycle and waits in progress are woken to re-read them, and
+//! the video recorder finishes its current segment and continues with the
+//! new settings.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use super::service_supervisor::ServiceSupervisor;
+use super::work_schedule::{WorkPolicy, WorkSchedule};
+use super::{container_from_str, MainAppState, VideoSettings, VIDEO_SERVICE_NAME};
+
+const POLICY_URL: &str = "http://192.168.1.26:3000/api/v1/policy";
+
+const POLICY_PUBLIC_KEY: Option<&str> = option_env!("POLICY_PUBLIC_KEY");
+
+/// Poll interval until a policy sets its own.
+const DEFAULT_REFRESH: Duration = Duration::from_secs(300);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AgentPolicy {
+    /// Raised by the server on every change. Older versions are rejected so a
+    /// replayed response cannot roll the policy back.
+    pub version: u64,
+    pub screenshot_interval_secs: u64,
+    pub activity_flush_secs: u64,
+    pub idle_threshold_secs: u64,
+    /// How often pending screenshots and activity logs are re-uploaded.
+    pub upload_retry_secs: u64,
+    /// How often the policy itself is polled.
+    pub refresh_secs: u64,
+    /// Used for every video start while a server policy is in effect.
+    pub video: VideoSettings,
+    /// Replaces the local work-hours policy when set.
+    pub work_hours: Option<WorkPolicy>,
+}
+
+impl Default for AgentPolicy {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            screenshot_interval_secs: 10,
+            activity_flush_secs: 30,
+            idle_threshold_secs: 120,
+            upload_retry_secs: 300,
+            refresh_secs: DEFAULT_REFRESH.as_secs(),
+            video: VideoSettings::default(),
+            work_hours: None,
+        }
+    }
+}
+
+impl AgentPolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let intervals = [
+            ("screenshot_interval_secs", self.screenshot_interval_secs),
+            ("activity_flush_secs", self.activity_flush_secs),
+            ("idle_threshold_secs", self.idle_threshold_secs),
+            ("upload_retry_secs", self.upload_retry_secs),
+            ("refresh_secs", self.refresh_secs),
+        ];
+        for (name, secs) in intervals {
+            if !(1..=86_400).contains(&secs) {
+                anyhow::bail!("{} must be between 1 and 86400 seconds, got {}", name, secs);
+            }
+        }
+        if !(1..=60).contains(&self.video.fps) {
+            anyhow::bail!("video fps must be between 1 and 60, got {}", self.video.fps);
+        }
+        if !(10..=3600).contains(&self.video.segment_duration) {
+            anyhow::bail!(
+                "video segment_duration must be between 10 and 3600 seconds, got {}",
+                self.video.segment_duration
+            );
+        }
+        if !["Mp4", "Avi", "Webm"].contains(&self.video.container.as_str()) {
+            anyhow::bail!("unknown video container {:?}", self.video.container);
+        }
+        if !["Microphone", "System", "Both"].contains(&self.video.audio_source.as_str()) {
+            anyhow::bail!("unknown audio source {:?}", self.video.audio_source);
+        }
+        if let Some(work_hours) = &self.work_hours {
+            work_hours.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn screenshot_interval(&self) -> Duration {
+        Duration::from_secs(self.screenshot_interval_secs)
+    }
+
+    pub fn activity_flush_interval(&self) -> Duration {
+        Duration::from_secs(self.activity_flush_secs)
+    }
+
+    pub fn idle_threshold(&self) -> Duration {
+        Duration::from_secs(self.idle_threshold_secs)
+    }
+
+    pub fn upload_retry_interval(&self) -> Duration {
+        Duration::from_secs(self.upload_retry_secs)
+    }
+}
+
+/// Response body of `POLICY_URL`. The signature covers the exact bytes of
+/// `payload`, which is the policy as a JSON string.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedPolicy {
+    pub payload: String,
+    /// Base64 ed25519 signature.
+    pub signature: String,
+}
+
+/// The last-known-good file: the signed document as received, so it is
+/// verified again when loaded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredPolicy {
+    etag: Option<String>,
+    #[serde(flatten)]
+    signed: SignedPolicy,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    /// No policy received yet; built-in defaults.
+    Default,
+    /// Loaded from disk at startup; the server has not been reached since.
+    LastKnownGood,
+    Server,
+}
+
+struct PolicyState {
+    policy: AgentPolicy,
+    source: PolicySource,
+    etag: Option<String>,
+    checked_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    refresh_requested: bool,
+}
+
+/// Managed state holding the policy in effect.
+pub struct RemotePolicy {
+    last_known_good: PathBuf,
+    state: Mutex<PolicyState>,
+    wake: Condvar,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PolicyStatus {
+    pub policy: AgentPolicy,
+    pub source: PolicySource,
+    /// Last time the server answered.
+    pub checked_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// `false` in builds without `POLICY_PUBLIC_KEY`.
+    pub enabled: bool,
+}
+
+enum FetchOutcome {
+    NotModified,
+    Updated { signed: SignedPolicy, etag: Option<String> },
+}
+
+fn verifying_key() -> anyhow::Result<VerifyingKey> {
+    let key = POLICY_PUBLIC_KEY.ok_or_else(|| anyhow::anyhow!("built without POLICY_PUBLIC_KEY"))?;
+    let bytes: [u8; 32] = BASE64
+        .decode(key.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("POLICY_PUBLIC_KEY must be 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Checks the signature, then parses and validates the policy.
+fn verify(key: &VerifyingKey, signed: &SignedPolicy) -> anyhow::Result<AgentPolicy> {
+    let signature = Signature::from_slice(&BASE64.decode(signed.signature.trim())?)?;
+    key.verify_strict(signed.payload.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("policy signature is invalid"))?;
+    let policy: AgentPolicy = serde_json::from_str(&signed.payload)?;
+    policy.validate()?;
+    Ok(policy)
+}
+
+fn fetch(client: &Client, etag: Option<&str>) -> anyhow::Result<FetchOutcome> {
+    let mut request = client.get(POLICY_URL).timeout(Duration::from_secs(15));
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request.send()?;
+    match response.status() {
+        StatusCode::NOT_MODIFIED => Ok(FetchOutcome::NotModified),
+        status if status.is_success() => {
+            let etag = response
+                .headers()
+                .get(ETAG)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            Ok(FetchOutcome::Updated {
+                signed: serde_json::from_str(&response.text()?)?,
+                etag,
+            })
+        }
+        status => anyhow::bail!("policy request failed: {}", status),
+    }
+}
+
+impl RemotePolicy {
+    /// Starts from the last-known-good policy in `last_known_good`, if it is
+    /// present and still verifies, or from the defaults.
+    pub fn load(last_known_good: PathBuf) -> Self {
+        let stored = fs::read_to_string(&last_known_good)
+            .ok()
+            .and_then(|json| serde_json::from_str::<StoredPolicy>(&json).ok());
+        let loaded = stored.and_then(|stored| {
+            match verifying_key().and_then(|key| verify(&key, &stored.signed)) {
+                Ok(policy) => Some((policy, stored.etag)),
+                Err(e) => {
+                    log::warn!("Ignoring last-known-good policy: {:#}", e);
+                    None
+                }
+            }
+        });
+
+        let (policy, source, etag) = match loaded {
+            Some((policy, etag)) => {
+                log::info!("Using last-known-good policy version {}", policy.version);
+                (policy, PolicySource::LastKnownGood, etag)
+            }
+            None => (AgentPolicy::default(), PolicySource::Default, None),
+        };
+        Self {
+            last_known_good,
+            state: Mutex::new(PolicyState {
+                policy,
+                source,
+                etag,
+                checked_at: None,
+                last_error: None,
+                refresh_requested: false,
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    pub fn policy(&self) -> AgentPolicy {
+        self.state.lock().unwrap().policy.clone()
+    }
+
+    pub fn status(&self) -> PolicyStatus {
+        let state = self.state.lock().unwrap();
+        PolicyStatus {
+            policy: state.policy.clone(),
+            source: state.source,
+            checked_at: state.checked_at,
+            last_error: state.last_error.clone(),
+            enabled: POLICY_PUBLIC_KEY.is_some(),
+        }
+    }
+
+    /// Wakes the sync thread for an immediate check.
+    pub fn request_refresh(&self) {
+        self.state.lock().unwrap().refresh_requested = true;
+        self.wake.notify_all();
+    }
+
+    fn wait_for_refresh(&self, timeout: Duration) {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .wake
+            .wait_timeout_while(state, timeout, |state| !state.refresh_requested)
+            .unwrap();
+        state.refresh_requested = false;
+    }
+
+    fn save_last_known_good(&self, stored: &StoredPolicy) -> anyhow::Result<()> {
+        if let Some(dir) = self.last_known_good.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename, so a crash never leaves a half-written file.
+        let tmp = self.last_known_good.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(stored)?)?;
+        fs::rename(&tmp, &self.last_known_good)?;
+        Ok(())
+    }
+
+    /// Asks the server for a newer policy. Returns the previous and the new
+    /// policy if it changed.
+    fn sync(&self, client: &Client, key: &VerifyingKey) -> anyhow::Result<Option<(AgentPolicy, AgentPolicy)>> {
+        let etag = self.state.lock().unwrap().etag.clone();
+        match fetch(client, etag.as_deref())? {
+            FetchOutcome::NotModified => {
+                let mut state = self.state.lock().unwrap();
+                state.checked_at = Some(Utc::now());
+                if state.source == PolicySource::LastKnownGood {
+                    state.source = PolicySource::Server;
+                }
+                Ok(None)
+            }
+            FetchOutcome::Updated { signed, etag } => self.receive(key, signed, etag),
+        }
+    }
+
+    /// Verifies a policy from the server and makes it the one in effect,
+    /// unless it is older than that. Returns the previous and the new policy
+    /// if it changed.
+    fn receive(
+        &self,
+        key: &VerifyingKey,
+        signed: SignedPolicy,
+        etag: Option<String>,
+    ) -> anyhow::Result<Option<(AgentPolicy, AgentPolicy)>> {
+        let mut state = self.state.lock().unwrap();
+        let policy = verify(key, &signed)?;
+        state.checked_at = Some(Utc::now());
+
+        if policy.version < state.policy.version {
+            anyhow::bail!(
+                "server sent policy version {}, older than the current {}",
+                policy.version,
+                state.policy.version
+            );
+        }
+
+        self.save_last_known_good(&StoredPolicy {
+            etag: etag.clone(),
+            signed,
+        })?;
+        state.etag = etag;
+        state.source = PolicySource::Server;
+        if policy == state.policy {
+            return Ok(None);
+        }
+        let previous = std::mem::replace(&mut state.policy, policy.clone());
+        Ok(Some((previous, policy)))
+    }
+}
+
+/// The policy in effect, or the defaults if none is managed.
+pub fn current(app: &AppHandle) -> AgentPolicy {
+    app.try_state::<RemotePolicy>()
+        .map(|remote| remote.policy())
+        .unwrap_or_default()
+}
+
+/// Video settings from the server policy, if one is in effect.
+pub fn video_settings(app: &AppHandle) -> Option<VideoSettings> {
+    let remote = app.try_state::<RemotePolicy>()?;
+    let state = remote.state.lock().unwrap();
+    (state.source != PolicySource::Default).then(|| state.policy.video.clone())
+}
+
+/// Applies what cannot simply be read on the next cycle.
+fn apply(app: &AppHandle, previous: &AgentPolicy, policy: &AgentPolicy) {
+    log::info!(
+        "Applying policy version {} (was {})",
+        policy.version,
+        previous.version
+    );
+
+    app.state::<ServiceSupervisor>().settings_changed();
+
+    if previous.work_hours != policy.work_hours {
+        if let Err(e) = app.state::<WorkSchedule>().set_policy(policy.work_hours.clone()) {
+            log::error!("Failed to apply work hours from policy: {:#}", e);
+        }
+    }
+
+    if previous.video != policy.video && app.state::<ServiceSupervisor>().is_active(VIDEO_SERVICE_NAME) {
+        let video_state = &app.state::<MainAppState>().video_state;
+        *video_state.settings.lock().unwrap() = Some(policy.video.clone());
+        video_state.reconfigure.store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(stop_handle) = video_state.stop_handle.lock().unwrap().as_ref() {
+            stop_handle.stop_at_segment_end();
+        }
+        log::info!(
+            "Video switches to {} fps {:?} at the next segment boundary",
+            policy.video.fps,
+            container_from_str(&policy.video.container)
+        );
+    }
+}
+
+/// Policy sync thread.
+pub fn run_policy_sync(app: AppHandle) {
+    let key = match verifying_key() {
+        Ok(key) => key,
+        Err(e) => {
+            log::warn!("Remote policy disabled: {:#}", e);
+            return;
+        }
+    };
+    let remote = app.state::<RemotePolicy>();
+    let client = Client::new();
+
+    loop {
+        match remote.sync(&client, &key) {
+            Ok(Some((previous, policy))) => {
+                remote.state.lock().unwrap().last_error = None;
+                apply(&app, &previous, &policy);
+            }
+            Ok(None) => remote.state.lock().unwrap().last_error = None,
+            Err(e) => {
+                // Keep running on the policy we have.
+                log::warn!("Policy sync failed: {:#}", e);
+                remote.state.lock().unwrap().last_error = Some(format!("{:#}", e));
+            }
+        }
+        let refresh = Duration::from_secs(remote.policy().refresh_secs);
+        remote.wait_for_refresh(refresh);
+    }
+}
+
+#[tauri::command]
+pub fn get_agent_policy(remote: State<'_, RemotePolicy>) -> PolicyStatus {
+    remote.status()
+}
+
+/// Checks the server for a new policy now instead of at the next poll.
+#[tauri::command]
+pub fn refresh_agent_policy(remote: State<'_, RemotePolicy>) -> Result<(), String> {
+    if POLICY_PUBLIC_KEY.is_none() {
+        return Err("Remote policy is disabled in this build.".into());
+    }
+    remote.request_refresh();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// Fixed test keypair; the server's key is never in the tree.
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn sign(key: &SigningKey, payload: String) -> SignedPolicy {
+        let signature = BASE64.encode(key.sign(payload.as_bytes()).to_bytes());
+        SignedPolicy { payload, signature }
+    }
+
+    fn signed(policy: &AgentPolicy) -> SignedPolicy {
+        sign(&signing_key(), serde_json::to_string(policy).unwrap())
+    }
+
+    fn version(version: u64) -> AgentPolicy {
+        AgentPolicy {
+            version,
+            screenshot_interval_secs: 30,
+            ..AgentPolicy::default()
+        }
+    }
+
+    fn last_known_good(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("remote_policy_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("policy.json")
+    }
+
+    #[test]
+    fn signed_policy_verifies() {
+        let key = signing_key().verifying_key();
+        assert_eq!(verify(&key, &signed(&version(3))).unwrap(), version(3));
+    }
+
+    #[test]
+    fn tampered_or_foreign_policy_is_rejected() {
+        let key = signing_key().verifying_key();
+
+        let mut tampered = signed(&version(3));
+        tampered.payload = tampered.payload.replace("\"screenshot_interval_secs\":30", "\"screenshot_interval_secs\":1");
+        assert_ne!(tampered.payload, signed(&version(3)).payload);
+        assert!(verify(&key, &tampered).unwrap_err().to_string().contains("signature"));
+
+        let foreign = sign(&SigningKey::from_bytes(&[8; 32]), signed(&version(3)).payload);
+        assert!(verify(&key, &foreign).is_err());
+
+        let mut garbled = signed(&version(3));
+        garbled.signature = "not base64!".into();
+        assert!(verify(&key, &garbled).is_err());
+    }
+
+    type Change = fn(&mut AgentPolicy);
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let invalid: [(&str, Change); 12] = [
+            ("no screenshot interval", |p| p.screenshot_interval_secs = 0),
+            ("screenshot interval over a day", |p| p.screenshot_interval_secs = 86_401),
+            ("no flush interval", |p| p.activity_flush_secs = 0),
+            ("no idle threshold", |p| p.idle_threshold_secs = 0),
+            ("retry interval over a day", |p| p.upload_retry_secs = 86_401),
+            ("no refresh interval", |p| p.refresh_secs = 0),
+            ("0 fps", |p| p.video.fps = 0),
+            ("61 fps", |p| p.video.fps = 61),
+            ("short segments", |p| p.video.segment_duration = 9),
+            ("long segments", |p| p.video.segment_duration = 3601),
+            ("unknown container", |p| p.video.container = "Mkv".into()),
+            ("unknown audio source", |p| p.video.audio_source = "Line in".into()),
+        ];
+        let key = signing_key().verifying_key();
+        for (what, change) in invalid {
+            let mut policy = version(3);
+            change(&mut policy);
+            assert!(policy.validate().is_err(), "{}", what);
+            // A valid signature does not make the values acceptable.
+            assert!(verify(&key, &signed(&policy)).is_err(), "{}", what);
+        }
+
+        let empty_work_hours: WorkPolicy = serde_json::from_str(
+            r#"{ "timezone": "UTC", "hours": { "Mon": [{ "start": "09:00:00", "end": "09:00:00" }] } }"#,
+        )
+        .unwrap();
+        let policy = AgentPolicy {
+            work_hours: Some(empty_work_hours),
+            ..version(3)
+        };
+        assert!(policy.validate().is_err());
+
+        let mut edges = version(3);
+        edges.screenshot_interval_secs = 86_400;
+        edges.activity_flush_secs = 1;
+        edges.video.fps = 60;
+        edges.video.segment_duration = 10;
+        edges.video.container = "Webm".into();
+        edges.video.audio_source = "System".into();
+        edges.validate().unwrap();
+    }
+
+    #[test]
+    fn older_version_is_rejected_and_the_current_one_kept() {
+        let path = last_known_good("older");
+        let remote = RemotePolicy::load(path.clone());
+        let key = signing_key().verifying_key();
+
+        let (previous, policy) = remote.receive(&key, signed(&version(5)), Some("\"v5\"".into())).unwrap().unwrap();
+        assert_eq!((previous.version, policy.version), (0, 5));
+        assert_eq!(remote.status().source, PolicySource::Server);
+
+        let error = remote.receive(&key, signed(&version(4)), Some("\"v4\"".into())).unwrap_err();
+        assert!(error.to_string().contains("older"), "{}", error);
+        assert_eq!(remote.policy(), version(5));
+
+        // Only the accepted policy was saved as last-known-good.
+        let stored: StoredPolicy = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored.etag.as_deref(), Some("\"v5\""));
+        assert_eq!(verify(&key, &stored.signed).unwrap(), version(5));
+
+        // The same policy again is accepted but is no change.
+        assert!(remote.receive(&key, signed(&version(5)), None).unwrap().is_none());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
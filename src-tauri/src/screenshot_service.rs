@@ -5,94 +5,132 @@ use reqwest::blocking::Client;
 use std::{
     fs,
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::atomic::{AtomicU32, Ordering},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tauri::Manager; // Ensure Manager is imported for app.path()
 use uuid::Uuid;
 use xcap::Monitor;
 
-// This line is crucial: it brings MainAppState from main.rs into scope
-use super::MainAppState;
+use super::capture_blocked_reason;
+use super::remote_policy;
+use super::service_events::{self, ScreenshotCaptured, UploadQueue};
+use super::service_supervisor::{Health, MonitoringService, ServiceHandle, ServiceSupervisor};
 
-// We no longer need this AppState struct here, as MainAppState is managing it.
-// #[derive(Clone)]
-// pub struct AppState {
-//     pub is_running: Arc<Mutex<bool>>,
-// }
+/// Name of this service in the supervisor.
+pub const SERVICE_NAME: &str = "screenshots";
 
-#[tauri::command]
-pub fn start_screenshot_service(app: tauri::AppHandle, state: tauri::State<MainAppState>) {
-    // FIX: Access `screenshot_is_running` from MainAppState
-    let is_running = state.screenshot_is_running.clone();
-    {
-        let mut running = is_running.lock().unwrap();
-        if *running {
-            println!("⚠️ Screenshot service already running");
-            return;
-        }
-        *running = true;
+/// Consecutive failed captures after which the service reports itself degraded.
+const DEGRADED_AFTER_ERRORS: u32 = 3;
+
+#[derive(Default)]
+pub struct ScreenshotService {
+    consecutive_errors: AtomicU32,
+}
+
+impl MonitoringService for ScreenshotService {
+    fn name(&self) -> &'static str {
+        SERVICE_NAME
     }
 
-    thread::spawn(move || {
-        let pending_dir = get_pending_dir(&app);
+    fn run(&self, app: &tauri::AppHandle, handle: &ServiceHandle) -> anyhow::Result<()> {
+        let pending_dir = get_pending_dir(app);
         if !pending_dir.exists() {
-            if let Err(e) = dir::create_all(&pending_dir, false) {
-                eprintln!("❌ Failed to create pending dir: {}", e);
-                return;
-            }
+            dir::create_all(&pending_dir, false)
+                .map_err(|e| anyhow::anyhow!("failed to create pending dir: {}", e))?;
         }
 
         let client = Client::new();
 
         // Retry thread (every 5 min)
         {
+            let retry_app = app.clone();
             let retry_client = client.clone();
             let retry_dir = pending_dir.clone();
-            // FIX: Access `screenshot_is_running` from MainAppState
-            let retry_is_running = is_running.clone(); // `is_running` already holds the Arc to screenshot_is_running
+            let retry_handle = handle.clone();
             thread::spawn(move || loop {
-                {
-                    let running = retry_is_running.lock().unwrap();
-                    if !*running {
-                        println!("🛑 Retry thread stopped");
-                        break;
-                    }
+                if retry_handle.is_stopped() {
+                    log::info!("Retry thread stopped");
+                    break;
                 }
-                println!("\n🔁 ===== RETRY CYCLE STARTED =====");
-                retry_all_pending(&retry_client, &retry_dir);
-                println!("===== RETRY CYCLE ENDED =====\n");
-                thread::sleep(Duration::from_secs(300));
+                let cycle_start = Instant::now();
+                log::info!("===== RETRY CYCLE STARTED =====");
+                retry_all_pending(&retry_app, &retry_client, &retry_dir);
+                log::info!("===== RETRY CYCLE ENDED =====");
+                wait_interval(&retry_handle, cycle_start, || {
+                    remote_policy::current(&retry_app).upload_retry_interval()
+                });
             });
         }
 
         // Screenshot loop (every 10 sec)
         loop {
-            {
-                // FIX: `is_running` already holds the Arc to screenshot_is_running
-                let running = is_running.lock().unwrap();
-                if !*running {
-                    println!("🛑 Screenshot service stopped");
-                    break;
+            if handle.is_stopped() {
+                log::info!("Screenshot service stopped");
+                break;
+            }
+            let cycle_start = Instant::now();
+
+            match take_save_and_try_upload(app, &client, &pending_dir) {
+                Ok(uploaded) => {
+                    self.consecutive_errors.store(0, Ordering::Relaxed);
+                    handle.count("captured");
+                    handle.count(if uploaded { "uploaded" } else { "kept_pending" });
+                }
+                Err(e) => {
+                    self.consecutive_errors.fetch_add(1, Ordering::Relaxed);
+                    handle.count("capture_errors");
+                    log::warn!("Screenshot error: {}", e);
                 }
             }
 
-            if let Err(e) = take_save_and_try_upload(&client, &pending_dir) {
-                eprintln!("⚠️ Screenshot error: {}", e);
+            wait_interval(handle, cycle_start, || remote_policy::current(app).screenshot_interval());
+        }
+        Ok(())
+    }
+
+    /// Nothing to do: both loops watch their run's `ServiceHandle`.
+    fn stop(&self, _app: &tauri::AppHandle) {}
+
+    fn health(&self, _app: &tauri::AppHandle) -> Health {
+        let errors = self.consecutive_errors.load(Ordering::Relaxed);
+        if errors >= DEGRADED_AFTER_ERRORS {
+            Health::Degraded {
+                reason: format!("the last {} screenshots failed", errors),
             }
+        } else {
+            Health::Healthy
+        }
+    }
+}
 
-            thread::sleep(Duration::from_secs(10));
+/// Sleeps until `interval()` has passed since `cycle_start`, or the run is
+/// stopped. The interval is read again whenever the settings change, so a
+/// new policy applies to the wait already in progress.
+fn wait_interval(handle: &ServiceHandle, cycle_start: Instant, interval: impl Fn() -> Duration) {
+    while let Some(left) = interval().checked_sub(cycle_start.elapsed()) {
+        if left.is_zero() || handle.wait_stopped(left) {
+            return;
         }
-    });
+    }
 }
 
 #[tauri::command]
-pub fn stop_screenshot_service(state: tauri::State<MainAppState>) {
-    // FIX: Access `screenshot_is_running` from MainAppState
-    let mut running = state.screenshot_is_running.lock().unwrap();
-    *running = false;
-    println!("🛑 Screenshot service manually stopped");
+pub fn start_screenshot_service(app: tauri::AppHandle, supervisor: tauri::State<ServiceSupervisor>) {
+    if let Some(reason) = capture_blocked_reason(&app) {
+        log::info!("Screenshot service not started: {}", reason);
+        return;
+    }
+    if !supervisor.start(&app, SERVICE_NAME) {
+        log::warn!("Screenshot service already running");
+    }
+}
+
+#[tauri::command]
+pub fn stop_screenshot_service(app: tauri::AppHandle, supervisor: tauri::State<ServiceSupervisor>) {
+    supervisor.stop(&app, SERVICE_NAME);
+    log::info!("Screenshot service manually stopped");
 }
 
 fn get_pending_dir(app: &tauri::AppHandle) -> PathBuf {
@@ -112,10 +150,13 @@ fn get_today_folder(base_dir: &PathBuf) -> PathBuf {
     ))
 }
 
+/// Takes one screenshot and tries to upload it. Returns whether the upload
+/// succeeded; if not, the file stays in the pending folder.
 fn take_save_and_try_upload(
+    app: &tauri::AppHandle,
     client: &Client,
     base_dir: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<bool, Box<dyn std::error::Error>> {
     let monitors = Monitor::all()?;
     let monitor = monitors.first().ok_or("No monitor found")?;
     let rgba_image: RgbaImage = monitor.capture_image()?;
@@ -128,21 +169,34 @@ fn take_save_and_try_upload(
     let filepath = today_dir.join(&filename);
 
     rgba_image.save(&filepath)?;
-    println!("📸 Screenshot saved: {}", filepath.display());
+    log::info!("Screenshot saved: {}", filepath.display());
+    service_events::emit(
+        app,
+        ScreenshotCaptured {
+            path: filepath.clone(),
+            captured_at: Utc::now(),
+        },
+    );
 
-    match try_upload_file(client, &filepath) {
+    let result = try_upload_file(client, &filepath);
+    service_events::report_upload_result(app, UploadQueue::Screenshots, &filepath, &result);
+    let uploaded = match result {
         Ok(_) => {
-            println!("✅ Uploaded immediately: {}", filename);
+            log::info!("Uploaded immediately: {}", filename);
             if let Err(e) = fs::remove_file(&filepath) {
-                eprintln!("⚠️ Failed to delete {}: {}", filepath.display(), e);
+                log::warn!("Failed to delete {}: {}", filepath.display(), e);
             } else {
-                println!("🗑️ Deleted after successful upload: {}", filename);
+                log::info!("Deleted after successful upload: {}", filename);
             }
+            true
         }
-        Err(e) => println!("💾 Upload failed, kept on disk: {} - {}", filename, e),
-    }
-
-    Ok(())
+        Err(e) => {
+            log::warn!("Upload failed, kept on disk: {} - {}", filename, e);
+            false
+        }
+    };
+    service_events::update_queue_depth(app, UploadQueue::Screenshots);
+    Ok(uploaded)
 }
 
 fn try_upload_file(client: &Client, filepath: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
@@ -169,18 +223,18 @@ fn try_upload_file(client: &Client, filepath: &PathBuf) -> Result<(), Box<dyn st
     let text = response.text().unwrap_or_default();
 
     if status.is_success() {
-        println!("✅ Upload success: {} ({})", filename, status);
+        log::info!("Upload success: {} ({})", filename, status);
         Ok(())
     } else {
         Err(format!("Upload failed: {} ({}) - {}", filename, status, text).into())
     }
 }
 
-pub fn retry_all_pending(client: &Client, base_dir: &PathBuf) {
+pub fn retry_all_pending(app: &tauri::AppHandle, client: &Client, base_dir: &PathBuf) {
     let date_dirs = match fs::read_dir(base_dir) {
         Ok(dirs) => dirs,
         Err(e) => {
-            eprintln!("⚠️ Failed to read pending dir: {}", e);
+            log::warn!("Failed to read pending dir: {}", e);
             return;
         }
     };
@@ -198,7 +252,7 @@ pub fn retry_all_pending(client: &Client, base_dir: &PathBuf) {
         let files = match fs::read_dir(&dir_path) {
             Ok(f) => f,
             Err(e) => {
-                eprintln!("⚠️ Failed to read folder {}: {}", dir_path.display(), e);
+                log::warn!("Failed to read folder {}: {}", dir_path.display(), e);
                 continue;
             }
         };
@@ -215,8 +269,8 @@ pub fn retry_all_pending(client: &Client, base_dir: &PathBuf) {
 
         png_files.sort();
         total_found += png_files.len();
-        println!(
-            "📂 Found {} pending in {}",
+        log::info!(
+            "Found {} pending in {}",
             png_files.len(),
             dir_path.display()
         );
@@ -226,29 +280,32 @@ pub fn retry_all_pending(client: &Client, base_dir: &PathBuf) {
                 .file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown.png");
-            match try_upload_file(client, &file) {
+            let result = try_upload_file(client, &file);
+            service_events::report_upload_result(app, UploadQueue::Screenshots, &file, &result);
+            match result {
                 Ok(_) => {
-                    println!("✅ Retry upload success: {}", filename);
+                    log::info!("Retry upload success: {}", filename);
                     total_uploaded += 1;
                     if let Err(e) = fs::remove_file(&file) {
-                        eprintln!("⚠️ Failed to delete {}: {}", filename, e);
+                        log::warn!("Failed to delete {}: {}", filename, e);
                     } else {
-                        println!("🗑️ Deleted after successful retry: {}", filename);
+                        log::info!("Deleted after successful retry: {}", filename);
                     }
                 }
                 Err(e) => {
-                    println!("❌ Retry failed: {} - {}", filename, e);
+                    log::warn!("Retry failed: {} - {}", filename, e);
                     total_failed += 1;
                 }
             }
         }
     }
 
-    println!(
-        "📊 Retry summary: Found={}, Uploaded={}, Failed={}, Remaining={}",
+    log::info!(
+        "Retry summary: Found={}, Uploaded={}, Failed={}, Remaining={}",
         total_found,
         total_uploaded,
         total_failed,
         total_found - total_uploaded
     );
+    service_events::update_queue_depth(app, UploadQueue::Screenshots);
 }
@@ -0,0 +1,212 @@
+// src/service_events.rs
+
+//! Events pushed to the frontend, so the UI can show live service status
+//! instead of guessing it from the commands it sent. Each payload type
+//! carries the name of the Tauri event it is emitted as.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+pub trait ServiceEvent: Serialize + Clone {
+    /// Event name the frontend listens for.
+    const NAME: &'static str;
+}
+
+/// A folder of files waiting to be uploaded.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadQueue {
+    Screenshots,
+    ActivityLogs,
+    Videos,
+    Heartbeats,
+}
+
+impl UploadQueue {
+    pub const ALL: [UploadQueue; 4] = [
+        UploadQueue::Screenshots,
+        UploadQueue::ActivityLogs,
+        UploadQueue::Videos,
+        UploadQueue::Heartbeats,
+    ];
+
+    /// Folder in the app data dir holding this queue.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            UploadQueue::Screenshots => "screenshots_pending",
+            UploadQueue::ActivityLogs => "activity_logs_pending",
+            UploadQueue::Videos => "video_recordings_pending",
+            UploadQueue::Heartbeats => "heartbeats_pending",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScreenshotCaptured {
+    pub path: PathBuf,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl ServiceEvent for ScreenshotCaptured {
+    const NAME: &'static str = "screenshot-captured";
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UploadSucceeded {
+    pub queue: UploadQueue,
+    pub path: PathBuf,
+}
+
+impl ServiceEvent for UploadSucceeded {
+    const NAME: &'static str = "upload-succeeded";
+}
+
+/// The file stays queued and is retried later.
+#[derive(Serialize, Debug, Clone)]
+pub struct UploadFailed {
+    pub queue: UploadQueue,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+impl ServiceEvent for UploadFailed {
+    const NAME: &'static str = "upload-failed";
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SegmentFinalized {
+    pub path: PathBuf,
+    pub frames: u64,
+    pub dropped_frames: u64,
+    pub unchanged_frames: u64,
+    pub achieved_fps: f64,
+}
+
+impl ServiceEvent for SegmentFinalized {
+    const NAME: &'static str = "segment-finalized";
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ServiceCrashed {
+    pub service: &'static str,
+    pub error: String,
+    pub panicked: bool,
+    /// `false` if the service was stopped meanwhile or the supervisor gave up on it.
+    pub restarting: bool,
+}
+
+impl ServiceEvent for ServiceCrashed {
+    const NAME: &'static str = "service-crashed";
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueDepthChanged {
+    pub queue: UploadQueue,
+    /// Files waiting for upload.
+    pub pending: usize,
+}
+
+impl ServiceEvent for QueueDepthChanged {
+    const NAME: &'static str = "queue-depth-changed";
+}
+
+pub fn emit<E: ServiceEvent>(app: &AppHandle, event: E) {
+    if let Err(e) = app.emit(E::NAME, event) {
+        log::warn!("Failed to emit {}: {}", E::NAME, e);
+    }
+}
+
+/// Last upload outcomes of one queue, reported in the heartbeat.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct UploadOutcomes {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Managed state with the last upload outcomes per queue.
+#[derive(Default)]
+pub struct UploadHistory(Mutex<HashMap<UploadQueue, UploadOutcomes>>);
+
+impl UploadHistory {
+    pub fn get(&self, queue: UploadQueue) -> UploadOutcomes {
+        self.0.lock().unwrap().get(&queue).cloned().unwrap_or_default()
+    }
+}
+
+/// Emits `upload-succeeded` or `upload-failed` for an upload attempt and
+/// records the outcome in `UploadHistory`.
+pub fn report_upload_result<E: std::fmt::Display>(
+    app: &AppHandle,
+    queue: UploadQueue,
+    path: &Path,
+    result: &Result<(), E>,
+) {
+    if let Some(history) = app.try_state::<UploadHistory>() {
+        let mut history = history.0.lock().unwrap();
+        let outcomes = history.entry(queue).or_default();
+        match result {
+            Ok(()) => outcomes.last_success = Some(Utc::now()),
+            Err(e) => {
+                outcomes.last_failure = Some(Utc::now());
+                outcomes.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    let path = path.to_path_buf();
+    match result {
+        Ok(()) => emit(app, UploadSucceeded { queue, path }),
+        Err(e) => emit(
+            app,
+            UploadFailed {
+                queue,
+                path,
+                error: e.to_string(),
+            },
+        ),
+    }
+}
+
+/// Managed state with the last depth sent per queue, so unchanged depths
+/// are not sent again.
+#[derive(Default)]
+pub struct QueueDepths(Mutex<HashMap<UploadQueue, usize>>);
+
+/// Recounts the files in `queue` and emits `queue-depth-changed` if the
+/// count differs from the last one sent.
+pub fn update_queue_depth(app: &AppHandle, queue: UploadQueue) {
+    let Ok(app_data_dir) = app.path().app_data_dir() else {
+        return;
+    };
+    let pending = count_files(&app_data_dir.join(queue.dir_name()));
+    if let Some(depths) = app.try_state::<QueueDepths>() {
+        if depths.0.lock().unwrap().insert(queue, pending) == Some(pending) {
+            return;
+        }
+    }
+    emit(app, QueueDepthChanged { queue, pending });
+}
+
+/// Files anywhere below `dir`; pending files live in dated subfolders.
+fn count_files(dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                count_files(&path)
+            } else {
+                1
+            }
+        })
+        .sum()
+}
@@ -0,0 +1,611 @@
+// src/service_supervisor.rs
+
+//! Runs the monitoring services on supervised threads.
+//!
+//! Each service implements `MonitoringService`: `run` does the work on the
+//! calling thread until asked to `stop`. The supervisor owns that thread,
+//! catches panics and errors, restarts the service with exponential backoff
+//! and gives up after `MAX_CONSECUTIVE_FAILURES`, so a crashed service is
+//! reported as failed instead of looking "running" forever.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use super::service_events::{self, ServiceCrashed};
+
+/// Delay before the first restart; doubled after every further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A run that lasted this long counts as healthy and resets the backoff.
+const STABLE_RUN: Duration = Duration::from_secs(120);
+
+/// Failures in a row after which the supervisor stops restarting.
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+pub trait MonitoringService: Send + Sync + 'static {
+    /// Stable identifier used in status reports and by `start`/`stop`.
+    fn name(&self) -> &'static str;
+
+    /// Does the service's work on the calling thread and returns once `stop`
+    /// has been called. Returning an error (or panicking) counts as a crash.
+    fn run(&self, app: &AppHandle, handle: &ServiceHandle) -> anyhow::Result<()>;
+
+    /// Asks a running `run` to return. Must not block.
+    fn stop(&self, app: &AppHandle);
+
+    /// Problems a running service can report without crashing.
+    fn health(&self, _app: &AppHandle) -> Health {
+        Health::Healthy
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    Stopped,
+    Running,
+    /// Stop was requested; waiting for `run` to return.
+    Stopping,
+    /// Crashed; waiting out the backoff before the next attempt.
+    Restarting,
+    /// Crashed too often in a row and was given up on.
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Health {
+    Healthy,
+    Degraded { reason: String },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ServiceStatus {
+    pub name: &'static str,
+    pub state: ServiceState,
+    pub health: Health,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Start of the current run, if running.
+    pub running_since: Option<DateTime<Utc>>,
+    pub uptime_secs: u64,
+    pub restarts: u32,
+    pub panics: u32,
+    /// Service-specific counters, e.g. screenshots taken.
+    pub counters: BTreeMap<&'static str, u64>,
+}
+
+struct Inner {
+    state: ServiceState,
+    /// Whether the service should be running. Cleared by `stop`.
+    desired: bool,
+    /// Bumped by every `stop`, so a run that returned because of a stop
+    /// followed by a quick `start` is rerun rather than treated as finished.
+    stops: u64,
+    /// Bumped whenever a run returns, crashed or not, so threads the run
+    /// started see its handle stopped before the next run begins.
+    runs: u64,
+    /// Bumped by `ServiceSupervisor::settings_changed`.
+    settings_changes: u64,
+    running_since: Option<(DateTime<Utc>, Instant)>,
+    last_error: Option<(String, DateTime<Utc>)>,
+    restarts: u32,
+    panics: u32,
+    counters: BTreeMap<&'static str, u64>,
+    /// Set while a worker thread owns the service. Only the worker clears
+    /// it, under the lock, once it has decided to exit.
+    worker_alive: bool,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Supervised {
+    service: Arc<dyn MonitoringService>,
+    inner: Mutex<Inner>,
+    wake: Condvar,
+}
+
+/// Given to `MonitoringService::run` for reporting progress and noticing
+/// `stop`. Each run gets its own handle, tied to the stops seen when it
+/// started, so a run stopped and then replaced by a quick `start` still
+/// sees its stop. The handle also counts as stopped once its run has
+/// returned, so helper threads holding a clone end with the run instead
+/// of piling up across crash restarts.
+#[derive(Clone)]
+pub struct ServiceHandle {
+    supervised: Arc<Supervised>,
+    generation: u64,
+    run: u64,
+}
+
+impl ServiceHandle {
+    /// Whether `stop` was called since this run started, or the run has
+    /// returned.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped(&self.supervised.inner.lock().unwrap())
+    }
+
+    fn stopped(&self, inner: &Inner) -> bool {
+        inner.stops != self.generation || inner.runs != self.run
+    }
+
+    /// Sleeps for up to `timeout`, waking early if this run is stopped or
+    /// the settings change. Returns whether it was stopped.
+    pub fn wait_stopped(&self, timeout: Duration) -> bool {
+        let inner = self.supervised.inner.lock().unwrap();
+        let changes = inner.settings_changes;
+        let (inner, _) = self
+            .supervised
+            .wake
+            .wait_timeout_while(inner, timeout, |inner| {
+                !self.stopped(inner) && inner.settings_changes == changes
+            })
+            .unwrap();
+        self.stopped(&inner)
+    }
+
+    /// Adds one to a named counter shown in the service status.
+    pub fn count(&self, counter: &'static str) {
+        self.add(counter, 1);
+    }
+
+    pub fn add(&self, counter: &'static str, n: u64) {
+        *self
+            .supervised
+            .inner
+            .lock()
+            .unwrap()
+            .counters
+            .entry(counter)
+            .or_default() += n;
+    }
+}
+
+impl Supervised {
+    /// Records that the service should run. `None` if it already was;
+    /// otherwise whether a new worker thread is needed.
+    fn begin_start(&self) -> Option<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.desired {
+            return None;
+        }
+        inner.desired = true;
+        inner.state = ServiceState::Running;
+
+        // A worker that is still winding down picks the new start up itself
+        // once its current run returns.
+        if inner.worker_alive {
+            self.wake.notify_all();
+            return Some(false);
+        }
+        inner.worker_alive = true;
+        Some(true)
+    }
+
+    fn settings_changed(&self) {
+        self.inner.lock().unwrap().settings_changes += 1;
+        self.wake.notify_all();
+    }
+
+    fn request_stop(&self) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.desired = false;
+            inner.stops += 1;
+            if matches!(inner.state, ServiceState::Running | ServiceState::Restarting) {
+                inner.state = ServiceState::Stopping;
+            }
+        }
+        self.wake.notify_all();
+    }
+
+    /// Called by the worker before each run. Returns the run's handle, or
+    /// `None` (and retires the worker) if a stop came in before the run
+    /// could start.
+    fn begin_run(self: &Arc<Self>) -> Option<ServiceHandle> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.desired {
+            inner.state = ServiceState::Stopped;
+            inner.worker_alive = false;
+            return None;
+        }
+        inner.running_since = Some((Utc::now(), Instant::now()));
+        inner.state = ServiceState::Running;
+        Some(ServiceHandle {
+            supervised: self.clone(),
+            generation: inner.stops,
+            run: inner.runs,
+        })
+    }
+
+    /// Called by the worker once a run has returned. Stops the run's
+    /// handle and returns the state, still locked, for deciding what next.
+    fn end_run(&self) -> MutexGuard<'_, Inner> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.running_since = None;
+        inner.runs += 1;
+        self.wake.notify_all();
+        inner
+    }
+}
+
+pub struct ServiceSupervisor {
+    services: Vec<Arc<Supervised>>,
+}
+
+impl ServiceSupervisor {
+    pub fn new(services: Vec<Arc<dyn MonitoringService>>) -> Self {
+        Self {
+            services: services
+                .into_iter()
+                .map(|service| {
+                    Arc::new(Supervised {
+                        service,
+                        inner: Mutex::new(Inner {
+                            state: ServiceState::Stopped,
+                            desired: false,
+                            stops: 0,
+                            runs: 0,
+                            settings_changes: 0,
+                            running_since: None,
+                            last_error: None,
+                            restarts: 0,
+                            panics: 0,
+                            counters: BTreeMap::new(),
+                            worker_alive: false,
+                            thread: None,
+                        }),
+                        wake: Condvar::new(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Arc<Supervised>> {
+        self.services.iter().find(|s| s.service.name() == name)
+    }
+
+    /// Starts the service unless it is already running. Returns `false` if
+    /// it was.
+    pub fn start(&self, app: &AppHandle, name: &str) -> bool {
+        let Some(supervised) = self.find(name) else {
+            log::error!("Unknown service: {}", name);
+            return false;
+        };
+
+        match supervised.begin_start() {
+            None => false,
+            Some(false) => true,
+            Some(true) => {
+                let worker = supervised.clone();
+                let app = app.clone();
+                let thread = thread::spawn(move || supervise(app, worker));
+                supervised.inner.lock().unwrap().thread = Some(thread);
+                true
+            }
+        }
+    }
+
+    /// Asks the service to stop. Does not wait for it.
+    pub fn stop(&self, app: &AppHandle, name: &str) {
+        let Some(supervised) = self.find(name) else {
+            return;
+        };
+        supervised.request_stop();
+        supervised.service.stop(app);
+    }
+
+    /// Wakes every run waiting in `ServiceHandle::wait_stopped`, so it
+    /// picks up new settings, e.g. a shorter interval, without waiting out
+    /// the old one.
+    pub fn settings_changed(&self) {
+        for supervised in &self.services {
+            supervised.settings_changed();
+        }
+    }
+
+    /// Whether the service is meant to be running (including while it waits
+    /// to be restarted).
+    pub fn is_active(&self, name: &str) -> bool {
+        self.find(name)
+            .is_some_and(|s| s.inner.lock().unwrap().desired)
+    }
+
+    /// Stops every service and waits up to `timeout` for their threads, so
+    /// buffered data is flushed before the process exits.
+    pub fn shutdown(&self, app: &AppHandle, timeout: Duration) {
+        for supervised in &self.services {
+            self.stop(app, supervised.service.name());
+        }
+        let deadline = Instant::now() + timeout;
+        for supervised in &self.services {
+            let handle = supervised.inner.lock().unwrap().thread.take();
+            if let Some(handle) = handle {
+                while !handle.is_finished() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                if handle.is_finished() {
+                    let _ = handle.join();
+                } else {
+                    log::warn!("{} did not stop in time", supervised.service.name());
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, app: &AppHandle) -> Vec<ServiceStatus> {
+        self.services
+            .iter()
+            .map(|supervised| {
+                let inner = supervised.inner.lock().unwrap();
+                let running = inner.state == ServiceState::Running;
+                ServiceStatus {
+                    name: supervised.service.name(),
+                    state: inner.state,
+                    health: if running {
+                        supervised.service.health(app)
+                    } else {
+                        Health::Healthy
+                    },
+                    last_error: inner.last_error.as_ref().map(|(e, _)| e.clone()),
+                    last_error_at: inner.last_error.as_ref().map(|(_, at)| *at),
+                    running_since: inner.running_since.map(|(at, _)| at),
+                    uptime_secs: inner
+                        .running_since
+                        .map_or(0, |(_, started)| started.elapsed().as_secs()),
+                    restarts: inner.restarts,
+                    panics: inner.panics,
+                    counters: inner.counters.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Worker thread: runs the service, restarting it after crashes until it is
+/// stopped or has failed too often.
+fn supervise(app: AppHandle, supervised: Arc<Supervised>) {
+    let name = supervised.service.name();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut failures = 0u32;
+
+    loop {
+        let Some(handle) = supervised.begin_run() else {
+            log::info!("{} stopped before it ran", name);
+            return;
+        };
+
+        let started = Instant::now();
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| supervised.service.run(&app, &handle)));
+
+        let mut inner = supervised.end_run();
+
+        let panicked = outcome.is_err();
+        let error = match outcome {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{:#}", e)),
+            Err(payload) => {
+                inner.panics += 1;
+                Some(format!("panicked: {}", panic_message(payload.as_ref())))
+            }
+        };
+
+        match error {
+            // Stopped and started again while it was winding down.
+            None if inner.desired && inner.stops != handle.generation => continue,
+            None => {
+                inner.desired = false;
+                inner.state = ServiceState::Stopped;
+                log::info!("{} stopped", name);
+            }
+            Some(error) => {
+                log::error!("{} crashed: {}", name, error);
+                inner.last_error = Some((error.clone(), Utc::now()));
+
+                if started.elapsed() >= STABLE_RUN {
+                    backoff = INITIAL_BACKOFF;
+                    failures = 0;
+                }
+                failures += 1;
+
+                let restarting = inner.desired && failures < MAX_CONSECUTIVE_FAILURES;
+                service_events::emit(
+                    &app,
+                    ServiceCrashed {
+                        service: name,
+                        error,
+                        panicked,
+                        restarting,
+                    },
+                );
+
+                if !inner.desired {
+                    inner.state = ServiceState::Stopped;
+                } else if !restarting {
+                    log::error!("{} failed {} times in a row, giving up", name, failures);
+                    inner.desired = false;
+                    inner.state = ServiceState::Failed;
+                } else {
+                    inner.state = ServiceState::Restarting;
+                    inner = supervised
+                        .wake
+                        .wait_timeout_while(inner, backoff, |inner| inner.desired)
+                        .unwrap()
+                        .0;
+                    if inner.desired {
+                        inner.restarts += 1;
+                        drop(inner);
+                        log::info!("Restarting {} after {:?}", name, backoff);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        continue;
+                    }
+                    inner.state = ServiceState::Stopped;
+                }
+            }
+        }
+        drop(inner);
+
+        // Let the service clear its own "running" flags, which a crash may
+        // have left set.
+        supervised.service.stop(&app);
+
+        let mut inner = supervised.inner.lock().unwrap();
+        if inner.desired {
+            // Started again in the meantime.
+            continue;
+        }
+        inner.worker_alive = false;
+        return;
+    }
+}
+
+#[tauri::command]
+pub fn get_services_status(app: AppHandle, supervisor: State<'_, ServiceSupervisor>) -> Vec<ServiceStatus> {
+    supervisor.status(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Idle;
+
+    impl MonitoringService for Idle {
+        fn name(&self) -> &'static str {
+            "idle"
+        }
+
+        fn run(&self, _app: &AppHandle, _handle: &ServiceHandle) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn stop(&self, _app: &AppHandle) {}
+    }
+
+    fn supervised() -> Arc<Supervised> {
+        ServiceSupervisor::new(vec![Arc::new(Idle)]).services.remove(0)
+    }
+
+    #[test]
+    fn stop_before_run_retires_the_worker() {
+        let supervised = supervised();
+        assert_eq!(supervised.begin_start(), Some(true));
+        supervised.request_stop();
+
+        assert!(supervised.begin_run().is_none());
+        let inner = supervised.inner.lock().unwrap();
+        assert_eq!(inner.state, ServiceState::Stopped);
+        assert!(!inner.worker_alive);
+    }
+
+    #[test]
+    fn stop_then_start_stops_the_old_run_only() {
+        let supervised = supervised();
+        assert_eq!(supervised.begin_start(), Some(true));
+        let old = supervised.begin_run().unwrap();
+        assert!(!old.is_stopped());
+
+        supervised.request_stop();
+        // The worker is still inside the old run, so no new thread.
+        assert_eq!(supervised.begin_start(), Some(false));
+        assert!(old.is_stopped());
+        assert!(old.wait_stopped(Duration::from_secs(60)));
+
+        let new = supervised.begin_run().unwrap();
+        assert!(!new.is_stopped());
+        assert!(!new.wait_stopped(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn restart_after_a_crash_stops_the_old_run() {
+        let supervised = supervised();
+        supervised.begin_start();
+        let crashed = supervised.begin_run().unwrap();
+        // A helper thread of the run keeps a clone of its handle.
+        let helper = crashed.clone();
+
+        // The run crashed; the worker restarts it after the backoff
+        // without any stop being requested.
+        drop(supervised.end_run());
+        assert!(supervised.inner.lock().unwrap().desired);
+        let restarted = supervised.begin_run().unwrap();
+
+        assert!(helper.is_stopped());
+        assert!(helper.wait_stopped(Duration::from_secs(60)));
+        assert!(!restarted.is_stopped());
+        assert!(!restarted.wait_stopped(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn wait_stopped_wakes_when_the_run_returns() {
+        let supervised = supervised();
+        supervised.begin_start();
+        let handle = supervised.begin_run().unwrap();
+
+        let worker = {
+            let supervised = supervised.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                drop(supervised.end_run());
+            })
+        };
+        let started = Instant::now();
+        assert!(handle.wait_stopped(Duration::from_secs(60)));
+        assert!(started.elapsed() < Duration::from_secs(30));
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn wait_stopped_wakes_on_stop() {
+        let supervised = supervised();
+        supervised.begin_start();
+        let handle = supervised.begin_run().unwrap();
+
+        let stopper = {
+            let supervised = supervised.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                supervised.request_stop();
+            })
+        };
+        let started = Instant::now();
+        assert!(handle.wait_stopped(Duration::from_secs(60)));
+        assert!(started.elapsed() < Duration::from_secs(30));
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn wait_stopped_wakes_on_settings_change() {
+        let supervised = supervised();
+        supervised.begin_start();
+        let handle = supervised.begin_run().unwrap();
+
+        let changer = {
+            let supervised = supervised.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                supervised.settings_changed();
+            })
+        };
+        let started = Instant::now();
+        assert!(!handle.wait_stopped(Duration::from_secs(60)));
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert!(!handle.is_stopped());
+        changer.join().unwrap();
+    }
+}
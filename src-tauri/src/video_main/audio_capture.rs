@@ -0,0 +1,374 @@
+// src/video_main/audio_capture.rs
+
+//! System (WASAPI loopback) and microphone capture shared by the MP4 and
+//! WebM writers. Both sources are buffered as interleaved 16-bit PCM at
+//! `SAMPLE_RATE`/`CHANNELS` and mixed when a writer takes them.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+#[cfg(feature = "audio_capture")]
+use windows::Win32::Media::Audio::{
+    eCapture, eConsole, eRender, IAudioCaptureClient, IAudioClient, IMMDeviceEnumerator,
+    MMDeviceEnumerator, AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_LOOPBACK,
+};
+#[cfg(feature = "audio_capture")]
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL,
+    COINIT_MULTITHREADED,
+};
+
+#[cfg_attr(not(any(target_os = "windows", feature = "webm")), allow(dead_code))]
+pub const SAMPLE_RATE: u32 = 48_000;
+pub const CHANNELS: u32 = 2;
+
+// --- START: VOLUME CONTROL CONSTANTS ---
+// Adjust these values to change the volume balance in the final video.
+// 2.0 = 200% volume (louder), 0.5 = 50% volume (quieter)
+const MIC_VOLUME: f32 = 2.5;
+const SYSTEM_VOLUME: f32 = 0.09;
+// --- END: VOLUME CONTROL CONSTANTS ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioSource {
+    Microphone,
+    System,
+    Both,
+}
+
+/// Running capture threads for one `AudioSource`. Stops on drop.
+pub struct AudioCapture {
+    source: AudioSource,
+    stop_signal: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    system_buffer: Arc<Mutex<Vec<i16>>>,
+    mic_buffer: Arc<Mutex<Vec<i16>>>,
+}
+
+impl AudioCapture {
+    /// Starts capturing. A device that fails to open is logged and yields
+    /// silence, so a missing microphone never stops the video.
+    pub fn start(source: AudioSource) -> Self {
+        #[cfg_attr(not(feature = "audio_capture"), allow(unused_mut))]
+        let mut capture = Self {
+            source,
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            threads: Vec::new(),
+            system_buffer: Arc::new(Mutex::new(Vec::new())),
+            mic_buffer: Arc::new(Mutex::new(Vec::new())),
+        };
+
+        #[cfg(feature = "audio_capture")]
+        {
+            log::info!("Starting audio capture with source: {:?}", source);
+
+            // Start WASAPI system audio capture (loopback)
+            if source == AudioSource::System || source == AudioSource::Both {
+                let buffer = capture.system_buffer.clone();
+                let stop_signal = capture.stop_signal.clone();
+                capture.threads.push(std::thread::spawn(move || {
+                    if let Err(e) = capture_system_audio_wasapi(stop_signal, buffer) {
+                        log::error!("System audio capture failed: {:?}", e);
+                    }
+                }));
+                log::info!("✓ Started WASAPI system audio loopback thread");
+            }
+
+            // Start WASAPI microphone capture
+            if source == AudioSource::Microphone || source == AudioSource::Both {
+                let buffer = capture.mic_buffer.clone();
+                let stop_signal = capture.stop_signal.clone();
+                capture.threads.push(std::thread::spawn(move || {
+                    if let Err(e) = capture_microphone_wasapi(stop_signal, buffer) {
+                        log::error!("Microphone capture failed: {:?}", e);
+                    }
+                }));
+                log::info!("✓ Started WASAPI microphone capture thread");
+            }
+        }
+
+        #[cfg(not(feature = "audio_capture"))]
+        log::warn!("Audio requested but audio_capture feature not enabled!");
+
+        capture
+    }
+
+    /// Takes `frames` interleaved frames, mixing both sources for
+    /// `AudioSource::Both`. Whatever has not been captured yet is silence.
+    pub fn take_mixed(&self, frames: usize) -> Vec<i16> {
+        let samples_needed_total = frames * CHANNELS as usize;
+        match self.source {
+            AudioSource::System => take_padded(&self.system_buffer, samples_needed_total),
+            AudioSource::Microphone => take_padded(&self.mic_buffer, samples_needed_total),
+            AudioSource::Both => {
+                // PROPER MIXING: Weighted mix with volume control
+                let sys_data = take_padded(&self.system_buffer, samples_needed_total);
+                let mic_data = take_padded(&self.mic_buffer, samples_needed_total);
+                sys_data
+                    .iter()
+                    .zip(&mic_data)
+                    .map(|(&sys, &mic)| {
+                        let mixed = (sys as f32 * SYSTEM_VOLUME) + (mic as f32 * MIC_VOLUME);
+                        mixed.clamp(-32768.0, 32767.0) as i16
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Interleaved frames captured and not taken yet; for
+    /// `AudioSource::Both`, those of the source that is furthest ahead.
+    pub fn buffered_frames(&self) -> usize {
+        let buffered = |buffer: &Mutex<Vec<i16>>| buffer.lock().unwrap().len();
+        let samples = match self.source {
+            AudioSource::System => buffered(&self.system_buffer),
+            AudioSource::Microphone => buffered(&self.mic_buffer),
+            AudioSource::Both => buffered(&self.system_buffer).max(buffered(&self.mic_buffer)),
+        };
+        samples / CHANNELS as usize
+    }
+
+    /// Stops the capture threads and waits for them.
+    pub fn stop(&mut self) {
+        self.stop_signal.store(true, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn take_padded(buffer: &Mutex<Vec<i16>>, len: usize) -> Vec<i16> {
+    let mut buf = buffer.lock().unwrap();
+    let to_take = buf.len().min(len);
+    let mut data: Vec<i16> = buf.drain(..to_take).collect();
+    data.resize(len, 0);
+    data
+}
+
+// WASAPI System Audio Capture (Loopback)
+#[cfg(feature = "audio_capture")]
+fn capture_system_audio_wasapi(
+    stop_signal: Arc<AtomicBool>,
+    mix_buffer: Arc<Mutex<Vec<i16>>>,
+) -> anyhow::Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+
+        // Get default output device
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+        let audio_client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
+        let wave_format_ptr = audio_client.GetMixFormat()?;
+        let wave_format = *wave_format_ptr;
+
+        let sample_rate = wave_format.nSamplesPerSec;
+        let channels = wave_format.nChannels;
+        let bits_per_sample = wave_format.wBitsPerSample;
+
+        log::info!(
+            "System audio format: {}Hz, {} channels, {} bits",
+            sample_rate,
+            channels,
+            bits_per_sample
+        );
+
+        audio_client.Initialize(
+            AUDCLNT_SHAREMODE_SHARED,
+            AUDCLNT_STREAMFLAGS_LOOPBACK,
+            200_000_000, // 2 seconds buffer
+            0,
+            wave_format_ptr,
+            None,
+        )?;
+
+        CoTaskMemFree(Some(wave_format_ptr as *const _));
+
+        let capture_client: IAudioCaptureClient = audio_client.GetService()?;
+        audio_client.Start()?;
+
+        log::info!("✓ WASAPI system audio capture started");
+
+        let max_samples = 288_000; // 3 seconds @ 48kHz stereo
+
+        let mut prev_sample: i16 = 0;
+        while !stop_signal.load(Ordering::SeqCst) {
+            let packet_size = capture_client.GetNextPacketSize()?;
+            if packet_size == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                continue;
+            }
+
+            let mut data_ptr = std::ptr::null_mut();
+            let mut num_frames = 0;
+            let mut flags = 0;
+            capture_client.GetBuffer(&mut data_ptr, &mut num_frames, &mut flags, None, None)?;
+
+            if num_frames > 0 {
+                let ch = channels as usize;
+                let num_samples = num_frames as usize * ch;
+
+                let mut buf = mix_buffer.lock().unwrap();
+
+                if bits_per_sample == 32 {
+                    // Float32 PCM — most common for system loopback
+                    let samples = std::slice::from_raw_parts(data_ptr as *const f32, num_samples);
+                    for &sample in samples {
+                        let s = (sample * 1.2).clamp(-1.0, 1.0);
+                        let mut s16 = if s >= 0.0 {
+                            (s * 32767.0) as i16
+                        } else {
+                            (s * 32768.0) as i16
+                        };
+
+                        // Simple low-pass filter to smooth hiss
+                        s16 = ((prev_sample as f32 * 0.3) + (s16 as f32 * 0.7)) as i16;
+                        prev_sample = s16;
+
+                        buf.push(s16);
+                    }
+                } else if bits_per_sample == 16 {
+                    let samples = std::slice::from_raw_parts(data_ptr as *const i16, num_samples);
+                    buf.extend_from_slice(samples);
+                } else {
+                    log::warn!("Unsupported PCM bit depth: {}", bits_per_sample);
+                }
+
+                if buf.len() > max_samples {
+                    let len = buf.len();
+                    let excess = len - max_samples;
+                    buf.drain(0..excess);
+                }
+
+                capture_client.ReleaseBuffer(num_frames)?;
+            }
+        }
+
+        audio_client.Stop()?;
+        CoUninitialize();
+        log::info!("WASAPI system audio capture stopped");
+    }
+
+    Ok(())
+}
+
+// WASAPI Microphone Capture
+#[cfg(feature = "audio_capture")]
+fn capture_microphone_wasapi(
+    stop_signal: Arc<AtomicBool>,
+    mix_buffer: Arc<Mutex<Vec<i16>>>,
+) -> anyhow::Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = enumerator.GetDefaultAudioEndpoint(eCapture, eConsole)?;
+
+        let audio_client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
+        let wave_format_ptr = audio_client.GetMixFormat()?;
+        let wave_format = *wave_format_ptr;
+
+        let sample_rate = wave_format.nSamplesPerSec;
+        let channels = wave_format.nChannels;
+        let bits_per_sample = wave_format.wBitsPerSample;
+
+        log::info!(
+            "Microphone format: {}Hz, {} channels, {} bits",
+            sample_rate,
+            channels,
+            bits_per_sample
+        );
+
+        audio_client.Initialize(
+            AUDCLNT_SHAREMODE_SHARED,
+            0, // No special flags for microphone
+            200_000_000,
+            0,
+            wave_format_ptr,
+            None,
+        )?;
+
+        CoTaskMemFree(Some(wave_format_ptr as *const _));
+
+        let capture_client: IAudioCaptureClient = audio_client.GetService()?;
+        audio_client.Start()?;
+
+        log::info!("✓ WASAPI microphone capture started");
+
+        let max_samples = 288_000;
+
+        while !stop_signal.load(Ordering::SeqCst) {
+            let packet_size = capture_client.GetNextPacketSize()?;
+            if packet_size == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                continue;
+            }
+
+            let mut data_ptr = std::ptr::null_mut();
+            let mut num_frames = 0;
+            let mut flags = 0;
+
+            capture_client.GetBuffer(&mut data_ptr, &mut num_frames, &mut flags, None, None)?;
+
+            if num_frames > 0 {
+                let ch = channels as usize;
+                let num_samples = num_frames as usize * ch;
+                let samples_slice = std::slice::from_raw_parts(data_ptr as *const f32, num_samples);
+
+                let mut buf = mix_buffer.lock().unwrap();
+                let mut prev = 0i16;
+
+                // Convert to stereo if mono
+                if ch == 1 {
+                    for &sample in samples_slice {
+                        let s = (sample * 1.2).clamp(-1.0, 1.0);
+                        let s16 = if s >= 0.0 {
+                            (s * 32767.0) as i16
+                        } else {
+                            (s * 32768.0) as i16
+                        };
+
+                        // Simple low-pass smoothing filter
+                        let filtered = ((prev as f32 * 0.3) + (s16 as f32 * 0.7)) as i16;
+                        prev = filtered;
+
+                        // Duplicate for stereo
+                        buf.push(filtered);
+                        buf.push(filtered);
+                    }
+                } else {
+                    for &sample in samples_slice {
+                        let s = (sample * 1.2).clamp(-1.0, 1.0);
+                        let s16 = if s >= 0.0 {
+                            (s * 32767.0) as i16
+                        } else {
+                            (s * 32768.0) as i16
+                        };
+                        buf.push(s16);
+                    }
+                }
+
+                if buf.len() > max_samples {
+                    let excess = buf.len() - max_samples;
+                    buf.drain(0..excess);
+                }
+
+                capture_client.ReleaseBuffer(num_frames)?;
+            }
+        }
+
+        audio_client.Stop()?;
+        CoUninitialize();
+        log::info!("WASAPI microphone capture stopped");
+    }
+
+    Ok(())
+}
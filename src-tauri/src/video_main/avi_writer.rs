@@ -28,6 +28,9 @@ pub struct AviSegmentWriter {
     file: File,
     // positions for later size fixups
     riff_size_pos: u64,
+    // avih dwTotalFrames and strh dwLength, both frame counts
+    total_frames_pos: u64,
+    length_pos: u64,
     _movi_list_start: u64,
     movi_size_pos: u64,
     idx: Vec<IdxEntry>,
@@ -67,7 +70,12 @@ impl AviSegmentWriter {
         let hdrl_size = 4 + hdrl_buf.len() as u32; // includes 'hdrl'
         file.write_u32::<LittleEndian>(hdrl_size)?;
         file.write_all(b"hdrl")?;
+        let hdrl_data_start = file.stream_position()?;
         file.write_all(&hdrl_buf)?;
+        // 'avih' + size + 4 fields; then the 56-byte avih, LIST 'strl',
+        // 'strh' + size and 8 fields
+        let total_frames_pos = hdrl_data_start + 8 + 16;
+        let length_pos = hdrl_data_start + 8 + 56 + 12 + 8 + 32;
 
         // LIST 'movi'
         file.write_all(b"LIST")?;
@@ -80,6 +88,8 @@ impl AviSegmentWriter {
             _cfg: cfg,
             file,
             riff_size_pos,
+            total_frames_pos,
+            length_pos,
             _movi_list_start: movi_list_start,
             movi_size_pos,
             idx: Vec::with_capacity(4096),
@@ -121,6 +131,25 @@ impl AviSegmentWriter {
         Ok(())
     }
 
+    /// Writes an empty video chunk, which players show as a repeat of the
+    /// previous frame. Used for frames that did not change.
+    pub fn write_repeat_frame(&mut self) -> anyhow::Result<()> {
+        let chunk_id = *b"00dc";
+        let chunk_id_pos = self.file.stream_position()?;
+        self.file.write_all(&chunk_id)?;
+        self.file.write_u32::<LittleEndian>(0)?;
+
+        let movi_data_start = self.movi_size_pos + 4;
+        self.idx.push(IdxEntry {
+            ckid: chunk_id,
+            flags: 0,
+            offset_from_movi: (chunk_id_pos - movi_data_start) as u32,
+            length: 0,
+        });
+        self.frames_written = self.frames_written.saturating_add(1);
+        Ok(())
+    }
+
     pub fn finalize(mut self) -> anyhow::Result<PathBuf> {
         // If no frames written, delete the file to avoid zero/tiny segments
         if self.frames_written == 0 {
@@ -150,6 +179,12 @@ impl AviSegmentWriter {
             self.file.write_u32::<LittleEndian>(e.length)?;
         }
 
+        // frame counts, unknown until now
+        for pos in [self.total_frames_pos, self.length_pos] {
+            self.file.seek(SeekFrom::Start(pos))?;
+            self.file.write_u32::<LittleEndian>(self.frames_written)?;
+        }
+
         // fix RIFF size
         let final_len = self.file.seek(SeekFrom::End(0))?;
         let riff_size = (final_len - 8) as u32;
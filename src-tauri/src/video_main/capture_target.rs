@@ -0,0 +1,392 @@
+// src/video_main/capture_target.rs
+
+//! What part of the desktop the recorder captures: a whole display, a fixed
+//! region, or one application window that is followed as it moves. Regions
+//! and windows are cropped out of the display frames before the recorder
+//! scales and encodes them.
+
+use std::time::{Duration, Instant};
+
+use super::frame_source::{DisplayInfo, Frame, FrameSource, PixelFormat};
+use super::combined_source::{CombineLayout, CombineScaling, CombinedSource};
+
+/// How often `WindowSource` looks up where its window has moved to.
+const WINDOW_LOOKUP_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureTarget {
+    /// A whole display, as numbered by `list_displays`.
+    Display(usize),
+    /// A rectangle in virtual desktop coordinates.
+    Region { x: i32, y: i32, width: u32, height: u32 },
+    /// The first window matching, followed as it moves.
+    Window(WindowMatch),
+}
+
+/// Picks a window by case-insensitive substrings of its title and of its
+/// application name. A window must match every part that is given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowMatch {
+    title: Option<String>,
+    app: Option<String>,
+}
+
+impl WindowMatch {
+    /// Fails unless at least one non-empty part is given, as a match on
+    /// nothing would record whichever window happens to come first.
+    pub fn new(title: Option<String>, app: Option<String>) -> anyhow::Result<Self> {
+        let given = |part: Option<String>| part.filter(|part| !part.is_empty());
+        let (title, app) = (given(title), given(app));
+        if title.is_none() && app.is_none() {
+            return Err(anyhow::anyhow!("A window match needs a title or an application name"));
+        }
+        Ok(Self { title, app })
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn app(&self) -> Option<&str> {
+        self.app.as_deref()
+    }
+
+    fn matches(&self, title: &str, app: &str) -> bool {
+        let contains = |text: &str, part: &Option<String>| {
+            part.as_ref()
+                .is_none_or(|part| text.to_lowercase().contains(&part.to_lowercase()))
+        };
+        contains(title, &self.title) && contains(app, &self.app)
+    }
+}
+
+/// Screen rectangle of a window that is currently visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WindowRect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// A fixed region of the desktop. When it spans several displays they are
+/// combined as laid out on the virtual desktop.
+pub struct RegionSource {
+    inner: Box<dyn FrameSource>,
+    /// Position of the region in the frames of `inner`.
+    x: i64,
+    y: i64,
+    width: u32,
+    height: u32,
+}
+
+impl RegionSource {
+    pub fn new(
+        displays: Vec<(DisplayInfo, Box<dyn FrameSource>)>,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(anyhow::anyhow!("Capture region {}x{} is empty", width, height));
+        }
+        let right = x + width as i32 - 1;
+        let bottom = y + height as i32 - 1;
+        let single = displays
+            .iter()
+            .position(|(display, _)| display.contains(x, y) && display.contains(right, bottom));
+
+        let (origin_x, origin_y, inner): (i32, i32, Box<dyn FrameSource>) = match single {
+            Some(i) => {
+                let (display, source) = displays.into_iter().nth(i).unwrap();
+                (display.x, display.y, source)
+            }
+            None => {
+                let left = displays.iter().map(|(d, _)| d.x).min().unwrap_or(0);
+                let top = displays.iter().map(|(d, _)| d.y).min().unwrap_or(0);
+                let combined = CombinedSource::new(displays, CombineLayout::VirtualDesktop, CombineScaling::Native)?;
+                (left, top, Box::new(combined))
+            }
+        };
+        log::info!("Capturing region {}x{} at ({}, {})", width, height, x, y);
+        Ok(Self {
+            inner,
+            x: (x - origin_x) as i64,
+            y: (y - origin_y) as i64,
+            width,
+            height,
+        })
+    }
+}
+
+impl FrameSource for RegionSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        Ok(self
+            .inner
+            .next_frame()?
+            .map(|frame| crop(&frame, self.x, self.y, self.width, self.height)))
+    }
+}
+
+/// One application window, cropped out of the display it is on and
+/// followed as it moves. While it is minimized or closed the frames are
+/// black, so nothing else on the screen is recorded in its place.
+pub struct WindowSource {
+    displays: Vec<(DisplayInfo, Box<dyn FrameSource>)>,
+    matcher: WindowMatch,
+    /// xcap id of the matched window; found again by `matcher` if it closes.
+    window_id: Option<u32>,
+    rect: Option<WindowRect>,
+    last_lookup: Instant,
+    width: u32,
+    height: u32,
+}
+
+impl WindowSource {
+    pub fn new(displays: Vec<(DisplayInfo, Box<dyn FrameSource>)>, matcher: WindowMatch) -> anyhow::Result<Self> {
+        if displays.is_empty() {
+            return Err(anyhow::anyhow!("No displays to capture the window from"));
+        }
+        let (window_id, rect) = find_window(&matcher, None)?
+            .ok_or_else(|| anyhow::anyhow!("No window matches {:?}", matcher))?;
+        log::info!("Capturing window {:?}: {}x{} at ({}, {})", matcher, rect.width, rect.height, rect.x, rect.y);
+        Ok(Self {
+            displays,
+            matcher,
+            window_id: Some(window_id),
+            rect: Some(rect),
+            last_lookup: Instant::now(),
+            width: rect.width,
+            height: rect.height,
+        })
+    }
+
+    fn refresh_rect(&mut self) {
+        if self.last_lookup.elapsed() < WINDOW_LOOKUP_INTERVAL {
+            return;
+        }
+        self.last_lookup = Instant::now();
+        match find_window(&self.matcher, self.window_id) {
+            Ok(Some((id, rect))) => {
+                if self.window_id != Some(id) {
+                    log::info!("Following window {:?} (id {}).", self.matcher, id);
+                }
+                self.window_id = Some(id);
+                self.rect = Some(rect);
+            }
+            Ok(None) => {
+                if self.rect.is_some() {
+                    log::info!("Window {:?} is gone or minimized; recording black frames.", self.matcher);
+                }
+                self.window_id = None;
+                self.rect = None;
+            }
+            Err(e) => log::warn!("Window lookup failed: {:#}", e),
+        }
+    }
+}
+
+impl FrameSource for WindowSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        self.refresh_rect();
+        let Some(rect) = self.rect else {
+            let bpp = PixelFormat::Rgb8.bytes_per_pixel();
+            return Ok(Some(Frame {
+                width: self.width,
+                height: self.height,
+                format: PixelFormat::Rgb8,
+                data: vec![0; self.width as usize * self.height as usize * bpp],
+                timestamp: Instant::now(),
+            }));
+        };
+
+        // The display holding the window's centre; a window straddling two
+        // displays is cut at the edge of that one. Off every display, it is
+        // cropped from the first, which leaves black where it is not.
+        let center_x = rect.x + rect.width as i32 / 2;
+        let center_y = rect.y + rect.height as i32 / 2;
+        let index = self
+            .displays
+            .iter()
+            .position(|(display, _)| display.contains(center_x, center_y))
+            .unwrap_or(0);
+        let (display, source) = &mut self.displays[index];
+        let Some(frame) = source.next_frame()? else {
+            return Ok(None);
+        };
+        let x = (rect.x - display.x) as i64;
+        let y = (rect.y - display.y) as i64;
+        Ok(Some(crop(&frame, x, y, rect.width, rect.height)))
+    }
+}
+
+/// Looks up the window with id `current` if it is still there, otherwise
+/// the first one `matcher` accepts. Minimized windows do not count.
+fn find_window(matcher: &WindowMatch, current: Option<u32>) -> anyhow::Result<Option<(u32, WindowRect)>> {
+    let windows = xcap::Window::all().map_err(|e| anyhow::anyhow!("Failed to list windows: {}", e))?;
+    let visible = |window: &&xcap::Window| !window.is_minimized() && window.width() > 0 && window.height() > 0;
+    let window = current
+        .and_then(|id| windows.iter().filter(visible).find(|window| window.id() == id))
+        .or_else(|| {
+            windows
+                .iter()
+                .filter(visible)
+                .find(|window| matcher.matches(window.title(), window.app_name()))
+        });
+    Ok(window.map(|window| {
+        (
+            window.id(),
+            WindowRect {
+                x: window.x(),
+                y: window.y(),
+                width: window.width(),
+                height: window.height(),
+            },
+        )
+    }))
+}
+
+/// The `width` x `height` rectangle of `frame` at (`x`, `y`). Parts outside
+/// the frame are black, so the size never changes.
+fn crop(frame: &Frame, x: i64, y: i64, width: u32, height: u32) -> Frame {
+    let bpp = frame.format.bytes_per_pixel();
+    let src_stride = frame.width as usize * bpp;
+    let dst_stride = width as usize * bpp;
+    let mut data = vec![0u8; dst_stride * height as usize];
+
+    // Columns of the crop that lie inside the frame.
+    let first_col = (-x).clamp(0, width as i64) as usize;
+    let last_col = (frame.width as i64 - x).clamp(0, width as i64) as usize;
+    if first_col < last_col {
+        for row in 0..height as usize {
+            let src_row = y + row as i64;
+            if src_row < 0 || src_row >= frame.height as i64 {
+                continue;
+            }
+            let src_start = src_row as usize * src_stride + (x + first_col as i64) as usize * bpp;
+            let len = (last_col - first_col) * bpp;
+            let dst_start = row * dst_stride + first_col * bpp;
+            data[dst_start..dst_start + len].copy_from_slice(&frame.data[src_start..src_start + len]);
+        }
+    }
+
+    Frame {
+        width,
+        height,
+        format: frame.format,
+        data,
+        timestamp: frame.timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_match_needs_a_part() {
+        assert!(WindowMatch::new(None, None).is_err());
+        assert!(WindowMatch::new(Some(String::new()), Some(String::new())).is_err());
+
+        let matcher = WindowMatch::new(Some(String::new()), Some("code".into())).unwrap();
+        assert_eq!((matcher.title(), matcher.app()), (None, Some("code")));
+    }
+
+    #[test]
+    fn window_match_is_case_insensitive_and_needs_every_part() {
+        let title = WindowMatch::new(Some("Report.DOCX".into()), None).unwrap();
+        assert!(title.matches("Quarterly report.docx - Word", "WINWORD"));
+        assert!(!title.matches("report.doc - Word", "WINWORD"));
+
+        let both = WindowMatch::new(Some("ärger".into()), Some("Mail".into())).unwrap();
+        assert!(both.matches("Re: ÄRGER mit dem Server", "Thunderbird Mail"));
+        assert!(!both.matches("Re: ÄRGER mit dem Server", "Firefox"));
+        assert!(!both.matches("Inbox", "Thunderbird Mail"));
+
+        let app = WindowMatch::new(None, Some("firefox".into())).unwrap();
+        assert!(app.matches("", "Firefox"));
+    }
+
+    /// A frame whose every byte is its own index plus one, so copied pixels
+    /// are never zero.
+    fn numbered(width: u32, height: u32, format: PixelFormat) -> Frame {
+        let len = width as usize * height as usize * format.bytes_per_pixel();
+        Frame {
+            width,
+            height,
+            format,
+            data: (1..=len).map(|i| i as u8).collect(),
+            timestamp: Instant::now(),
+        }
+    }
+
+    /// `crop` done pixel by pixel.
+    fn reference_crop(frame: &Frame, x: i64, y: i64, width: u32, height: u32) -> Vec<u8> {
+        let bpp = frame.format.bytes_per_pixel();
+        let mut data = Vec::new();
+        for row in y..y + height as i64 {
+            for col in x..x + width as i64 {
+                let inside = (0..frame.width as i64).contains(&col) && (0..frame.height as i64).contains(&row);
+                if inside {
+                    let start = (row as usize * frame.width as usize + col as usize) * bpp;
+                    data.extend_from_slice(&frame.data[start..start + bpp]);
+                } else {
+                    data.resize(data.len() + bpp, 0);
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn crop_keeps_its_size_and_blanks_what_is_off_the_frame() {
+        for format in [PixelFormat::Rgb8, PixelFormat::Bgra8] {
+            let frame = numbered(8, 5, format);
+            let rects = [
+                (0, 0, 8, 5),
+                (2, 1, 3, 2),
+                // Partly off the left and top, then the right and bottom.
+                (-3, -2, 5, 4),
+                (6, 3, 4, 4),
+                // Wider and taller than the frame on every side.
+                (-1, -1, 10, 7),
+                // Entirely off the frame.
+                (8, 0, 2, 2),
+                (-5, 1, 5, 2),
+                (0, 5, 3, 1),
+                (0, -9, 3, 2),
+            ];
+            for (x, y, width, height) in rects {
+                let cropped = crop(&frame, x, y, width, height);
+                assert_eq!((cropped.width, cropped.height, cropped.format), (width, height, format));
+                assert_eq!(
+                    cropped.data,
+                    reference_crop(&frame, x, y, width, height),
+                    "{:?} crop {}x{} at ({}, {})",
+                    format,
+                    width,
+                    height,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crop_entirely_off_the_frame_is_black() {
+        let frame = numbered(8, 5, PixelFormat::Rgb8);
+        let cropped = crop(&frame, 100, -100, 4, 3);
+        assert_eq!(cropped.data, vec![0; 4 * 3 * 3]);
+        assert_eq!(cropped.timestamp, frame.timestamp);
+    }
+}
@@ -0,0 +1,131 @@
+// src/video_main/change_detector.rs
+
+//! Tells whether the screen changed since the last frame the recorder kept,
+//! so static frames can be skipped. The frame is split into square blocks
+//! compared row by row against the kept frame, stopping at the first
+//! block that differs.
+
+use super::frame_source::Frame;
+
+/// Side of a comparison block in pixels.
+const BLOCK_SIZE: usize = 32;
+
+#[derive(Default)]
+pub struct ChangeDetector {
+    /// The last kept frame, in its captured format.
+    previous: Option<Frame>,
+}
+
+impl ChangeDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `frame` differs from the kept frame. Also `true` when there
+    /// is nothing to compare with: no kept frame yet, or one of another
+    /// size or format.
+    pub fn has_changed(&self, frame: &Frame) -> bool {
+        let Some(previous) = self.previous.as_ref() else {
+            return true;
+        };
+        if (previous.width, previous.height, previous.format) != (frame.width, frame.height, frame.format)
+            || previous.data.len() != frame.data.len()
+        {
+            return true;
+        }
+        let bpp = frame.format.bytes_per_pixel();
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let stride = width * bpp;
+        let block_bytes = BLOCK_SIZE * bpp;
+
+        (0..height).step_by(BLOCK_SIZE).any(|block_top| {
+            let rows = block_top..(block_top + BLOCK_SIZE).min(height);
+            (0..stride).step_by(block_bytes).any(|block_left| {
+                let columns = block_left..(block_left + block_bytes).min(stride);
+                rows.clone().any(|row| {
+                    let start = row * stride;
+                    let range = start + columns.start..start + columns.end;
+                    previous.data[range.clone()] != frame.data[range]
+                })
+            })
+        })
+    }
+
+    /// Makes `frame` the one later frames are compared with.
+    pub fn keep(&mut self, frame: Frame) {
+        self.previous = Some(frame);
+    }
+
+    /// Forgets the kept frame, so the next one counts as changed.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_main::frame_source::PixelFormat;
+    use std::time::Instant;
+
+    fn frame(width: u32, height: u32) -> Frame {
+        Frame {
+            width,
+            height,
+            format: PixelFormat::Rgb8,
+            data: (0..width * height * 3).map(|i| (i % 251) as u8).collect(),
+            timestamp: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn first_frame_counts_as_changed() {
+        assert!(ChangeDetector::new().has_changed(&frame(100, 70)));
+    }
+
+    #[test]
+    fn identical_frame_is_unchanged() {
+        let mut detector = ChangeDetector::new();
+        detector.keep(frame(100, 70));
+        assert!(!detector.has_changed(&frame(100, 70)));
+    }
+
+    #[test]
+    fn one_changed_pixel_is_found_anywhere() {
+        let mut detector = ChangeDetector::new();
+        let kept = frame(100, 70);
+        detector.keep(kept.clone());
+        // Corners, block edges and the partial blocks at the right and bottom.
+        for (x, y) in [(0, 0), (99, 69), (31, 31), (32, 0), (96, 10), (50, 64)] {
+            for channel in 0..3 {
+                let mut changed = kept.clone();
+                changed.data[(y * 100 + x) * 3 + channel] ^= 1;
+                assert!(detector.has_changed(&changed), "pixel {},{} channel {}", x, y, channel);
+            }
+        }
+    }
+
+    #[test]
+    fn other_size_counts_as_changed() {
+        let mut detector = ChangeDetector::new();
+        detector.keep(frame(100, 70));
+        assert!(detector.has_changed(&frame(70, 100)));
+        assert!(detector.has_changed(&frame(100, 71)));
+
+        // Same bytes, read as another format.
+        let bgra = Frame {
+            width: 75,
+            format: PixelFormat::Bgra8,
+            ..frame(100, 70)
+        };
+        assert!(detector.has_changed(&bgra));
+    }
+
+    #[test]
+    fn reset_forgets_the_kept_frame() {
+        let mut detector = ChangeDetector::new();
+        detector.keep(frame(100, 70));
+        detector.reset();
+        assert!(detector.has_changed(&frame(100, 70)));
+    }
+}
@@ -0,0 +1,449 @@
+// src/video_main/colorspace.rs
+
+//! Pixel format conversion and scaling for recorder frames, in integer
+//! arithmetic with SSSE3 shuffles for the byte reordering where the CPU has
+//! them. Everything works on tightly packed rows, top-down, as in `Frame`.
+
+/// Which RGB to YUV matrix to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matrix {
+    /// SD video; what most decoders assume when nothing is signalled.
+    Bt601,
+    /// HD video.
+    Bt709,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    /// Y and chroma use 0..=255.
+    Full,
+    /// Y uses 16..=235 and chroma 16..=240, the broadcast convention.
+    Limited,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub matrix: Matrix,
+    pub range: Range,
+}
+
+impl ColorSpace {
+    pub const BT601_FULL: ColorSpace = ColorSpace { matrix: Matrix::Bt601, range: Range::Full };
+    pub const BT709_LIMITED: ColorSpace = ColorSpace { matrix: Matrix::Bt709, range: Range::Limited };
+
+    /// Luma weights of red and blue.
+    fn kr_kb(self) -> (f64, f64) {
+        match self.matrix {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+
+    /// The conversion as floating point rows `[r, g, b, offset]` for Y, U
+    /// and V, on 0..=255 inputs.
+    pub fn matrix_rows(self) -> [[f64; 4]; 3] {
+        let (kr, kb) = self.kr_kb();
+        let kg = 1.0 - kr - kb;
+        let (y_scale, c_scale, y_offset) = match self.range {
+            Range::Full => (1.0, 1.0, 0.0),
+            Range::Limited => (219.0 / 255.0, 224.0 / 255.0, 16.0),
+        };
+        let cb = c_scale / (2.0 * (1.0 - kb));
+        let cr = c_scale / (2.0 * (1.0 - kr));
+        [
+            [kr * y_scale, kg * y_scale, kb * y_scale, y_offset],
+            [-kr * cb, -kg * cb, (1.0 - kb) * cb, 128.0],
+            [(1.0 - kr) * cr, -kg * cr, -kb * cr, 128.0],
+        ]
+    }
+}
+
+/// `matrix_rows` in 16.16 fixed point, offsets included with rounding.
+#[derive(Clone, Copy)]
+struct Coefficients {
+    rows: [[i32; 4]; 3],
+}
+
+impl Coefficients {
+    fn new(cs: ColorSpace) -> Self {
+        let to_fixed = |v: f64| (v * 65536.0).round() as i32;
+        let rows = cs.matrix_rows().map(|[r, g, b, offset]| {
+            [to_fixed(r), to_fixed(g), to_fixed(b), to_fixed(offset) + 32768]
+        });
+        Self { rows }
+    }
+
+    #[inline(always)]
+    fn apply(&self, row: usize, r: i32, g: i32, b: i32) -> u8 {
+        let [cr, cg, cb, offset] = self.rows[row];
+        ((cr * r + cg * g + cb * b + offset) >> 16).clamp(0, 255) as u8
+    }
+}
+
+/// Byte layout of the packed input pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedFormat {
+    Rgb,
+    Bgra,
+}
+
+impl PackedFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            PackedFormat::Rgb => 3,
+            PackedFormat::Bgra => 4,
+        }
+    }
+}
+
+/// Destination of a 4:2:0 conversion. Chroma planes have
+/// `ceil(width / 2) x ceil(height / 2)` samples.
+pub enum YuvPlanes<'a> {
+    /// Separate U and V planes.
+    I420 {
+        y: &'a mut [u8],
+        y_stride: usize,
+        u: &'a mut [u8],
+        v: &'a mut [u8],
+        uv_stride: usize,
+    },
+    /// One plane of interleaved U, V pairs.
+    Nv12 {
+        y: &'a mut [u8],
+        y_stride: usize,
+        uv: &'a mut [u8],
+        uv_stride: usize,
+    },
+}
+
+/// Converts packed `src` to 4:2:0. Each chroma sample is taken from the
+/// average of its 2x2 block.
+pub fn to_yuv420(src: &[u8], format: PackedFormat, width: usize, height: usize, planes: YuvPlanes<'_>, cs: ColorSpace) {
+    let bpp = format.bytes_per_pixel();
+    assert!(src.len() >= width * bpp * height, "source is smaller than {}x{}", width, height);
+    let coefficients = Coefficients::new(cs);
+
+    match planes {
+        YuvPlanes::I420 { y, y_stride, u, v, uv_stride } => {
+            convert_420(src, format, width, height, y, y_stride, &coefficients, |cy, cb, cr| {
+                u[cy * uv_stride..cy * uv_stride + cb.len()].copy_from_slice(cb);
+                v[cy * uv_stride..cy * uv_stride + cr.len()].copy_from_slice(cr);
+            });
+        }
+        YuvPlanes::Nv12 { y, y_stride, uv, uv_stride } => {
+            convert_420(src, format, width, height, y, y_stride, &coefficients, |cy, cb, cr| {
+                let row = &mut uv[cy * uv_stride..cy * uv_stride + cb.len() * 2];
+                for ((pair, &cb), &cr) in row.chunks_exact_mut(2).zip(cb).zip(cr) {
+                    pair[0] = cb;
+                    pair[1] = cr;
+                }
+            });
+        }
+    }
+}
+
+/// Picks the monomorphized `convert_420_rows` for `format`, so the inner
+/// loops see fixed channel offsets.
+#[allow(clippy::too_many_arguments)]
+fn convert_420(
+    src: &[u8],
+    format: PackedFormat,
+    width: usize,
+    height: usize,
+    y: &mut [u8],
+    y_stride: usize,
+    coefficients: &Coefficients,
+    put_chroma: impl FnMut(usize, &[u8], &[u8]),
+) {
+    match format {
+        PackedFormat::Rgb => convert_420_rows::<3, 0, 2>(src, width, height, y, y_stride, coefficients, put_chroma),
+        PackedFormat::Bgra => convert_420_rows::<4, 2, 0>(src, width, height, y, y_stride, coefficients, put_chroma),
+    }
+}
+
+/// Works through the image two rows at a time: luma for both, then one
+/// row of chroma handed to `put_chroma`. A missing last row or column is
+/// taken as a copy of the one before, which keeps the 2x2 average exact.
+fn convert_420_rows<const BPP: usize, const R: usize, const B: usize>(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    y: &mut [u8],
+    y_stride: usize,
+    coefficients: &Coefficients,
+    mut put_chroma: impl FnMut(usize, &[u8], &[u8]),
+) {
+    let stride = width * BPP;
+    let chroma_width = width.div_ceil(2);
+    let mut cb = vec![0u8; chroma_width];
+    let mut cr = vec![0u8; chroma_width];
+    let luma = |src_row: &[u8], dst_row: &mut [u8]| {
+        for (px, out) in src_row.chunks_exact(BPP).zip(dst_row.iter_mut()) {
+            *out = coefficients.apply(0, px[R] as i32, px[1] as i32, px[B] as i32);
+        }
+    };
+
+    for cy in 0..height.div_ceil(2) {
+        let top = &src[cy * 2 * stride..(cy * 2 + 1) * stride];
+        let bottom = if cy * 2 + 1 < height {
+            &src[(cy * 2 + 1) * stride..(cy * 2 + 2) * stride]
+        } else {
+            top
+        };
+        luma(top, &mut y[cy * 2 * y_stride..cy * 2 * y_stride + width]);
+        if cy * 2 + 1 < height {
+            luma(bottom, &mut y[(cy * 2 + 1) * y_stride..(cy * 2 + 1) * y_stride + width]);
+        }
+
+        for cx in 0..chroma_width {
+            let left = cx * 2 * BPP;
+            let right = if cx * 2 + 1 < width { left + BPP } else { left };
+            let sum = |c: usize| {
+                (top[left + c] as i32 + top[right + c] as i32 + bottom[left + c] as i32 + bottom[right + c] as i32 + 2) >> 2
+            };
+            let (r, g, b) = (sum(R), sum(1), sum(B));
+            cb[cx] = coefficients.apply(1, r, g, b);
+            cr[cx] = coefficients.apply(2, r, g, b);
+        }
+        put_chroma(cy, &cb, &cr);
+    }
+}
+
+/// BGRA to packed RGB; alpha is dropped. `dst` must hold 3 bytes per pixel.
+pub fn bgra_to_rgb(src: &[u8], dst: &mut [u8]) {
+    shuffle_4_to_3(src, dst, [2, 1, 0]);
+}
+
+/// RGBA to packed RGB; alpha is dropped. `dst` must hold 3 bytes per pixel.
+pub fn rgba_to_rgb(src: &[u8], dst: &mut [u8]) {
+    shuffle_4_to_3(src, dst, [0, 1, 2]);
+}
+
+/// Packed RGB to BGRA with opaque alpha. `dst` must hold 4 bytes per pixel.
+pub fn rgb_to_bgra(src: &[u8], dst: &mut [u8]) {
+    let pixels = src.len() / 3;
+    assert!(dst.len() >= pixels * 4, "destination too small");
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("ssse3") {
+        // SAFETY: SSSE3 was detected just above.
+        done = unsafe { simd::rgb_to_bgra(src, dst) };
+    }
+    for (s, d) in src[done * 3..pixels * 3]
+        .chunks_exact(3)
+        .zip(dst[done * 4..pixels * 4].chunks_exact_mut(4))
+    {
+        d.copy_from_slice(&[s[2], s[1], s[0], 255]);
+    }
+}
+
+/// Allocating `bgra_to_rgb`.
+pub fn bgra_to_rgb_vec(src: &[u8]) -> Vec<u8> {
+    let mut dst = vec![0; src.len() / 4 * 3];
+    bgra_to_rgb(src, &mut dst);
+    dst
+}
+
+/// Allocating `rgb_to_bgra`.
+pub fn rgb_to_bgra_vec(src: &[u8]) -> Vec<u8> {
+    let mut dst = vec![0; src.len() / 3 * 4];
+    rgb_to_bgra(src, &mut dst);
+    dst
+}
+
+/// Copies bytes `order` of every 4-byte pixel into 3-byte pixels.
+fn shuffle_4_to_3(src: &[u8], dst: &mut [u8], order: [usize; 3]) {
+    let pixels = src.len() / 4;
+    assert!(dst.len() >= pixels * 3, "destination too small");
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("ssse3") {
+        // SAFETY: SSSE3 was detected just above.
+        done = unsafe { simd::shuffle_4_to_3(src, dst, order) };
+    }
+    for (s, d) in src[done * 4..pixels * 4]
+        .chunks_exact(4)
+        .zip(dst[done * 3..pixels * 3].chunks_exact_mut(3))
+    {
+        d.copy_from_slice(&[s[order[0]], s[order[1]], s[order[2]]]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    /// Converts the first 4-pixel groups with `pshufb` and returns how many
+    /// pixels it did; the caller finishes the rest. Every store writes 16
+    /// bytes for 12, so it stops while a full store still fits.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn shuffle_4_to_3(src: &[u8], dst: &mut [u8], order: [usize; 3]) -> usize {
+        let mut mask = [0x80u8; 16];
+        for pixel in 0..4 {
+            for (channel, &from) in order.iter().enumerate() {
+                mask[pixel * 3 + channel] = (pixel * 4 + from) as u8;
+            }
+        }
+        let mask = _mm_loadu_si128(mask.as_ptr() as *const __m128i);
+        let pixels = (src.len() / 4).min(dst.len() / 3);
+        let mut i = 0;
+        while i + 4 <= pixels && i * 3 + 16 <= dst.len() {
+            let v = _mm_loadu_si128(src.as_ptr().add(i * 4) as *const __m128i);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i * 3) as *mut __m128i, _mm_shuffle_epi8(v, mask));
+            i += 4;
+        }
+        i
+    }
+
+    /// Like `shuffle_4_to_3` the other way; every load reads 16 bytes for 12.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn rgb_to_bgra(src: &[u8], dst: &mut [u8]) -> usize {
+        let mask = _mm_setr_epi8(2, 1, 0, -128, 5, 4, 3, -128, 8, 7, 6, -128, 11, 10, 9, -128);
+        let alpha = _mm_set1_epi32(0xff00_0000u32 as i32);
+        let pixels = (src.len() / 3).min(dst.len() / 4);
+        let mut i = 0;
+        while i + 4 <= pixels && i * 3 + 16 <= src.len() {
+            let v = _mm_loadu_si128(src.as_ptr().add(i * 3) as *const __m128i);
+            let bgra = _mm_or_si128(_mm_shuffle_epi8(v, mask), alpha);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i * 4) as *mut __m128i, bgra);
+            i += 4;
+        }
+        i
+    }
+}
+
+/// How `scale` resamples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleFilter {
+    /// Average of the source pixels each output pixel covers. Best for
+    /// shrinking by 2x or more.
+    Box,
+    /// Weighted from the 4 nearest source pixels.
+    Bilinear,
+}
+
+impl ScaleFilter {
+    /// Box when shrinking by at least half in both directions, bilinear otherwise.
+    pub fn for_sizes(src_width: u32, src_height: u32, dst_width: u32, dst_height: u32) -> Self {
+        if src_width >= dst_width * 2 && src_height >= dst_height * 2 {
+            ScaleFilter::Box
+        } else {
+            ScaleFilter::Bilinear
+        }
+    }
+}
+
+/// Scales packed pixels of `bpp` bytes each; every byte is treated as an
+/// independent channel, so any packed format works.
+pub fn scale(
+    src: &[u8],
+    src_width: u32,
+    src_height: u32,
+    bpp: usize,
+    dst_width: u32,
+    dst_height: u32,
+    filter: ScaleFilter,
+) -> Vec<u8> {
+    let (sw, sh, dw, dh) = (src_width as usize, src_height as usize, dst_width as usize, dst_height as usize);
+    assert!(src.len() >= sw * sh * bpp, "source is smaller than {}x{}", sw, sh);
+    if (sw, sh) == (dw, dh) {
+        return src[..sw * sh * bpp].to_vec();
+    }
+    let mut dst = vec![0u8; dw * dh * bpp];
+    if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+        return dst;
+    }
+    match filter {
+        ScaleFilter::Box => scale_box(src, sw, sh, bpp, &mut dst, dw, dh),
+        ScaleFilter::Bilinear => scale_bilinear(src, sw, sh, bpp, &mut dst, dw, dh),
+    }
+    dst
+}
+
+/// Source span `[start, end)` covered by output index `i`, never empty.
+fn box_span(i: usize, src: usize, dst: usize) -> (usize, usize) {
+    let start = i * src / dst;
+    let end = ((i + 1) * src / dst).max(start + 1).min(src);
+    (start, end)
+}
+
+fn scale_box(src: &[u8], sw: usize, sh: usize, bpp: usize, dst: &mut [u8], dw: usize, dh: usize) {
+    let x_spans: Vec<(usize, usize)> = (0..dw).map(|x| box_span(x, sw, dw)).collect();
+    let mut sums = vec![0u32; dw * bpp];
+    for y in 0..dh {
+        let (y0, y1) = box_span(y, sh, dh);
+        sums.iter_mut().for_each(|sum| *sum = 0);
+        for sy in y0..y1 {
+            let row = &src[sy * sw * bpp..(sy + 1) * sw * bpp];
+            for (x, &(x0, x1)) in x_spans.iter().enumerate() {
+                let sum = &mut sums[x * bpp..(x + 1) * bpp];
+                for px in row[x0 * bpp..x1 * bpp].chunks_exact(bpp) {
+                    for (s, &v) in sum.iter_mut().zip(px) {
+                        *s += v as u32;
+                    }
+                }
+            }
+        }
+        let out = &mut dst[y * dw * bpp..(y + 1) * dw * bpp];
+        for (x, &(x0, x1)) in x_spans.iter().enumerate() {
+            let n = ((x1 - x0) * (y1 - y0)) as u32;
+            for c in 0..bpp {
+                out[x * bpp + c] = ((sums[x * bpp + c] + n / 2) / n) as u8;
+            }
+        }
+    }
+}
+
+/// Pixel-centre sample positions in 24.8 fixed point: the lower source
+/// index and the weight of the next one.
+fn bilinear_taps(dst: usize, src: usize) -> Vec<(usize, u32)> {
+    (0..dst)
+        .map(|i| {
+            let pos = (((2 * i + 1) * src * 256) / (2 * dst)) as i64 - 128;
+            let pos = pos.clamp(0, ((src - 1) * 256) as i64) as usize;
+            (pos >> 8, (pos & 0xff) as u32)
+        })
+        .collect()
+}
+
+fn scale_bilinear(src: &[u8], sw: usize, sh: usize, bpp: usize, dst: &mut [u8], dw: usize, dh: usize) {
+    let x_taps = bilinear_taps(dw, sw);
+    let y_taps = bilinear_taps(dh, sh);
+    let stride = sw * bpp;
+    for (y, &(y0, wy)) in y_taps.iter().enumerate() {
+        let y1 = (y0 + 1).min(sh - 1);
+        let top = &src[y0 * stride..(y0 + 1) * stride];
+        let bottom = &src[y1 * stride..(y1 + 1) * stride];
+        let out = &mut dst[y * dw * bpp..(y + 1) * dw * bpp];
+        for (x, &(x0, wx)) in x_taps.iter().enumerate() {
+            let x1 = (x0 + 1).min(sw - 1);
+            for c in 0..bpp {
+                let t = top[x0 * bpp + c] as u32 * (256 - wx) + top[x1 * bpp + c] as u32 * wx;
+                let b = bottom[x0 * bpp + c] as u32 * (256 - wx) + bottom[x1 * bpp + c] as u32 * wx;
+                out[x * bpp + c] = ((t * (256 - wy) + b * wy + (1 << 15)) >> 16) as u8;
+            }
+        }
+    }
+}
+
+/// Mirrors packed pixels top to bottom.
+pub fn flip_vertical(data: &mut [u8], width: u32, height: u32, bpp: usize) {
+    let stride = width as usize * bpp;
+    let height = height as usize;
+    for row in 0..height / 2 {
+        let (upper, lower) = data.split_at_mut((height - 1 - row) * stride);
+        upper[row * stride..(row + 1) * stride].swap_with_slice(&mut lower[..stride]);
+    }
+}
+
+/// Mirrors packed pixels left to right.
+pub fn flip_horizontal(data: &mut [u8], width: u32, height: u32, bpp: usize) {
+    let stride = width as usize * bpp;
+    for row in data.chunks_exact_mut(stride).take(height as usize) {
+        let width = width as usize;
+        for x in 0..width / 2 {
+            let (left, right) = row.split_at_mut((width - 1 - x) * bpp);
+            left[x * bpp..(x + 1) * bpp].swap_with_slice(&mut right[..bpp]);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// src/video_main/combined_source.rs
+
+//! All monitors composited into one frame, for `RecorderConfig::combine_all`.
+
+use std::time::Instant;
+
+use super::colorspace::{self, ScaleFilter};
+use super::frame_source::{DisplayInfo, Frame, FrameSource, PixelFormat};
+
+/// How the monitors are arranged in the combined frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CombineLayout {
+    /// Where they are on the virtual desktop; gaps stay black.
+    VirtualDesktop,
+    /// Side by side, left to right in desktop order.
+    Horizontal,
+    /// Stacked, top to bottom in desktop order.
+    Vertical,
+}
+
+/// How monitors of different resolutions are fitted together in the
+/// `Horizontal` and `Vertical` layouts. `VirtualDesktop` is always native.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CombineScaling {
+    /// Every monitor at its own resolution.
+    Native,
+    /// Scaled to the height (horizontal) or width (vertical) of the smallest.
+    MatchSmallest,
+    /// Scaled to the height (horizontal) or width (vertical) of the largest.
+    MatchLargest,
+}
+
+/// Where one monitor lands in the combined frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+pub struct CombinedSource {
+    sources: Vec<(Placement, Box<dyn FrameSource>)>,
+    width: u32,
+    height: u32,
+}
+
+impl CombinedSource {
+    pub fn new(
+        displays: Vec<(DisplayInfo, Box<dyn FrameSource>)>,
+        layout: CombineLayout,
+        scaling: CombineScaling,
+    ) -> anyhow::Result<Self> {
+        if displays.is_empty() {
+            return Err(anyhow::anyhow!("No displays to combine"));
+        }
+        let infos: Vec<DisplayInfo> = displays.iter().map(|(display, _)| *display).collect();
+        let (width, height, placements) = arrange(&infos, layout, scaling);
+        log::info!(
+            "Combining {} displays into {}x{} ({:?}, {:?})",
+            infos.len(), width, height, layout, scaling
+        );
+        Ok(Self {
+            sources: placements
+                .into_iter()
+                .zip(displays)
+                .map(|(placement, (_, source))| (placement, source))
+                .collect(),
+            width,
+            height,
+        })
+    }
+}
+
+impl FrameSource for CombinedSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let timestamp = Instant::now();
+        let canvas_stride = self.width as usize * 3;
+        let mut canvas = vec![0u8; canvas_stride * self.height as usize];
+        for (placement, source) in &mut self.sources {
+            let Some(frame) = source.next_frame()? else {
+                return Ok(None);
+            };
+            let (frame_width, frame_height) = (frame.width, frame.height);
+            let mut rgb = frame.into_rgb();
+            if rgb.len() < frame_width as usize * frame_height as usize * 3 {
+                return Err(anyhow::anyhow!("Frame buffer does not match its size {}x{}", frame_width, frame_height));
+            }
+            if (frame_width, frame_height) != (placement.width, placement.height) {
+                let filter = ScaleFilter::for_sizes(frame_width, frame_height, placement.width, placement.height);
+                rgb = colorspace::scale(&rgb, frame_width, frame_height, 3, placement.width, placement.height, filter);
+            }
+            let row_len = placement.width as usize * 3;
+            for (row, src) in rgb.chunks_exact(row_len).take(placement.height as usize).enumerate() {
+                let start = (placement.y as usize + row) * canvas_stride + placement.x as usize * 3;
+                canvas[start..start + row_len].copy_from_slice(src);
+            }
+        }
+        Ok(Some(Frame {
+            width: self.width,
+            height: self.height,
+            format: PixelFormat::Rgb8,
+            data: canvas,
+            timestamp,
+        }))
+    }
+}
+
+/// Size of the combined frame and each display's place in it, in the
+/// order of `displays`.
+fn arrange(displays: &[DisplayInfo], layout: CombineLayout, scaling: CombineScaling) -> (u32, u32, Vec<Placement>) {
+    if layout == CombineLayout::VirtualDesktop {
+        let left = displays.iter().map(|d| d.x).min().unwrap_or(0);
+        let top = displays.iter().map(|d| d.y).min().unwrap_or(0);
+        let placements: Vec<Placement> = displays
+            .iter()
+            .map(|d| Placement {
+                x: (d.x - left) as u32,
+                y: (d.y - top) as u32,
+                width: d.width,
+                height: d.height,
+            })
+            .collect();
+        let width = placements.iter().map(|p| p.x + p.width).max().unwrap_or(0);
+        let height = placements.iter().map(|p| p.y + p.height).max().unwrap_or(0);
+        return (width, height, placements);
+    }
+
+    let horizontal = layout == CombineLayout::Horizontal;
+    // The side every display shares: height when side by side, width when stacked.
+    let shared = |d: &DisplayInfo| if horizontal { d.height } else { d.width };
+    let target = match scaling {
+        CombineScaling::Native => None,
+        CombineScaling::MatchSmallest => displays.iter().map(shared).min(),
+        CombineScaling::MatchLargest => displays.iter().map(shared).max(),
+    };
+
+    let mut order: Vec<usize> = (0..displays.len()).collect();
+    order.sort_by_key(|&i| {
+        let d = &displays[i];
+        if horizontal { (d.x, d.y) } else { (d.y, d.x) }
+    });
+
+    let mut placements = vec![Placement { x: 0, y: 0, width: 0, height: 0 }; displays.len()];
+    let mut offset = 0u32;
+    for i in order {
+        let d = &displays[i];
+        let (width, height) = match target {
+            Some(side) if horizontal => ((d.width as u64 * side as u64 / d.height.max(1) as u64) as u32, side),
+            Some(side) => (side, (d.height as u64 * side as u64 / d.width.max(1) as u64) as u32),
+            None => (d.width, d.height),
+        };
+        placements[i] = if horizontal {
+            Placement { x: offset, y: 0, width, height }
+        } else {
+            Placement { x: 0, y: offset, width, height }
+        };
+        offset += if horizontal { width } else { height };
+    }
+    let width = placements.iter().map(|p| p.x + p.width).max().unwrap_or(0);
+    let height = placements.iter().map(|p| p.y + p.height).max().unwrap_or(0);
+    (width, height, placements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(index: usize, x: i32, y: i32, width: u32, height: u32) -> DisplayInfo {
+        DisplayInfo { index, x, y, width, height, primary: index == 0 }
+    }
+
+    fn at(x: u32, y: u32, width: u32, height: u32) -> Placement {
+        Placement { x, y, width, height }
+    }
+
+    const ALL_SCALINGS: [CombineScaling; 3] =
+        [CombineScaling::Native, CombineScaling::MatchSmallest, CombineScaling::MatchLargest];
+
+    #[test]
+    fn virtual_desktop_shifts_negative_origins_to_zero() {
+        // A monitor up and to the left of the primary, and one below it.
+        let displays = [
+            display(0, 0, 0, 1920, 1080),
+            display(1, -1280, -200, 1280, 1024),
+            display(2, 0, 1080, 1920, 1080),
+        ];
+        for scaling in ALL_SCALINGS {
+            assert_eq!(
+                arrange(&displays, CombineLayout::VirtualDesktop, scaling),
+                (3200, 2360, vec![at(1280, 200, 1920, 1080), at(0, 0, 1280, 1024), at(1280, 1280, 1920, 1080)]),
+                "{:?}",
+                scaling
+            );
+        }
+    }
+
+    #[test]
+    fn virtual_desktop_keeps_mixed_dpi_monitors_native() {
+        let displays = [display(0, 0, 0, 3840, 2160), display(1, 3840, 540, 1920, 1080)];
+        assert_eq!(
+            arrange(&displays, CombineLayout::VirtualDesktop, CombineScaling::MatchSmallest),
+            (5760, 2160, vec![at(0, 0, 3840, 2160), at(3840, 540, 1920, 1080)])
+        );
+    }
+
+    #[test]
+    fn horizontal_follows_desktop_order() {
+        // Listed right to left; the one at a negative x comes first.
+        let displays = [display(0, 0, 0, 3840, 2160), display(1, -1920, 300, 1920, 1080)];
+        let cases = [
+            (CombineScaling::Native, (5760, 2160, vec![at(1920, 0, 3840, 2160), at(0, 0, 1920, 1080)])),
+            (CombineScaling::MatchSmallest, (3840, 1080, vec![at(1920, 0, 1920, 1080), at(0, 0, 1920, 1080)])),
+            (CombineScaling::MatchLargest, (7680, 2160, vec![at(3840, 0, 3840, 2160), at(0, 0, 3840, 2160)])),
+        ];
+        for (scaling, expected) in cases {
+            assert_eq!(arrange(&displays, CombineLayout::Horizontal, scaling), expected, "{:?}", scaling);
+        }
+    }
+
+    #[test]
+    fn vertical_stacks_top_to_bottom() {
+        // The 5:4 monitor sits above the primary.
+        let displays = [display(0, 0, 0, 1920, 1080), display(1, 200, -1024, 1280, 1024)];
+        let cases = [
+            (CombineScaling::Native, (1920, 2104, vec![at(0, 1024, 1920, 1080), at(0, 0, 1280, 1024)])),
+            (CombineScaling::MatchSmallest, (1280, 1744, vec![at(0, 1024, 1280, 720), at(0, 0, 1280, 1024)])),
+            (CombineScaling::MatchLargest, (1920, 2616, vec![at(0, 1536, 1920, 1080), at(0, 0, 1920, 1536)])),
+        ];
+        for (scaling, expected) in cases {
+            assert_eq!(arrange(&displays, CombineLayout::Vertical, scaling), expected, "{:?}", scaling);
+        }
+    }
+
+    #[test]
+    fn scaled_sizes_round_down() {
+        // 1366x768 at 1080 lines is 1920.9 wide.
+        let displays = [display(0, 0, 0, 1366, 768), display(1, 1366, 0, 1920, 1080)];
+        let (width, height, placements) = arrange(&displays, CombineLayout::Horizontal, CombineScaling::MatchLargest);
+        assert_eq!((width, height), (3840, 1080));
+        assert_eq!(placements[0], at(0, 0, 1920, 1080));
+    }
+
+    /// One solid colour per frame, for checking where each display lands.
+    struct Solid {
+        width: u32,
+        height: u32,
+        rgb: [u8; 3],
+    }
+
+    impl FrameSource for Solid {
+        fn dimensions(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+            Ok(Some(Frame {
+                width: self.width,
+                height: self.height,
+                format: PixelFormat::Rgb8,
+                data: self.rgb.repeat((self.width * self.height) as usize),
+                timestamp: Instant::now(),
+            }))
+        }
+    }
+
+    #[test]
+    fn composites_each_display_into_its_place() {
+        let solid = |display: DisplayInfo, rgb| -> (DisplayInfo, Box<dyn FrameSource>) {
+            (display, Box::new(Solid { width: display.width, height: display.height, rgb }))
+        };
+        let mut source = CombinedSource::new(
+            vec![
+                solid(display(0, 0, 0, 4, 2), [255, 0, 0]),
+                solid(display(1, -2, 2, 2, 2), [0, 0, 255]),
+            ],
+            CombineLayout::VirtualDesktop,
+            CombineScaling::Native,
+        )
+        .unwrap();
+        assert_eq!(source.dimensions(), (6, 4));
+
+        let frame = source.next_frame().unwrap().unwrap();
+        let pixel = |x: usize, y: usize| &frame.data[(y * 6 + x) * 3..][..3];
+        assert_eq!(pixel(2, 0), [255, 0, 0]);
+        assert_eq!(pixel(5, 1), [255, 0, 0]);
+        assert_eq!(pixel(0, 2), [0, 0, 255]);
+        assert_eq!(pixel(1, 3), [0, 0, 255]);
+        // The gaps stay black.
+        assert_eq!(pixel(0, 0), [0, 0, 0]);
+        assert_eq!(pixel(5, 3), [0, 0, 0]);
+    }
+
+    #[test]
+    fn nothing_to_combine_is_an_error() {
+        assert!(CombinedSource::new(Vec::new(), CombineLayout::Horizontal, CombineScaling::Native).is_err());
+    }
+}
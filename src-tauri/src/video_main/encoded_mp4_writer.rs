@@ -0,0 +1,169 @@
+// src/video_main/encoded_mp4_writer.rs
+
+//! MP4 segments from a software `VideoEncoder` through `Mp4Muxer`, the
+//! counterpart of `Mp4SegmentWriter` where Media Foundation is missing.
+
+use chrono::{DateTime, Local};
+use log::info;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::encoder::{open_encoder, EncodedPacket, EncoderSettings, VideoEncoder, VideoEncoderKind};
+use super::mp4_mux::{Mp4Muxer, VideoTrackConfig};
+
+/// Media timescale of the video track.
+const TIMESCALE: u64 = 90_000;
+
+/// Length of one fragment in fragmented mode.
+const FRAGMENT_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct EncodedMp4Config {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub output_dir: PathBuf,
+    pub base_name: String,
+    pub bitrate_kbps: u32,
+    pub encoder: VideoEncoderKind,
+    pub fragmented: bool,
+}
+
+pub struct EncodedMp4Writer {
+    encoder: Box<dyn VideoEncoder>,
+    muxer: Mp4Muxer<BufWriter<File>>,
+    fps: u64,
+    packets_written: u64,
+    frames_sent: u64,
+    /// Capture time of the first frame; timestamps count from here.
+    first_frame_at: Option<Instant>,
+    /// Timestamps, in `TIMESCALE` units, of frames given to the encoder
+    /// whose packets have not come out yet.
+    pending_times: VecDeque<u64>,
+    /// The newest packet and its timestamp. Its duration is known once the
+    /// next frame's timestamp is.
+    held: Option<(EncodedPacket, u64)>,
+    /// Timestamp of the last frame given to the encoder.
+    last_time: Option<u64>,
+    pub output_path: PathBuf,
+}
+
+impl EncodedMp4Writer {
+    pub fn create_new(cfg: EncodedMp4Config) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&cfg.output_dir)?;
+        let encoder = open_encoder(
+            cfg.encoder,
+            &EncoderSettings {
+                width: cfg.width,
+                height: cfg.height,
+                fps: cfg.fps,
+                bitrate_kbps: cfg.bitrate_kbps,
+                keyframe_interval: cfg.fps.max(1) * FRAGMENT_DURATION.as_secs() as u32,
+            },
+        )?;
+
+        let now: DateTime<Local> = Local::now();
+        let filename = format!("{}_{}.mp4", cfg.base_name, now.format("%Y%m%d_%H%M%S"));
+        let path = cfg.output_dir.join(filename);
+        let track = VideoTrackConfig {
+            codec: encoder.codec(),
+            width: cfg.width,
+            height: cfg.height,
+            timescale: TIMESCALE as u32,
+        };
+        let muxer = if cfg.fragmented {
+            Mp4Muxer::create_fragmented(&path, track, None, FRAGMENT_DURATION)?
+        } else {
+            Mp4Muxer::create(&path, track, None)?
+        };
+
+        info!("Creating MP4 writer - encoder: {:?}, fragmented: {}", cfg.encoder, cfg.fragmented);
+        Ok(Self {
+            encoder,
+            muxer,
+            fps: cfg.fps.max(1) as u64,
+            packets_written: 0,
+            frames_sent: 0,
+            first_frame_at: None,
+            pending_times: VecDeque::new(),
+            held: None,
+            last_time: None,
+            output_path: path,
+        })
+    }
+
+    /// Encodes a frame at the configured frame rate.
+    pub fn encode_rgb_frame(&mut self, rgb: &[u8]) -> anyhow::Result<()> {
+        // From the frame's position, so rounding never adds up to drift.
+        self.encode_at(rgb, self.frames_sent * TIMESCALE / self.fps)
+    }
+
+    /// Encodes a frame timed by when it was captured, for variable frame
+    /// rate recordings where unchanged frames are left out.
+    pub fn encode_rgb_frame_at(&mut self, rgb: &[u8], captured_at: Instant) -> anyhow::Result<()> {
+        let first = *self.first_frame_at.get_or_insert(captured_at);
+        let elapsed = captured_at.saturating_duration_since(first);
+        self.encode_at(rgb, (elapsed.as_nanos() * TIMESCALE as u128 / 1_000_000_000) as u64)
+    }
+
+    fn encode_at(&mut self, rgb: &[u8], time: u64) -> anyhow::Result<()> {
+        // Samples need increasing timestamps, even if the clock stalls.
+        let time = match self.last_time {
+            Some(last) if time <= last => last + 1,
+            _ => time,
+        };
+        self.last_time = Some(time);
+        self.frames_sent += 1;
+        self.pending_times.push_back(time);
+        let packets = self.encoder.encode_rgb(rgb)?;
+        self.write_packets(packets)
+    }
+
+    /// The encoders keep input order, so each packet takes the oldest
+    /// pending timestamp. A packet is written when the next one arrives and
+    /// lasts until it.
+    fn write_packets(&mut self, packets: Vec<EncodedPacket>) -> anyhow::Result<()> {
+        for packet in packets {
+            let time = match self.pending_times.pop_front() {
+                Some(time) => time,
+                None => self.held.as_ref().map_or(0, |(_, time)| time + self.nominal_duration()),
+            };
+            if let Some((held, held_time)) = self.held.replace((packet, time)) {
+                let duration = time.saturating_sub(held_time).clamp(1, u32::MAX as u64) as u32;
+                self.muxer.write_video(&held.data, duration, held.keyframe)?;
+                self.packets_written += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Length of one frame at the configured frame rate.
+    fn nominal_duration(&self) -> u64 {
+        (TIMESCALE / self.fps).max(1)
+    }
+
+    pub fn finalize(mut self) -> anyhow::Result<PathBuf> {
+        let packets = self.encoder.flush()?;
+        self.write_packets(packets)?;
+        if let Some((last, _)) = self.held.take() {
+            self.muxer.write_video(&last.data, self.nominal_duration() as u32, last.keyframe)?;
+            self.packets_written += 1;
+        }
+        self.muxer.finish()?;
+
+        if self.packets_written == 0 {
+            let _ = std::fs::remove_file(&self.output_path);
+            info!("MP4 segment had no frames, removed: {}", self.output_path.display());
+        } else {
+            info!(
+                "MP4 segment finalized: {} frames -> {}",
+                self.packets_written,
+                self.output_path.display()
+            );
+        }
+        Ok(self.output_path)
+    }
+}
@@ -0,0 +1,190 @@
+// src/video_main/encoder.rs
+
+//! Video encoders for MP4 output that do not need Media Foundation. Each
+//! backend turns RGB frames into packets for `Mp4Muxer`; which ones exist
+//! depends on cargo features (`av1` for the rav1e software encoder).
+
+use super::mp4_mux::VideoCodec;
+
+/// How `Container::Mp4` segments are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoEncoderKind {
+    /// H.264 through `Mp4SegmentWriter`, Windows only.
+    MediaFoundation,
+    /// AV1 in software with rav1e. Needs the `av1` feature; no GPU needed.
+    Av1,
+}
+
+impl VideoEncoderKind {
+    /// Media Foundation on Windows, the software encoder elsewhere if this
+    /// build has one. `None` when it can not encode MP4 at all.
+    pub fn platform_default() -> Option<Self> {
+        if cfg!(target_os = "windows") {
+            Some(VideoEncoderKind::MediaFoundation)
+        } else if cfg!(feature = "av1") {
+            Some(VideoEncoderKind::Av1)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EncoderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub bitrate_kbps: u32,
+    /// Longest gap between keyframes, in frames.
+    pub keyframe_interval: u32,
+}
+
+/// One encoded frame, in the sample format `Mp4Muxer` expects for `codec`.
+#[derive(Debug, Clone)]
+pub struct EncodedPacket {
+    pub data: Vec<u8>,
+    pub keyframe: bool,
+}
+
+pub trait VideoEncoder: Send {
+    /// Codec and its decoder configuration, for the MP4 sample entry.
+    fn codec(&self) -> VideoCodec;
+
+    /// Encodes one packed RGB frame of the configured size. Encoders may
+    /// hold frames back, so a call can return no packets or several.
+    fn encode_rgb(&mut self, rgb: &[u8]) -> anyhow::Result<Vec<EncodedPacket>>;
+
+    /// Returns the packets of all frames still held back.
+    fn flush(&mut self) -> anyhow::Result<Vec<EncodedPacket>>;
+}
+
+/// Opens a software encoder. `MediaFoundation` is not one: it encodes and
+/// muxes inside `Mp4SegmentWriter`.
+pub fn open_encoder(kind: VideoEncoderKind, settings: &EncoderSettings) -> anyhow::Result<Box<dyn VideoEncoder>> {
+    match kind {
+        VideoEncoderKind::MediaFoundation => Err(anyhow::anyhow!(
+            "Media Foundation is not a standalone encoder; use Mp4SegmentWriter"
+        )),
+        VideoEncoderKind::Av1 => {
+            #[cfg(feature = "av1")]
+            {
+                Ok(Box::new(av1::Av1Encoder::new(settings)?))
+            }
+            #[cfg(not(feature = "av1"))]
+            {
+                let _ = settings;
+                Err(anyhow::anyhow!("AV1 encoder is not enabled; build with the `av1` feature"))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "av1")]
+pub(crate) mod av1 {
+    use super::{EncodedPacket, EncoderSettings, VideoEncoder};
+    use crate::video_main::colorspace::{self, ColorSpace, PackedFormat, YuvPlanes};
+    use crate::video_main::mp4_mux::VideoCodec;
+    use rav1e::prelude::*;
+
+    /// Temporal delimiter OBU with an empty payload. rav1e starts every
+    /// packet with one; in MP4 samples they are to be left out.
+    const TEMPORAL_DELIMITER: [u8; 2] = [0x12, 0x00];
+
+    pub struct Av1Encoder {
+        context: Context<u8>,
+        width: usize,
+        height: usize,
+    }
+
+    impl Av1Encoder {
+        pub fn new(settings: &EncoderSettings) -> anyhow::Result<Self> {
+            let mut enc_config = EncoderConfig::with_speed_preset(10);
+            enc_config.width = settings.width as usize;
+            enc_config.height = settings.height as usize;
+            enc_config.time_base = Rational::new(1, settings.fps.max(1) as u64);
+            enc_config.bitrate = settings.bitrate_kbps.saturating_mul(1000).min(i32::MAX as u32) as i32;
+            enc_config.max_key_frame_interval = settings.keyframe_interval.max(1) as u64;
+            // Packets come out in input order with little delay, which is
+            // what a live recorder wants.
+            enc_config.low_latency = true;
+            enc_config.speed_settings.rdo_lookahead_frames = 1;
+
+            let threads = std::thread::available_parallelism().map_or(2, |n| n.get().min(4));
+            let context: Context<u8> = Config::new()
+                .with_encoder_config(enc_config)
+                .with_threads(threads)
+                .new_context()?;
+            Ok(Self {
+                context,
+                width: settings.width as usize,
+                height: settings.height as usize,
+            })
+        }
+
+        fn receive_packets(&mut self) -> anyhow::Result<Vec<EncodedPacket>> {
+            let mut packets = Vec::new();
+            loop {
+                match self.context.receive_packet() {
+                    Ok(packet) => {
+                        let data = match packet.data.strip_prefix(&TEMPORAL_DELIMITER) {
+                            Some(rest) => rest.to_vec(),
+                            None => packet.data,
+                        };
+                        packets.push(EncodedPacket {
+                            data,
+                            keyframe: packet.frame_type == FrameType::KEY,
+                        });
+                    }
+                    Err(EncoderStatus::Encoded) => continue,
+                    Err(EncoderStatus::NeedMoreData) | Err(EncoderStatus::LimitReached) => break,
+                    Err(e) => return Err(anyhow::anyhow!("Failed to receive packet from rav1e: {:?}", e)),
+                }
+            }
+            Ok(packets)
+        }
+    }
+
+    impl VideoEncoder for Av1Encoder {
+        fn codec(&self) -> VideoCodec {
+            VideoCodec::Av1 {
+                av1c: self.context.container_sequence_header(),
+            }
+        }
+
+        fn encode_rgb(&mut self, rgb: &[u8]) -> anyhow::Result<Vec<EncodedPacket>> {
+            let mut frame = self.context.new_frame();
+            fill_yuv420(&mut frame, rgb, self.width, self.height);
+            self.context
+                .send_frame(frame)
+                .map_err(|e| anyhow::anyhow!("Failed to send frame to rav1e: {:?}", e))?;
+            self.receive_packets()
+        }
+
+        fn flush(&mut self) -> anyhow::Result<Vec<EncodedPacket>> {
+            self.context.flush();
+            self.receive_packets()
+        }
+    }
+
+    /// Fills a rav1e frame from packed RGB, BT.601 full range. Shared with
+    /// the WebM writer.
+    pub(crate) fn fill_yuv420(frame: &mut Frame<u8>, rgb: &[u8], width: usize, height: usize) {
+        let [y_plane, u_plane, v_plane] = &mut frame.planes;
+        let y_stride = y_plane.cfg.stride;
+        let uv_stride = u_plane.cfg.stride;
+        colorspace::to_yuv420(
+            rgb,
+            PackedFormat::Rgb,
+            width,
+            height,
+            YuvPlanes::I420 {
+                y: y_plane.data_origin_mut(),
+                y_stride,
+                u: u_plane.data_origin_mut(),
+                v: v_plane.data_origin_mut(),
+                uv_stride,
+            },
+            ColorSpace::BT601_FULL,
+        );
+    }
+}
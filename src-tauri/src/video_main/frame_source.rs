@@ -0,0 +1,513 @@
+// src/video_main/frame_source.rs
+
+//! Where the recorder gets its frames from. The screen sources capture the
+//! desktop (GDI on Windows, X11 via xcap on Linux); the test pattern and
+//! image sequence sources need no display at all, so the whole recording
+//! pipeline can run headless or under Xvfb.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::colorspace;
+
+#[cfg(target_os = "windows")]
+use windows::Win32::{
+    Foundation::{BOOL, HWND, LPARAM, RECT, TRUE},
+    Graphics::Gdi::{
+        BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject,
+        EnumDisplayMonitors, GetDC, GetDIBits, GetMonitorInfoW, ReleaseDC, SelectObject,
+        BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HDC, HGDIOBJ, HMONITOR,
+        MONITORINFO, SRCCOPY,
+    },
+    UI::WindowsAndMessaging::{GetSystemMetrics, MONITORINFOF_PRIMARY, SM_CXSCREEN, SM_CYSCREEN},
+};
+
+/// Image file extensions `ImageSequenceSource` picks up.
+const SEQUENCE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb8,
+    Bgra8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Bgra8 => 4,
+        }
+    }
+}
+
+/// One captured frame, rows top-down without padding.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+    pub timestamp: Instant,
+}
+
+impl Frame {
+    /// The pixels as packed RGB.
+    pub fn into_rgb(self) -> Vec<u8> {
+        match self.format {
+            PixelFormat::Rgb8 => self.data,
+            PixelFormat::Bgra8 => colorspace::bgra_to_rgb_vec(&self.data),
+        }
+    }
+}
+
+pub trait FrameSource: Send {
+    /// Size of the frames this source produces. A frame may still differ,
+    /// e.g. after a resolution change; the recorder scales it.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Captures the next frame. `Ok(None)` means a finite source is exhausted.
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>>;
+}
+
+/// A monitor and where it sits on the virtual desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayInfo {
+    pub index: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+impl DisplayInfo {
+    /// Whether the virtual desktop point (`x`, `y`) is on this display.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width as i32 && y < self.y + self.height as i32
+    }
+}
+
+/// The monitors of this machine. Index 0 is the one recorded by default:
+/// the primary display on Windows, the first xcap lists on Linux.
+#[cfg(target_os = "windows")]
+pub fn list_displays() -> anyhow::Result<Vec<DisplayInfo>> {
+    unsafe extern "system" fn collect(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+        let displays = &mut *(data.0 as *mut Vec<DisplayInfo>);
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if GetMonitorInfoW(monitor, &mut info).as_bool() {
+            let rect = info.rcMonitor;
+            displays.push(DisplayInfo {
+                index: 0,
+                x: rect.left,
+                y: rect.top,
+                width: (rect.right - rect.left) as u32,
+                height: (rect.bottom - rect.top) as u32,
+                primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+            });
+        }
+        TRUE
+    }
+
+    let mut displays: Vec<DisplayInfo> = Vec::new();
+    let ok = unsafe {
+        EnumDisplayMonitors(HDC(0), None, Some(collect), LPARAM(&mut displays as *mut _ as isize))
+    };
+    if !ok.as_bool() {
+        return Err(anyhow::anyhow!("Failed to enumerate monitors"));
+    }
+    // Stable sort: the primary display first, the rest in enumeration order.
+    displays.sort_by_key(|display| !display.primary);
+    for (index, display) in displays.iter_mut().enumerate() {
+        display.index = index;
+    }
+    Ok(displays)
+}
+
+/// The monitors of this machine. Index 0 is the one recorded by default:
+/// the primary display on Windows, the first xcap lists on Linux.
+#[cfg(target_os = "linux")]
+pub fn list_displays() -> anyhow::Result<Vec<DisplayInfo>> {
+    let monitors = xcap::Monitor::all().map_err(|e| anyhow::anyhow!("Failed to list monitors: {}", e))?;
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| DisplayInfo {
+            index,
+            x: monitor.x(),
+            y: monitor.y(),
+            width: monitor.width(),
+            height: monitor.height(),
+            primary: monitor.is_primary(),
+        })
+        .collect())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn list_displays() -> anyhow::Result<Vec<DisplayInfo>> {
+    Err(anyhow::anyhow!("Screen recording is not supported on this OS"))
+}
+
+/// Which `FrameSource` a `Recorder` opens when none is given explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSourceKind {
+    /// The desktop of this machine.
+    Screen,
+    /// A generated moving pattern.
+    TestPattern { width: u32, height: u32 },
+    /// The images in `dir` in file name order, from the start again if `repeat`.
+    ImageSequence { dir: PathBuf, repeat: bool },
+}
+
+impl FrameSourceKind {
+    pub fn open(&self, display_index: usize) -> anyhow::Result<Box<dyn FrameSource>> {
+        Ok(match self {
+            FrameSourceKind::Screen => open_screen(display_index)?,
+            FrameSourceKind::TestPattern { width, height } => {
+                Box::new(TestPatternSource::new(*width, *height))
+            }
+            FrameSourceKind::ImageSequence { dir, repeat } => {
+                Box::new(ImageSequenceSource::open(dir, *repeat)?)
+            }
+        })
+    }
+
+    /// Opens one source per display, for recording or combining all of
+    /// them. Sources other than the screen are a single display.
+    pub fn open_all(&self) -> anyhow::Result<Vec<(DisplayInfo, Box<dyn FrameSource>)>> {
+        match self {
+            FrameSourceKind::Screen => list_displays()?
+                .into_iter()
+                .map(|display| Ok((display, open_screen(display.index)?)))
+                .collect(),
+            _ => {
+                let source = self.open(0)?;
+                let (width, height) = source.dimensions();
+                let display = DisplayInfo {
+                    index: 0,
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                    primary: true,
+                };
+                Ok(vec![(display, source)])
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn open_screen(display_index: usize) -> anyhow::Result<Box<dyn FrameSource>> {
+    Ok(Box::new(GdiSource::for_display(display_index)?))
+}
+
+#[cfg(target_os = "linux")]
+fn open_screen(display_index: usize) -> anyhow::Result<Box<dyn FrameSource>> {
+    Ok(Box::new(X11Source::new(display_index)?))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn open_screen(_display_index: usize) -> anyhow::Result<Box<dyn FrameSource>> {
+    Err(anyhow::anyhow!("Screen recording is not supported on this OS"))
+}
+
+/// One monitor through GDI, the primary screen unless opened with
+/// `for_display`.
+#[cfg(target_os = "windows")]
+pub struct GdiSource {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+#[cfg(target_os = "windows")]
+impl GdiSource {
+    pub fn new() -> Self {
+        let (width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_CXSCREEN) as u32,
+                GetSystemMetrics(SM_CYSCREEN) as u32,
+            )
+        };
+        Self { x: 0, y: 0, width, height }
+    }
+
+    /// Opens monitor `display_index` as numbered by `list_displays`.
+    pub fn for_display(display_index: usize) -> anyhow::Result<Self> {
+        let displays = list_displays()?;
+        let count = displays.len();
+        let display = displays
+            .into_iter()
+            .nth(display_index)
+            .ok_or_else(|| anyhow::anyhow!("Display {} not found ({} available)", display_index, count))?;
+        Ok(Self {
+            x: display.x,
+            y: display.y,
+            width: display.width,
+            height: display.height,
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Default for GdiSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl FrameSource for GdiSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let timestamp = Instant::now();
+        let data = capture_screen_gdi(self.x, self.y, self.width, self.height);
+        Ok(Some(Frame {
+            width: self.width,
+            height: self.height,
+            format: PixelFormat::Bgra8,
+            data,
+            timestamp,
+        }))
+    }
+}
+
+// Windows GDI screen capture. Using a negative biHeight gives us a top-down image,
+// which is what most encoders expect. No manual flipping is needed.
+#[cfg(target_os = "windows")]
+fn capture_screen_gdi(x: i32, y: i32, width: u32, height: u32) -> Vec<u8> {
+    unsafe {
+        let hdc_screen = GetDC(HWND(0));
+        let hdc_mem = CreateCompatibleDC(hdc_screen);
+        let hbm_mem = CreateCompatibleBitmap(hdc_screen, width as i32, height as i32);
+        let old_bmp = SelectObject(hdc_mem, HGDIOBJ(hbm_mem.0));
+
+        BitBlt(
+            hdc_mem,
+            0,
+            0,
+            width as i32,
+            height as i32,
+            hdc_screen,
+            x,
+            y,
+            SRCCOPY,
+        );
+
+        let mut bmi = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width as i32,
+                biHeight: -(height as i32), // IMPORTANT: This creates a top-down bitmap
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0 as u32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let buffer_size = (width * height * 4) as usize;
+        let mut bgra_bits = vec![0u8; buffer_size];
+
+        GetDIBits(
+            hdc_mem,
+            hbm_mem,
+            0,
+            height,
+            Some(bgra_bits.as_mut_ptr() as *mut _),
+            &mut bmi,
+            DIB_RGB_COLORS,
+        );
+
+        SelectObject(hdc_mem, old_bmp);
+        DeleteObject(HGDIOBJ(hbm_mem.0));
+        DeleteDC(hdc_mem);
+        ReleaseDC(HWND(0), hdc_screen);
+
+        bgra_bits
+    }
+}
+
+/// One X11 monitor, captured through xcap.
+#[cfg(target_os = "linux")]
+pub struct X11Source {
+    monitor: xcap::Monitor,
+}
+
+#[cfg(target_os = "linux")]
+impl X11Source {
+    /// Opens monitor `display_index` in the order xcap lists them.
+    pub fn new(display_index: usize) -> anyhow::Result<Self> {
+        let monitors = xcap::Monitor::all().map_err(|e| anyhow::anyhow!("Failed to list monitors: {}", e))?;
+        let count = monitors.len();
+        let monitor = monitors
+            .into_iter()
+            .nth(display_index)
+            .ok_or_else(|| anyhow::anyhow!("Display {} not found ({} available)", display_index, count))?;
+        Ok(Self { monitor })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl FrameSource for X11Source {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.monitor.width(), self.monitor.height())
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let timestamp = Instant::now();
+        let image = self
+            .monitor
+            .capture_image()
+            .map_err(|e| anyhow::anyhow!("Screen capture failed: {}", e))?;
+        let (width, height) = (image.width(), image.height());
+        let mut rgb = vec![0; (width * height * 3) as usize];
+        colorspace::rgba_to_rgb(image.as_raw(), &mut rgb);
+        Ok(Some(Frame {
+            width,
+            height,
+            format: PixelFormat::Rgb8,
+            data: rgb,
+            timestamp,
+        }))
+    }
+}
+
+/// Colour bars with a square moving across them, one step per frame, so
+/// dropped or duplicated frames are visible in the output.
+pub struct TestPatternSource {
+    width: u32,
+    height: u32,
+    frame: u64,
+}
+
+impl TestPatternSource {
+    const BARS: [[u8; 3]; 8] = [
+        [255, 255, 255],
+        [255, 255, 0],
+        [0, 255, 255],
+        [0, 255, 0],
+        [255, 0, 255],
+        [255, 0, 0],
+        [0, 0, 255],
+        [0, 0, 0],
+    ];
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+            frame: 0,
+        }
+    }
+}
+
+impl FrameSource for TestPatternSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let timestamp = Instant::now();
+        let (width, height) = (self.width as u64, self.height as u64);
+        let side = (height / 4).max(1);
+        let square_x = (self.frame * 8) % width;
+        let square_y = (height - side) / 2;
+
+        let mut data = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let in_square = (square_x..square_x + side).contains(&x) && (square_y..square_y + side).contains(&y);
+                let pixel = if in_square {
+                    [128, 128, 128]
+                } else {
+                    Self::BARS[(x * Self::BARS.len() as u64 / width) as usize]
+                };
+                data.extend_from_slice(&pixel);
+            }
+        }
+        self.frame += 1;
+
+        Ok(Some(Frame {
+            width: self.width,
+            height: self.height,
+            format: PixelFormat::Rgb8,
+            data,
+            timestamp,
+        }))
+    }
+}
+
+/// Image files from a folder, one per frame, in file name order.
+pub struct ImageSequenceSource {
+    files: Vec<PathBuf>,
+    next: usize,
+    repeat: bool,
+    width: u32,
+    height: u32,
+}
+
+impl ImageSequenceSource {
+    pub fn open(dir: &Path, repeat: bool) -> anyhow::Result<Self> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", dir.display(), e))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| SEQUENCE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            })
+            .collect();
+        files.sort();
+
+        let first = files
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No images found in {}", dir.display()))?;
+        let (width, height) = image::image_dimensions(first)?;
+        Ok(Self {
+            files,
+            next: 0,
+            repeat,
+            width,
+            height,
+        })
+    }
+}
+
+impl FrameSource for ImageSequenceSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        if self.next == self.files.len() {
+            if !self.repeat {
+                return Ok(None);
+            }
+            self.next = 0;
+        }
+        let timestamp = Instant::now();
+        let path = &self.files[self.next];
+        self.next += 1;
+
+        let image = image::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))?
+            .to_rgb8();
+        Ok(Some(Frame {
+            width: image.width(),
+            height: image.height(),
+            format: PixelFormat::Rgb8,
+            data: image.into_raw(),
+            timestamp,
+        }))
+    }
+}
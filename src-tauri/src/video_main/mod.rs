@@ -1,7 +1,36 @@
+mod audio_capture;
+#[cfg(target_os = "windows")]
 mod mp4_writer;
+mod mp4_mux;
 mod recorder;
 mod avi_writer;
+mod frame_source;
+mod combined_source;
+mod capture_target;
+mod change_detector;
+mod encoder;
+mod encoded_mp4_writer;
+#[cfg(feature = "webm")]
+mod webm_writer;
+pub mod recovery;
+pub mod colorspace;
 
-pub use mp4_writer::{AudioSource, Mp4SegmentConfig, Mp4SegmentWriter};
-pub use recorder::{Container, Recorder, RecorderConfig};
+pub use audio_capture::{AudioCapture, AudioSource};
+#[cfg(target_os = "windows")]
+pub use mp4_writer::{Mp4SegmentConfig, Mp4SegmentWriter};
+pub use encoder::{open_encoder, EncodedPacket, EncoderSettings, VideoEncoder, VideoEncoderKind};
+pub use encoded_mp4_writer::{EncodedMp4Config, EncodedMp4Writer};
+pub use mp4_mux::{annexb_to_avcc, AudioCodec, AudioTrackConfig, Mp4Muxer, VideoCodec, VideoTrackConfig};
+pub use recorder::{Container, FinishedSegment, FrameDropPolicy, Recorder, RecorderConfig, StopHandle};
 pub use avi_writer::{AviSegmentConfig, AviSegmentWriter};
+#[cfg(feature = "webm")]
+pub use webm_writer::{WebmSegmentConfig, WebmSegmentWriter};
+pub use frame_source::{
+    list_displays, DisplayInfo, Frame, FrameSource, FrameSourceKind, ImageSequenceSource, PixelFormat, TestPatternSource,
+};
+pub use combined_source::{CombineLayout, CombineScaling, CombinedSource};
+pub use capture_target::{CaptureTarget, RegionSource, WindowMatch, WindowSource};
+#[cfg(target_os = "windows")]
+pub use frame_source::GdiSource;
+#[cfg(target_os = "linux")]
+pub use frame_source::X11Source;
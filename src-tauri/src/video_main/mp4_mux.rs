@@ -0,0 +1,734 @@
+// src/video_main/mp4_mux.rs
+
+//! Pure-Rust ISO-BMFF (MP4) muxer for already encoded packets.
+//!
+//! The file is laid out as `ftyp`, `mdat`, `moov`: samples are streamed
+//! into `mdat` as they arrive and the sample tables are written once at
+//! `finish`. This does not depend on Media Foundation, so an MP4 can be
+//! produced by any encoder on any OS; `Mp4SegmentWriter` stays as the
+//! Windows backend that muxes through `IMFSinkWriter` itself.
+//!
+//! In fragmented mode an empty `moov` goes first and samples are written as
+//! `moof` + `mdat` fragments of about `fragment_duration` each. Every
+//! fragment on disk is playable, so a crash only loses the one in progress.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Timescale of the movie header; track durations are converted to it.
+const MOVIE_TIMESCALE: u32 = 1000;
+
+/// ISO 639-2 "und", packed as three 5-bit letters.
+const LANGUAGE_UND: u16 = 0x55c4;
+
+/// `trun` sample flags: a sync sample that depends on no other.
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+/// `trun` sample flags: depends on other samples, not a sync sample.
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VideoCodec {
+    /// Samples are AVCC: NAL units each prefixed with a 4-byte length.
+    /// `annexb_to_avcc` converts encoder output in Annex B form.
+    H264 { sps: Vec<u8>, pps: Vec<u8> },
+    /// Samples are temporal units of low-overhead OBUs. `av1c` is the body
+    /// of the `av1C` box, as returned by rav1e's `container_sequence_header`.
+    Av1 { av1c: Vec<u8> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// Raw AAC frames without ADTS headers.
+    Aac { audio_specific_config: Vec<u8> },
+    /// Opus packets. Opus is always timed at 48 kHz.
+    Opus { pre_skip: u16 },
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoTrackConfig {
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    /// Ticks per second of the sample durations passed to `write_video`.
+    pub timescale: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioTrackConfig {
+    pub codec: AudioCodec,
+    /// Also the timescale of the sample durations passed to `write_audio`.
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrackKind {
+    Video,
+    Audio,
+}
+
+struct Sample {
+    size: u32,
+    duration: u32,
+    sync: bool,
+}
+
+struct Track {
+    id: u32,
+    timescale: u32,
+    samples: Vec<Sample>,
+    /// File offset and sample count of each chunk.
+    chunks: Vec<(u64, u32)>,
+    /// Fragmented mode: first sample of the fragment in progress, and the
+    /// sample data held back until the fragment is written.
+    fragment_start: usize,
+    fragment_data: Vec<u8>,
+}
+
+impl Track {
+    fn new(id: u32, timescale: u32) -> Self {
+        Self {
+            id,
+            timescale,
+            samples: Vec::new(),
+            chunks: Vec::new(),
+            fragment_start: 0,
+            fragment_data: Vec::new(),
+        }
+    }
+
+    fn duration(&self) -> u64 {
+        self.samples.iter().map(|s| s.duration as u64).sum()
+    }
+
+    /// Decode time of the first sample of the fragment in progress.
+    fn fragment_decode_time(&self) -> u64 {
+        self.samples[..self.fragment_start].iter().map(|s| s.duration as u64).sum()
+    }
+
+    fn fragment_samples(&self) -> &[Sample] {
+        &self.samples[self.fragment_start..]
+    }
+
+    fn movie_duration(&self) -> u64 {
+        self.duration() * MOVIE_TIMESCALE as u64 / self.timescale.max(1) as u64
+    }
+}
+
+pub struct Mp4Muxer<W: Write + Seek> {
+    out: W,
+    /// Bytes written so far, i.e. the offset of the next sample.
+    position: u64,
+    mdat_start: u64,
+    video_config: VideoTrackConfig,
+    audio_config: Option<AudioTrackConfig>,
+    video: Track,
+    audio: Option<Track>,
+    /// Track of the last sample written; a sample of the same track
+    /// extends its chunk.
+    last_track: Option<TrackKind>,
+    /// Fragment length in video ticks, `None` for a progressive file.
+    fragment_ticks: Option<u64>,
+    fragment_sequence: u32,
+}
+
+impl Mp4Muxer<BufWriter<File>> {
+    /// Creates `path` and starts muxing into it.
+    pub fn create(
+        path: &Path,
+        video: VideoTrackConfig,
+        audio: Option<AudioTrackConfig>,
+    ) -> anyhow::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), video, audio)
+    }
+
+    /// Creates `path` and starts writing fragments into it.
+    pub fn create_fragmented(
+        path: &Path,
+        video: VideoTrackConfig,
+        audio: Option<AudioTrackConfig>,
+        fragment_duration: Duration,
+    ) -> anyhow::Result<Self> {
+        Self::new_fragmented(BufWriter::new(File::create(path)?), video, audio, fragment_duration)
+    }
+}
+
+impl<W: Write + Seek> Mp4Muxer<W> {
+    pub fn new(out: W, video: VideoTrackConfig, audio: Option<AudioTrackConfig>) -> anyhow::Result<Self> {
+        let mut muxer = Self::with_tracks(out, video, audio)?;
+        let mut header = Vec::new();
+        write_ftyp(&mut header, &muxer.video_config.codec);
+        muxer.mdat_start = header.len() as u64;
+        put_u32(&mut header, 1);
+        header.extend_from_slice(b"mdat");
+        put_u64(&mut header, 0); // patched in `finish`
+        muxer.write_out(&header)?;
+        Ok(muxer)
+    }
+
+    /// Writes `ftyp` and an empty `moov` right away; samples follow in
+    /// fragments that start at a keyframe once `fragment_duration` is reached.
+    pub fn new_fragmented(
+        out: W,
+        video: VideoTrackConfig,
+        audio: Option<AudioTrackConfig>,
+        fragment_duration: Duration,
+    ) -> anyhow::Result<Self> {
+        let mut muxer = Self::with_tracks(out, video, audio)?;
+        let ticks = fragment_duration.as_secs_f64() * muxer.video.timescale as f64;
+        muxer.fragment_ticks = Some((ticks as u64).max(1));
+
+        let mut header = Vec::new();
+        write_ftyp(&mut header, &muxer.video_config.codec);
+        muxer.write_moov(&mut header);
+        muxer.write_out(&header)?;
+        muxer.out.flush()?;
+        Ok(muxer)
+    }
+
+    fn with_tracks(out: W, video: VideoTrackConfig, audio: Option<AudioTrackConfig>) -> anyhow::Result<Self> {
+        if video.timescale == 0 {
+            return Err(anyhow::anyhow!("Video timescale must not be 0"));
+        }
+        if audio.as_ref().is_some_and(|a| a.sample_rate == 0) {
+            return Err(anyhow::anyhow!("Audio sample rate must not be 0"));
+        }
+        let audio_track = audio.as_ref().map(|a| Track::new(2, audio_timescale(a)));
+        Ok(Self {
+            out,
+            position: 0,
+            mdat_start: 0,
+            video: Track::new(1, video.timescale),
+            audio: audio_track,
+            video_config: video,
+            audio_config: audio,
+            last_track: None,
+            fragment_ticks: None,
+            fragment_sequence: 0,
+        })
+    }
+
+    fn write_out(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.out.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    /// Appends one encoded video frame lasting `duration` ticks of the
+    /// video timescale.
+    pub fn write_video(&mut self, data: &[u8], duration: u32, keyframe: bool) -> anyhow::Result<()> {
+        self.write_sample(TrackKind::Video, data, duration, keyframe)
+    }
+
+    /// Appends one encoded audio packet lasting `duration` samples.
+    pub fn write_audio(&mut self, data: &[u8], duration: u32) -> anyhow::Result<()> {
+        if self.audio.is_none() {
+            return Err(anyhow::anyhow!("The muxer has no audio track"));
+        }
+        self.write_sample(TrackKind::Audio, data, duration, true)
+    }
+
+    /// Video frames written so far.
+    pub fn video_frames(&self) -> usize {
+        self.video.samples.len()
+    }
+
+    fn write_sample(&mut self, kind: TrackKind, data: &[u8], duration: u32, sync: bool) -> anyhow::Result<()> {
+        let size = u32::try_from(data.len()).map_err(|_| anyhow::anyhow!("Sample too large"))?;
+        if let Some(fragment_ticks) = self.fragment_ticks {
+            if kind == TrackKind::Video {
+                let elapsed: u64 = self.video.fragment_samples().iter().map(|s| s.duration as u64).sum();
+                // Start fragments at keyframes, unless keyframes are rare.
+                if (sync && elapsed >= fragment_ticks) || elapsed >= fragment_ticks * 2 {
+                    self.write_fragment()?;
+                }
+            }
+            let track = match kind {
+                TrackKind::Video => &mut self.video,
+                TrackKind::Audio => self.audio.as_mut().expect("audio track checked by caller"),
+            };
+            track.fragment_data.extend_from_slice(data);
+            track.samples.push(Sample { size, duration, sync });
+            return Ok(());
+        }
+
+        let offset = self.position;
+        self.write_out(data)?;
+
+        let same_chunk = self.last_track == Some(kind);
+        let track = match kind {
+            TrackKind::Video => &mut self.video,
+            TrackKind::Audio => self.audio.as_mut().expect("audio track checked by caller"),
+        };
+        match track.chunks.last_mut() {
+            Some((_, count)) if same_chunk => *count += 1,
+            _ => track.chunks.push((offset, 1)),
+        }
+        track.samples.push(Sample { size, duration, sync });
+        self.last_track = Some(kind);
+        Ok(())
+    }
+
+    /// Writes the samples held back as one `moof` + `mdat` and flushes, so
+    /// the fragment survives the process.
+    fn write_fragment(&mut self) -> anyhow::Result<()> {
+        let tracks: Vec<&Track> = std::iter::once(&self.video)
+            .chain(self.audio.as_ref())
+            .filter(|track| !track.fragment_samples().is_empty())
+            .collect();
+        if tracks.is_empty() {
+            return Ok(());
+        }
+        self.fragment_sequence += 1;
+
+        let mut moof = Vec::new();
+        // Positions of the `trun` data offsets, patched once the `moof` size is known.
+        let mut data_offset_positions = Vec::new();
+        mp4_box(&mut moof, b"moof", |out| {
+            full_box(out, b"mfhd", 0, 0, |out| put_u32(out, self.fragment_sequence));
+            for track in &tracks {
+                mp4_box(out, b"traf", |out| {
+                    // Flag 0x020000: offsets are relative to the `moof`.
+                    full_box(out, b"tfhd", 0, 0x02_0000, |out| put_u32(out, track.id));
+                    full_box(out, b"tfdt", 1, 0, |out| put_u64(out, track.fragment_decode_time()));
+                    // Flags: data offset, then duration, size and flags per sample.
+                    full_box(out, b"trun", 0, 0x0701, |out| {
+                        put_u32(out, track.fragment_samples().len() as u32);
+                        data_offset_positions.push(out.len());
+                        put_u32(out, 0);
+                        for sample in track.fragment_samples() {
+                            put_u32(out, sample.duration);
+                            put_u32(out, sample.size);
+                            put_u32(out, if sample.sync { SAMPLE_FLAGS_SYNC } else { SAMPLE_FLAGS_NON_SYNC });
+                        }
+                    });
+                });
+            }
+        });
+
+        // Track data follows the `moof` and the 8-byte `mdat` header in track order.
+        let mut data_offset = moof.len() as u64 + 8;
+        for (position, track) in data_offset_positions.into_iter().zip(&tracks) {
+            let offset = u32::try_from(data_offset).map_err(|_| anyhow::anyhow!("Fragment too large"))?;
+            moof[position..position + 4].copy_from_slice(&offset.to_be_bytes());
+            data_offset += track.fragment_data.len() as u64;
+        }
+        let mdat_size = u32::try_from(data_offset - moof.len() as u64)
+            .map_err(|_| anyhow::anyhow!("Fragment too large"))?;
+        let mut mdat_header = Vec::with_capacity(8);
+        put_u32(&mut mdat_header, mdat_size);
+        mdat_header.extend_from_slice(b"mdat");
+
+        self.write_out(&moof)?;
+        self.write_out(&mdat_header)?;
+        for track in std::iter::once(&mut self.video).chain(self.audio.as_mut()) {
+            self.out.write_all(&track.fragment_data)?;
+            self.position += track.fragment_data.len() as u64;
+            track.fragment_data.clear();
+            track.fragment_start = track.samples.len();
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Progressive: patches the `mdat` size and writes `moov`. Fragmented:
+    /// writes the last fragment. Returns the writer.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        if self.fragment_ticks.is_some() {
+            self.write_fragment()?;
+            return Ok(self.out);
+        }
+        let mdat_size = self.position - self.mdat_start;
+        self.out.seek(SeekFrom::Start(self.mdat_start + 8))?;
+        self.out.write_all(&mdat_size.to_be_bytes())?;
+        self.out.seek(SeekFrom::Start(self.position))?;
+
+        let mut moov = Vec::new();
+        self.write_moov(&mut moov);
+        self.out.write_all(&moov)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_moov(&self, out: &mut Vec<u8>) {
+        let fragmented = self.fragment_ticks.is_some();
+        let duration = self
+            .audio
+            .iter()
+            .map(Track::movie_duration)
+            .fold(self.video.movie_duration(), u64::max);
+        let next_track_id = if self.audio.is_some() { 3 } else { 2 };
+
+        mp4_box(out, b"moov", |out| {
+            full_box(out, b"mvhd", 1, 0, |out| {
+                put_u64(out, 0); // creation time
+                put_u64(out, 0); // modification time
+                put_u32(out, MOVIE_TIMESCALE);
+                put_u64(out, duration);
+                put_u32(out, 0x0001_0000); // rate 1.0
+                put_u16(out, 0x0100); // volume 1.0
+                out.extend_from_slice(&[0; 10]);
+                put_matrix(out);
+                out.extend_from_slice(&[0; 24]); // pre_defined
+                put_u32(out, next_track_id);
+            });
+            self.write_trak(out, TrackKind::Video, &self.video);
+            if let Some(audio) = &self.audio {
+                self.write_trak(out, TrackKind::Audio, audio);
+            }
+            if fragmented {
+                mp4_box(out, b"mvex", |out| {
+                    for track in std::iter::once(&self.video).chain(self.audio.as_ref()) {
+                        full_box(out, b"trex", 0, 0, |out| {
+                            put_u32(out, track.id);
+                            put_u32(out, 1); // sample description index
+                            put_u32(out, 0); // default duration
+                            put_u32(out, 0); // default size
+                            put_u32(out, 0); // default flags
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    fn write_trak(&self, out: &mut Vec<u8>, kind: TrackKind, track: &Track) {
+        mp4_box(out, b"trak", |out| {
+            // Flags: enabled, in movie.
+            full_box(out, b"tkhd", 1, 0x3, |out| {
+                put_u64(out, 0);
+                put_u64(out, 0);
+                put_u32(out, track.id);
+                put_u32(out, 0);
+                put_u64(out, track.movie_duration());
+                out.extend_from_slice(&[0; 8]);
+                put_u16(out, 0); // layer
+                put_u16(out, 0); // alternate group
+                put_u16(out, if kind == TrackKind::Audio { 0x0100 } else { 0 });
+                put_u16(out, 0);
+                put_matrix(out);
+                let (width, height) = match kind {
+                    TrackKind::Video => (self.video_config.width, self.video_config.height),
+                    TrackKind::Audio => (0, 0),
+                };
+                put_u32(out, width << 16);
+                put_u32(out, height << 16);
+            });
+            mp4_box(out, b"mdia", |out| {
+                full_box(out, b"mdhd", 1, 0, |out| {
+                    put_u64(out, 0);
+                    put_u64(out, 0);
+                    put_u32(out, track.timescale);
+                    put_u64(out, track.duration());
+                    put_u16(out, LANGUAGE_UND);
+                    put_u16(out, 0);
+                });
+                full_box(out, b"hdlr", 0, 0, |out| {
+                    put_u32(out, 0);
+                    out.extend_from_slice(match kind {
+                        TrackKind::Video => b"vide",
+                        TrackKind::Audio => b"soun",
+                    });
+                    out.extend_from_slice(&[0; 12]);
+                    out.extend_from_slice(match kind {
+                        TrackKind::Video => b"VideoHandler\0".as_slice(),
+                        TrackKind::Audio => b"SoundHandler\0".as_slice(),
+                    });
+                });
+                mp4_box(out, b"minf", |out| {
+                    match kind {
+                        TrackKind::Video => full_box(out, b"vmhd", 0, 1, |out| out.extend_from_slice(&[0; 8])),
+                        TrackKind::Audio => full_box(out, b"smhd", 0, 0, |out| put_u32(out, 0)),
+                    }
+                    mp4_box(out, b"dinf", |out| {
+                        full_box(out, b"dref", 0, 0, |out| {
+                            put_u32(out, 1);
+                            // Flag 1: media data is in this file.
+                            full_box(out, b"url ", 0, 1, |_| {});
+                        });
+                    });
+                    self.write_stbl(out, kind, track);
+                });
+            });
+        });
+    }
+
+    fn write_stbl(&self, out: &mut Vec<u8>, kind: TrackKind, track: &Track) {
+        mp4_box(out, b"stbl", |out| {
+            full_box(out, b"stsd", 0, 0, |out| {
+                put_u32(out, 1);
+                match kind {
+                    TrackKind::Video => write_video_entry(out, &self.video_config),
+                    TrackKind::Audio => {
+                        if let Some(audio) = &self.audio_config {
+                            write_audio_entry(out, audio);
+                        }
+                    }
+                }
+            });
+
+            // Run-length coded sample durations.
+            let mut stts: Vec<(u32, u32)> = Vec::new();
+            for sample in &track.samples {
+                match stts.last_mut() {
+                    Some((count, duration)) if *duration == sample.duration => *count += 1,
+                    _ => stts.push((1, sample.duration)),
+                }
+            }
+            full_box(out, b"stts", 0, 0, |out| {
+                put_u32(out, stts.len() as u32);
+                for (count, duration) in &stts {
+                    put_u32(out, *count);
+                    put_u32(out, *duration);
+                }
+            });
+
+            // Without `stss` every sample is a sync sample.
+            if track.samples.iter().any(|s| !s.sync) {
+                let sync: Vec<u32> = (1..)
+                    .zip(&track.samples)
+                    .filter(|(_, s)| s.sync)
+                    .map(|(number, _)| number)
+                    .collect();
+                full_box(out, b"stss", 0, 0, |out| {
+                    put_u32(out, sync.len() as u32);
+                    sync.iter().for_each(|number| put_u32(out, *number));
+                });
+            }
+
+            // Only entries where the samples per chunk change.
+            let mut stsc: Vec<(u32, u32)> = Vec::new();
+            for (number, (_, count)) in (1..).zip(&track.chunks) {
+                if stsc.last().is_none_or(|(_, last)| last != count) {
+                    stsc.push((number, *count));
+                }
+            }
+            full_box(out, b"stsc", 0, 0, |out| {
+                put_u32(out, stsc.len() as u32);
+                for (first_chunk, count) in &stsc {
+                    put_u32(out, *first_chunk);
+                    put_u32(out, *count);
+                    put_u32(out, 1);
+                }
+            });
+
+            full_box(out, b"stsz", 0, 0, |out| {
+                put_u32(out, 0);
+                put_u32(out, track.samples.len() as u32);
+                track.samples.iter().for_each(|s| put_u32(out, s.size));
+            });
+
+            if track.chunks.last().is_some_and(|(offset, _)| *offset > u32::MAX as u64) {
+                full_box(out, b"co64", 0, 0, |out| {
+                    put_u32(out, track.chunks.len() as u32);
+                    track.chunks.iter().for_each(|(offset, _)| put_u64(out, *offset));
+                });
+            } else {
+                full_box(out, b"stco", 0, 0, |out| {
+                    put_u32(out, track.chunks.len() as u32);
+                    track.chunks.iter().for_each(|(offset, _)| put_u32(out, *offset as u32));
+                });
+            }
+        });
+    }
+}
+
+fn audio_timescale(audio: &AudioTrackConfig) -> u32 {
+    match audio.codec {
+        AudioCodec::Opus { .. } => 48_000,
+        AudioCodec::Aac { .. } => audio.sample_rate,
+    }
+}
+
+fn write_ftyp(out: &mut Vec<u8>, codec: &VideoCodec) {
+    mp4_box(out, b"ftyp", |out| {
+        out.extend_from_slice(b"isom");
+        put_u32(out, 0x200);
+        out.extend_from_slice(b"isomiso2mp41");
+        out.extend_from_slice(match codec {
+            VideoCodec::H264 { .. } => b"avc1",
+            VideoCodec::Av1 { .. } => b"av01",
+        });
+    });
+}
+
+fn write_video_entry(out: &mut Vec<u8>, video: &VideoTrackConfig) {
+    let kind = match video.codec {
+        VideoCodec::H264 { .. } => b"avc1",
+        VideoCodec::Av1 { .. } => b"av01",
+    };
+    mp4_box(out, kind, |out| {
+        out.extend_from_slice(&[0; 6]);
+        put_u16(out, 1); // data reference index
+        out.extend_from_slice(&[0; 16]);
+        put_u16(out, video.width as u16);
+        put_u16(out, video.height as u16);
+        put_u32(out, 0x0048_0000); // 72 dpi
+        put_u32(out, 0x0048_0000);
+        put_u32(out, 0);
+        put_u16(out, 1); // frames per sample
+        out.extend_from_slice(&[0; 32]); // compressor name
+        put_u16(out, 0x0018); // depth
+        put_u16(out, 0xffff); // pre_defined -1
+
+        match &video.codec {
+            VideoCodec::H264 { sps, pps } => mp4_box(out, b"avcC", |out| {
+                put_u8(out, 1);
+                // Profile, compatibility and level straight from the SPS.
+                out.extend_from_slice(sps.get(1..4).unwrap_or(&[66, 0, 31]));
+                put_u8(out, 0xff); // 4-byte NAL lengths
+                put_u8(out, 0xe1); // one SPS
+                put_u16(out, sps.len() as u16);
+                out.extend_from_slice(sps);
+                put_u8(out, 1);
+                put_u16(out, pps.len() as u16);
+                out.extend_from_slice(pps);
+            }),
+            VideoCodec::Av1 { av1c } => mp4_box(out, b"av1C", |out| out.extend_from_slice(av1c)),
+        }
+    });
+}
+
+fn write_audio_entry(out: &mut Vec<u8>, audio: &AudioTrackConfig) {
+    let kind = match audio.codec {
+        AudioCodec::Aac { .. } => b"mp4a",
+        AudioCodec::Opus { .. } => b"Opus",
+    };
+    let sample_rate = audio_timescale(audio);
+    mp4_box(out, kind, |out| {
+        out.extend_from_slice(&[0; 6]);
+        put_u16(out, 1);
+        out.extend_from_slice(&[0; 8]);
+        put_u16(out, audio.channels);
+        put_u16(out, 16); // sample size
+        put_u32(out, 0);
+        put_u32(out, sample_rate.min(0xffff) << 16);
+
+        match &audio.codec {
+            AudioCodec::Aac { audio_specific_config } => full_box(out, b"esds", 0, 0, |out| {
+                let mut decoder_config = Vec::new();
+                put_u8(&mut decoder_config, 0x40); // MPEG-4 audio
+                put_u8(&mut decoder_config, 0x15); // audio stream
+                decoder_config.extend_from_slice(&[0; 3]); // buffer size
+                put_u32(&mut decoder_config, 0); // max bitrate
+                put_u32(&mut decoder_config, 0); // average bitrate
+                put_descriptor(&mut decoder_config, 0x05, audio_specific_config);
+
+                let mut es = Vec::new();
+                put_u16(&mut es, 2); // ES id
+                put_u8(&mut es, 0);
+                put_descriptor(&mut es, 0x04, &decoder_config);
+                put_descriptor(&mut es, 0x06, &[0x02]);
+                put_descriptor(out, 0x03, &es);
+            }),
+            AudioCodec::Opus { pre_skip } => mp4_box(out, b"dOps", |out| {
+                put_u8(out, 0);
+                put_u8(out, audio.channels as u8);
+                put_u16(out, *pre_skip);
+                put_u32(out, audio.sample_rate);
+                put_u16(out, 0); // output gain
+                put_u8(out, 0); // mono or stereo mapping
+            }),
+        }
+    });
+}
+
+/// Splits Annex B output (start-code delimited) into AVCC NAL units.
+/// SPS and PPS units are returned separately instead of kept in the sample,
+/// as `VideoCodec::H264` needs them.
+pub fn annexb_to_avcc(data: &[u8]) -> (Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>) {
+    let mut sample = Vec::with_capacity(data.len());
+    let (mut sps, mut pps) = (None, None);
+    for nal in annexb_nal_units(data) {
+        match nal[0] & 0x1f {
+            7 => sps = Some(nal.to_vec()),
+            8 => pps = Some(nal.to_vec()),
+            _ => {
+                put_u32(&mut sample, nal.len() as u32);
+                sample.extend_from_slice(nal);
+            }
+        }
+    }
+    (sample, sps, pps)
+}
+
+fn annexb_nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    // Positions right after each 00 00 01 start code.
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<usize> = starts.iter().skip(1).map(|s| s - 3).chain([data.len()]).collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(start, end)| {
+            // A 4-byte start code leaves a trailing zero on the previous unit.
+            let mut end = end;
+            while end > start && data[end - 1] == 0 {
+                end -= 1;
+            }
+            &data[start..end]
+        })
+        .filter(|nal| !nal.is_empty())
+}
+
+fn mp4_box(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    put_u32(out, 0);
+    out.extend_from_slice(kind);
+    body(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn full_box(out: &mut Vec<u8>, kind: &[u8; 4], version: u8, flags: u32, body: impl FnOnce(&mut Vec<u8>)) {
+    mp4_box(out, kind, |out| {
+        put_u32(out, (version as u32) << 24 | (flags & 0x00ff_ffff));
+        body(out);
+    });
+}
+
+/// An MPEG-4 descriptor (ISO 14496-1) with a variable-length size.
+fn put_descriptor(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    put_u8(out, tag);
+    let len = body.len() as u32;
+    for shift in [21, 14, 7] {
+        if len >> shift != 0 {
+            put_u8(out, 0x80 | ((len >> shift) & 0x7f) as u8);
+        }
+    }
+    put_u8(out, (len & 0x7f) as u8);
+    out.extend_from_slice(body);
+}
+
+/// Unity transformation matrix.
+fn put_matrix(out: &mut Vec<u8>) {
+    for value in [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000u32] {
+        put_u32(out, value);
+    }
+}
+
+fn put_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
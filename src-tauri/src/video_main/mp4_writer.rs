@@ -1,42 +1,26 @@
+//! MP4 segments encoded and muxed by Media Foundation (`IMFSinkWriter`),
+//! Windows only. `mp4_mux` writes MP4 files without it.
+
 use chrono::{DateTime, Local};
 use std::path::PathBuf;
 use std::sync::Once;
 use std::time::Instant;
 use windows::core::HSTRING;
 use windows::Win32::Media::MediaFoundation::{
-    IMFMediaBuffer, IMFMediaType, IMFSample, IMFSinkWriter, MFAudioFormat_AAC, MFAudioFormat_PCM,
-    MFCreateMediaType, MFCreateMemoryBuffer, MFCreateSample, MFCreateSinkWriterFromURL,
+    IMFMediaBuffer, IMFMediaType, IMFSample, IMFSinkWriter, MFAudioFormat_AAC,
+    MFAudioFormat_PCM, MFCreateAttributes, MFCreateMediaType, MFCreateMemoryBuffer, MFCreateSample, MFCreateSinkWriterFromURL,
     MFMediaType_Audio, MFMediaType_Video, MFStartup, MFVideoFormat_H264, MFVideoFormat_RGB32,
     MFVideoInterlace_Progressive, MFVideoPrimaries_BT709, MFVideoTransFunc_709, MFSTARTUP_FULL,
     MF_MT_AUDIO_AVG_BYTES_PER_SECOND, MF_MT_AUDIO_BITS_PER_SAMPLE, MF_MT_AUDIO_BLOCK_ALIGNMENT,
     MF_MT_AUDIO_NUM_CHANNELS, MF_MT_AUDIO_SAMPLES_PER_SECOND, MF_MT_AVG_BITRATE, MF_MT_FRAME_RATE,
     MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE, MF_MT_PIXEL_ASPECT_RATIO,
     MF_MT_SUBTYPE, MF_MT_TRANSFER_FUNCTION, MF_MT_VIDEO_PRIMARIES, MF_SDK_VERSION,
+    MF_TRANSCODE_CONTAINERTYPE, MFTranscodeContainerType_FMPEG4,
 };
-use windows::Win32::System::Com::{
-    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL,
-    COINIT_APARTMENTTHREADED, COINIT_MULTITHREADED,
-};
-
-#[cfg(feature = "audio_capture")]
-use windows::Win32::Media::Audio::{
-    eCapture, eConsole, eRender, IAudioCaptureClient, IAudioClient, IMMDeviceEnumerator,
-    MMDeviceEnumerator, AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_LOOPBACK,
-};
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED};
 
-// --- START: VOLUME CONTROL CONSTANTS ---
-// Adjust these values to change the volume balance in the final video.
-// 2.0 = 200% volume (louder), 0.5 = 50% volume (quieter)
-const MIC_VOLUME: f32 = 2.5;
-const SYSTEM_VOLUME: f32 = 0.09;
-// --- END: VOLUME CONTROL CONSTANTS ---
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum AudioSource {
-    Microphone,
-    System,
-    Both,
-}
+use super::audio_capture::{self, AudioCapture, AudioSource};
+use super::colorspace;
 
 #[derive(Debug, Clone)]
 pub struct Mp4SegmentConfig {
@@ -49,6 +33,9 @@ pub struct Mp4SegmentConfig {
     pub include_audio: bool,
     pub audio_bitrate_kbps: u32,
     pub audio_source: AudioSource,
+    /// Write a fragmented MP4, so the file stays playable up to the last
+    /// fragment if the app dies before `finalize`.
+    pub fragmented: bool,
 }
 
 pub struct Mp4SegmentWriter {
@@ -64,15 +51,10 @@ pub struct Mp4SegmentWriter {
     audio_time_100ns: u64,
     start_instant: Instant,
     last_video_time_100ns: u64,
-    #[cfg(feature = "audio_capture")]
-    _system_audio_thread: Option<std::thread::JoinHandle<()>>,
-    #[cfg(feature = "audio_capture")]
-    _mic_audio_thread: Option<std::thread::JoinHandle<()>>,
-    #[cfg(feature = "audio_capture")]
-    audio_stop_signal: std::sync::Arc<std::sync::atomic::AtomicBool>,
-    system_buffer: std::sync::Arc<std::sync::Mutex<Vec<i16>>>,
-    mic_buffer: std::sync::Arc<std::sync::Mutex<Vec<i16>>>,
-    audio_source: AudioSource,
+    /// The newest frame and its start time. It is written once the next
+    /// frame's start gives its real duration.
+    held: Option<(IMFSample, u64)>,
+    audio: Option<AudioCapture>,
     pub output_path: PathBuf,
 }
 
@@ -115,7 +97,15 @@ impl Mp4SegmentWriter {
         let path_h = HSTRING::from(path.to_string_lossy().to_string());
 
         let (sink, stream_index, audio_stream_index_opt) = unsafe {
-            let sink: IMFSinkWriter = MFCreateSinkWriterFromURL(&path_h, None, None)?;
+            let sink: IMFSinkWriter = if cfg.fragmented {
+                let mut attributes: Option<IMFAttributes> = None;
+                MFCreateAttributes(&mut attributes, 1)?;
+                let attributes = attributes.ok_or_else(|| anyhow::anyhow!("MFCreateAttributes returned nothing"))?;
+                attributes.SetGUID(&MF_TRANSCODE_CONTAINERTYPE, &MFTranscodeContainerType_FMPEG4)?;
+                MFCreateSinkWriterFromURL(&path_h, None, &attributes)?
+            } else {
+                MFCreateSinkWriterFromURL(&path_h, None, None)?
+            };
 
             // Video Output Type (H.264)
             let out_type = MFCreateMediaType()?;
@@ -140,8 +130,8 @@ impl Mp4SegmentWriter {
             sink.SetInputMediaType(stream_index, &in_type, None)?;
 
             // Audio Stream Setup
-            let audio_channels: u32 = 2;
-            let audio_sample_rate: u32 = 48_000;
+            let audio_channels = audio_capture::CHANNELS;
+            let audio_sample_rate = audio_capture::SAMPLE_RATE;
             let mut audio_stream_index_opt: Option<u32> = None;
 
             if cfg.include_audio {
@@ -183,7 +173,7 @@ impl Mp4SegmentWriter {
             (sink, stream_index, audio_stream_index_opt)
         };
 
-        let mut writer = Self {
+        Ok(Self {
             sink,
             stream_index,
             audio_stream_index: audio_stream_index_opt,
@@ -191,43 +181,34 @@ impl Mp4SegmentWriter {
             width: cfg.width,
             height: cfg.height,
             fps: cfg.fps,
-            audio_sample_rate: 48_000,
-            audio_channels: 2,
+            audio_sample_rate: audio_capture::SAMPLE_RATE,
+            audio_channels: audio_capture::CHANNELS,
             audio_time_100ns: 0,
             start_instant: Instant::now(),
             last_video_time_100ns: 0,
-            #[cfg(feature = "audio_capture")]
-            _system_audio_thread: None,
-            #[cfg(feature = "audio_capture")]
-            _mic_audio_thread: None,
-            #[cfg(feature = "audio_capture")]
-            audio_stop_signal: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
-            system_buffer: std::sync::Arc::new(std::sync::Mutex::new(Vec::new())),
-            mic_buffer: std::sync::Arc::new(std::sync::Mutex::new(Vec::new())),
-            audio_source: cfg.audio_source,
+            held: None,
+            audio: cfg.include_audio.then(|| AudioCapture::start(cfg.audio_source)),
             output_path: path,
-        };
-
-        // Start audio capture if enabled
-        #[cfg(feature = "audio_capture")]
-        if cfg.include_audio {
-            log::info!("Attempting to start audio capture...");
-            match writer.start_audio_capture(cfg.audio_source) {
-                Ok(_) => log::info!("Audio capture initialization completed"),
-                Err(e) => log::error!("Failed to start audio capture: {:?}", e),
-            }
-        }
+        })
+    }
 
-        #[cfg(not(feature = "audio_capture"))]
-        if cfg.include_audio {
-            log::warn!("Audio requested but audio_capture feature not enabled!");
-        }
+    pub fn encode_rgb_frame(&mut self, rgb: &[u8]) -> anyhow::Result<()> {
+        self.encode_rgb_frame_at(rgb, Instant::now())
+    }
 
-        Ok(writer)
+    /// Writes a frame timed by when it was captured rather than when it
+    /// reaches the writer.
+    pub fn encode_rgb_frame_at(&mut self, rgb: &[u8], captured_at: Instant) -> anyhow::Result<()> {
+        self.encode_bgra_frame_at(&colorspace::rgb_to_bgra_vec(rgb), captured_at)
     }
 
-    pub fn encode_rgb_frame(&mut self, rgb: &[u8]) -> anyhow::Result<()> {
-        let elapsed_100ns: u64 = self.start_instant.elapsed().as_nanos().saturating_div(100) as u64;
+    /// Like `encode_rgb_frame_at` for frames already in the writer's RGB32
+    /// input layout, which is BGRA in memory.
+    pub fn encode_bgra_frame_at(&mut self, bgra_bytes: &[u8], captured_at: Instant) -> anyhow::Result<()> {
+        let elapsed_100ns: u64 = captured_at
+            .saturating_duration_since(self.start_instant)
+            .as_nanos()
+            .saturating_div(100) as u64;
         let nominal_frame_100ns: u64 = 10_000_000u64 / (self.fps.max(1) as u64);
         let target_video_time = if elapsed_100ns > self.last_video_time_100ns {
             elapsed_100ns
@@ -236,15 +217,6 @@ impl Mp4SegmentWriter {
                 .saturating_add(nominal_frame_100ns)
         };
 
-        // Convert RGB -> BGRA
-        let mut bgra_bytes: Vec<u8> = Vec::with_capacity(rgb.len() / 3 * 4);
-        for px in rgb.chunks_exact(3) {
-            bgra_bytes.push(px[2]); // B
-            bgra_bytes.push(px[1]); // G
-            bgra_bytes.push(px[0]); // R
-            bgra_bytes.push(255); // A
-        }
-
         unsafe {
             let sample: IMFSample = MFCreateSample()?;
             let buffer: IMFMediaBuffer = MFCreateMemoryBuffer(bgra_bytes.len() as u32)?;
@@ -255,12 +227,16 @@ impl Mp4SegmentWriter {
             buffer.SetCurrentLength(bgra_bytes.len() as u32)?;
             sample.AddBuffer(&buffer)?;
 
-            let video_start_100ns = self.last_video_time_100ns;
-            let video_dur_100ns = (target_video_time.saturating_sub(video_start_100ns)).max(1);
+            // Stamped with its own capture time, so after a run of unchanged
+            // frames that were left out it does not show early. The previous
+            // frame lasts until it.
+            let video_start_100ns = if self.frame_index == 0 { 0 } else { target_video_time };
             sample.SetSampleTime(video_start_100ns as i64)?;
-            sample.SetSampleDuration(video_dur_100ns as i64)?;
-
-            self.sink.WriteSample(self.stream_index, &sample)?;
+            if let Some((previous, previous_start)) = self.held.replace((sample, video_start_100ns)) {
+                let duration = video_start_100ns.saturating_sub(previous_start).max(1);
+                previous.SetSampleDuration(duration as i64)?;
+                self.sink.WriteSample(self.stream_index, &previous)?;
+            }
             self.frame_index += 1;
         }
 
@@ -274,20 +250,18 @@ impl Mp4SegmentWriter {
         log::info!("Finalizing MP4 file with {} frames", self.frame_index);
 
         // Stop audio threads
-        #[cfg(feature = "audio_capture")]
-        {
-            self.audio_stop_signal
-                .store(true, std::sync::atomic::Ordering::SeqCst);
-
-            if let Some(thread) = self._system_audio_thread.take() {
-                let _ = thread.join();
-            }
-            if let Some(thread) = self._mic_audio_thread.take() {
-                let _ = thread.join();
-            }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.stop();
         }
 
         unsafe {
+            // Nothing follows the last frame; it lasts one frame period.
+            if let Some((last, _)) = self.held.take() {
+                let nominal_frame_100ns = 10_000_000u64 / (self.fps.max(1) as u64);
+                if last.SetSampleDuration(nominal_frame_100ns as i64).is_ok() {
+                    let _ = self.sink.WriteSample(self.stream_index, &last);
+                }
+            }
             let _ = self.sink.Finalize();
         }
         if self.frame_index == 0 {
@@ -296,43 +270,6 @@ impl Mp4SegmentWriter {
         Ok(self.output_path)
     }
 
-    #[cfg(feature = "audio_capture")]
-    fn start_audio_capture(&mut self, source: AudioSource) -> anyhow::Result<()> {
-        log::info!("Starting audio capture with source: {:?}", source);
-
-        // Start WASAPI system audio capture (loopback)
-        if source == AudioSource::System || source == AudioSource::Both {
-            let buffer = self.system_buffer.clone();
-            let stop_signal = self.audio_stop_signal.clone();
-
-            let thread = std::thread::spawn(move || {
-                if let Err(e) = capture_system_audio_wasapi(stop_signal, buffer) {
-                    log::error!("System audio capture failed: {:?}", e);
-                }
-            });
-
-            self._system_audio_thread = Some(thread);
-            log::info!("✓ Started WASAPI system audio loopback thread");
-        }
-
-        // Start WASAPI microphone capture
-        if source == AudioSource::Microphone || source == AudioSource::Both {
-            let buffer = self.mic_buffer.clone();
-            let stop_signal = self.audio_stop_signal.clone();
-
-            let thread = std::thread::spawn(move || {
-                if let Err(e) = capture_microphone_wasapi(stop_signal, buffer) {
-                    log::error!("Microphone capture failed: {:?}", e);
-                }
-            });
-
-            self._mic_audio_thread = Some(thread);
-            log::info!("✓ Started WASAPI microphone capture thread");
-        }
-
-        Ok(())
-    }
-
     fn write_mixed_audio_until(&mut self, target_time_100ns: u64) -> anyhow::Result<()> {
         let Some(audio_stream) = self.audio_stream_index else {
             return Ok(());
@@ -350,48 +287,9 @@ impl Mp4SegmentWriter {
         let channels = self.audio_channels as usize;
         let samples_needed_total = (samples_needed as usize) * channels;
 
-        // Get audio from appropriate source(s) and mix
-        let audio_data = match self.audio_source {
-            AudioSource::System => {
-                let mut sys = self.system_buffer.lock().unwrap();
-                let to_take = sys.len().min(samples_needed_total);
-                let mut data: Vec<i16> = sys.drain(..to_take).collect();
-                data.resize(samples_needed_total, 0);
-                data
-            }
-            AudioSource::Microphone => {
-                let mut mic = self.mic_buffer.lock().unwrap();
-                let to_take = mic.len().min(samples_needed_total);
-                let mut data: Vec<i16> = mic.drain(..to_take).collect();
-                data.resize(samples_needed_total, 0);
-                data
-            }
-            AudioSource::Both => {
-                // PROPER MIXING: Weighted mix with volume control
-                let mut sys = self.system_buffer.lock().unwrap();
-                let mut mic = self.mic_buffer.lock().unwrap();
-
-                let sys_take = sys.len().min(samples_needed_total);
-                let mic_take = mic.len().min(samples_needed_total);
-
-                let sys_data: Vec<i16> = sys.drain(..sys_take).collect();
-                let mic_data: Vec<i16> = mic.drain(..mic_take).collect();
-
-                let max_len = sys_data.len().max(mic_data.len()).max(samples_needed_total);
-                let mut mixed = Vec::with_capacity(max_len);
-
-                for i in 0..max_len {
-                    let sys_sample = sys_data.get(i).copied().unwrap_or(0) as f32;
-                    let mic_sample = mic_data.get(i).copied().unwrap_or(0) as f32;
-                    
-                    // Apply volume multipliers and mix
-                    let mixed_f32 = (sys_sample * SYSTEM_VOLUME) + (mic_sample * MIC_VOLUME);
-                    let mixed_sample = mixed_f32.clamp(-32768.0, 32767.0) as i16;
-                    mixed.push(mixed_sample);
-                }
-
-                mixed
-            }
+        let audio_data = match &self.audio {
+            Some(audio) => audio.take_mixed(samples_needed as usize),
+            None => vec![0; samples_needed_total],
         };
 
         // Convert to bytes and write
@@ -419,232 +317,12 @@ impl Mp4SegmentWriter {
     }
 }
 
-// WASAPI System Audio Capture (Loopback)
-#[cfg(feature = "audio_capture")]
-fn capture_system_audio_wasapi(
-    stop_signal: std::sync::Arc<std::sync::atomic::AtomicBool>,
-    mix_buffer: std::sync::Arc<std::sync::Mutex<Vec<i16>>>,
-) -> anyhow::Result<()> {
-    unsafe {
-        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
-
-        // Get default output device
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-        let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
-        let audio_client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
-        let wave_format_ptr = audio_client.GetMixFormat()?;
-        let wave_format = *wave_format_ptr;
-
-        let sample_rate = wave_format.nSamplesPerSec;
-        let channels = wave_format.nChannels;
-        let bits_per_sample = wave_format.wBitsPerSample;
-
-        log::info!(
-            "System audio format: {}Hz, {} channels, {} bits",
-            sample_rate,
-            channels,
-            bits_per_sample
-        );
-
-        audio_client.Initialize(
-            AUDCLNT_SHAREMODE_SHARED,
-            AUDCLNT_STREAMFLAGS_LOOPBACK,
-            200_000_000, // 2 seconds buffer
-            0,
-            wave_format_ptr,
-            None,
-        )?;
-
-        CoTaskMemFree(Some(wave_format_ptr as *const _));
-
-        let capture_client: IAudioCaptureClient = audio_client.GetService()?;
-        audio_client.Start()?;
-
-        log::info!("✓ WASAPI system audio capture started");
-
-        let max_samples = 288_000; // 3 seconds @ 48kHz stereo
-
-        let mut prev_sample: i16 = 0;
-        while !stop_signal.load(std::sync::atomic::Ordering::SeqCst) {
-            let packet_size = capture_client.GetNextPacketSize()?;
-            if packet_size == 0 {
-                std::thread::sleep(std::time::Duration::from_millis(1));
-                continue;
-            }
-
-            let mut data_ptr = std::ptr::null_mut();
-            let mut num_frames = 0;
-            let mut flags = 0;
-            capture_client.GetBuffer(&mut data_ptr, &mut num_frames, &mut flags, None, None)?;
-
-            if num_frames > 0 {
-                let ch = channels as usize;
-                let num_samples = num_frames as usize * ch;
-
-                let mut buf = mix_buffer.lock().unwrap();
-
-                if bits_per_sample == 32 {
-                    // Float32 PCM — most common for system loopback
-                    let samples = std::slice::from_raw_parts(data_ptr as *const f32, num_samples);
-                    for &sample in samples {
-                        let s = (sample * 1.2).clamp(-1.0, 1.0);
-                        let mut s16 = if s >= 0.0 {
-                            (s * 32767.0) as i16
-                        } else {
-                            (s * 32768.0) as i16
-                        };
-
-                        // Simple low-pass filter to smooth hiss
-                        s16 = ((prev_sample as f32 * 0.3) + (s16 as f32 * 0.7)) as i16;
-                        prev_sample = s16;
-
-                        buf.push(s16);
-                    }
-                } else if bits_per_sample == 16 {
-                    let samples = std::slice::from_raw_parts(data_ptr as *const i16, num_samples);
-                    buf.extend_from_slice(samples);
-                } else {
-                    log::warn!("Unsupported PCM bit depth: {}", bits_per_sample);
-                }
-
-                if buf.len() > max_samples {
-                    let len = buf.len();
-                    let excess = len - max_samples;
-                    buf.drain(0..excess);
-                }
-
-                capture_client.ReleaseBuffer(num_frames)?;
-            }
-        }
-
-        audio_client.Stop()?;
-        CoUninitialize();
-        log::info!("WASAPI system audio capture stopped");
-    }
-
-    Ok(())
-}
-
-// WASAPI Microphone Capture
-#[cfg(feature = "audio_capture")]
-fn capture_microphone_wasapi(
-    stop_signal: std::sync::Arc<std::sync::atomic::AtomicBool>,
-    mix_buffer: std::sync::Arc<std::sync::Mutex<Vec<i16>>>,
-) -> anyhow::Result<()> {
-    unsafe {
-        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
-
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-        let device = enumerator.GetDefaultAudioEndpoint(eCapture, eConsole)?;
-
-        let audio_client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
-        let wave_format_ptr = audio_client.GetMixFormat()?;
-        let wave_format = *wave_format_ptr;
-
-        let sample_rate = wave_format.nSamplesPerSec;
-        let channels = wave_format.nChannels;
-        let bits_per_sample = wave_format.wBitsPerSample;
-
-        log::info!(
-            "Microphone format: {}Hz, {} channels, {} bits",
-            sample_rate,
-            channels,
-            bits_per_sample
-        );
-
-        audio_client.Initialize(
-            AUDCLNT_SHAREMODE_SHARED,
-            0, // No special flags for microphone
-            200_000_000,
-            0,
-            wave_format_ptr,
-            None,
-        )?;
-
-        CoTaskMemFree(Some(wave_format_ptr as *const _));
-
-        let capture_client: IAudioCaptureClient = audio_client.GetService()?;
-        audio_client.Start()?;
-
-        log::info!("✓ WASAPI microphone capture started");
-
-        let max_samples = 288_000;
-
-        while !stop_signal.load(std::sync::atomic::Ordering::SeqCst) {
-            let packet_size = capture_client.GetNextPacketSize()?;
-            if packet_size == 0 {
-                std::thread::sleep(std::time::Duration::from_millis(1));
-                continue;
-            }
-
-            let mut data_ptr = std::ptr::null_mut();
-            let mut num_frames = 0;
-            let mut flags = 0;
-
-            capture_client.GetBuffer(&mut data_ptr, &mut num_frames, &mut flags, None, None)?;
-
-            if num_frames > 0 {
-                let ch = channels as usize;
-                let num_samples = num_frames as usize * ch;
-                let samples_slice = std::slice::from_raw_parts(data_ptr as *const f32, num_samples);
-
-                let mut buf = mix_buffer.lock().unwrap();
-                let mut prev = 0i16;
-
-                // Convert to stereo if mono
-                if ch == 1 {
-                    for &sample in samples_slice {
-                        let s = (sample * 1.2).clamp(-1.0, 1.0);
-                        let s16 = if s >= 0.0 {
-                            (s * 32767.0) as i16
-                        } else {
-                            (s * 32768.0) as i16
-                        };
-
-                        // Simple low-pass smoothing filter
-                        let filtered = ((prev as f32 * 0.3) + (s16 as f32 * 0.7)) as i16;
-                        prev = filtered;
-
-                        // Duplicate for stereo
-                        buf.push(filtered);
-                        buf.push(filtered);
-                    }
-                } else {
-                    for &sample in samples_slice {
-                        let s = (sample * 1.2).clamp(-1.0, 1.0);
-                        let s16 = if s >= 0.0 {
-                            (s * 32767.0) as i16
-                        } else {
-                            (s * 32768.0) as i16
-                        };
-                        buf.push(s16);
-                    }
-                }
-
-                if buf.len() > max_samples {
-                    let excess = buf.len() - max_samples;
-                    buf.drain(0..excess);
-                }
-
-                capture_client.ReleaseBuffer(num_frames)?;
-            }
-        }
-
-        audio_client.Stop()?;
-        CoUninitialize();
-        log::info!("WASAPI microphone capture stopped");
-    }
-
-    Ok(())
-}
-
 // Backward compatibility
 impl Mp4SegmentWriter {
     #[cfg(feature = "audio_capture")]
     pub fn start_mic_capture(&mut self) -> anyhow::Result<()> {
-        self.start_audio_capture(AudioSource::Microphone)
+        self.audio = Some(AudioCapture::start(AudioSource::Microphone));
+        Ok(())
     }
 }
 
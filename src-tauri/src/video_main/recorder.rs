@@ -1,31 +1,33 @@
 // src/video_main/recorder.rs
 
 use super::avi_writer::{AviSegmentConfig, AviSegmentWriter};
-use super::mp4_writer::AudioSource;
+use super::encoded_mp4_writer::{EncodedMp4Config, EncodedMp4Writer};
+use super::capture_target::{CaptureTarget, RegionSource, WindowSource};
+use super::change_detector::ChangeDetector;
+use super::combined_source::{CombineLayout, CombineScaling, CombinedSource};
+use super::encoder::VideoEncoderKind;
+use super::colorspace::{self, ScaleFilter};
+use super::frame_source::{Frame, FrameSource, FrameSourceKind, PixelFormat};
+use super::audio_capture::AudioSource;
+#[cfg(target_os = "windows")]
 use super::mp4_writer::{Mp4SegmentConfig, Mp4SegmentWriter};
 #[cfg(feature = "webm")]
 use super::webm_writer::{WebmSegmentConfig, WebmSegmentWriter};
 
-use image::{imageops, ImageBuffer, Rgb};
+use crossbeam_channel::{Receiver, Sender};
 use image::codecs::jpeg::JpegEncoder;
 use image::ColorType;
 use log::{error, warn};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-// Windows GDI
-#[cfg(target_os = "windows")]
-use windows::Win32::{
-    Foundation::HWND,
-    Graphics::Gdi::{
-        BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC,
-        GetDIBits, ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
-        HGDIOBJ, SRCCOPY,
-    },
-    UI::WindowsAndMessaging::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN},
-};
+/// Consecutive failed captures after which recording gives up.
+const MAX_CAPTURE_FAILURES: u32 = 50;
+
+/// Frames per stream each queue between two pipeline stages holds.
+const QUEUE_FRAMES: usize = 4;
 
 #[derive(Debug, Clone)]
 pub struct RecorderConfig {
@@ -34,9 +36,16 @@ pub struct RecorderConfig {
     pub segment_duration: Duration,
     pub fps: u32,
     pub container: Container,
-    pub display_index: usize,
+    /// What to record: a display, a region or a window.
+    pub target: CaptureTarget,
+    /// Record every display into its own segments, all cut at the same
+    /// time. Only for a `CaptureTarget::Display` target.
     pub record_all: bool,
+    /// Record all displays composited into one frame. Wins over
+    /// `record_all`; only for a `CaptureTarget::Display` target.
     pub combine_all: bool,
+    pub combine_layout: CombineLayout,
+    pub combine_scaling: CombineScaling,
     pub flip_vertical: bool,
     pub flip_horizontal: bool,
     pub video_bitrate_kbps: u32,
@@ -44,84 +53,153 @@ pub struct RecorderConfig {
     pub include_audio: bool,
     pub audio_bitrate_kbps: u32,
     pub audio_source: AudioSource,
+    /// Write MP4 segments as fragments, so a crash loses at most the
+    /// fragment in progress instead of the whole segment.
+    pub fragmented_mp4: bool,
+    /// rav1e speed preset for `Container::Webm`, 0 (slowest) to 10 (fastest).
+    pub webm_speed_preset: u8,
+    /// rav1e quantizer for `Container::Webm`, 0 (best) to 255.
+    pub webm_quantizer: usize,
+    /// Encoder for `Container::Mp4`.
+    pub video_encoder: VideoEncoderKind,
+    /// Used unless a source is passed to `Recorder::with_frame_source`.
+    pub source: FrameSourceKind,
+    /// What the capture does while the encoder falls behind.
+    pub drop_policy: FrameDropPolicy,
+    /// Leave out frames identical to the last one written, so a static
+    /// screen costs almost nothing. Segments get variable frame rate
+    /// timestamps; AVI, which has none, repeats the previous frame instead.
+    pub skip_unchanged_frames: bool,
+    /// With `skip_unchanged_frames`, still write a frame this many times a
+    /// second while nothing changes, so players can seek. 0 writes none.
+    pub keepalive_fps: u32,
 }
 
+impl RecorderConfig {
+    /// The app's defaults: the main display at 15 fps into 5 minute MP4
+    /// segments, or AVI where MP4 can not be encoded.
+    pub fn new(output_dir: PathBuf) -> Self {
+        Self {
+            output_dir,
+            base_name: "recording".to_string(),
+            segment_duration: Duration::from_secs(300),
+            fps: 15,
+            container: Container::Mp4.supported(),
+            target: CaptureTarget::Display(0),
+            record_all: false,
+            combine_all: false,
+            combine_layout: CombineLayout::VirtualDesktop,
+            combine_scaling: CombineScaling::Native,
+            flip_vertical: true,
+            flip_horizontal: false,
+            video_bitrate_kbps: 4000,
+            scale_max_width: None,
+            include_audio: false,
+            audio_bitrate_kbps: 128,
+            audio_source: AudioSource::Both,
+            fragmented_mp4: true,
+            webm_speed_preset: 10,
+            webm_quantizer: 160,
+            // Only used for MP4, which `supported` keeps only when there is one.
+            video_encoder: VideoEncoderKind::platform_default().unwrap_or(VideoEncoderKind::Av1),
+            source: FrameSourceKind::Screen,
+            drop_policy: FrameDropPolicy::DropNewest,
+            skip_unchanged_frames: true,
+            keepalive_fps: 1,
+        }
+    }
+}
+
+/// What happens to new frames while the encoder lags and its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDropPolicy {
+    /// Skip the capture, so the recording stays in real time with fewer
+    /// frames. The skipped frames are counted in `FinishedSegment`.
+    DropNewest,
+    /// Wait for the encoder, so no frame is lost but the capture rate falls
+    /// to what the encoder manages.
+    Block,
+}
+
+/// A segment that was finalized and kept on disk.
+#[derive(Debug, Clone)]
+pub struct FinishedSegment {
+    pub path: PathBuf,
+    pub frames: u64,
+    /// The display this segment shows when recording each one separately
+    /// with `record_all`.
+    pub display_index: Option<usize>,
+    /// Frames lost because the encoder lagged or failed to take them.
+    pub dropped_frames: u64,
+    /// Frames left out because nothing changed since the one before.
+    pub unchanged_frames: u64,
+    /// Frames captured per second of segment, written or unchanged.
+    pub achieved_fps: f64,
+}
+
+type SegmentCallback = Box<dyn Fn(&FinishedSegment) + Send + Sync>;
+
+/// A source to record and the display it shows, if it is one of several.
+type StreamSource = (Option<usize>, Box<dyn FrameSource>);
+
 pub struct Recorder {
     cfg: RecorderConfig,
     stop: Arc<AtomicBool>,
+    keep_partial: Arc<AtomicBool>,
+    finish_segment: Arc<AtomicBool>,
+    on_segment: Option<SegmentCallback>,
+    frame_counter: Option<Arc<AtomicU64>>,
+    source: Mutex<Option<Box<dyn FrameSource>>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Container {
-    Avi,
-    Webm,
-    Mp4,
+/// Stops a running `Recorder` from another thread.
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    stop: Arc<AtomicBool>,
+    keep_partial: Arc<AtomicBool>,
+    finish_segment: Arc<AtomicBool>,
 }
 
-// Windows GDI screen capture. Using a negative biHeight gives us a top-down image,
-// which is what most encoders expect. No manual flipping is needed.
-#[cfg(target_os = "windows")]
-fn capture_screen_gdi(width: u32, height: u32) -> Vec<u8> {
-    unsafe {
-        let hdc_screen = GetDC(HWND(0));
-        let hdc_mem = CreateCompatibleDC(hdc_screen);
-        let hbm_mem = CreateCompatibleBitmap(hdc_screen, width as i32, height as i32);
-        let old_bmp = SelectObject(hdc_mem, HGDIOBJ(hbm_mem.0));
-
-        BitBlt(
-            hdc_mem,
-            0,
-            0,
-            width as i32,
-            height as i32,
-            hdc_screen,
-            0,
-            0,
-            SRCCOPY,
-        );
-
-        let mut bmi = BITMAPINFO {
-            bmiHeader: BITMAPINFOHEADER {
-                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
-                biWidth: width as i32,
-                biHeight: -(height as i32), // IMPORTANT: This creates a top-down bitmap
-                biPlanes: 1,
-                biBitCount: 32,
-                biCompression: BI_RGB.0 as u32,
-                ..Default::default()
-            },
-            ..Default::default()
-        };
+impl StopHandle {
+    /// Stops recording. A last segment shorter than `segment_duration` is discarded.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
 
-        let buffer_size = (width * height * 4) as usize;
-        let mut bgra_bits = vec![0u8; buffer_size];
-
-        GetDIBits(
-            hdc_mem,
-            hbm_mem,
-            0,
-            height,
-            Some(bgra_bits.as_mut_ptr() as *mut _),
-            &mut bmi,
-            DIB_RGB_COLORS,
-        );
+    /// Stops recording and keeps the last segment however short it is.
+    pub fn stop_and_keep_segment(&self) {
+        self.keep_partial.store(true, Ordering::Relaxed);
+        self.stop.store(true, Ordering::Relaxed);
+    }
 
-        SelectObject(hdc_mem, old_bmp);
-        DeleteObject(HGDIOBJ(hbm_mem.0));
-        DeleteDC(hdc_mem);
-        ReleaseDC(HWND(0), hdc_screen);
+    /// Lets the current segment run to its full length, then stops. Used to
+    /// switch to a new configuration without cutting a segment short.
+    pub fn stop_at_segment_end(&self) {
+        self.finish_segment.store(true, Ordering::Relaxed);
+    }
 
-        bgra_bits
+    /// Whether `stop` or `stop_and_keep_segment` was called.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
     }
 }
 
-#[cfg(target_os = "windows")]
-fn get_screen_dimensions() -> (u32, u32) {
-    unsafe {
-        (
-            GetSystemMetrics(SM_CXSCREEN) as u32,
-            GetSystemMetrics(SM_CYSCREEN) as u32,
-        )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Avi,
+    Webm,
+    Mp4,
+}
+
+impl Container {
+    /// `self`, or AVI for MP4 when this build has no MP4 encoder for the
+    /// platform (outside Windows without the `av1` feature).
+    pub fn supported(self) -> Self {
+        if self == Container::Mp4 && VideoEncoderKind::platform_default().is_none() {
+            warn!("No MP4 encoder in this build (enable the `av1` feature); recording AVI instead.");
+            return Container::Avi;
+        }
+        self
     }
 }
 
@@ -130,136 +208,536 @@ impl Recorder {
         Self {
             cfg,
             stop: Arc::new(AtomicBool::new(false)),
+            keep_partial: Arc::new(AtomicBool::new(false)),
+            finish_segment: Arc::new(AtomicBool::new(false)),
+            on_segment: None,
+            frame_counter: None,
+            source: Mutex::new(None),
         }
     }
 
-    pub fn stop_flag(&self) -> Arc<AtomicBool> {
-        self.stop.clone()
+    /// Calls `callback` on the recording thread for every segment that is
+    /// finalized and kept, including the last one.
+    pub fn on_segment_finalized(
+        mut self,
+        callback: impl Fn(&FinishedSegment) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_segment = Some(Box::new(callback));
+        self
     }
 
-    pub fn run_blocking(&self) -> anyhow::Result<()> {
-        std::fs::create_dir_all(&self.cfg.output_dir)?;
+    /// Adds one to `counter` for every frame written or left out as
+    /// unchanged (of the first display with `record_all`), so the caller can
+    /// measure the frame rate actually achieved.
+    pub fn count_frames_into(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.frame_counter = Some(counter);
+        self
+    }
+
+    /// Records from `source` instead of opening `RecorderConfig::source`.
+    pub fn with_frame_source(self, source: impl FrameSource + 'static) -> Self {
+        *self.source.lock().unwrap() = Some(Box::new(source));
+        self
+    }
 
-        #[cfg(target_os = "windows")]
-        let (screen_width, screen_height) = get_screen_dimensions();
+    fn segment_finished(&self, path: PathBuf, stream: &Stream, dropped: u64, duration: Duration) {
+        let segment = FinishedSegment {
+            path,
+            frames: stream.frames,
+            display_index: stream.display_index,
+            dropped_frames: dropped + stream.failed,
+            unchanged_frames: stream.unchanged,
+            achieved_fps: (stream.frames + stream.unchanged) as f64 / duration.as_secs_f64().max(f64::EPSILON),
+        };
+        log::info!(
+            "Segment finished: {} frames, {} unchanged, {:.1} fps achieved, {} dropped -> {}",
+            segment.frames, segment.unchanged_frames, segment.achieved_fps, segment.dropped_frames, segment.path.display()
+        );
+        if let Some(callback) = &self.on_segment {
+            callback(&segment);
+        }
+    }
 
-        #[cfg(not(target_os = "windows"))]
-        return Err(anyhow::anyhow!("Only Windows is supported for recording"));
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
 
-        let mut width = screen_width;
-        let mut height = screen_height;
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            stop: self.stop.clone(),
+            keep_partial: self.keep_partial.clone(),
+            finish_segment: self.finish_segment.clone(),
+        }
+    }
 
-        if let Some(max_w) = self.cfg.scale_max_width {
-            if max_w > 0 && width > max_w {
-                height = (height as u64 * max_w as u64 / width as u64) as u32;
-                width = max_w;
+    /// Opens the sources to record, one per output stream: the target
+    /// display, region or window, every display for `record_all`, or all of
+    /// them composited into one for `combine_all`.
+    fn open_streams(&self) -> anyhow::Result<Vec<StreamSource>> {
+        let multi_display = self.cfg.record_all || self.cfg.combine_all;
+        if let Some(source) = self.source.lock().unwrap().take() {
+            if multi_display {
+                warn!("record_all and combine_all are ignored for a given frame source.");
             }
+            return Ok(vec![(None, source)]);
         }
-
-        log::info!(
-            "Recording: container={:?}, {}x{} (screen {}x{}), fps={}",
-            self.cfg.container, width, height, screen_width, screen_height, self.cfg.fps
-        );
-
-        enum WriterKind {
-            Avi(AviSegmentWriter),
-            #[cfg(feature = "webm")]
-            Webm(WebmSegmentWriter),
-            Mp4(Mp4SegmentWriter),
+        let display_index = match &self.cfg.target {
+            CaptureTarget::Display(index) => *index,
+            CaptureTarget::Region { x, y, width, height } => {
+                if multi_display {
+                    warn!("record_all and combine_all are ignored when recording a region.");
+                }
+                let region = RegionSource::new(self.cfg.source.open_all()?, *x, *y, *width, *height)?;
+                return Ok(vec![(None, Box::new(region))]);
+            }
+            CaptureTarget::Window(matcher) => {
+                if multi_display {
+                    warn!("record_all and combine_all are ignored when recording a window.");
+                }
+                let window = WindowSource::new(self.cfg.source.open_all()?, matcher.clone())?;
+                return Ok(vec![(None, Box::new(window))]);
+            }
+        };
+        if self.cfg.combine_all {
+            let combined = CombinedSource::new(self.cfg.source.open_all()?, self.cfg.combine_layout, self.cfg.combine_scaling)?;
+            return Ok(vec![(None, Box::new(combined))]);
+        }
+        if self.cfg.record_all {
+            return Ok(self
+                .cfg
+                .source
+                .open_all()?
+                .into_iter()
+                .map(|(display, source)| (Some(display.index), source))
+                .collect());
         }
+        Ok(vec![(None, self.cfg.source.open(display_index)?)])
+    }
 
-        let mut writer = match self.cfg.container {
-            Container::Avi => WriterKind::Avi(AviSegmentWriter::create_new(AviSegmentConfig {
+    fn open_writer(&self, stream: &Stream) -> anyhow::Result<SegmentWriter> {
+        let (width, height) = (stream.width, stream.height);
+        let output_dir = self.cfg.output_dir.join("videos");
+        let base_name = stream.base_name.clone();
+        Ok(match self.cfg.container {
+            Container::Avi => SegmentWriter::Avi(AviSegmentWriter::create_new(AviSegmentConfig {
                 width,
                 height,
                 fps: self.cfg.fps,
-                output_dir: self.cfg.output_dir.join("videos"),
-                base_name: self.cfg.base_name.clone(),
+                output_dir,
+                base_name,
             })?),
 
             Container::Webm => {
                 #[cfg(feature = "webm")]
                 {
-                    WriterKind::Webm(WebmSegmentWriter::create_new(WebmSegmentConfig {
+                    SegmentWriter::Webm(WebmSegmentWriter::create_new(WebmSegmentConfig {
                         width,
                         height,
                         fps: self.cfg.fps,
-                        output_dir: self.cfg.output_dir.join("videos"),
-                        base_name: self.cfg.base_name.clone(),
-                        quantizer: 160,
+                        output_dir,
+                        base_name,
+                        speed_preset: self.cfg.webm_speed_preset,
+                        quantizer: self.cfg.webm_quantizer,
+                        include_audio: stream.include_audio,
+                        audio_bitrate_kbps: self.cfg.audio_bitrate_kbps,
+                        audio_source: self.cfg.audio_source,
                     })?)
                 }
                 #[cfg(not(feature = "webm"))]
                 return Err(anyhow::anyhow!("WebM feature is not enabled"));
             }
 
-            Container::Mp4 => WriterKind::Mp4(Mp4SegmentWriter::create_new(Mp4SegmentConfig {
+            Container::Mp4 if self.cfg.video_encoder != VideoEncoderKind::MediaFoundation => {
+                SegmentWriter::EncodedMp4(Box::new(EncodedMp4Writer::create_new(EncodedMp4Config {
+                    width,
+                    height,
+                    fps: self.cfg.fps,
+                    output_dir,
+                    base_name,
+                    bitrate_kbps: self.cfg.video_bitrate_kbps,
+                    encoder: self.cfg.video_encoder,
+                    fragmented: self.cfg.fragmented_mp4,
+                })?))
+            }
+
+            #[cfg(not(target_os = "windows"))]
+            Container::Mp4 => {
+                return Err(anyhow::anyhow!("Media Foundation MP4 encoding is only available on Windows"));
+            }
+
+            #[cfg(target_os = "windows")]
+            Container::Mp4 => SegmentWriter::Mp4(Mp4SegmentWriter::create_new(Mp4SegmentConfig {
                 width,
                 height,
                 fps: self.cfg.fps,
-                output_dir: self.cfg.output_dir.join("videos"),
-                base_name: self.cfg.base_name.clone(),
+                output_dir,
+                base_name,
                 bitrate_kbps: self.cfg.video_bitrate_kbps,
-                include_audio: self.cfg.include_audio,
+                include_audio: stream.include_audio,
                 audio_bitrate_kbps: self.cfg.audio_bitrate_kbps,
                 audio_source: self.cfg.audio_source,
+                fragmented: self.cfg.fragmented_mp4,
             })?),
+        })
+    }
+
+    /// Pixel layout the writers take: BGRA for Media Foundation, whose RGB32
+    /// input is BGRA in memory, packed RGB for everything else.
+    fn writer_format(&self) -> PixelFormat {
+        if self.cfg.container == Container::Mp4 && self.cfg.video_encoder == VideoEncoderKind::MediaFoundation {
+            PixelFormat::Bgra8
+        } else {
+            PixelFormat::Rgb8
+        }
+    }
+
+    /// Scales and flips `frame` to `width` x `height` in `writer_format`.
+    /// Scaling happens in the captured format, so a frame is converted at
+    /// most once and only at the output size.
+    fn convert_frame(&self, frame: Frame, width: u32, height: u32) -> Option<Vec<u8>> {
+        let (frame_width, frame_height) = (frame.width, frame.height);
+        let bpp = frame.format.bytes_per_pixel();
+        if frame.data.len() < frame_width as usize * frame_height as usize * bpp {
+            warn!("Frame buffer does not match its size {}x{}.", frame_width, frame_height);
+            return None;
+        }
+        let mut data = if width != frame_width || height != frame_height {
+            let filter = ScaleFilter::for_sizes(frame_width, frame_height, width, height);
+            colorspace::scale(&frame.data, frame_width, frame_height, bpp, width, height, filter)
+        } else {
+            frame.data
         };
+        if self.cfg.flip_vertical {
+            colorspace::flip_vertical(&mut data, width, height, bpp);
+        }
+        if self.cfg.flip_horizontal {
+            colorspace::flip_horizontal(&mut data, width, height, bpp);
+        }
+        Some(match (frame.format, self.writer_format()) {
+            (PixelFormat::Bgra8, PixelFormat::Rgb8) => colorspace::bgra_to_rgb_vec(&data),
+            (PixelFormat::Rgb8, PixelFormat::Bgra8) => colorspace::rgb_to_bgra_vec(&data),
+            _ => data,
+        })
+    }
 
-        let mut segment_start = Instant::now();
-        let expected_frames =
-            (self.cfg.fps as u64).saturating_mul(self.cfg.segment_duration.as_secs());
-        let mut frames = 0u64;
+    /// Writes `frame` to `writer`. Returns whether it made it into the segment.
+    fn encode_frame(&self, stream: &mut Stream, writer: &mut SegmentWriter, frame: &ConvertedFrame, segment_start: Instant) -> bool {
+        let (width, height) = (stream.width, stream.height);
+        match writer {
+            SegmentWriter::Avi(w) => {
+                if !self.repeat_avi_frames(stream, w, frame.timestamp, segment_start) {
+                    return false;
+                }
+                let mut jpeg = Vec::with_capacity((width * height / 10) as usize);
+                let mut enc = JpegEncoder::new_with_quality(&mut jpeg, 70);
+                if enc.encode(&frame.data, width, height, ColorType::Rgb8.into()).is_ok() {
+                    let written = w.write_jpeg_frame(&jpeg).is_ok();
+                    stream.slots += written as u64;
+                    written
+                } else {
+                    warn!("JPEG encoding failed.");
+                    false
+                }
+            }
+            #[cfg(feature = "webm")]
+            SegmentWriter::Webm(w) => w.encode_rgb_frame_at(&frame.data, frame.timestamp).is_ok(),
+            #[cfg(target_os = "windows")]
+            SegmentWriter::Mp4(w) => w.encode_bgra_frame_at(&frame.data, frame.timestamp).is_ok(),
+            SegmentWriter::EncodedMp4(w) => w.encode_rgb_frame_at(&frame.data, frame.timestamp).is_ok(),
+        }
+    }
 
-        let frame_interval = Duration::from_nanos(1_000_000_000 / self.cfg.fps.max(1) as u64);
-        let mut next_frame_time = Instant::now();
+    /// AVI plays at a fixed rate, so the slots of unchanged frames before
+    /// `until` are filled with repeats of the last written frame. Returns
+    /// false if the writer failed.
+    fn repeat_avi_frames(&self, stream: &mut Stream, writer: &mut AviSegmentWriter, until: Instant, segment_start: Instant) -> bool {
+        if !self.cfg.skip_unchanged_frames || stream.frames == 0 {
+            return true;
+        }
+        let slot = (until.saturating_duration_since(segment_start).as_secs_f64() * self.cfg.fps as f64).round() as u64;
+        while stream.slots < slot {
+            if writer.write_repeat_frame().is_err() {
+                return false;
+            }
+            stream.slots += 1;
+        }
+        true
+    }
 
-        log::info!("Starting video recording loop...");
-        while !self.stop.load(Ordering::Relaxed) {
-            let now = Instant::now();
+    /// Adds a frame of stream `index` to the counter from `count_frames_into`,
+    /// which follows the first stream only.
+    fn count_frame(&self, index: usize) {
+        if index == 0 {
+            if let Some(counter) = &self.frame_counter {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
 
-            if now.duration_since(segment_start) >= self.cfg.segment_duration {
-                log::info!("Segment duration reached. Finalizing and starting new segment.");
-                match &mut writer {
-                    WriterKind::Avi(w) => {
-                        let new_writer = AviSegmentWriter::create_new(AviSegmentConfig {
-                            width, height, fps: self.cfg.fps,
-                            output_dir: self.cfg.output_dir.join("videos"),
-                            base_name: self.cfg.base_name.clone(),
-                        })?;
-                        if let Err(e) = std::mem::replace(w, new_writer).finalize() {
-                            error!("Failed to finalize AVI segment: {:?}", e);
+    /// Whether `frame` can be left out: no block differs from the last kept
+    /// frame and the keep-alive interval since it has not run out.
+    fn is_unchanged(&self, detector: &ChangeDetector, last_kept: Option<Instant>, frame: &Frame) -> bool {
+        let Some(last_kept) = last_kept else {
+            return false;
+        };
+        if self.cfg.keepalive_fps > 0 {
+            let keepalive = Duration::from_nanos(1_000_000_000 / self.cfg.keepalive_fps as u64);
+            if frame.timestamp.saturating_duration_since(last_kept) >= keepalive {
+                return false;
+            }
+        }
+        !detector.has_changed(frame)
+    }
+
+    /// Conversion stage: turns captured frames into scaled frames for the
+    /// encoder and passes segment ends through in order.
+    fn convert_stage(&self, sizes: &[(u32, u32)], frames: Receiver<Stage<Frame>>, converted: Sender<Stage<ConvertedFrame>>) {
+        let mut detectors: Vec<ChangeDetector> = sizes.iter().map(|_| ChangeDetector::new()).collect();
+        let mut last_kept: Vec<Option<Instant>> = vec![None; sizes.len()];
+        for message in frames {
+            let message = match message {
+                Stage::Frame { stream, frame } => {
+                    let timestamp = frame.timestamp;
+                    if self.cfg.skip_unchanged_frames {
+                        if self.is_unchanged(&detectors[stream], last_kept[stream], &frame) {
+                            Stage::Unchanged { stream }
+                        } else {
+                            let (width, height) = sizes[stream];
+                            // Conversion consumes its frame, so it gets a copy:
+                            // later frames are only compared with one that
+                            // made it through.
+                            let Some(data) = self.convert_frame(frame.clone(), width, height) else {
+                                continue;
+                            };
+                            detectors[stream].keep(frame);
+                            last_kept[stream] = Some(timestamp);
+                            Stage::Frame {
+                                stream,
+                                frame: ConvertedFrame { data, timestamp },
+                            }
+                        }
+                    } else {
+                        let (width, height) = sizes[stream];
+                        let Some(data) = self.convert_frame(frame, width, height) else {
+                            continue;
+                        };
+                        Stage::Frame {
+                            stream,
+                            frame: ConvertedFrame { data, timestamp },
                         }
                     }
-                    #[cfg(feature = "webm")]
-                    WriterKind::Webm(w) => {
-                        let new_writer = WebmSegmentWriter::create_new(WebmSegmentConfig {
-                             width, height, fps: self.cfg.fps,
-                             output_dir: self.cfg.output_dir.join("videos"),
-                             base_name: self.cfg.base_name.clone(),
-                             quantizer: 160,
-                        })?;
-                        if let Err(e) = std::mem::replace(w, new_writer).finalize() {
-                            error!("Failed to finalize WebM segment: {:?}", e);
+                }
+                Stage::Unchanged { stream } => Stage::Unchanged { stream },
+                Stage::EndSegment { at, dropped } => {
+                    // Every segment starts with a full frame of its own.
+                    detectors.iter_mut().for_each(ChangeDetector::reset);
+                    last_kept.iter_mut().for_each(|kept| *kept = None);
+                    Stage::EndSegment { at, dropped }
+                }
+                Stage::Finish { at, dropped, keep_partial } => Stage::Finish { at, dropped, keep_partial },
+            };
+            if converted.send(message).is_err() {
+                // The encoder is gone; dropping `frames` stops the capture.
+                break;
+            }
+        }
+    }
+
+    /// Encoding stage: owns the writers, starts new segments and reports
+    /// the finished ones.
+    fn encode_stage(&self, mut streams: Vec<Stream>, started: Instant, frames: Receiver<Stage<ConvertedFrame>>) -> anyhow::Result<()> {
+        let mut writers = streams
+            .iter()
+            .map(|stream| self.open_writer(stream))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut segment_start = started;
+
+        for message in frames {
+            match message {
+                Stage::Frame { stream: index, frame } => {
+                    let stream = &mut streams[index];
+                    if self.encode_frame(stream, &mut writers[index], &frame, segment_start) {
+                        stream.frames += 1;
+                        self.count_frame(index);
+                    } else {
+                        stream.failed += 1;
+                    }
+                }
+                Stage::Unchanged { stream: index } => {
+                    streams[index].unchanged += 1;
+                    // The frame before covers it, so it counts as recorded.
+                    self.count_frame(index);
+                }
+                Stage::EndSegment { at, dropped } => {
+                    // All streams roll over together, so their segments cover the same time.
+                    // Each writer is finalized before the next one opens, so
+                    // their audio captures never run at the same time.
+                    let mut next_writers = Vec::with_capacity(writers.len());
+                    for ((stream, mut writer), dropped) in streams.iter_mut().zip(writers).zip(dropped) {
+                        if let SegmentWriter::Avi(w) = &mut writer {
+                            self.repeat_avi_frames(stream, w, at, segment_start);
+                        }
+                        match writer.finalize() {
+                            Ok(path) => self.segment_finished(path, stream, dropped, at - segment_start),
+                            Err(e) => error!("Failed to finalize {:?} segment: {:?}", self.cfg.container, e),
                         }
+                        stream.frames = 0;
+                        stream.failed = 0;
+                        stream.unchanged = 0;
+                        stream.slots = 0;
+                        next_writers.push(self.open_writer(stream)?);
                     }
-                    WriterKind::Mp4(w) => {
-                        let new_writer = Mp4SegmentWriter::create_new(Mp4SegmentConfig {
-                            width, height, fps: self.cfg.fps,
-                            output_dir: self.cfg.output_dir.join("videos"),
-                            base_name: self.cfg.base_name.clone(),
-                            bitrate_kbps: self.cfg.video_bitrate_kbps,
-                            include_audio: self.cfg.include_audio,
-                            audio_bitrate_kbps: self.cfg.audio_bitrate_kbps,
-                            audio_source: self.cfg.audio_source,
-                        })?;
-                        if let Err(e) = std::mem::replace(w, new_writer).finalize() {
-                            error!("Failed to finalize MP4 segment: {:?}", e);
+                    writers = next_writers;
+                    segment_start = at;
+                }
+                Stage::Finish { at, dropped, keep_partial } => {
+                    for (stream, writer) in streams.iter_mut().zip(writers.iter_mut()) {
+                        if let SegmentWriter::Avi(w) = writer {
+                            self.repeat_avi_frames(stream, w, at, segment_start);
                         }
                     }
+                    self.finish_segments(&streams, writers, &dropped, at - segment_start, keep_partial);
+                    return Ok(());
+                }
+            }
+        }
+
+        // The capture side went away without finishing; keep what was recorded.
+        let dropped = vec![0; streams.len()];
+        self.finish_segments(&streams, writers, &dropped, segment_start.elapsed(), true);
+        Ok(())
+    }
+
+    /// Finalizes the last segments, deleting incomplete ones unless `keep_partial`.
+    fn finish_segments(
+        &self,
+        streams: &[Stream],
+        writers: Vec<SegmentWriter>,
+        dropped: &[u64],
+        duration: Duration,
+        keep_partial: bool,
+    ) {
+        let expected_frames =
+            (self.cfg.fps as u64).saturating_mul(self.cfg.segment_duration.as_secs());
+        for ((stream, writer), &dropped) in streams.iter().zip(writers).zip(dropped) {
+            if let Ok(path) = writer.finalize() {
+                // Unchanged frames were captured too; they just needed no writing.
+                let captured = stream.frames + stream.unchanged;
+                if captured < expected_frames && !keep_partial {
+                    log::warn!("Segment incomplete ({} / {} frames). Deleting file: {:?}", captured, expected_frames, path);
+                    let _ = std::fs::remove_file(&path);
+                } else {
+                    self.segment_finished(path, stream, dropped, duration);
+                }
+            }
+        }
+    }
+
+    pub fn run_blocking(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.cfg.output_dir)?;
+
+        let opened = self.open_streams()?;
+        let mut sources = Vec::with_capacity(opened.len());
+        let mut streams = Vec::with_capacity(opened.len());
+        for (i, (display_index, source)) in opened.into_iter().enumerate() {
+            let (source_width, source_height) = source.dimensions();
+            let mut width = source_width;
+            let mut height = source_height;
+
+            if let Some(max_w) = self.cfg.scale_max_width {
+                if max_w > 0 && width > max_w {
+                    height = (height as u64 * max_w as u64 / width as u64) as u32;
+                    width = max_w;
+                }
+            }
+
+            log::info!(
+                "Recording: container={:?}, {}x{} (source {:?} {}x{}, display {:?}), fps={}",
+                self.cfg.container, width, height, self.cfg.source, source_width, source_height, display_index, self.cfg.fps
+            );
+            sources.push(source);
+            streams.push(Stream {
+                width,
+                height,
+                base_name: match display_index {
+                    Some(index) => format!("{}_display{}", self.cfg.base_name, index),
+                    None => self.cfg.base_name.clone(),
+                },
+                // Every stream capturing the same audio would only duplicate it.
+                include_audio: self.cfg.include_audio && i == 0,
+                display_index,
+                frames: 0,
+                failed: 0,
+                unchanged: 0,
+                slots: 0,
+            });
+        }
+
+        if self.cfg.container == Container::Mp4
+            && self.cfg.video_encoder != VideoEncoderKind::MediaFoundation
+            && self.cfg.include_audio
+        {
+            warn!("Audio is not recorded with the {:?} encoder.", self.cfg.video_encoder);
+        }
+
+        let sizes: Vec<(u32, u32)> = streams.iter().map(|stream| (stream.width, stream.height)).collect();
+        // Each capture tick queues a frame for every stream.
+        let queue_len = QUEUE_FRAMES * streams.len();
+        let (frame_tx, frame_rx) = crossbeam_channel::bounded(queue_len);
+        let (converted_tx, converted_rx) = crossbeam_channel::bounded(queue_len);
+        let started = Instant::now();
+
+        std::thread::scope(|scope| {
+            let converter = scope.spawn(|| self.convert_stage(&sizes, frame_rx, converted_tx));
+            let encoder = scope.spawn(move || self.encode_stage(streams, started, converted_rx));
+
+            let captured = self.capture_stage(&mut sources, started, frame_tx);
+            let converted = converter
+                .join()
+                .map_err(|_| anyhow::anyhow!("Converter thread panicked"));
+            let encoded = encoder
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Encoder thread panicked")));
+            // A dead converter or encoder also ends the capture, so their
+            // errors come first.
+            converted.and(encoded).and(captured)
+        })
+    }
+
+    /// Capture stage, on the calling thread: grabs a frame from every
+    /// source at the configured rate and cuts segments.
+    fn capture_stage(
+        &self,
+        sources: &mut [Box<dyn FrameSource>],
+        started: Instant,
+        frames: Sender<Stage<Frame>>,
+    ) -> anyhow::Result<()> {
+        let mut segment_start = started;
+        let mut dropped = vec![0u64; sources.len()];
+        let mut ended_at_boundary = false;
+        let mut source_ended = false;
+        let mut capture_failures = 0u32;
+        let mut capture_error = None;
+
+        let frame_interval = Duration::from_nanos(1_000_000_000 / self.cfg.fps.max(1) as u64);
+        let mut next_frame_time = started;
+
+        log::info!("Starting video recording loop...");
+        'recording: while !self.stop.load(Ordering::Relaxed) {
+            let now = Instant::now();
+
+            if now.duration_since(segment_start) >= self.cfg.segment_duration {
+                if self.finish_segment.load(Ordering::Relaxed) {
+                    log::info!("Segment duration reached. Stopping at the segment boundary as requested.");
+                    ended_at_boundary = true;
+                    break;
+                }
+                log::info!("Segment duration reached. Finalizing and starting new segments.");
+                let dropped = std::mem::replace(&mut dropped, vec![0; sources.len()]);
+                if frames.send(Stage::EndSegment { at: now, dropped }).is_err() {
+                    break;
                 }
                 segment_start = now;
-                frames = 0;
             }
 
             if now < next_frame_time {
@@ -271,78 +749,122 @@ impl Recorder {
             }
             next_frame_time += frame_interval;
 
-            let bgra = capture_screen_gdi(screen_width, screen_height);
-
-            let mut rgb = Vec::with_capacity((screen_width * screen_height * 3) as usize);
-            rgb.extend(bgra.chunks_exact(4).flat_map(|p| [p[2], p[1], p[0]]));
-
-            let mut img = ImageBuffer::<Rgb<u8>, _>::from_raw(screen_width, screen_height, rgb).unwrap();
-            if width != screen_width || height != screen_height {
-                img = imageops::resize(&img, width, height, imageops::FilterType::Triangle);
-            }
-            if self.cfg.flip_vertical {
-                imageops::flip_vertical_in_place(&mut img);
-            }
-            if self.cfg.flip_horizontal {
-                imageops::flip_horizontal_in_place(&mut img);
-            }
-            let rgb_buf = img.into_raw();
-
-            match &mut writer {
-                WriterKind::Avi(w) => {
-                    let mut jpeg = Vec::with_capacity((width * height / 10) as usize);
-                    let mut enc = JpegEncoder::new_with_quality(&mut jpeg, 70);
-                    if enc.encode(&rgb_buf, width, height, ColorType::Rgb8.into()).is_ok() {
-                        if w.write_jpeg_frame(&jpeg).is_ok() {
-                            frames += 1;
-                        }
-                    } else {
-                        warn!("JPEG encoding failed.");
-                    }
-                }
-                #[cfg(feature = "webm")]
-                WriterKind::Webm(w) => {
-                    if w.encode_rgb_frame(&rgb_buf).is_ok() {
-                        frames += 1;
-                    }
-                }
-                WriterKind::Mp4(w) => {
-                    if w.encode_rgb_frame(&rgb_buf).is_ok() {
-                        frames += 1;
-                    }
-                }
+            if self.cfg.drop_policy == FrameDropPolicy::DropNewest
+                && frames.capacity().is_some_and(|capacity| frames.len() + sources.len() > capacity)
+            {
+                // The encoder is behind; skip this tick for every stream alike.
+                dropped.iter_mut().for_each(|count| *count += 1);
+                continue;
             }
-        }
 
-        log::info!("Stop signal received. Finalizing the last segment.");
-        match writer {
-            WriterKind::Avi(w) => {
-                if let Ok(path) = w.finalize() {
-                    if frames < expected_frames {
-                        log::warn!("Segment incomplete ({} / {} frames). Deleting file: {:?}", frames, expected_frames, path);
-                        let _ = std::fs::remove_file(&path);
+            let mut any_failed = false;
+            for (stream, source) in sources.iter_mut().enumerate() {
+                let frame = match source.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => {
+                        log::info!("Frame source exhausted. Finalizing the last segment.");
+                        source_ended = true;
+                        break 'recording;
                     }
-                }
-            }
-            #[cfg(feature = "webm")]
-            WriterKind::Webm(w) => {
-                if let Ok(path) = w.finalize() {
-                    if frames < expected_frames {
-                        log::warn!("Segment incomplete ({} / {} frames). Deleting file: {:?}", frames, expected_frames, path);
-                        let _ = std::fs::remove_file(&path);
+                    Err(e) => {
+                        if !any_failed {
+                            capture_failures += 1;
+                            any_failed = true;
+                            if capture_failures == 1 {
+                                warn!("Frame capture failed: {:#}", e);
+                            }
+                            if capture_failures >= MAX_CAPTURE_FAILURES {
+                                capture_error = Some(e);
+                                break 'recording;
+                            }
+                        }
+                        continue;
                     }
+                };
+                if frames.send(Stage::Frame { stream, frame }).is_err() {
+                    // The encoder stopped; its error is reported by `run_blocking`.
+                    break 'recording;
                 }
             }
-            WriterKind::Mp4(w) => {
-                if let Ok(path) = w.finalize() {
-                    if frames < expected_frames {
-                        log::warn!("Segment incomplete ({} / {} frames). Deleting file: {:?}", frames, expected_frames, path);
-                        let _ = std::fs::remove_file(&path);
-                    }
-                }
+            if !any_failed {
+                capture_failures = 0;
             }
         }
 
-        Ok(())
+        log::info!("Recording loop ended. Finalizing the last segment.");
+        // A segment ended at the boundary is complete even if a few frames
+        // were dropped; an exhausted source has nothing more to give.
+        let keep_partial = self.keep_partial.load(Ordering::Relaxed) || ended_at_boundary || source_ended;
+        let _ = frames.send(Stage::Finish {
+            at: Instant::now(),
+            dropped,
+            keep_partial,
+        });
+
+        match capture_error {
+            Some(e) => Err(e.context(format!("{} frame captures in a row failed", MAX_CAPTURE_FAILURES))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What flows from stage to stage. Frames of all streams share one queue,
+/// tagged with the stream they belong to.
+enum Stage<T> {
+    Frame { stream: usize, frame: T },
+    /// A frame of `stream` identical to the last one written, left out.
+    Unchanged { stream: usize },
+    /// The current segments end here. `dropped` has, per stream, the frames
+    /// skipped since the previous segment end because the encoder lagged.
+    EndSegment { at: Instant, dropped: Vec<u64> },
+    /// Recording stopped; the last segments end here.
+    Finish { at: Instant, dropped: Vec<u64>, keep_partial: bool },
+}
+
+/// A frame scaled and converted to `Recorder::writer_format`.
+struct ConvertedFrame {
+    data: Vec<u8>,
+    timestamp: Instant,
+}
+
+/// One recorded source and the segments it goes to.
+struct Stream {
+    width: u32,
+    height: u32,
+    base_name: String,
+    /// Only the Media Foundation MP4 and WebM writers record audio.
+    #[cfg_attr(not(any(target_os = "windows", feature = "webm")), allow(dead_code))]
+    include_audio: bool,
+    display_index: Option<usize>,
+    /// Frames in the current segment.
+    frames: u64,
+    /// Frames of the current segment the writer failed to take.
+    failed: u64,
+    /// Frames of the current segment left out as unchanged.
+    unchanged: u64,
+    /// Fixed-rate frame slots the current segment covers, for AVI, which
+    /// has no timestamps.
+    slots: u64,
+}
+
+enum SegmentWriter {
+    Avi(AviSegmentWriter),
+    #[cfg(feature = "webm")]
+    Webm(WebmSegmentWriter),
+    #[cfg(target_os = "windows")]
+    Mp4(Mp4SegmentWriter),
+    EncodedMp4(Box<EncodedMp4Writer>),
+}
+
+impl SegmentWriter {
+    fn finalize(self) -> anyhow::Result<PathBuf> {
+        match self {
+            SegmentWriter::Avi(w) => w.finalize(),
+            #[cfg(feature = "webm")]
+            SegmentWriter::Webm(w) => w.finalize(),
+            #[cfg(target_os = "windows")]
+            SegmentWriter::Mp4(w) => w.finalize(),
+            SegmentWriter::EncodedMp4(w) => w.finalize(),
+        }
     }
-}
\ No newline at end of file
+}
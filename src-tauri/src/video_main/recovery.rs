@@ -0,0 +1,286 @@
+// src/video_main/recovery.rs
+
+//! Repairs segments left behind when the app died mid-recording, so they
+//! can still be uploaded. Meant to run at startup before the recorder
+//! writes anything.
+//!
+//! - Fragmented MP4: cut after the last complete `moof` + `mdat` pair.
+//! - MP4 without a `moov` (never finalized, not fragmented): removed, as
+//!   there is no index to play it with.
+//! - AVI: cut after the last complete frame chunk, then the `movi` size,
+//!   `idx1`, frame counts and RIFF size are written as
+//!   `AviSegmentWriter::finalize` would.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// `idx1` flag marking a keyframe; every MJPEG frame is one.
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// The file was complete.
+    Intact,
+    /// The incomplete tail was cut off and the file is playable again.
+    Repaired,
+    /// Nothing playable was left and the file was deleted.
+    Removed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mp4Box {
+    kind: [u8; 4],
+    start: u64,
+    end: u64,
+}
+
+/// Checks and, where needed, repairs every `.mp4` and `.avi` below `dir`.
+/// Files that could not be read are logged and left alone.
+pub fn recover_segments(dir: &Path) -> Vec<(PathBuf, RecoveryAction)> {
+    let mut results = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return results;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            results.extend(recover_segments(&path));
+            continue;
+        }
+        match recover_segment(&path) {
+            Ok(Some(RecoveryAction::Intact)) | Ok(None) => {}
+            Ok(Some(action)) => {
+                log::warn!("Recovered partial segment {}: {:?}", path.display(), action);
+                results.push((path, action));
+            }
+            Err(e) => log::error!("Failed to check segment {}: {:#}", path.display(), e),
+        }
+    }
+    results
+}
+
+/// Repairs one segment. `None` for files that are not MP4 or AVI.
+pub fn recover_segment(path: &Path) -> anyhow::Result<Option<RecoveryAction>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    let action = match extension.as_deref() {
+        Some("mp4") => recover_mp4(path)?,
+        Some("avi") => recover_avi(path)?,
+        _ => return Ok(None),
+    };
+    if action == RecoveryAction::Removed {
+        fs::remove_file(path)?;
+    }
+    Ok(Some(action))
+}
+
+/// Complete boxes from `start` to `end`, and whether the range ended
+/// exactly at a box boundary.
+fn read_boxes(file: &mut File, start: u64, end: u64) -> anyhow::Result<(Vec<Mp4Box>, bool)> {
+    let mut boxes = Vec::new();
+    let mut position = start;
+    while position < end {
+        if end - position < 8 {
+            return Ok((boxes, false));
+        }
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes(header[..4].try_into()?) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            if end - position < 16 {
+                return Ok((boxes, false));
+            }
+            file.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..].try_into()?);
+            header_len = 16;
+        } else if size == 0 {
+            // Runs to the end of the file.
+            size = end - position;
+        }
+        // A size of 1 with a zero 64-bit size is the placeholder of a
+        // progressive `mdat` that was never finished.
+        if size < header_len || size > end - position {
+            return Ok((boxes, false));
+        }
+        boxes.push(Mp4Box {
+            kind: header[4..8].try_into()?,
+            start: position,
+            end: position + size,
+        });
+        position += size;
+    }
+    Ok((boxes, true))
+}
+
+fn recover_mp4(path: &Path) -> anyhow::Result<RecoveryAction> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let (boxes, complete) = read_boxes(&mut file, 0, len)?;
+
+    let Some(moov) = boxes.iter().find(|b| &b.kind == b"moov") else {
+        return Ok(RecoveryAction::Removed);
+    };
+    // The `moov` header is 8 bytes; a 64-bit size never occurs for it.
+    let (children, _) = read_boxes(&mut file, moov.start + 8, moov.end)?;
+    let fragmented = children.iter().any(|b| &b.kind == b"mvex");
+
+    let keep_end = if fragmented {
+        // End of the last `mdat` that directly follows a `moof`.
+        let last_fragment_end = boxes
+            .windows(2)
+            .rfind(|pair| &pair[0].kind == b"moof" && &pair[1].kind == b"mdat")
+            .map(|pair| pair[1].end);
+        let Some(last_fragment_end) = last_fragment_end else {
+            return Ok(RecoveryAction::Removed);
+        };
+        let dangling_moof = boxes.last().is_some_and(|b| &b.kind == b"moof");
+        if complete && !dangling_moof {
+            return Ok(RecoveryAction::Intact);
+        }
+        last_fragment_end
+    } else {
+        if complete {
+            return Ok(RecoveryAction::Intact);
+        }
+        // Finalized, with junk after it.
+        boxes.last().map_or(0, |b| b.end)
+    };
+
+    drop(file);
+    OpenOptions::new().write(true).open(path)?.set_len(keep_end)?;
+    Ok(RecoveryAction::Repaired)
+}
+
+fn read_u32_le(file: &mut File) -> anyhow::Result<u32> {
+    let mut bytes = [0u8; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_fourcc(file: &mut File) -> anyhow::Result<[u8; 4]> {
+    let mut bytes = [0u8; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Stream chunk ids look like `00dc` or `01wb`.
+fn is_stream_chunk(id: &[u8; 4]) -> bool {
+    id[0].is_ascii_digit() && id[1].is_ascii_digit() && id[2].is_ascii_alphabetic() && id[3].is_ascii_alphabetic()
+}
+
+fn recover_avi(path: &Path) -> anyhow::Result<RecoveryAction> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    if len < 12 || &read_fourcc(&mut file)? != b"RIFF" {
+        return Ok(RecoveryAction::Removed);
+    }
+    let riff_size = read_u32_le(&mut file)? as u64;
+    if riff_size != 0 && riff_size + 8 == len {
+        return Ok(RecoveryAction::Intact);
+    }
+
+    // Find LIST 'movi' among the top-level chunks, noting LIST 'hdrl' on
+    // the way.
+    let mut position = 12;
+    let mut hdrl = None;
+    let movi_fourcc_pos = loop {
+        if position + 12 > len {
+            return Ok(RecoveryAction::Removed);
+        }
+        file.seek(SeekFrom::Start(position))?;
+        let id = read_fourcc(&mut file)?;
+        let size = read_u32_le(&mut file)? as u64;
+        if &id == b"LIST" {
+            match &read_fourcc(&mut file)? {
+                b"movi" => break position + 8,
+                b"hdrl" => hdrl = Some((position + 12, (position + 8 + size).min(len))),
+                _ => {}
+            }
+        }
+        position += 8 + size + (size & 1);
+    };
+
+    // Index offsets are relative to the 'movi' fourcc, as in the writer.
+    let mut index = Vec::new();
+    let mut position = movi_fourcc_pos + 4;
+    while position + 8 <= len {
+        file.seek(SeekFrom::Start(position))?;
+        let id = read_fourcc(&mut file)?;
+        let size = read_u32_le(&mut file)? as u64;
+        let chunk_end = position + 8 + size + (size & 1);
+        if !is_stream_chunk(&id) || chunk_end > len {
+            break;
+        }
+        index.push((id, (position - movi_fourcc_pos) as u32, size as u32));
+        position = chunk_end;
+    }
+    if index.is_empty() {
+        return Ok(RecoveryAction::Removed);
+    }
+
+    file.set_len(position)?;
+    file.seek(SeekFrom::Start(movi_fourcc_pos - 4))?;
+    file.write_all(&((position - movi_fourcc_pos) as u32).to_le_bytes())?;
+
+    let mut idx1 = Vec::with_capacity(8 + index.len() * 16);
+    idx1.extend_from_slice(b"idx1");
+    idx1.extend_from_slice(&(index.len() as u32 * 16).to_le_bytes());
+    for (id, offset, size) in &index {
+        idx1.extend_from_slice(id);
+        idx1.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+        idx1.extend_from_slice(&offset.to_le_bytes());
+        idx1.extend_from_slice(&size.to_le_bytes());
+    }
+    file.seek(SeekFrom::Start(position))?;
+    file.write_all(&idx1)?;
+
+    if let Some((start, end)) = hdrl {
+        let video_frames = index.iter().filter(|(id, _, _)| &id[..2] == b"00").count() as u32;
+        patch_frame_counts(&mut file, start, end, video_frames)?;
+    }
+
+    let final_len = position + idx1.len() as u64;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((final_len - 8) as u32).to_le_bytes())?;
+    file.flush()?;
+    Ok(RecoveryAction::Repaired)
+}
+
+/// Sets `dwTotalFrames` in `avih` and `dwLength` in the video stream's
+/// `strh` within the `hdrl` list data from `start` to `end`. Both are 0
+/// until the writer finalizes.
+fn patch_frame_counts(file: &mut File, start: u64, end: u64, frames: u32) -> anyhow::Result<()> {
+    let mut position = start;
+    let mut video_strh_done = false;
+    while position + 8 <= end {
+        file.seek(SeekFrom::Start(position))?;
+        let id = read_fourcc(file)?;
+        let size = read_u32_le(file)? as u64;
+        let data = position + 8;
+        match &id {
+            b"avih" if size >= 20 => {
+                file.seek(SeekFrom::Start(data + 16))?;
+                file.write_all(&frames.to_le_bytes())?;
+            }
+            b"LIST" if !video_strh_done && &read_fourcc(file)? == b"strl" => {
+                // The first stream is the video; its 'strh' comes first.
+                file.seek(SeekFrom::Start(data + 4))?;
+                let child = read_fourcc(file)?;
+                let child_size = read_u32_le(file)?;
+                if &child == b"strh" && child_size >= 36 && &read_fourcc(file)? == b"vids" {
+                    file.seek(SeekFrom::Start(data + 12 + 32))?;
+                    file.write_all(&frames.to_le_bytes())?;
+                }
+                video_strh_done = true;
+            }
+            _ => {}
+        }
+        position = data + size + (size & 1);
+    }
+    Ok(())
+}
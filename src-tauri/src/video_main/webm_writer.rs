@@ -1,11 +1,28 @@
+// src/video_main/webm_writer.rs
+
+//! WebM segments with AV1 video from rav1e and an optional Opus track fed
+//! by the same `AudioCapture` as the MP4 writers.
+
 use chrono::{DateTime, Local};
 use log::{info, warn};
 use rav1e::prelude::*;
-// These imports are now correct based on your new code structure.
-use webm::mux::{Segment, SegmentBuilder, VideoCodecId, VideoTrack, Writer};
+use audiopus::coder::Encoder as OpusEncoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use webm::mux::{AudioCodecId, AudioTrack, Segment, SegmentBuilder, VideoCodecId, VideoTrack, Writer};
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::PathBuf;
+use std::time::Instant;
+
+use super::audio_capture::{self, AudioCapture, AudioSource};
+use super::encoder::av1::fill_yuv420;
+
+/// Samples per channel in one Opus packet: 20 ms at 48 kHz.
+const OPUS_FRAME_SAMPLES: usize = 960;
+
+/// Largest Opus packet we accept from the encoder.
+const MAX_OPUS_PACKET: usize = 4000;
 
 #[derive(Debug, Clone)]
 pub struct WebmSegmentConfig {
@@ -14,15 +31,53 @@ pub struct WebmSegmentConfig {
     pub fps: u32,
     pub output_dir: PathBuf,
     pub base_name: String,
+    /// rav1e speed preset, 0 (slowest, best) to 10 (fastest).
+    pub speed_preset: u8,
     pub quantizer: usize,
+    pub include_audio: bool,
+    pub audio_bitrate_kbps: u32,
+    pub audio_source: AudioSource,
+}
+
+/// Opus track of a segment and the capture feeding it.
+struct OpusAudio {
+    track: AudioTrack,
+    encoder: OpusEncoder,
+    capture: AudioCapture,
+    /// Timestamp of the next packet, in samples per channel.
+    samples_written: u64,
+}
+
+impl OpusAudio {
+    /// Encodes the next `OPUS_FRAME_SAMPLES` captured samples per channel
+    /// into one packet.
+    fn write_packet(&mut self, segment: &mut Segment<BufWriter<File>>) -> anyhow::Result<()> {
+        let mut packet = [0u8; MAX_OPUS_PACKET];
+        let pcm = self.capture.take_mixed(OPUS_FRAME_SAMPLES);
+        let len = self
+            .encoder
+            .encode(&pcm, &mut packet)
+            .map_err(|e| anyhow::anyhow!("Opus encoding failed: {:?}", e))?;
+        let packet_ns = self.samples_written * 1_000_000_000 / audio_capture::SAMPLE_RATE as u64;
+        segment.add_frame(self.track, &packet[..len], packet_ns, true)?;
+        self.samples_written += OPUS_FRAME_SAMPLES as u64;
+        Ok(())
+    }
 }
 
 pub struct WebmSegmentWriter {
     // This now correctly holds a Segment, not a Muxer.
     segment: Segment<BufWriter<File>>,
     video_track: VideoTrack,
+    audio: Option<OpusAudio>,
     encoder: Context<u8>,
     frame_count: u64,
+    frames_sent: u64,
+    /// Capture time of the first frame; timestamps count from here.
+    first_frame_at: Option<Instant>,
+    /// Timestamps in ns of frames sent to rav1e whose packets are still to
+    /// come. It keeps input order with `low_latency`.
+    pending_times: VecDeque<u64>,
     fps: u64,
     width: usize,
     height: usize,
@@ -45,12 +100,42 @@ impl WebmSegmentWriter {
             VideoCodecId::AV1,
             None,
         )?;
+
+        let (builder, audio) = if cfg.include_audio {
+            let mut encoder = OpusEncoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio)
+                .map_err(|e| anyhow::anyhow!("Failed to create Opus encoder: {:?}", e))?;
+            let bitrate = cfg.audio_bitrate_kbps.saturating_mul(1000).min(i32::MAX as u32) as i32;
+            encoder
+                .set_bitrate(Bitrate::BitsPerSecond(bitrate))
+                .map_err(|e| anyhow::anyhow!("Failed to set Opus bitrate: {:?}", e))?;
+            let pre_skip = encoder.lookahead().unwrap_or(0) as u16;
+
+            let (builder, track) = builder.add_audio_track(
+                audio_capture::SAMPLE_RATE as i32,
+                audio_capture::CHANNELS as i32,
+                AudioCodecId::Opus,
+                None,
+            )?;
+            let builder = builder.set_codec_private(track, &opus_head(pre_skip))?;
+            let audio = OpusAudio {
+                track,
+                encoder,
+                capture: AudioCapture::start(cfg.audio_source),
+                samples_written: 0,
+            };
+            (builder, Some(audio))
+        } else {
+            (builder, None)
+        };
         let segment = builder.build();
 
-        let mut enc_config = EncoderConfig::with_speed_preset(10);
+        let mut enc_config = EncoderConfig::with_speed_preset(cfg.speed_preset.min(10));
         enc_config.width = cfg.width as usize;
         enc_config.height = cfg.height as usize;
         enc_config.quantizer = cfg.quantizer.min(255);
+        // Packets come out in input order, so audio can be interleaved by
+        // timestamp without going back in time.
+        enc_config.low_latency = true;
         enc_config.speed_settings.rdo_lookahead_frames = 1;
         enc_config.time_base = Rational::new(1, cfg.fps as u64);
 
@@ -59,11 +144,19 @@ impl WebmSegmentWriter {
             .with_threads(4);
         let encoder: Context<u8> = rav1e_cfg.new_context()?;
 
+        info!(
+            "Creating WebM writer - speed: {}, quantizer: {}, audio: {}",
+            cfg.speed_preset, cfg.quantizer, cfg.include_audio
+        );
         Ok(Self {
             segment,
             video_track,
+            audio,
             encoder,
             frame_count: 0,
+            frames_sent: 0,
+            first_frame_at: None,
+            pending_times: VecDeque::new(),
             fps: cfg.fps as u64,
             width: cfg.width as usize,
             height: cfg.height as usize,
@@ -71,9 +164,23 @@ impl WebmSegmentWriter {
         })
     }
 
+    /// Encodes a frame at the configured frame rate.
     pub fn encode_rgb_frame(&mut self, rgb: &[u8]) -> anyhow::Result<()> {
+        self.encode_at(rgb, self.frames_sent * 1_000_000_000 / self.fps)
+    }
+
+    /// Encodes a frame timed by when it was captured, for variable frame
+    /// rate recordings where unchanged frames are left out.
+    pub fn encode_rgb_frame_at(&mut self, rgb: &[u8], captured_at: Instant) -> anyhow::Result<()> {
+        let first = *self.first_frame_at.get_or_insert(captured_at);
+        self.encode_at(rgb, captured_at.saturating_duration_since(first).as_nanos() as u64)
+    }
+
+    fn encode_at(&mut self, rgb: &[u8], timestamp_ns: u64) -> anyhow::Result<()> {
+        self.pending_times.push_back(timestamp_ns);
+        self.frames_sent += 1;
         let mut frame = self.encoder.new_frame();
-        fill_yuv_planes_from_rgb(&mut frame, rgb, self.width, self.height);
+        fill_yuv420(&mut frame, rgb, self.width, self.height);
         self.encoder.send_frame(frame)?;
         self.receive_packets()?;
         Ok(())
@@ -83,9 +190,15 @@ impl WebmSegmentWriter {
         loop {
             match self.encoder.receive_packet() {
                 Ok(packet) => {
-                    let timestamp_ns = (self.frame_count * 1_000_000_000) / self.fps;
+                    let timestamp_ns = self
+                        .pending_times
+                        .pop_front()
+                        .unwrap_or((self.frame_count * 1_000_000_000) / self.fps);
                     let is_keyframe = packet.frame_type == FrameType::KEY;
-                    
+
+                    // Audio up to this frame goes first: a block may not be
+                    // older than the cluster a keyframe just started.
+                    self.write_audio_until(timestamp_ns)?;
                     self.segment.add_frame(
                         self.video_track,
                         &packet.data,
@@ -107,6 +220,33 @@ impl WebmSegmentWriter {
         Ok(())
     }
 
+    /// Encodes captured audio into Opus packets until the track reaches
+    /// `timestamp_ns`.
+    fn write_audio_until(&mut self, timestamp_ns: u64) -> anyhow::Result<()> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Ok(());
+        };
+        let sample_rate = audio_capture::SAMPLE_RATE as u64;
+        while audio.samples_written * 1_000_000_000 / sample_rate < timestamp_ns {
+            audio.write_packet(&mut self.segment)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the capture and encodes what it still holds, so the track
+    /// does not end at the last video packet. The final packet is padded
+    /// with silence.
+    fn write_remaining_audio(&mut self) -> anyhow::Result<()> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Ok(());
+        };
+        audio.capture.stop();
+        while audio.capture.buffered_frames() > 0 {
+            audio.write_packet(&mut self.segment)?;
+        }
+        Ok(())
+    }
+
     // Changed the receiver to `self` from `mut self` because finalize consumes `self.segment`
     pub fn finalize(mut self) -> anyhow::Result<PathBuf> {
         loop {
@@ -115,6 +255,9 @@ impl WebmSegmentWriter {
                 break;
             }
         }
+        if let Err(e) = self.write_remaining_audio() {
+            warn!("Failed to write the remaining WebM audio: {:?}", e);
+        }
 
         // finalize() consumes self.segment, so we can't use it after this.
         if let Err(e) = self.segment.finalize(None) {
@@ -142,32 +285,16 @@ impl WebmSegmentWriter {
     }
 }
 
-fn fill_yuv_planes_from_rgb(frame: &mut Frame<u8>, rgb_data: &[u8], width: usize, height: usize) {
-    if let [y_plane, u_plane, v_plane] = frame.planes.as_mut_slice() {
-        for row in 0..height {
-            let y_stride = y_plane.cfg.stride;
-            let uv_stride = u_plane.cfg.stride;
-
-            let y_row = &mut y_plane.data_origin_mut()[(row * y_stride)..];
-            let u_row = &mut u_plane.data_origin_mut()[((row / 2) * uv_stride)..];
-            let v_row = &mut v_plane.data_origin_mut()[((row / 2) * uv_stride)..];
-
-            for col in 0..width {
-                let r = rgb_data[(row * width + col) * 3] as f32;
-                let g = rgb_data[(row * width + col) * 3 + 1] as f32;
-                let b = rgb_data[(row * width + col) * 3 + 2] as f32;
-
-                let y = (0.299 * r + 0.587 * g + 0.114 * b).round().clamp(0.0, 255.0) as u8;
-                let u = (-0.169 * r - 0.331 * g + 0.5 * b + 128.0).round().clamp(0.0, 255.0) as u8;
-                let v = (0.5 * r - 0.419 * g - 0.081 * b + 128.0).round().clamp(0.0, 255.0) as u8;
-
-                y_row[col] = y;
-
-                if row % 2 == 0 && col % 2 == 0 {
-                    u_row[col / 2] = u;
-                    v_row[col / 2] = v;
-                }
-            }
-        }
-    }
-}
\ No newline at end of file
+/// `OpusHead` identification header, the CodecPrivate of an Opus track
+/// (RFC 7845, channel mapping family 0).
+fn opus_head(pre_skip: u16) -> Vec<u8> {
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1);
+    head.push(audio_capture::CHANNELS as u8);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&audio_capture::SAMPLE_RATE.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
@@ -0,0 +1,454 @@
+// src/work_schedule.rs
+
+//! Work-hours policy and the scheduler that applies it.
+//!
+//! The policy lists, per weekday, the hours in the company's time zone during
+//! which monitoring may run, plus holidays and daily breaks. It is read from
+//! `work_policy.json` in the app data folder and can be replaced at runtime
+//! with `set_work_policy` (e.g. when the server pushes a new one).
+//!
+//! While a policy is set:
+//! * entering work hours starts all services (if `auto_start` is on);
+//! * leaving work hours stops them;
+//! * outside work hours the `start_*` commands refuse to start anything.
+//!
+//! Without a policy nothing is started or stopped automatically.
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use super::activity_service::{start_activity_logging_service, stop_activity_logging_service};
+use super::screenshot_service::{start_screenshot_service, stop_screenshot_service};
+use super::{start_video, stop_video_recording, VideoSettings};
+
+/// How often the scheduler re-evaluates the policy. Policy changes wake it
+/// immediately.
+const SCHEDULE_TICK: Duration = Duration::from_secs(30);
+
+/// A span of local time, `start` inclusive, `end` exclusive. An `end` before
+/// `start` runs past midnight into the next day.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn wraps(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Whether `time` falls in the window on any day.
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.wraps() {
+            self.start <= time || time < self.end
+        } else {
+            self.start <= time && time < self.end
+        }
+    }
+}
+
+/// Whether and how the scheduler starts video on its own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ScheduledVideo {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub settings: VideoSettings,
+}
+
+impl Default for ScheduledVideo {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            settings: VideoSettings::default(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// When monitoring may run. Days missing from `hours` are days off.
+///
+/// A window in `hours` that ends before it starts is an overnight shift. It
+/// belongs to the day it starts on: `"Fri": [{ "start": "22:00:00", "end":
+/// "06:00:00" }]` covers Friday night until 06:00 on Saturday, and a holiday
+/// on that Friday cancels the whole shift.
+///
+/// ```json
+/// {
+///   "timezone": "Europe/Berlin",
+///   "hours": { "Mon": [{ "start": "09:00:00", "end": "17:30:00" }] },
+///   "breaks": [{ "start": "12:00:00", "end": "12:45:00" }],
+///   "holidays": ["2026-12-25"]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkPolicy {
+    pub timezone: Tz,
+    #[serde(default)]
+    pub hours: HashMap<Weekday, Vec<TimeWindow>>,
+    /// Breaks apply to every working day.
+    #[serde(default)]
+    pub breaks: Vec<TimeWindow>,
+    /// Dates in `timezone` with no work hours at all.
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+    /// Start all services when work hours begin. When off, the policy only
+    /// stops and blocks them outside work hours.
+    #[serde(default = "default_true")]
+    pub auto_start: bool,
+    #[serde(default)]
+    pub video: ScheduledVideo,
+}
+
+impl WorkPolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for window in self.hours.values().flatten().chain(&self.breaks) {
+            if window.start == window.end {
+                anyhow::bail!("time window {}-{} is empty", window.start, window.end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `at` falls inside work hours and outside holidays and breaks.
+    pub fn is_work_time(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone).naive_local();
+        let (date, time) = (local.date(), local.time());
+        let started_today = self
+            .shifts(date)
+            .any(|w| w.start <= time && (w.wraps() || time < w.end));
+        // The part of an overnight shift after midnight is on the next day.
+        let from_yesterday = date
+            .pred_opt()
+            .is_some_and(|yesterday| self.shifts(yesterday).any(|w| w.wraps() && time < w.end));
+        (started_today || from_yesterday) && !self.breaks.iter().any(|w| w.contains(time))
+    }
+
+    /// Work windows starting on `date`; none on holidays.
+    fn shifts(&self, date: NaiveDate) -> impl Iterator<Item = &TimeWindow> {
+        let windows = if self.holidays.contains(&date) {
+            None
+        } else {
+            self.hours.get(&date.weekday())
+        };
+        windows.into_iter().flatten()
+    }
+}
+
+/// Managed state holding the current policy.
+pub struct WorkSchedule {
+    policy_path: PathBuf,
+    policy: Mutex<Option<WorkPolicy>>,
+    changed: Condvar,
+}
+
+impl WorkSchedule {
+    /// Loads the policy from `policy_path`. A missing file means no policy;
+    /// an unreadable one is reported and ignored.
+    pub fn load(policy_path: PathBuf) -> Self {
+        let policy = match fs::read_to_string(&policy_path) {
+            Ok(json) => match serde_json::from_str::<WorkPolicy>(&json)
+                .map_err(anyhow::Error::from)
+                .and_then(|policy| policy.validate().map(|()| policy))
+            {
+                Ok(policy) => Some(policy),
+                Err(e) => {
+                    log::error!("Ignoring invalid work policy in {}: {}", policy_path.display(), e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        Self {
+            policy_path,
+            policy: Mutex::new(policy),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn policy(&self) -> Option<WorkPolicy> {
+        self.policy.lock().unwrap().clone()
+    }
+
+    /// Replaces the policy, persists it and wakes the scheduler. `None`
+    /// removes the policy.
+    pub fn set_policy(&self, policy: Option<WorkPolicy>) -> anyhow::Result<()> {
+        match &policy {
+            Some(policy) => {
+                policy.validate()?;
+                if let Some(dir) = self.policy_path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&self.policy_path, serde_json::to_string_pretty(policy)?)?;
+            }
+            None => {
+                if self.policy_path.exists() {
+                    fs::remove_file(&self.policy_path)?;
+                }
+            }
+        }
+        *self.policy.lock().unwrap() = policy;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Whether capture may run at `at`. Always true without a policy.
+    pub fn capture_allowed(&self, at: DateTime<Utc>) -> bool {
+        self.policy
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|policy| policy.is_work_time(at))
+    }
+
+    /// Sleeps for up to `timeout`, returning early when the policy changes.
+    fn wait_for_change(&self, timeout: Duration) {
+        let policy = self.policy.lock().unwrap();
+        let _ = self.changed.wait_timeout(policy, timeout).unwrap();
+    }
+}
+
+/// Checked by the `start_*` commands, through `capture_blocked_reason`,
+/// before starting a service.
+pub fn capture_allowed(app: &AppHandle) -> bool {
+    app.try_state::<WorkSchedule>()
+        .is_none_or(|schedule| schedule.capture_allowed(Utc::now()))
+}
+
+/// Scheduler thread. Starts and stops the services whenever the policy
+/// says work hours began or ended. Only transitions are acted on, so a
+/// service the user stopped during work hours stays stopped.
+pub fn run_scheduler(app: AppHandle) {
+    let schedule = app.state::<WorkSchedule>();
+    // Work-time state at the previous check; `None` while no policy is set.
+    let mut was_work_time: Option<bool> = None;
+
+    loop {
+        let policy = schedule.policy();
+        let is_work_time = policy.as_ref().map(|p| p.is_work_time(Utc::now()));
+
+        if let (Some(policy), Some(now_working)) = (&policy, is_work_time) {
+            if was_work_time != Some(now_working) {
+                if now_working && policy.auto_start {
+                    log::info!("Work hours started, starting monitoring services.");
+                    start_all_services(&app, &policy.video);
+                } else if !now_working {
+                    log::info!("Outside work hours, stopping monitoring services.");
+                    stop_all_services(&app);
+                }
+            }
+        }
+        was_work_time = is_work_time;
+
+        schedule.wait_for_change(SCHEDULE_TICK);
+    }
+}
+
+fn start_all_services(app: &AppHandle, video: &ScheduledVideo) {
+    start_screenshot_service(app.clone(), app.state());
+    start_activity_logging_service(app.clone(), app.state());
+    if video.enabled {
+        if let Err(e) = start_video(app.clone(), app.state(), video.settings.clone()) {
+            log::error!("Scheduled video start failed: {}", e);
+        }
+    }
+}
+
+fn stop_all_services(app: &AppHandle) {
+    stop_screenshot_service(app.clone(), app.state());
+    stop_activity_logging_service(app.clone(), app.state());
+    if let Err(e) = stop_video_recording(app.clone(), app.state()) {
+        log::error!("Scheduled video stop failed: {}", e);
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkScheduleStatus {
+    pub policy_set: bool,
+    pub capture_allowed: bool,
+}
+
+#[tauri::command]
+pub fn get_work_policy(schedule: State<'_, WorkSchedule>) -> Option<WorkPolicy> {
+    schedule.policy()
+}
+
+/// Installs a new policy, e.g. one pushed by the server, or clears it with `null`.
+#[tauri::command]
+pub fn set_work_policy(
+    schedule: State<'_, WorkSchedule>,
+    policy: Option<WorkPolicy>,
+) -> Result<(), String> {
+    schedule.set_policy(policy).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_work_schedule_status(schedule: State<'_, WorkSchedule>) -> WorkScheduleStatus {
+    WorkScheduleStatus {
+        policy_set: schedule.policy().is_some(),
+        capture_allowed: schedule.capture_allowed(Utc::now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn policy(json: &str) -> WorkPolicy {
+        let policy: WorkPolicy = serde_json::from_str(json).unwrap();
+        policy.validate().unwrap();
+        policy
+    }
+
+    /// `date` (`YYYY-MM-DD`) at `time` (`HH:MM`) on the policy's wall clock.
+    fn local(policy: &WorkPolicy, date: &str, time: &str) -> DateTime<Utc> {
+        let naive = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        policy
+            .timezone
+            .from_local_datetime(&naive)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        at.parse().unwrap()
+    }
+
+    #[test]
+    fn office_hours_with_a_lunch_break() {
+        let policy = policy(
+            r#"{
+                "timezone": "Europe/Berlin",
+                "hours": { "Mon": [{ "start": "09:00:00", "end": "17:30:00" }] },
+                "breaks": [{ "start": "12:00:00", "end": "12:45:00" }]
+            }"#,
+        );
+        // 2026-03-02 is a Monday.
+        for (time, expected) in [
+            ("08:59", false),
+            ("09:00", true),
+            ("11:59", true),
+            ("12:00", false),
+            ("12:44", false),
+            ("12:45", true),
+            ("17:29", true),
+            ("17:30", false),
+            ("23:59", false),
+        ] {
+            assert_eq!(
+                policy.is_work_time(local(&policy, "2026-03-02", time)),
+                expected,
+                "Monday {}",
+                time
+            );
+        }
+        assert!(!policy.is_work_time(local(&policy, "2026-03-01", "10:00")), "Sunday");
+        assert!(!policy.is_work_time(local(&policy, "2026-03-03", "10:00")), "Tuesday");
+    }
+
+    #[test]
+    fn hours_are_in_the_policy_time_zone() {
+        let berlin = policy(
+            r#"{
+                "timezone": "Europe/Berlin",
+                "hours": { "Mon": [{ "start": "09:00:00", "end": "17:00:00" }] }
+            }"#,
+        );
+        // UTC+1 in winter, UTC+2 in summer.
+        assert!(!berlin.is_work_time(utc("2026-03-02T07:59:00Z")));
+        assert!(berlin.is_work_time(utc("2026-03-02T08:00:00Z")));
+        assert!(berlin.is_work_time(utc("2026-07-06T07:00:00Z")));
+        assert!(!berlin.is_work_time(utc("2026-07-06T15:00:00Z")));
+
+        // Monday afternoon in Los Angeles is already Tuesday in UTC.
+        let los_angeles = policy(
+            r#"{
+                "timezone": "America/Los_Angeles",
+                "hours": { "Mon": [{ "start": "09:00:00", "end": "17:00:00" }] }
+            }"#,
+        );
+        assert!(los_angeles.is_work_time(utc("2026-03-03T00:30:00Z")));
+        assert!(!los_angeles.is_work_time(utc("2026-03-03T01:00:00Z")));
+    }
+
+    #[test]
+    fn holidays_are_local_dates() {
+        let policy = policy(
+            r#"{
+                "timezone": "Asia/Tokyo",
+                "hours": { "Mon": [{ "start": "00:00:00", "end": "23:59:59" }] },
+                "holidays": ["2026-03-02"]
+            }"#,
+        );
+        // Sunday 16:00 UTC is Monday 01:00 in Tokyo.
+        assert!(!policy.is_work_time(utc("2026-03-01T16:00:00Z")));
+        assert!(policy.is_work_time(utc("2026-03-08T16:00:00Z")));
+    }
+
+    #[test]
+    fn overnight_shift_belongs_to_its_start_day() {
+        let mut policy = policy(
+            r#"{
+                "timezone": "UTC",
+                "hours": { "Fri": [{ "start": "22:00:00", "end": "06:00:00" }] },
+                "breaks": [{ "start": "23:45:00", "end": "00:15:00" }]
+            }"#,
+        );
+        // 2026-03-06 is a Friday.
+        for (date, time, expected) in [
+            ("2026-03-05", "23:00", false),
+            ("2026-03-06", "05:00", false),
+            ("2026-03-06", "21:59", false),
+            ("2026-03-06", "22:00", true),
+            ("2026-03-06", "23:44", true),
+            ("2026-03-06", "23:45", false),
+            ("2026-03-07", "00:00", false),
+            ("2026-03-07", "00:15", true),
+            ("2026-03-07", "05:59", true),
+            ("2026-03-07", "06:00", false),
+            ("2026-03-07", "22:00", false),
+        ] {
+            assert_eq!(
+                policy.is_work_time(local(&policy, date, time)),
+                expected,
+                "{} {}",
+                date,
+                time
+            );
+        }
+
+        // A holiday on Saturday leaves Friday's shift alone; one on Friday
+        // cancels it, including the hours after midnight.
+        policy.holidays = vec!["2026-03-07".parse().unwrap()];
+        assert!(policy.is_work_time(utc("2026-03-07T02:00:00Z")));
+        policy.holidays = vec!["2026-03-06".parse().unwrap()];
+        assert!(!policy.is_work_time(utc("2026-03-06T23:00:00Z")));
+        assert!(!policy.is_work_time(utc("2026-03-07T02:00:00Z")));
+    }
+
+    #[test]
+    fn empty_windows_are_rejected() {
+        let policy: WorkPolicy = serde_json::from_str(
+            r#"{
+                "timezone": "UTC",
+                "hours": { "Mon": [{ "start": "09:00:00", "end": "09:00:00" }] }
+            }"#,
+        )
+        .unwrap();
+        assert!(policy.validate().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// tests/colorspace.rs
+
+//! The fast paths in `video_main::colorspace` against straightforward
+//! reference code: byte shuffles must match exactly, YUV and scaling must
+//! stay within one step of floating point.
+
+use main_dashboard_spinup_lib::video_main::colorspace::{
+    self, ColorSpace, Matrix, PackedFormat, Range, ScaleFilter, YuvPlanes,
+};
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 7) as u8).collect()
+}
+
+#[test]
+fn byte_shuffles_match_reference() {
+    // Lengths around the 4-pixel SIMD blocks so the scalar tails run too.
+    for pixels in [0, 1, 3, 4, 5, 7, 8, 17, 1000] {
+        let bgra = pattern(pixels * 4);
+        let rgb: Vec<u8> = bgra.chunks_exact(4).flat_map(|p| [p[2], p[1], p[0]]).collect();
+        assert_eq!(colorspace::bgra_to_rgb_vec(&bgra), rgb, "bgra_to_rgb, {} pixels", pixels);
+
+        let mut from_rgba = vec![0; pixels * 3];
+        colorspace::rgba_to_rgb(&bgra, &mut from_rgba);
+        let expected: Vec<u8> = bgra.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+        assert_eq!(from_rgba, expected, "rgba_to_rgb, {} pixels", pixels);
+
+        let back: Vec<u8> = rgb.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0], 255]).collect();
+        assert_eq!(colorspace::rgb_to_bgra_vec(&rgb), back, "rgb_to_bgra, {} pixels", pixels);
+    }
+}
+
+fn reference_yuv(cs: ColorSpace, r: f64, g: f64, b: f64) -> [f64; 3] {
+    cs.matrix_rows()
+        .map(|[cr, cg, cb, offset]| (cr * r + cg * g + cb * b + offset).clamp(0.0, 255.0))
+}
+
+fn assert_close(actual: u8, expected: f64, what: &str) {
+    assert!(
+        (actual as f64 - expected).abs() <= 1.0,
+        "{}: got {}, expected {:.2}",
+        what, actual, expected
+    );
+}
+
+#[test]
+fn yuv420_matches_float_reference() {
+    let (width, height) = (7, 5);
+    let rgb = pattern(width * height * 3);
+    let bgra: Vec<u8> = rgb.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0], 255]).collect();
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+
+    for matrix in [Matrix::Bt601, Matrix::Bt709] {
+        for range in [Range::Full, Range::Limited] {
+            let cs = ColorSpace { matrix, range };
+            let mut y = vec![0; width * height];
+            let mut u = vec![0; cw * ch];
+            let mut v = vec![0; cw * ch];
+            let planes = YuvPlanes::I420 { y: &mut y, y_stride: width, u: &mut u, v: &mut v, uv_stride: cw };
+            colorspace::to_yuv420(&rgb, PackedFormat::Rgb, width, height, planes, cs);
+
+            for (i, p) in rgb.chunks_exact(3).enumerate() {
+                let [ey, _, _] = reference_yuv(cs, p[0] as f64, p[1] as f64, p[2] as f64);
+                assert_close(y[i], ey, &format!("{:?} Y[{}]", cs, i));
+            }
+            for cy in 0..ch {
+                for cx in 0..cw {
+                    let mut sum = [0.0; 3];
+                    let mut n = 0.0;
+                    for py in cy * 2..(cy * 2 + 2).min(height) {
+                        for px in cx * 2..(cx * 2 + 2).min(width) {
+                            let i = (py * width + px) * 3;
+                            for c in 0..3 {
+                                sum[c] += rgb[i + c] as f64;
+                            }
+                            n += 1.0;
+                        }
+                    }
+                    let [_, eu, ev] = reference_yuv(cs, sum[0] / n, sum[1] / n, sum[2] / n);
+                    assert_close(u[cy * cw + cx], eu, &format!("{:?} U[{},{}]", cs, cx, cy));
+                    assert_close(v[cy * cw + cx], ev, &format!("{:?} V[{},{}]", cs, cx, cy));
+                }
+            }
+
+            // NV12 from BGRA must be the same samples, interleaved.
+            let mut y2 = vec![0; width * height];
+            let mut uv = vec![0; cw * 2 * ch];
+            let planes = YuvPlanes::Nv12 { y: &mut y2, y_stride: width, uv: &mut uv, uv_stride: cw * 2 };
+            colorspace::to_yuv420(&bgra, PackedFormat::Bgra, width, height, planes, cs);
+            assert_eq!(y2, y);
+            let interleaved: Vec<u8> = u.iter().zip(&v).flat_map(|(&u, &v)| [u, v]).collect();
+            assert_eq!(uv, interleaved);
+        }
+    }
+}
+
+#[test]
+fn yuv_range_endpoints() {
+    for (range, black, white) in [(Range::Full, 0, 255), (Range::Limited, 16, 235)] {
+        for matrix in [Matrix::Bt601, Matrix::Bt709] {
+            let cs = ColorSpace { matrix, range };
+            for (value, expected_y) in [(0u8, black), (255u8, white)] {
+                let rgb = [value; 2 * 2 * 3];
+                let (mut y, mut u, mut v) = ([0; 4], [0; 1], [0; 1]);
+                let planes = YuvPlanes::I420 { y: &mut y, y_stride: 2, u: &mut u, v: &mut v, uv_stride: 1 };
+                colorspace::to_yuv420(&rgb, PackedFormat::Rgb, 2, 2, planes, cs);
+                assert_eq!(y, [expected_y; 4], "{:?} Y of {}", cs, value);
+                assert_eq!((u[0], v[0]), (128, 128), "{:?} chroma of {}", cs, value);
+            }
+        }
+    }
+}
+
+#[test]
+fn box_downscale_averages_blocks() {
+    // 4x2 RGB halved: each output pixel is the rounded mean of a 2x2 block.
+    let src = pattern(4 * 2 * 3);
+    let out = colorspace::scale(&src, 4, 2, 3, 2, 1, ScaleFilter::Box);
+    for x in 0..2 {
+        for c in 0..3 {
+            let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|&(dx, dy)| src[(dy * 4 + x * 2 + dx) * 3 + c] as u32)
+                .sum();
+            assert_eq!(out[x * 3 + c] as u32, (sum + 2) / 4);
+        }
+    }
+}
+
+#[test]
+fn bilinear_keeps_flat_colour_and_size() {
+    let src: Vec<u8> = [10u8, 200, 30, 255].repeat(13 * 9);
+    for (w, h) in [(13, 9), (6, 4), (20, 17), (1, 1)] {
+        let out = colorspace::scale(&src, 13, 9, 4, w, h, ScaleFilter::Bilinear);
+        assert_eq!(out, [10u8, 200, 30, 255].repeat((w * h) as usize), "{}x{}", w, h);
+    }
+}
+
+#[test]
+fn bilinear_halfway_between_pixels() {
+    // Doubling a 2-pixel row puts the inner samples a quarter of the way in.
+    let src = [0u8, 100];
+    let out = colorspace::scale(&src, 2, 1, 1, 4, 1, ScaleFilter::Bilinear);
+    assert_eq!(out, [0, 25, 75, 100]);
+}
+
+#[test]
+fn flips_mirror_pixels() {
+    let src: Vec<u8> = (0..6).collect(); // 3x2, one byte per pixel
+    let mut v = src.clone();
+    colorspace::flip_vertical(&mut v, 3, 2, 1);
+    assert_eq!(v, [3, 4, 5, 0, 1, 2]);
+    let mut h = src;
+    colorspace::flip_horizontal(&mut h, 3, 2, 1);
+    assert_eq!(h, [2, 1, 0, 5, 4, 3]);
+}
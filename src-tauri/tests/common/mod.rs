@@ -0,0 +1,109 @@
+// tests/common/mod.rs
+
+//! Helpers shared by the integration tests: a small ISO-BMFF box reader for
+//! checking what the muxer and recovery write.
+
+// Each test crate uses its own subset.
+#![allow(dead_code)]
+
+pub fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+pub fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+pub fn u64_at(data: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+/// One box in a sequence of boxes.
+pub struct Mp4Box<'a> {
+    pub kind: [u8; 4],
+    /// Offset of the box header in the parsed slice.
+    pub offset: usize,
+    /// Whole box, header included.
+    pub size: usize,
+    pub body: &'a [u8],
+}
+
+/// Splits `data` into boxes, which must fill it exactly.
+pub fn boxes(data: &[u8]) -> Vec<Mp4Box<'_>> {
+    let mut found = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        assert!(at + 8 <= data.len(), "truncated box header at {}", at);
+        let kind: [u8; 4] = data[at + 4..at + 8].try_into().unwrap();
+        let (size, header) = match u32_at(data, at) {
+            1 => (u64_at(data, at + 8) as usize, 16),
+            0 => (data.len() - at, 8),
+            size => (size as usize, 8),
+        };
+        let name = String::from_utf8_lossy(&kind);
+        assert!(size >= header, "{} at {} is smaller than its header", name, at);
+        assert!(at + size <= data.len(), "{} at {} runs past the end ({} bytes)", name, at, size);
+        found.push(Mp4Box {
+            kind,
+            offset: at,
+            size,
+            body: &data[at + header..at + size],
+        });
+        at += size;
+    }
+    found
+}
+
+/// Body of the first box of `kind` among `data`'s boxes.
+pub fn find<'a>(data: &'a [u8], kind: &[u8; 4]) -> &'a [u8] {
+    boxes(data)
+        .into_iter()
+        .find(|b| &b.kind == kind)
+        .unwrap_or_else(|| panic!("no {} box", String::from_utf8_lossy(kind)))
+        .body
+}
+
+/// Body of the box at `path`, descending through container boxes.
+pub fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+    path.iter().fold(data, |data, kind| find(data, kind))
+}
+
+/// Body of a full box without its version and flags.
+pub fn full_body(body: &[u8]) -> &[u8] {
+    &body[4..]
+}
+
+/// The `u32` entries of a table box such as `stco` or `stss`.
+pub fn u32_table(body: &[u8]) -> Vec<u32> {
+    let body = full_body(body);
+    (0..u32_at(body, 0) as usize).map(|i| u32_at(body, 4 + i * 4)).collect()
+}
+
+/// The `(count, duration)` runs of an `stts` box.
+pub fn stts_runs(body: &[u8]) -> Vec<(u32, u32)> {
+    let body = full_body(body);
+    (0..u32_at(body, 0) as usize)
+        .map(|i| (u32_at(body, 4 + i * 8), u32_at(body, 8 + i * 8)))
+        .collect()
+}
+
+/// The sample sizes of an `stsz` box without a common size.
+pub fn stsz_sizes(body: &[u8]) -> Vec<u32> {
+    let body = full_body(body);
+    assert_eq!(u32_at(body, 0), 0, "expected per-sample sizes");
+    (0..u32_at(body, 4) as usize).map(|i| u32_at(body, 8 + i * 4)).collect()
+}
+
+/// The sample entry in an `stsd` box: its type and the child boxes after
+/// its fixed fields.
+pub fn sample_entry(stsd: &[u8]) -> ([u8; 4], &[u8]) {
+    let entries = full_body(stsd);
+    assert_eq!(u32_at(entries, 0), 1, "expected one sample entry");
+    let entry = &boxes(&entries[4..])[0];
+    // Visual sample entries have 78 bytes of fixed fields, audio ones 28.
+    let fixed = match &entry.kind {
+        b"avc1" | b"av01" => 78,
+        _ => 28,
+    };
+    (entry.kind, &entry.body[fixed..])
+}
@@ -0,0 +1,50 @@
+// tests/encoded_mp4_writer.rs
+
+//! `EncodedMp4Writer` timing: each sample lasts until the next frame's
+//! capture time, timestamps only go forward, and the last sample gets the
+//! nominal frame length.
+
+#![cfg(feature = "av1")]
+
+mod common;
+
+use common::*;
+use main_dashboard_spinup_lib::video_main::{EncodedMp4Config, EncodedMp4Writer, VideoEncoderKind};
+use std::time::{Duration, Instant};
+
+#[test]
+fn samples_last_until_the_next_capture() {
+    let dir = std::env::temp_dir().join(format!("encoded_mp4_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut writer = EncodedMp4Writer::create_new(EncodedMp4Config {
+        width: 64,
+        height: 48,
+        fps: 10,
+        output_dir: dir.clone(),
+        base_name: "timing".to_string(),
+        bitrate_kbps: 500,
+        encoder: VideoEncoderKind::Av1,
+        fragmented: false,
+    })
+    .unwrap();
+
+    let rgb = vec![128u8; 64 * 48 * 3];
+    let start = Instant::now();
+    // The third frame repeats the second's capture time and must still
+    // come after it.
+    for ms in [0, 100, 100, 350, 400] {
+        writer.encode_rgb_frame_at(&rgb, start + Duration::from_millis(ms)).unwrap();
+    }
+    let path = writer.finalize().unwrap();
+    let file = std::fs::read(&path).unwrap();
+
+    let stbl = find_path(&file, &[b"moov", b"trak", b"mdia", b"minf", b"stbl"]);
+    let durations: Vec<u32> = stts_runs(find(stbl, b"stts"))
+        .into_iter()
+        .flat_map(|(count, duration)| std::iter::repeat_n(duration, count as usize))
+        .collect();
+    // 90 kHz ticks: 0, 9000, 9001, 31500 and 36000, then one frame at 10 fps.
+    assert_eq!(durations, [9000, 1, 22499, 4500, 9000]);
+    assert_eq!(stsz_sizes(find(stbl, b"stsz")).len(), 5);
+    let _ = std::fs::remove_dir_all(&dir);
+}
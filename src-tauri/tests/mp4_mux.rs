@@ -0,0 +1,297 @@
+// tests/mp4_mux.rs
+
+//! `Mp4Muxer` output read back box by box: sizes add up, the sample tables
+//! point at the samples written, and the codec configuration boxes carry
+//! what was passed in.
+
+mod common;
+
+use common::*;
+use main_dashboard_spinup_lib::video_main::{
+    annexb_to_avcc, AudioCodec, AudioTrackConfig, Mp4Muxer, VideoCodec, VideoTrackConfig,
+};
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+const SPS: [u8; 5] = [0x67, 0x42, 0xc0, 0x1f, 0xaa];
+const PPS: [u8; 4] = [0x68, 0xce, 0x3c, 0x80];
+
+fn h264(timescale: u32) -> VideoTrackConfig {
+    VideoTrackConfig {
+        codec: VideoCodec::H264 {
+            sps: SPS.to_vec(),
+            pps: PPS.to_vec(),
+        },
+        width: 64,
+        height: 48,
+        timescale,
+    }
+}
+
+fn aac() -> AudioTrackConfig {
+    AudioTrackConfig {
+        codec: AudioCodec::Aac {
+            audio_specific_config: vec![0x12, 0x10],
+        },
+        sample_rate: 48_000,
+        channels: 2,
+    }
+}
+
+/// The `trak` boxes of a `moov` body, video first.
+fn traks(moov: &[u8]) -> Vec<&[u8]> {
+    boxes(moov)
+        .into_iter()
+        .filter(|b| &b.kind == b"trak")
+        .map(|b| b.body)
+        .collect()
+}
+
+fn stbl(trak: &[u8]) -> &[u8] {
+    find_path(trak, &[b"mdia", b"minf", b"stbl"])
+}
+
+#[test]
+fn progressive_tables_point_at_the_samples() {
+    let mut muxer = Mp4Muxer::new(Cursor::new(Vec::new()), h264(90_000), Some(aac())).unwrap();
+    let video: Vec<(Vec<u8>, u32, bool)> = vec![
+        (vec![1; 100], 3000, true),
+        (vec![4; 50], 3000, false),
+        (vec![5; 60], 3000, false),
+        (vec![6; 70], 6000, false),
+    ];
+    let audio = [vec![2u8; 20], vec![3; 21]];
+
+    muxer.write_video(&video[0].0, video[0].1, video[0].2).unwrap();
+    for packet in &audio {
+        muxer.write_audio(packet, 1024).unwrap();
+    }
+    for (data, duration, keyframe) in &video[1..] {
+        muxer.write_video(data, *duration, *keyframe).unwrap();
+    }
+    assert_eq!(muxer.video_frames(), 4);
+    let file = muxer.finish().unwrap().into_inner();
+
+    let top = boxes(&file);
+    let kinds: Vec<&[u8; 4]> = top.iter().map(|b| &b.kind).collect();
+    assert_eq!(kinds, [b"ftyp", b"mdat", b"moov"]);
+    assert_eq!(top.iter().map(|b| b.size).sum::<usize>(), file.len());
+    // 64-bit `mdat` size, covering exactly the samples.
+    let mdat = &top[1];
+    assert_eq!(u32_at(&file, mdat.offset), 1);
+    let total: usize = video.iter().map(|v| v.0.len()).sum::<usize>() + audio.iter().map(Vec::len).sum::<usize>();
+    assert_eq!(mdat.body.len(), total);
+
+    let moov = top[2].body;
+    let mvhd = full_body(find(moov, b"mvhd"));
+    // 15000 ticks of 90 kHz video outlast 2048 samples of 48 kHz audio.
+    assert_eq!(u32_at(mvhd, 16), 1000);
+    assert_eq!(u64_at(mvhd, 20), 15000 * 1000 / 90_000);
+
+    let traks = traks(moov);
+    assert_eq!(traks.len(), 2);
+
+    let video_stbl = stbl(traks[0]);
+    assert_eq!(stts_runs(find(video_stbl, b"stts")), [(3, 3000), (1, 6000)]);
+    assert_eq!(stsz_sizes(find(video_stbl, b"stsz")), [100, 50, 60, 70]);
+    assert_eq!(u32_table(find(video_stbl, b"stss")), [1]);
+    // The audio packets split the video into two chunks.
+    let stsc = full_body(find(video_stbl, b"stsc"));
+    assert_eq!(u32_at(stsc, 0), 2);
+    assert_eq!((u32_at(stsc, 4), u32_at(stsc, 8)), (1, 1));
+    assert_eq!((u32_at(stsc, 16), u32_at(stsc, 20)), (2, 3));
+    let chunks = u32_table(find(video_stbl, b"stco"));
+    assert_eq!(chunks.len(), 2);
+    let first = chunks[0] as usize;
+    assert_eq!(first, mdat.offset + 16);
+    assert_eq!(&file[first..first + 100], &video[0].0[..]);
+    let second = chunks[1] as usize;
+    let run: Vec<u8> = video[1..].iter().flat_map(|v| v.0.clone()).collect();
+    assert_eq!(&file[second..second + run.len()], &run[..]);
+
+    let audio_stbl = stbl(traks[1]);
+    assert_eq!(stts_runs(find(audio_stbl, b"stts")), [(2, 1024)]);
+    assert_eq!(stsz_sizes(find(audio_stbl, b"stsz")), [20, 21]);
+    let audio_chunks = u32_table(find(audio_stbl, b"stco"));
+    assert_eq!(audio_chunks, [(first + 100) as u32]);
+    assert_eq!(&file[first + 100..first + 120], &audio[0][..]);
+
+    let (entry, children) = sample_entry(find(video_stbl, b"stsd"));
+    assert_eq!(&entry, b"avc1");
+    let mut avcc = vec![1, 0x42, 0xc0, 0x1f, 0xff, 0xe1, 0, 5];
+    avcc.extend_from_slice(&SPS);
+    avcc.extend_from_slice(&[1, 0, 4]);
+    avcc.extend_from_slice(&PPS);
+    assert_eq!(find(children, b"avcC"), &avcc[..]);
+}
+
+#[test]
+fn av1c_and_dops_carry_the_configuration() {
+    let av1c = vec![0x81, 0x00, 0x0c, 0x00, 0x0a, 0x0b];
+    let video = VideoTrackConfig {
+        codec: VideoCodec::Av1 { av1c: av1c.clone() },
+        width: 64,
+        height: 48,
+        timescale: 90_000,
+    };
+    let audio = AudioTrackConfig {
+        codec: AudioCodec::Opus { pre_skip: 312 },
+        sample_rate: 48_000,
+        channels: 2,
+    };
+    let mut muxer = Mp4Muxer::new(Cursor::new(Vec::new()), video, Some(audio)).unwrap();
+    muxer.write_video(&[1; 10], 3000, true).unwrap();
+    muxer.write_audio(&[2; 10], 960).unwrap();
+    let file = muxer.finish().unwrap().into_inner();
+
+    assert!(find(&file, b"ftyp").windows(4).any(|brand| brand == b"av01"));
+    let traks = traks(find(&file, b"moov"));
+
+    let (entry, children) = sample_entry(find(stbl(traks[0]), b"stsd"));
+    assert_eq!(&entry, b"av01");
+    assert_eq!(find(children, b"av1C"), &av1c[..]);
+
+    let audio_trak = traks[1];
+    let mdhd = full_body(find_path(audio_trak, &[b"mdia", b"mdhd"]));
+    assert_eq!(u32_at(mdhd, 16), 48_000);
+    let (entry, children) = sample_entry(find(stbl(audio_trak), b"stsd"));
+    assert_eq!(&entry, b"Opus");
+    // Version, channels, pre-skip, input rate, gain, mapping family.
+    assert_eq!(find(children, b"dOps"), &[0, 2, 0x01, 0x38, 0, 0, 0xbb, 0x80, 0, 0, 0]);
+}
+
+#[test]
+fn fragments_start_at_keyframes_and_point_at_their_data() {
+    let mut muxer =
+        Mp4Muxer::new_fragmented(Cursor::new(Vec::new()), h264(1000), None, Duration::from_secs(1)).unwrap();
+    let samples: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 10 + i as usize]).collect();
+    for sample in &samples {
+        muxer.write_video(sample, 500, true).unwrap();
+    }
+    let file = muxer.finish().unwrap().into_inner();
+
+    let top = boxes(&file);
+    let kinds: Vec<&[u8; 4]> = top.iter().map(|b| &b.kind).collect();
+    assert_eq!(kinds, [b"ftyp", b"moov", b"moof", b"mdat", b"moof", b"mdat", b"moof", b"mdat"]);
+    let trex = full_body(find_path(top[1].body, &[b"mvex", b"trex"]));
+    assert_eq!(u32_at(trex, 0), 1);
+    // The empty `moov` has no samples.
+    assert!(stsz_sizes(find(stbl(traks(top[1].body)[0]), b"stsz")).is_empty());
+
+    let expected_fragments = [&samples[0..2], &samples[2..4], &samples[4..5]];
+    for (i, (pair, expected)) in top[2..].chunks(2).zip(expected_fragments).enumerate() {
+        let (moof, mdat) = (&pair[0], &pair[1]);
+        assert_eq!(u32_at(full_body(find(moof.body, b"mfhd")), 0), i as u32 + 1);
+        let traf = find(moof.body, b"traf");
+        assert_eq!(u64_at(full_body(find(traf, b"tfdt")), 0), 1000 * i as u64);
+
+        let trun = full_body(find(traf, b"trun"));
+        assert_eq!(u32_at(trun, 0) as usize, expected.len());
+        let data_offset = u32_at(trun, 4) as usize;
+        assert_eq!(moof.offset + data_offset, mdat.offset + 8);
+        let mut at = moof.offset + data_offset;
+        for (j, sample) in expected.iter().enumerate() {
+            let entry = 8 + j * 12;
+            assert_eq!(u32_at(trun, entry), 500);
+            assert_eq!(u32_at(trun, entry + 4) as usize, sample.len());
+            assert_eq!(&file[at..at + sample.len()], &sample[..]);
+            at += sample.len();
+        }
+        assert_eq!(at, mdat.offset + mdat.size);
+    }
+}
+
+/// Keeps only small writes with their offsets and skips large ones, so a
+/// file past 4 GiB needs no memory: sample data goes, headers stay.
+#[derive(Default)]
+struct Sparse {
+    position: u64,
+    writes: Vec<(u64, Vec<u8>)>,
+}
+
+impl Write for Sparse {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() <= 1 << 20 {
+            self.writes.push((self.position, buf.to_vec()));
+        }
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Sparse {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match pos {
+            SeekFrom::Start(position) => self.position = position,
+            _ => unimplemented!(),
+        }
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn chunks_past_4_gib_use_co64() {
+    const BLOCK: usize = 64 << 20;
+    let block = vec![0u8; BLOCK];
+
+    let mut muxer = Mp4Muxer::new(Sparse::default(), h264(90_000), Some(aac())).unwrap();
+    muxer.write_video(&[1; 16], 3000, true).unwrap();
+    for _ in 0..64 {
+        muxer.write_video(&block, 3000, false).unwrap();
+    }
+    muxer.write_audio(&[2; 8], 1024).unwrap();
+    muxer.write_video(&[3; 16], 3000, false).unwrap();
+    let out = muxer.finish().unwrap();
+
+    let header_len = out.writes[0].1.len() as u64;
+    let mdat_start = header_len - 16;
+    let first = header_len;
+    let audio = first + 16 + 64 * BLOCK as u64;
+    let last_video = audio + 8;
+    assert!(audio > u32::MAX as u64);
+
+    // The 64-bit `mdat` size is patched in place.
+    let (at, size) = out.writes.iter().find(|(at, _)| *at == mdat_start + 8).unwrap();
+    assert_eq!(*at, mdat_start + 8);
+    assert_eq!(u64::from_be_bytes(size[..8].try_into().unwrap()), last_video + 16 - mdat_start);
+
+    let (moov_at, moov) = out.writes.last().unwrap();
+    assert_eq!(*moov_at, last_video + 16);
+    let traks = traks(find(moov, b"moov"));
+    let co64 = |trak: &[u8]| -> Vec<u64> {
+        let stbl = stbl(trak);
+        assert!(boxes(stbl).iter().all(|b| &b.kind != b"stco"));
+        let body = full_body(find(stbl, b"co64"));
+        (0..u32_at(body, 0) as usize).map(|i| u64_at(body, 4 + i * 8)).collect()
+    };
+    assert_eq!(co64(traks[0]), [first, last_video]);
+    assert_eq!(co64(traks[1]), [audio]);
+    assert_eq!(stsz_sizes(find(stbl(traks[0]), b"stsz")).len(), 66);
+}
+
+#[test]
+fn annexb_to_avcc_handles_both_start_code_lengths() {
+    let annexb = [
+        0, 0, 0, 1, 0x67, 1, 2, 3, // SPS, 4-byte start code
+        0, 0, 0, 1, 0x68, 4, 5, // PPS
+        0, 0, 1, 0x65, 9, 9, 9, // IDR slice, 3-byte start code
+        0, 0, 1, 0x41, 7, // non-IDR slice
+    ];
+    let (sample, sps, pps) = annexb_to_avcc(&annexb);
+    assert_eq!(sps, Some(vec![0x67, 1, 2, 3]));
+    assert_eq!(pps, Some(vec![0x68, 4, 5]));
+    assert_eq!(sample, [0, 0, 0, 4, 0x65, 9, 9, 9, 0, 0, 0, 2, 0x41, 7]);
+
+    // Only 3-byte start codes, no parameter sets.
+    let (sample, sps, pps) = annexb_to_avcc(&[0, 0, 1, 0x41, 1, 0, 0, 1, 0x41, 2, 3]);
+    assert_eq!((sps, pps), (None, None));
+    assert_eq!(sample, [0, 0, 0, 2, 0x41, 1, 0, 0, 0, 3, 0x41, 2, 3]);
+
+    // Only 4-byte start codes.
+    let (sample, _, _) = annexb_to_avcc(&[0, 0, 0, 1, 0x65, 8, 0, 0, 0, 1, 0x41, 6]);
+    assert_eq!(sample, [0, 0, 0, 2, 0x65, 8, 0, 0, 0, 2, 0x41, 6]);
+}
@@ -0,0 +1,311 @@
+// tests/recorder.rs
+
+//! The recorder end to end with sources that need no display: segments are
+//! written, rotated and reported, and the pipeline shuts down cleanly.
+
+use main_dashboard_spinup_lib::video_main::{
+    Container, FinishedSegment, Frame, FrameDropPolicy, FrameSource, FrameSourceKind, Recorder, RecorderConfig,
+    TestPatternSource,
+};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// An empty directory of its own for each test.
+fn output_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("recorder_test_{}_{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The test pattern, ending after a fixed number of frames.
+struct FinitePattern {
+    pattern: TestPatternSource,
+    left: u64,
+}
+
+impl FinitePattern {
+    fn new(frames: u64) -> Self {
+        Self {
+            pattern: TestPatternSource::new(64, 48),
+            left: frames,
+        }
+    }
+}
+
+impl FrameSource for FinitePattern {
+    fn dimensions(&self) -> (u32, u32) {
+        self.pattern.dimensions()
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        self.pattern.next_frame()
+    }
+}
+
+/// Sizes of the video chunks listed in the index of an AVI segment; 0 for
+/// repeats of the previous frame.
+fn avi_index(path: &std::path::Path) -> Vec<u32> {
+    let data = std::fs::read(path).unwrap();
+    assert_eq!(&data[..4], b"RIFF");
+    assert_eq!(&data[8..12], b"AVI ");
+    let le32 = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+    let at = data.windows(4).rposition(|id| id == b"idx1").expect("no idx1");
+    (0..le32(at + 4) as usize / 16).map(|i| le32(at + 8 + i * 16 + 12)).collect()
+}
+
+/// Records `source` for a second into AVI and checks the file holds the
+/// frames reported.
+fn record_source(test: &str, source: impl FrameSource + 'static) {
+    let dir = output_dir(test);
+    let cfg = RecorderConfig {
+        fps: 10,
+        container: Container::Avi,
+        include_audio: false,
+        skip_unchanged_frames: false,
+        ..RecorderConfig::new(dir.clone())
+    };
+    let segments = record_for(Recorder::new(cfg).with_frame_source(source), Duration::from_secs(1)).unwrap();
+    assert_eq!(segments.len(), 1);
+    let segment = &segments[0];
+    assert!(segment.frames > 0);
+    assert!(segment.path.starts_with(&dir));
+    assert!(avi_index(&segment.path).len() as u64 >= segment.frames);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// The same frame over and over, ending after a fixed number of frames.
+struct StillSource {
+    frame: Frame,
+    left: u64,
+}
+
+impl FrameSource for StillSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.frame.width, self.frame.height)
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        Ok(Some(Frame {
+            timestamp: std::time::Instant::now(),
+            ..self.frame.clone()
+        }))
+    }
+}
+
+/// Plays back a fixed list of frames.
+struct FrameList(std::collections::VecDeque<Frame>);
+
+impl FrameSource for FrameList {
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.front().map_or((0, 0), |frame| (frame.width, frame.height))
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        Ok(self.0.pop_front().map(|frame| Frame {
+            timestamp: std::time::Instant::now(),
+            ..frame
+        }))
+    }
+}
+
+/// Runs `recorder` until its source is exhausted and returns what it
+/// reported.
+fn record_all_of(recorder: Recorder) -> anyhow::Result<Vec<FinishedSegment>> {
+    let segments = Arc::new(Mutex::new(Vec::new()));
+    let reported = segments.clone();
+    recorder
+        .on_segment_finalized(move |segment| reported.lock().unwrap().push(segment.clone()))
+        .run_blocking()?;
+    let segments = segments.lock().unwrap().clone();
+    Ok(segments)
+}
+
+/// Runs `recorder` for `duration`, then stops it keeping the last segment,
+/// and returns what it reported.
+fn record_for(recorder: Recorder, duration: Duration) -> anyhow::Result<Vec<FinishedSegment>> {
+    let segments = Arc::new(Mutex::new(Vec::new()));
+    let reported = segments.clone();
+    let recorder = recorder.on_segment_finalized(move |segment| reported.lock().unwrap().push(segment.clone()));
+    let stop = recorder.stop_handle();
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        stop.stop_and_keep_segment();
+    });
+    let result = recorder.run_blocking();
+    stopper.join().unwrap();
+    result?;
+    let segments = segments.lock().unwrap().clone();
+    Ok(segments)
+}
+
+#[test]
+fn default_config_records_in_this_build() {
+    let dir = output_dir("default");
+    let cfg = RecorderConfig {
+        fps: 10,
+        source: FrameSourceKind::TestPattern { width: 64, height: 48 },
+        ..RecorderConfig::new(dir.clone())
+    };
+    assert_eq!(cfg.container, Container::Mp4.supported());
+
+    let segments = record_for(Recorder::new(cfg), Duration::from_millis(1500)).unwrap();
+    assert_eq!(segments.len(), 1);
+    assert!(segments[0].frames > 0);
+    assert!(segments[0].path.starts_with(&dir));
+    assert!(std::fs::metadata(&segments[0].path).unwrap().len() > 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// 50 frames at 20 fps with one-second segments: the pipeline rotates
+/// twice, shuts down by itself once the source ends, and every frame lands
+/// in exactly one reported segment.
+fn record_finite(test: &str, drop_policy: FrameDropPolicy) -> Vec<FinishedSegment> {
+    let dir = output_dir(test);
+    let cfg = RecorderConfig {
+        fps: 20,
+        segment_duration: Duration::from_secs(1),
+        container: Container::Avi,
+        include_audio: false,
+        skip_unchanged_frames: false,
+        drop_policy,
+        ..RecorderConfig::new(dir.clone())
+    };
+    let segments = record_all_of(Recorder::new(cfg).with_frame_source(FinitePattern::new(50))).unwrap();
+
+    assert_eq!(segments.len(), 3, "{:?}", segments);
+    assert_eq!(segments.iter().map(|segment| segment.frames).sum::<u64>(), 50);
+    for (i, segment) in segments.iter().enumerate() {
+        assert!(segment.frames > 0, "segment {} is empty", i);
+        assert_eq!(segment.unchanged_frames, 0);
+        assert!(segment.achieved_fps > 0.0);
+        assert!(segment.path.starts_with(&dir));
+        assert!(std::fs::metadata(&segment.path).unwrap().len() > 0);
+    }
+    // Full segments cover one second: no more than 20 capture ticks, taken
+    // or dropped.
+    for segment in &segments[..2] {
+        assert!(segment.frames + segment.dropped_frames <= 21, "{:?}", segment);
+        assert!(segment.achieved_fps <= 21.0, "{:?}", segment);
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+    segments
+}
+
+#[test]
+fn pipeline_drop_newest_reports_each_segment() {
+    record_finite("drop_newest", FrameDropPolicy::DropNewest);
+}
+
+#[test]
+fn pipeline_block_never_drops() {
+    let segments = record_finite("block", FrameDropPolicy::Block);
+    assert!(segments.iter().all(|segment| segment.dropped_frames == 0), "{:?}", segments);
+}
+
+#[test]
+fn stop_ends_every_stage() {
+    let dir = output_dir("stop");
+    let cfg = RecorderConfig {
+        fps: 20,
+        container: Container::Avi,
+        include_audio: false,
+        source: FrameSourceKind::TestPattern { width: 64, height: 48 },
+        ..RecorderConfig::new(dir.clone())
+    };
+    let recorder = Recorder::new(cfg);
+    let stop = recorder.stop_handle();
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        stop.stop();
+    });
+    // Returns only once the converter and encoder threads have exited.
+    recorder.run_blocking().unwrap();
+    stopper.join().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn records_test_pattern_source() {
+    record_source("test_pattern", TestPatternSource::new(64, 48));
+}
+
+/// Needs an X server, e.g. `xvfb-run cargo test`; skipped without one.
+#[cfg(target_os = "linux")]
+#[test]
+fn records_x11_source() {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("DISPLAY not set, skipping");
+        return;
+    }
+    let source = main_dashboard_spinup_lib::video_main::X11Source::new(0).unwrap();
+    record_source("x11", source);
+}
+
+#[test]
+fn avi_repeats_fill_every_slot_up_to_the_segment_end() {
+    let dir = output_dir("avi_repeats");
+    let frame = TestPatternSource::new(64, 48).next_frame().unwrap().unwrap();
+    let cfg = RecorderConfig {
+        fps: 10,
+        segment_duration: Duration::from_secs(1),
+        container: Container::Avi,
+        include_audio: false,
+        skip_unchanged_frames: true,
+        keepalive_fps: 0,
+        ..RecorderConfig::new(dir.clone())
+    };
+    let source = StillSource { frame, left: 25 };
+    let segments = record_all_of(Recorder::new(cfg).with_frame_source(source)).unwrap();
+
+    assert_eq!(segments.len(), 3, "{:?}", segments);
+    for (i, segment) in segments.iter().enumerate() {
+        // Every segment starts with a full frame of its own; the rest of
+        // its slots repeat it.
+        assert_eq!(segment.frames, 1, "segment {}", i);
+        let index = avi_index(&segment.path);
+        assert!(index[0] > 0, "segment {} starts with a repeat", i);
+        assert!(index[1..].iter().all(|&size| size == 0), "segment {}: {:?}", i, index);
+        if i < 2 {
+            // Filled up to the segment end, not just to the last capture.
+            assert_eq!(index.len(), 10, "segment {}", i);
+            // A capture right at the boundary may fall on either side.
+            let captured = segment.unchanged_frames + segment.frames;
+            assert!((9..=11).contains(&captured), "segment {}: {} captured", i, captured);
+        }
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn frame_failing_conversion_is_not_compared_with() {
+    let dir = output_dir("bad_frame");
+    let good = TestPatternSource::new(64, 48).next_frame().unwrap().unwrap();
+    // Claims the right size but lacks data, so conversion rejects it.
+    let mut bad = good.clone();
+    bad.data.truncate(bad.data.len() / 2);
+    let cfg = RecorderConfig {
+        fps: 20,
+        container: Container::Avi,
+        include_audio: false,
+        skip_unchanged_frames: true,
+        keepalive_fps: 0,
+        ..RecorderConfig::new(dir.clone())
+    };
+    let source = FrameList([good.clone(), bad, good].into());
+    let segments = record_all_of(Recorder::new(cfg).with_frame_source(source)).unwrap();
+
+    assert_eq!(segments.len(), 1);
+    // The third frame matches the first, the last one written.
+    assert_eq!(segments[0].frames, 1);
+    assert_eq!(segments[0].unchanged_frames, 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
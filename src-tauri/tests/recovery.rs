@@ -0,0 +1,282 @@
+// tests/recovery.rs
+
+//! Segments cut off at various points, as a crash would leave them, and
+//! what `recovery` makes of them: cut back to the last complete fragment or
+//! chunk, with the index and sizes consistent again.
+
+mod common;
+
+use common::{boxes, Mp4Box};
+use main_dashboard_spinup_lib::video_main::recovery::{self, RecoveryAction};
+use main_dashboard_spinup_lib::video_main::{
+    AviSegmentConfig, AviSegmentWriter, Mp4Muxer, VideoCodec, VideoTrackConfig,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("recovery_test_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes the first `len` bytes of `data` to `dir/name`.
+fn cut(dir: &Path, name: &str, data: &[u8], len: usize) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, &data[..len]).unwrap();
+    path
+}
+
+fn video_config() -> VideoTrackConfig {
+    VideoTrackConfig {
+        codec: VideoCodec::H264 {
+            sps: vec![0x67, 0x42, 0xc0, 0x1f],
+            pps: vec![0x68, 0xce],
+        },
+        width: 64,
+        height: 48,
+        timescale: 1000,
+    }
+}
+
+/// A fragmented MP4 of four one-second fragments.
+fn fragmented_mp4(dir: &Path) -> Vec<u8> {
+    let path = dir.join("source.mp4");
+    let mut muxer = Mp4Muxer::create_fragmented(&path, video_config(), None, Duration::from_secs(1)).unwrap();
+    for i in 0..8u8 {
+        muxer.write_video(&vec![i; 100 + i as usize], 500, true).unwrap();
+    }
+    muxer.finish().unwrap();
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    data
+}
+
+#[test]
+fn fragmented_mp4_is_cut_after_the_last_complete_fragment() {
+    let dir = test_dir("fragmented");
+    let file = fragmented_mp4(&dir);
+    let top = boxes(&file);
+    let kinds: Vec<&[u8; 4]> = top.iter().map(|b| &b.kind).collect();
+    assert_eq!(&kinds[..2], [b"ftyp", b"moov"]);
+    let end = |b: &Mp4Box| b.offset + b.size;
+    // Ends of the fragments, each a `moof` + `mdat` pair.
+    let fragment_ends: Vec<usize> = top[2..].chunks(2).map(|pair| end(&pair[1])).collect();
+    assert_eq!(fragment_ends.len(), 4);
+    let moov_end = end(&top[1]);
+
+    assert_eq!(
+        recovery::recover_segment(&cut(&dir, "whole.mp4", &file, file.len())).unwrap(),
+        Some(RecoveryAction::Intact)
+    );
+
+    let second_moof = &top[4];
+    let last_mdat = &top[top.len() - 1];
+    let cases = [
+        // Inside the second fragment's `moof`.
+        (second_moof.offset + 10, Some(fragment_ends[0])),
+        // A complete `moof` without its `mdat`.
+        (end(second_moof), Some(fragment_ends[0])),
+        // Part of the second fragment's samples.
+        (end(second_moof) + 20, Some(fragment_ends[0])),
+        // Half of a box header after the third fragment.
+        (fragment_ends[2] + 4, Some(fragment_ends[2])),
+        // Inside the last `mdat`.
+        (last_mdat.offset + 9, Some(fragment_ends[2])),
+        // Before the first fragment is complete: nothing to play.
+        (moov_end + 30, None),
+        (moov_end, None),
+    ];
+    for (i, (len, expected)) in cases.into_iter().enumerate() {
+        let path = cut(&dir, &format!("cut_{}.mp4", i), &file, len);
+        let action = recovery::recover_segment(&path).unwrap();
+        match expected {
+            Some(expected_len) => {
+                assert_eq!(action, Some(RecoveryAction::Repaired), "cut at {}", len);
+                let repaired = fs::read(&path).unwrap();
+                assert_eq!(repaired.len(), expected_len, "cut at {}", len);
+                assert_eq!(&repaired[..], &file[..expected_len]);
+                // Whole boxes only, ending in a complete fragment.
+                let kinds: Vec<[u8; 4]> = boxes(&repaired).iter().map(|b| b.kind).collect();
+                assert_eq!(&kinds[kinds.len() - 2..], [*b"moof", *b"mdat"]);
+                assert_eq!(recovery::recover_segment(&path).unwrap(), Some(RecoveryAction::Intact));
+            }
+            None => {
+                assert_eq!(action, Some(RecoveryAction::Removed), "cut at {}", len);
+                assert!(!path.exists());
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn unfinished_progressive_mp4_is_removed() {
+    let dir = test_dir("progressive");
+    let path = dir.join("source.mp4");
+    let mut muxer = Mp4Muxer::create(&path, video_config(), None).unwrap();
+    for i in 0..4u8 {
+        muxer.write_video(&[i; 100], 500, true).unwrap();
+    }
+    muxer.finish().unwrap();
+    let file = fs::read(&path).unwrap();
+    assert_eq!(recovery::recover_segment(&path).unwrap(), Some(RecoveryAction::Intact));
+
+    // Cut anywhere before the `moov` is complete.
+    let moov = boxes(&file).into_iter().find(|b| &b.kind == b"moov").unwrap().offset;
+    for len in [100, moov, moov + 20] {
+        let cut_path = cut(&dir, "cut.mp4", &file, len);
+        assert_eq!(recovery::recover_segment(&cut_path).unwrap(), Some(RecoveryAction::Removed));
+        assert!(!cut_path.exists());
+    }
+
+    // Junk after a finished file is cut off.
+    let mut junk = file.clone();
+    junk.extend_from_slice(&[0, 0, 0, 50, b'f', b'r', b'e', b'e']);
+    let junk_path = cut(&dir, "junk.mp4", &junk, junk.len());
+    assert_eq!(recovery::recover_segment(&junk_path).unwrap(), Some(RecoveryAction::Repaired));
+    assert_eq!(fs::read(&junk_path).unwrap(), file);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+fn le32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+/// One RIFF chunk: its id, where its data starts and how long it is.
+fn riff_chunks(data: &[u8], start: usize, end: usize) -> Vec<([u8; 4], usize, usize)> {
+    let mut chunks = Vec::new();
+    let mut at = start;
+    while at + 8 <= end {
+        let size = le32(data, at + 4) as usize;
+        chunks.push((data[at..at + 4].try_into().unwrap(), at + 8, size));
+        at += 8 + size + (size & 1);
+    }
+    assert_eq!(at, end, "chunks do not fill their list");
+    chunks
+}
+
+/// Checks the sizes and index of an AVI and returns the sizes of the
+/// frames it indexes.
+fn check_avi(data: &[u8]) -> Vec<u32> {
+    assert_eq!(&data[..4], b"RIFF");
+    assert_eq!(le32(data, 4) as usize + 8, data.len(), "RIFF size");
+    assert_eq!(&data[8..12], b"AVI ");
+
+    let top = riff_chunks(data, 12, data.len());
+    let ids: Vec<&[u8; 4]> = top.iter().map(|(id, _, _)| id).collect();
+    assert_eq!(ids, [b"LIST", b"LIST", b"idx1"]);
+    let (_, hdrl, hdrl_size) = top[0];
+    assert_eq!(&data[hdrl..hdrl + 4], b"hdrl");
+    let (_, movi, movi_size) = top[1];
+    assert_eq!(&data[movi..movi + 4], b"movi");
+    let (_, idx1, idx1_size) = top[2];
+
+    // Every chunk in `movi` is indexed, in order, at its offset from 'movi'.
+    let frames = riff_chunks(data, movi + 4, movi + movi_size);
+    assert_eq!(idx1_size, frames.len() * 16);
+    for (i, (id, at, size)) in frames.iter().enumerate() {
+        let entry = idx1 + i * 16;
+        assert_eq!(&data[entry..entry + 4], id);
+        assert_eq!(le32(data, entry + 8) as usize, at - 8 - movi);
+        assert_eq!(le32(data, entry + 12) as usize, *size);
+    }
+
+    // Frame counts in the main and the stream header.
+    let headers = riff_chunks(data, hdrl + 4, hdrl + hdrl_size);
+    let (_, avih, _) = headers[0];
+    assert_eq!(le32(data, avih + 16) as usize, frames.len(), "avih dwTotalFrames");
+    let (_, strl, _) = headers[1];
+    let strh = strl + 4 + 8;
+    assert_eq!(&data[strh..strh + 4], b"vids");
+    assert_eq!(le32(data, strh + 32) as usize, frames.len(), "strh dwLength");
+
+    frames.iter().map(|(_, _, size)| *size as u32).collect()
+}
+
+#[test]
+fn avi_is_cut_after_the_last_complete_chunk_and_reindexed() {
+    let dir = test_dir("avi");
+    let mut writer = AviSegmentWriter::create_new(AviSegmentConfig {
+        width: 64,
+        height: 48,
+        fps: 10,
+        output_dir: dir.clone(),
+        base_name: "source".to_string(),
+    })
+    .unwrap();
+    // Odd sizes, so chunks are padded.
+    let sizes = [101u32, 80, 57, 90, 33];
+    for (i, size) in sizes.iter().enumerate() {
+        writer.write_jpeg_frame(&vec![i as u8 + 1; *size as usize]).unwrap();
+    }
+    writer.write_repeat_frame().unwrap();
+    let path = writer.finalize().unwrap();
+    let file = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut all = sizes.to_vec();
+    all.push(0);
+    assert_eq!(check_avi(&file), all);
+    assert_eq!(
+        recovery::recover_segment(&cut(&dir, "whole.avi", &file, file.len())).unwrap(),
+        Some(RecoveryAction::Intact)
+    );
+
+    let top = riff_chunks(&file, 12, file.len());
+    let (_, movi, _) = top[1];
+    let chunks = riff_chunks(&file, movi + 4, top[2].1 - 8);
+    let chunk_end = |i: usize| {
+        let (_, at, size) = chunks[i];
+        at + size + (size & 1)
+    };
+
+    let cases = [
+        // Inside the third frame.
+        (chunks[2].1 + 10, 2),
+        // Right after the second frame.
+        (chunk_end(1), 2),
+        // Inside the fourth frame's header.
+        (chunk_end(2) + 3, 3),
+        // A frame without its padding byte.
+        (chunk_end(0) - 1, 0),
+        // All frames, cut inside the index.
+        (chunk_end(5) + 12, 6),
+    ];
+    for (i, (len, frames)) in cases.into_iter().enumerate() {
+        let path = cut(&dir, &format!("cut_{}.avi", i), &file, len);
+        let action = recovery::recover_segment(&path).unwrap();
+        if frames == 0 {
+            assert_eq!(action, Some(RecoveryAction::Removed), "cut at {}", len);
+            assert!(!path.exists());
+            continue;
+        }
+        assert_eq!(action, Some(RecoveryAction::Repaired), "cut at {}", len);
+        let repaired = fs::read(&path).unwrap();
+        assert_eq!(check_avi(&repaired), &all[..frames], "cut at {}", len);
+        assert_eq!(recovery::recover_segment(&path).unwrap(), Some(RecoveryAction::Intact));
+    }
+
+    // Before `movi` is reached nothing can be saved.
+    let path = cut(&dir, "header.avi", &file, movi - 20);
+    assert_eq!(recovery::recover_segment(&path).unwrap(), Some(RecoveryAction::Removed));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn recover_segments_walks_subfolders() {
+    let dir = test_dir("walk");
+    let file = fragmented_mp4(&dir);
+    let day = dir.join("2024-01-01");
+    fs::create_dir_all(&day).unwrap();
+    let intact = cut(&day, "intact.mp4", &file, file.len());
+    let partial = cut(&day, "partial.mp4", &file, file.len() - 5);
+    fs::write(day.join("notes.txt"), b"not a segment").unwrap();
+
+    let results = recovery::recover_segments(&dir);
+    assert_eq!(results, [(partial.clone(), RecoveryAction::Repaired)]);
+    assert!(intact.exists() && partial.exists());
+    let _ = fs::remove_dir_all(&dir);
+}